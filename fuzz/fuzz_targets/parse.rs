@@ -0,0 +1,29 @@
+// fuzz/fuzz_targets/parse.rs
+//
+// 任意字节 (按 UTF-8 宽松解码) 作为一行输入: 拆分、别名展开和解析都不能 panic，
+// 参数的字节范围必须落在字符边界上
+#![no_main]
+
+use std::collections::BTreeMap;
+
+use libfuzzer_sys::fuzz_target;
+use rustprompt::command::parser;
+
+fuzz_target!(|data: &[u8]| {
+    let line = String::from_utf8_lossy(data);
+    for token in parser::tokenize_spans(&line) {
+        assert!(line.is_char_boundary(token.start) && line.is_char_boundary(token.end));
+    }
+    let _ = parser::parse(&line);
+    let _ = parser::parse(&format!("/add {}", line));
+
+    let mut aliases = BTreeMap::new();
+    aliases.insert("/x".to_string(), format!("/add {}", line));
+    if let Ok(expanded) = parser::expand_aliases(&format!("/x {}", line), &aliases) {
+        let _ = parser::parse(&expanded);
+    }
+
+    // quote_arg 的结果总能被 tokenize 还原
+    let value = line.to_string();
+    assert_eq!(parser::tokenize(&parser::quote_arg(&value)), [value]);
+});
@@ -0,0 +1,53 @@
+// fuzz/fuzz_targets/payload.rs
+//
+// 拼装完整的 payload: 任意个文件 (任意字节的路径和内容) 加 prompt，打开或关闭按语言分组。
+// 结果必须是良构的 XML，文档数与选中的文件数一致；拆分和清单也不能 panic
+#![no_main]
+
+use std::path::PathBuf;
+
+use libfuzzer_sys::fuzz_target;
+use rustprompt::{
+    app::{snippet_manager::SnippetManager, state::AppState},
+    core::{validate::validate_xml, xml::generate_single_file_snippet},
+};
+
+fuzz_target!(|data: &[u8]| {
+    let Some((&flags, data)) = data.split_first() else { return };
+    // 以 0xff 分隔: 第一段是 prompt，之后每两段是一个文件的路径和内容
+    let mut chunks = data.split(|&b| b == 0xff);
+    let prompt = String::from_utf8_lossy(chunks.next().unwrap_or_default()).into_owned();
+
+    let mut st = AppState::new();
+    st.settings.group_by_language = flags & 1 == 1;
+    st.prompt_text = prompt;
+    while let (Some(path), Some(content)) = (chunks.next(), chunks.next()) {
+        if path.is_empty() {
+            continue;
+        }
+        let path = path_from_bytes(path);
+        let content = String::from_utf8_lossy(content);
+        st.partial_docs.insert(path.clone(), generate_single_file_snippet(&path, &content, 0));
+        st.selected_paths.insert(path);
+    }
+
+    let payload = SnippetManager::assemble_payload(&st).expect("XML format never fails");
+    match validate_xml(&payload) {
+        Ok(report) => assert_eq!(report.documents, st.partial_docs.len()),
+        Err(e) => panic!("{}\n{}", e, payload),
+    }
+    let _ = SnippetManager::manifest(&st);
+    let _ = SnippetManager::split_payload(&st, 64 + (flags as usize) * 16);
+});
+
+fn path_from_bytes(bytes: &[u8]) -> PathBuf {
+    #[cfg(unix)]
+    {
+        use std::os::unix::ffi::OsStrExt;
+        PathBuf::from(std::ffi::OsStr::from_bytes(bytes))
+    }
+    #[cfg(not(unix))]
+    {
+        PathBuf::from(String::from_utf8_lossy(bytes).into_owned())
+    }
+}
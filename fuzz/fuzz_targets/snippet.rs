@@ -0,0 +1,46 @@
+// fuzz/fuzz_targets/snippet.rs
+//
+// 任意字节的路径和文件内容: 生成的 snippet 必须是良构的 XML，取回的内容与原文相同
+// (XML 不允许的控制字符替换为 U+FFFD)
+#![no_main]
+
+use std::path::PathBuf;
+
+use libfuzzer_sys::fuzz_target;
+use rustprompt::core::{
+    validate::validate_xml,
+    xml::{generate_single_file_snippet_with_attrs, snippet_content},
+};
+
+fuzz_target!(|data: &[u8]| {
+    // 第一个字节决定路径的长度，其余是文件内容
+    let Some((&len, rest)) = data.split_first() else { return };
+    let (path, content) = rest.split_at((len as usize).min(rest.len()));
+    if path.is_empty() {
+        return;
+    }
+    let path = path_from_bytes(path);
+    let content = String::from_utf8_lossy(content);
+
+    let snippet = generate_single_file_snippet_with_attrs(&path, &content, 0, &[("indent_unit", content.to_string())]);
+    if let Err(e) = validate_xml(&snippet) {
+        panic!("{}\n{}", e, snippet);
+    }
+    let expected: String = content
+        .chars()
+        .map(|c| if (c as u32) < 0x20 && !matches!(c, '\t' | '\n' | '\r') { '\u{FFFD}' } else { c })
+        .collect();
+    assert_eq!(snippet_content(&snippet), expected);
+});
+
+fn path_from_bytes(bytes: &[u8]) -> PathBuf {
+    #[cfg(unix)]
+    {
+        use std::os::unix::ffi::OsStrExt;
+        PathBuf::from(std::ffi::OsStr::from_bytes(bytes))
+    }
+    #[cfg(not(unix))]
+    {
+        PathBuf::from(String::from_utf8_lossy(bytes).into_owned())
+    }
+}
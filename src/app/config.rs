@@ -0,0 +1,327 @@
+// src/app/config.rs
+
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+
+use serde::Deserialize;
+
+use crate::{
+    app::{keys, settings::Settings, state::AppState},
+    command::parser,
+    core::{ignore_rules::IgnoreConfig, template::{self, OutputFormat}},
+};
+
+/// 项目配置文件名: 先在项目根目录查找，找不到再到 git 仓库根目录查找
+pub const CONFIG_FILE: &str = ".rustprompt.toml";
+
+/// 配置文件支持的键，/config 按这个顺序列出
+pub const CONFIG_KEYS: [&str; 14] = [
+    "ignore.hidden",
+    "ignore.gitignore",
+    "ignore.node_modules",
+    "ignore.binary",
+    "ignore.patterns",
+    "prompt",
+    "format",
+    "token_budget",
+    "edit_mode",
+    "hints",
+    "confirm_destructive",
+    "keybindings",
+    "aliases",
+    "on_start",
+];
+
+/// `.rustprompt.toml` 的内容；没有出现的键保持当前值
+#[derive(Debug, Default, Deserialize)]
+pub struct ProjectConfig {
+    #[serde(default)]
+    pub ignore: IgnoreSection,
+    /// 启动时预先填入的 prompt (instruction) 文本
+    pub prompt: Option<String>,
+    /// `xml` 或自定义模板名
+    pub format: Option<String>,
+    pub token_budget: Option<usize>,
+    /// REPL 的键位: `emacs` 或 `vi`
+    pub edit_mode: Option<String>,
+    /// 输入时的行内提示 (同 /set hints)
+    pub hints: Option<bool>,
+    /// /reset 和 /quit 之前确认 (同 /set confirm_destructive)
+    pub confirm_destructive: Option<bool>,
+    /// `[[keybindings]]` 数组: `key = "ctrl-s"`、`action = "submit"`，可选 `editor = "single" | "multiline"`
+    pub keybindings: Option<Vec<KeyBindingEntry>>,
+    /// `[aliases]` 表: 名字 = 展开的命令 (同 /alias，开头的 `/` 可以省略)
+    pub aliases: Option<BTreeMap<String, String>>,
+    /// 进入 REPL 之前依次执行的命令，例如 `["/add src", "/mode prompt"]` (在 --source 和 -c 之前)
+    pub on_start: Option<Vec<String>>,
+}
+
+/// `[ignore]` 表，对应 IgnoreConfig
+#[derive(Debug, Default, Deserialize)]
+pub struct IgnoreSection {
+    pub hidden: Option<bool>,
+    pub gitignore: Option<bool>,
+    pub node_modules: Option<bool>,
+    pub binary: Option<bool>,
+    pub patterns: Option<Vec<String>>,
+}
+
+/// `[[keybindings]]` 中的一项，由 keys::parse_binding 校验
+#[derive(Debug, Default, Deserialize)]
+pub struct KeyBindingEntry {
+    pub key: String,
+    pub action: String,
+    pub editor: Option<String>,
+}
+
+/// 一个配置项的当前值来自哪里
+#[derive(Debug, Clone, PartialEq)]
+pub enum ConfigSource {
+    Default,
+    File(PathBuf),
+    /// 本次会话中被命令修改 (命令名)；这类值优先于配置文件，/reload-config 不会覆盖
+    Command(&'static str),
+}
+
+impl ConfigSource {
+    pub fn describe(&self) -> String {
+        match self {
+            ConfigSource::Default => "default".to_string(),
+            ConfigSource::File(path) => path.display().to_string(),
+            ConfigSource::Command(name) => format!("{} in this session", name),
+        }
+    }
+}
+
+/// 查找配置文件: 项目根目录下的 `.rustprompt.toml`，否则是所在 git 仓库根目录下的
+pub fn find_config(root: &Path) -> Option<PathBuf> {
+    let local = root.join(CONFIG_FILE);
+    if local.is_file() {
+        return Some(local);
+    }
+    let git_root = root.ancestors().find(|dir| dir.join(".git").exists())?;
+    Some(git_root.join(CONFIG_FILE)).filter(|p| p.is_file())
+}
+
+/// 解析配置文件；未知的键只产生警告 (加入 warnings)，语法或类型错误返回 Err
+pub fn parse(text: &str, warnings: &mut Vec<String>) -> Result<ProjectConfig, String> {
+    let table: toml::Table = text.parse().map_err(|e: toml::de::Error| e.message().to_string())?;
+    for (key, value) in &table {
+        match (key.as_str(), value) {
+            ("ignore", toml::Value::Table(ignore)) => {
+                for key in ignore.keys() {
+                    if !["hidden", "gitignore", "node_modules", "binary", "patterns"].contains(&key.as_str()) {
+                        warnings.push(format!("Unknown key ignore.{}", key));
+                    }
+                }
+            }
+            ("keybindings", toml::Value::Array(entries)) => {
+                for key in entries.iter().filter_map(|e| e.as_table()).flat_map(|e| e.keys()) {
+                    if !["key", "action", "editor"].contains(&key.as_str()) {
+                        warnings.push(format!("Unknown key keybindings.{}", key));
+                    }
+                }
+            }
+            ("ignore" | "prompt" | "format" | "token_budget" | "edit_mode" | "hints" | "confirm_destructive" | "keybindings" | "aliases" | "on_start", _) => {}
+            _ => warnings.push(format!("Unknown key {}", key)),
+        }
+    }
+    table.try_into().map_err(|e: toml::de::Error| e.message().to_string())
+}
+
+/// 查找、读取并应用项目配置，返回要打印的提示和警告。
+///  - 被本次会话中的命令修改过的键 (ConfigSource::Command) 保持不变
+///  - 之前来自配置文件、现在文件中已经没有的键恢复为默认值
+///  - 调用方负责随后更新项目树并 rebuild (忽略规则和输出格式可能变化)
+pub fn reload(st: &mut AppState) -> Vec<String> {
+    let mut messages = Vec::new();
+    let found = find_config(&st.project_root);
+    let config = match &found {
+        None => ProjectConfig::default(),
+        Some(path) => {
+            let mut warnings = Vec::new();
+            let parsed = std::fs::read_to_string(path)
+                .map_err(|e| e.to_string())
+                .and_then(|text| parse(&text, &mut warnings));
+            for warning in warnings {
+                messages.push(format!("(Warning) {}: {}", path.display(), warning));
+            }
+            match parsed {
+                Ok(config) => config,
+                Err(e) => {
+                    messages.push(format!("(Warning) Ignoring {}: {}", path.display(), e));
+                    return messages;
+                }
+            }
+        }
+    };
+
+    let source = found.clone().map(ConfigSource::File);
+    let mut applied = 0;
+    let mut kept = Vec::new();
+    for key in CONFIG_KEYS {
+        let current = st.config_sources.get(key).cloned().unwrap_or(ConfigSource::Default);
+        if let ConfigSource::Command(_) = current {
+            if config_has(&config, key) {
+                kept.push(key);
+            }
+            continue;
+        }
+        let result = match (&source, config_has(&config, key)) {
+            (Some(source), true) => apply_key(st, &config, key).map(|()| Some(source.clone())),
+            // 文件中去掉了这个键: 恢复默认值
+            _ if matches!(current, ConfigSource::File(_)) => {
+                reset_key(st, key);
+                Ok(None)
+            }
+            _ => continue,
+        };
+        match result {
+            Ok(Some(source)) => {
+                st.config_sources.insert(key, source);
+                applied += 1;
+            }
+            Ok(None) => {
+                st.config_sources.remove(key);
+            }
+            Err(e) => messages.push(format!("(Warning) {}: {}", key, e)),
+        }
+    }
+
+    if let Some(path) = found {
+        messages.insert(0, format!("(Note) Loaded {} ({} settings applied)", path.display(), applied));
+    }
+    if !kept.is_empty() {
+        messages.push(format!("(Note) Kept values changed in this session: {}", kept.join(", ")));
+    }
+    messages
+}
+
+/// 配置项的当前值 (/config 显示)
+pub fn current_value(st: &AppState, key: &str) -> String {
+    let on_off = |v: bool| if v { "on" } else { "off" }.to_string();
+    match key {
+        "ignore.hidden" => on_off(st.ignore.ignore_hidden),
+        "ignore.gitignore" => on_off(st.ignore.use_gitignore),
+        "ignore.node_modules" => on_off(st.ignore.ignore_node_modules),
+        "ignore.binary" => on_off(st.ignore.ignore_binary),
+        "ignore.patterns" if st.ignore.patterns.is_empty() => "(none)".to_string(),
+        "ignore.patterns" => st.ignore.patterns.join(", "),
+        "prompt" if st.prompt_text.is_empty() => "(empty)".to_string(),
+        "prompt" => format!("{} chars, {} tokens", st.prompt_text.chars().count(), st.prompt_tokens),
+        "format" => st.output_format.describe(),
+        "token_budget" => st.settings.token_budget.to_string(),
+        "edit_mode" => st.settings.edit_mode.as_str().to_string(),
+        "hints" => on_off(st.settings.hints),
+        "confirm_destructive" => on_off(st.settings.confirm_destructive),
+        "keybindings" if st.keybindings.is_empty() => "(defaults)".to_string(),
+        "keybindings" => st
+            .keybindings
+            .iter()
+            .map(|b| format!("{} = {} ({})", b.key, b.action.as_str(), b.editor.as_str()))
+            .collect::<Vec<_>>()
+            .join(", "),
+        "aliases" if st.aliases.is_empty() => "(none)".to_string(),
+        "aliases" => st.aliases.iter().map(|(name, expansion)| format!("{} = {}", name, expansion)).collect::<Vec<_>>().join(", "),
+        "on_start" if st.on_start.is_empty() => "(none)".to_string(),
+        "on_start" => st.on_start.join(", "),
+        _ => String::new(),
+    }
+}
+
+fn config_has(config: &ProjectConfig, key: &str) -> bool {
+    match key {
+        "ignore.hidden" => config.ignore.hidden.is_some(),
+        "ignore.gitignore" => config.ignore.gitignore.is_some(),
+        "ignore.node_modules" => config.ignore.node_modules.is_some(),
+        "ignore.binary" => config.ignore.binary.is_some(),
+        "ignore.patterns" => config.ignore.patterns.is_some(),
+        "prompt" => config.prompt.is_some(),
+        "format" => config.format.is_some(),
+        "token_budget" => config.token_budget.is_some(),
+        "edit_mode" => config.edit_mode.is_some(),
+        "hints" => config.hints.is_some(),
+        "confirm_destructive" => config.confirm_destructive.is_some(),
+        "keybindings" => config.keybindings.is_some(),
+        "aliases" => config.aliases.is_some(),
+        "on_start" => config.on_start.is_some(),
+        _ => false,
+    }
+}
+
+fn apply_key(st: &mut AppState, config: &ProjectConfig, key: &str) -> Result<(), String> {
+    match key {
+        "ignore.hidden" => st.ignore.ignore_hidden = config.ignore.hidden.unwrap_or_default(),
+        "ignore.gitignore" => st.ignore.use_gitignore = config.ignore.gitignore.unwrap_or_default(),
+        "ignore.node_modules" => st.ignore.ignore_node_modules = config.ignore.node_modules.unwrap_or_default(),
+        "ignore.binary" => st.ignore.ignore_binary = config.ignore.binary.unwrap_or_default(),
+        "ignore.patterns" => {
+            let patterns = config.ignore.patterns.clone().unwrap_or_default();
+            for pattern in &patterns {
+                IgnoreConfig::validate_pattern(pattern).map_err(|e| format!("invalid pattern {}: {}", pattern, e))?;
+            }
+            st.ignore.patterns = patterns;
+        }
+        "prompt" => {
+            st.prompt_text = config.prompt.clone().unwrap_or_default();
+            st.recalc_prompt_tokens();
+        }
+        "format" => {
+            st.output_format = match config.format.as_deref().unwrap_or("xml") {
+                "xml" => OutputFormat::Xml,
+                name => {
+                    let (name, source) = template::find_template(name)?;
+                    OutputFormat::Custom { name, source }
+                }
+            };
+        }
+        "token_budget" => st.settings.token_budget = config.token_budget.unwrap_or_default(),
+        "edit_mode" => st.settings.set("edit_mode", config.edit_mode.as_deref().unwrap_or("emacs"))?,
+        "hints" => st.settings.hints = config.hints.unwrap_or(true),
+        "confirm_destructive" => st.settings.confirm_destructive = config.confirm_destructive.unwrap_or(true),
+        "keybindings" => {
+            let mut bindings = Vec::new();
+            for (i, entry) in config.keybindings.iter().flatten().enumerate() {
+                let parsed = keys::parse_binding(&entry.key, &entry.action, entry.editor.as_deref())
+                    .map_err(|e| format!("entry {}: {}", i + 1, e))?;
+                bindings.extend(parsed);
+            }
+            st.keybindings = bindings;
+        }
+        "aliases" => {
+            let mut aliases = BTreeMap::new();
+            for (name, expansion) in config.aliases.iter().flatten() {
+                let (name, expansion) = parser::normalize_alias(name, expansion, &aliases)?;
+                aliases.insert(name, expansion);
+            }
+            st.aliases = aliases;
+        }
+        "on_start" => st.on_start = config.on_start.clone().unwrap_or_default(),
+        _ => {}
+    }
+    Ok(())
+}
+
+fn reset_key(st: &mut AppState, key: &str) {
+    let ignore = IgnoreConfig::default();
+    match key {
+        "ignore.hidden" => st.ignore.ignore_hidden = ignore.ignore_hidden,
+        "ignore.gitignore" => st.ignore.use_gitignore = ignore.use_gitignore,
+        "ignore.node_modules" => st.ignore.ignore_node_modules = ignore.ignore_node_modules,
+        "ignore.binary" => st.ignore.ignore_binary = ignore.ignore_binary,
+        "ignore.patterns" => st.ignore.patterns.clear(),
+        "prompt" => {
+            st.prompt_text.clear();
+            st.recalc_prompt_tokens();
+        }
+        "format" => st.output_format = OutputFormat::Xml,
+        "token_budget" => st.settings.token_budget = Settings::default().token_budget,
+        "edit_mode" => st.settings.edit_mode = Settings::default().edit_mode,
+        "hints" => st.settings.hints = Settings::default().hints,
+        "confirm_destructive" => st.settings.confirm_destructive = Settings::default().confirm_destructive,
+        "keybindings" => st.keybindings.clear(),
+        "aliases" => st.aliases.clear(),
+        "on_start" => st.on_start.clear(),
+        _ => {}
+    }
+}
@@ -0,0 +1,96 @@
+// src/app/context.rs
+
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+
+use crate::{
+    app::{snippet_manager::SnippetManager, state::AppState},
+    core::{files_scanner, tokenizer::calculate_tokens_in_string},
+    error::AppError,
+};
+
+/// 不经过 REPL 构建上下文: 扫描路径 -> 生成 snippet -> 拼装 payload -> 统计 token。
+/// 与 REPL 的 /add、/copy 共用 AppState 和 SnippetManager，得到的 payload 完全相同，且不向终端输出任何内容。
+pub struct ContextBuilder {
+    state: Arc<Mutex<AppState>>,
+}
+
+impl Default for ContextBuilder {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl ContextBuilder {
+    pub fn new() -> Self {
+        Self {
+            state: Arc::new(Mutex::new(AppState::new())),
+        }
+    }
+
+    /// 底层的共享状态 (用于修改 settings、排序策略、输出格式等)
+    pub fn state(&self) -> Arc<Mutex<AppState>> {
+        self.state.clone()
+    }
+
+    /// 添加文件、目录或 glob (应用 AppState::ignore 中的忽略规则)，返回新加入的文件数
+    pub async fn add(&self, path: impl AsRef<Path>) -> Result<usize, AppError> {
+        let path = path.as_ref();
+        let ignore_config = self.state.lock().unwrap().ignore.clone();
+        let files = files_scanner::scan_dir(path, &ignore_config).await?;
+        let new_files: Vec<PathBuf> = {
+            let mut st = self.state.lock().unwrap();
+            if !files.is_empty() {
+                st.add_roots.record(path, files.iter().cloned());
+            }
+            let new_files: Vec<PathBuf> = files.into_iter().filter(|f| st.selected_paths.insert(f.clone())).collect();
+            st.file_count = st.selected_paths.len();
+            new_files
+        };
+
+        let added = new_files.len();
+        SnippetManager::add_files_snippet(self.state.clone(), new_files).await?;
+        SnippetManager::update_project_tree_snippet(self.state.clone(), &ignore_config).await?;
+        SnippetManager::rebuild_and_recalc(self.state.clone()).await?;
+        Ok(added)
+    }
+
+    /// 设置放在 payload 末尾的 instruction (即 prompt 模式下收集的提示词)
+    pub fn set_prompt(&self, prompt: &str) -> Result<(), AppError> {
+        let tokens = calculate_tokens_in_string(prompt)?;
+        let mut st = self.state.lock().unwrap();
+        st.prompt_text = prompt.to_string();
+        st.prompt_tokens = tokens;
+        Ok(())
+    }
+
+    /// 已选文件，按路径排序
+    pub fn files(&self) -> Vec<PathBuf> {
+        let mut files: Vec<PathBuf> = self.state.lock().unwrap().selected_paths.iter().cloned().collect();
+        files.sort();
+        files
+    }
+
+    /// 每个已选文件 snippet 的 token 数，按路径排序
+    pub fn file_tokens(&self) -> Vec<(PathBuf, usize)> {
+        let files = self.files();
+        let st = self.state.lock().unwrap();
+        files
+            .into_iter()
+            .map(|p| {
+                let tokens = st.doc_tokens.get(&p).copied().unwrap_or(0);
+                (p, tokens)
+            })
+            .collect()
+    }
+
+    /// 合并后 payload 主体的 token 数 (含项目树和 XML 标记，不含 prompt)
+    pub fn token_count(&self) -> usize {
+        self.state.lock().unwrap().token_count
+    }
+
+    /// 最终 payload，与 /copy 复制到剪贴板的内容相同
+    pub fn payload(&self) -> Result<String, AppError> {
+        SnippetManager::assemble_payload(&self.state.lock().unwrap())
+    }
+}
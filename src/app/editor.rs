@@ -0,0 +1,53 @@
+// src/app/editor.rs
+
+use std::path::PathBuf;
+
+use crate::command::parser::tokenize;
+
+/// 编辑 prompt 用的编辑器命令: 依次取 $VISUAL、$EDITOR (可以带参数，例如 `code --wait`)，
+/// 都没有设置时使用 vi (Windows 上为 notepad)。第二项为使用的环境变量名，回退时为 None
+pub fn resolve_editor() -> (Vec<String>, Option<&'static str>) {
+    for var in ["VISUAL", "EDITOR"] {
+        if let Ok(value) = std::env::var(var) {
+            let args = tokenize(&value);
+            if !args.is_empty() {
+                return (args, Some(var));
+            }
+        }
+    }
+    let fallback = if cfg!(windows) { "notepad" } else { "vi" };
+    (vec![fallback.to_string()], None)
+}
+
+/// 编辑用的临时文件，drop 时删除 (包括出错返回)
+struct TempFile(PathBuf);
+
+impl Drop for TempFile {
+    fn drop(&mut self) {
+        let _ = std::fs::remove_file(&self.0);
+    }
+}
+
+/// 把 text 写入临时文件，用编辑器打开并等待它退出，返回保存后的内容 (去掉编辑器补上的结尾换行)。
+/// 编辑器无法启动或以非零状态退出时返回面向用户的错误信息。
+///
+/// 会阻塞到编辑器退出，只应在 REPL 读取输入之外调用: 此时终端处于普通模式，编辑器可以独占终端
+pub fn edit_text(editor: &[String], text: &str) -> Result<String, String> {
+    let file = TempFile(std::env::temp_dir().join(format!("rustprompt-prompt-{}.md", std::process::id())));
+    std::fs::write(&file.0, text).map_err(|e| format!("cannot write {}: {}", file.0.display(), e))?;
+
+    let status = std::process::Command::new(&editor[0])
+        .args(&editor[1..])
+        .arg(&file.0)
+        .status()
+        .map_err(|e| format!("cannot start {}: {}", editor[0], e))?;
+    if !status.success() {
+        return Err(match status.code() {
+            Some(code) => format!("{} exited with status {}", editor[0], code),
+            None => format!("{} was terminated by a signal", editor[0]),
+        });
+    }
+
+    let edited = std::fs::read_to_string(&file.0).map_err(|e| format!("cannot read {}: {}", file.0.display(), e))?;
+    Ok(edited.trim_end_matches(['\r', '\n']).to_string())
+}
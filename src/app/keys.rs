@@ -0,0 +1,245 @@
+// src/app/keys.rs
+
+use reedline::{EditCommand, KeyCode, KeyModifiers, Keybindings, ReedlineEvent};
+
+/// 可以绑定到按键的动作 (配置文件的 `[[keybindings]]` 中 `action = "..."`)
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum KeyAction {
+    /// 打开补全菜单，菜单已打开时选择下一项
+    Complete,
+    /// 提交输入 (多行编辑时直接结束编辑)
+    Submit,
+    /// 插入换行
+    Newline,
+    /// 清空当前输入
+    ClearLine,
+    /// 清屏
+    ClearScreen,
+    /// 搜索历史 (同 Ctrl+R)
+    HistorySearch,
+    /// 取消 (同 Ctrl+C)
+    Cancel,
+    /// 什么也不做，用于取消默认绑定
+    None,
+}
+
+impl KeyAction {
+    const ALL: [KeyAction; 8] = [
+        KeyAction::Complete,
+        KeyAction::Submit,
+        KeyAction::Newline,
+        KeyAction::ClearLine,
+        KeyAction::ClearScreen,
+        KeyAction::HistorySearch,
+        KeyAction::Cancel,
+        KeyAction::None,
+    ];
+
+    pub fn as_str(self) -> &'static str {
+        match self {
+            KeyAction::Complete => "complete",
+            KeyAction::Submit => "submit",
+            KeyAction::Newline => "newline",
+            KeyAction::ClearLine => "clear_line",
+            KeyAction::ClearScreen => "clear_screen",
+            KeyAction::HistorySearch => "history_search",
+            KeyAction::Cancel => "cancel",
+            KeyAction::None => "none",
+        }
+    }
+
+    pub fn parse(value: &str) -> Result<Self, String> {
+        let value = value.trim().to_lowercase().replace('-', "_");
+        Self::ALL.into_iter().find(|a| a.as_str() == value).ok_or_else(|| {
+            let names: Vec<&str> = Self::ALL.iter().map(|a| a.as_str()).collect();
+            format!("unknown action {} (available: {})", value, names.join(", "))
+        })
+    }
+
+    /// 对应的 reedline 事件
+    pub fn event(self) -> ReedlineEvent {
+        match self {
+            KeyAction::Complete => ReedlineEvent::UntilFound(vec![
+                ReedlineEvent::Menu("completion_menu".to_string()), // 保持菜单名称引用，内部会处理
+                ReedlineEvent::MenuNext, // 如果菜单已打开，则选择下一项
+            ]),
+            KeyAction::Submit => ReedlineEvent::Submit,
+            KeyAction::Newline => ReedlineEvent::Edit(vec![EditCommand::InsertNewline]),
+            KeyAction::ClearLine => ReedlineEvent::Edit(vec![EditCommand::Clear]),
+            KeyAction::ClearScreen => ReedlineEvent::ClearScreen,
+            KeyAction::HistorySearch => ReedlineEvent::SearchHistory,
+            KeyAction::Cancel => ReedlineEvent::CtrlC,
+            KeyAction::None => ReedlineEvent::None,
+        }
+    }
+}
+
+/// 绑定在哪个编辑器中生效
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum KeyEditor {
+    /// 输入命令的单行编辑器
+    Single,
+    /// 编辑 prompt 的多行编辑器 (/prompt)
+    Multiline,
+}
+
+impl KeyEditor {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            KeyEditor::Single => "single-line",
+            KeyEditor::Multiline => "multiline",
+        }
+    }
+}
+
+/// 一条键位绑定；key 是规范化后的写法 (例如 `ctrl-s`)，用于 /keys 显示
+#[derive(Debug, Clone, PartialEq)]
+pub struct KeyBinding {
+    pub key: String,
+    pub modifiers: KeyModifiers,
+    pub code: KeyCode,
+    pub action: KeyAction,
+    pub editor: KeyEditor,
+}
+
+/// 解析 `ctrl-s`、`alt-enter`、`shift-tab`、`f5` 一类的写法 (不区分大小写，`-` 也可以写成 `+`)，
+/// 返回修饰键、按键和规范化后的写法
+///
+/// ```
+/// use reedline::{KeyCode, KeyModifiers};
+/// use rustprompt::app::keys::parse_key;
+///
+/// assert_eq!(parse_key("Ctrl+S"), Ok((KeyModifiers::CONTROL, KeyCode::Char('s'), "ctrl-s".to_string())));
+/// assert_eq!(parse_key("alt-enter").unwrap().1, KeyCode::Enter);
+/// assert_eq!(parse_key("f5").unwrap().1, KeyCode::F(5));
+/// assert!(parse_key("ctrl-banana").unwrap_err().contains("unknown key banana"));
+/// assert!(parse_key("hyper-x").unwrap_err().contains("unknown modifier hyper"));
+/// ```
+pub fn parse_key(key: &str) -> Result<(KeyModifiers, KeyCode, String), String> {
+    let lower = key.trim().to_lowercase();
+    // 最后一段是按键本身，`ctrl--` 表示 Ctrl 加减号
+    let (prefix, name) = match lower.strip_suffix("--").or_else(|| lower.strip_suffix("+-")) {
+        Some(prefix) => (prefix, "-"),
+        None => match lower.rfind(['-', '+']) {
+            Some(i) if i + 1 < lower.len() => (&lower[..i], &lower[i + 1..]),
+            _ => ("", lower.as_str()),
+        },
+    };
+    let mut modifiers = KeyModifiers::NONE;
+    let mut names = Vec::new();
+    for part in prefix.split(['-', '+']).filter(|p| !p.is_empty()) {
+        let (modifier, canonical) = match part {
+            "ctrl" | "control" => (KeyModifiers::CONTROL, "ctrl"),
+            "alt" | "meta" => (KeyModifiers::ALT, "alt"),
+            "shift" => (KeyModifiers::SHIFT, "shift"),
+            other => return Err(format!("unknown modifier {} in {} (use ctrl, alt or shift)", other, key.trim())),
+        };
+        if !modifiers.contains(modifier) {
+            modifiers |= modifier;
+            names.push(canonical);
+        }
+    }
+    let code = match name {
+        "tab" => KeyCode::Tab,
+        "enter" | "return" => KeyCode::Enter,
+        "esc" | "escape" => KeyCode::Esc,
+        "space" => KeyCode::Char(' '),
+        "backspace" => KeyCode::Backspace,
+        "delete" | "del" => KeyCode::Delete,
+        "up" => KeyCode::Up,
+        "down" => KeyCode::Down,
+        "left" => KeyCode::Left,
+        "right" => KeyCode::Right,
+        "home" => KeyCode::Home,
+        "end" => KeyCode::End,
+        "pageup" => KeyCode::PageUp,
+        "pagedown" => KeyCode::PageDown,
+        f if f.len() > 1 && f.starts_with('f') && f[1..].parse::<u8>().is_ok_and(|n| (1..=12).contains(&n)) => {
+            KeyCode::F(f[1..].parse().unwrap_or(1))
+        }
+        c if c.chars().count() == 1 => KeyCode::Char(c.chars().next().unwrap_or(' ')),
+        other => {
+            return Err(format!(
+                "unknown key {} in {} (use e.g. ctrl-s, alt-enter, shift-tab, f5)",
+                other,
+                key.trim()
+            ))
+        }
+    };
+    names.push(name);
+    Ok((modifiers, code, names.join("-")))
+}
+
+/// 配置文件中的一条绑定: editor 为 `single`、`multiline`，省略时两个编辑器都生效 (返回两条)
+///
+/// ```
+/// use rustprompt::app::keys::{parse_binding, KeyAction, KeyEditor};
+///
+/// let both = parse_binding("ctrl-l", "clear_line", None).unwrap();
+/// assert_eq!(both.len(), 2);
+/// assert_eq!(both[0].action, KeyAction::ClearLine);
+/// let multi = parse_binding("alt-enter", "submit", Some("multiline")).unwrap();
+/// assert_eq!(multi[0].editor, KeyEditor::Multiline);
+/// assert!(parse_binding("ctrl-s", "save", None).unwrap_err().starts_with("unknown action save"));
+/// assert!(parse_binding("ctrl-s", "submit", Some("vi")).is_err());
+/// ```
+pub fn parse_binding(key: &str, action: &str, editor: Option<&str>) -> Result<Vec<KeyBinding>, String> {
+    let (modifiers, code, key) = parse_key(key)?;
+    let action = KeyAction::parse(action)?;
+    let editors: &[KeyEditor] = match editor.map(|e| e.trim().to_lowercase()).as_deref() {
+        None | Some("all") => &[KeyEditor::Single, KeyEditor::Multiline],
+        Some("single" | "single-line" | "single_line") => &[KeyEditor::Single],
+        Some("multiline" | "multi") => &[KeyEditor::Multiline],
+        Some(other) => return Err(format!("unknown editor {} (expected single, multiline or all)", other)),
+    };
+    Ok(editors
+        .iter()
+        .map(|&editor| KeyBinding { key: key.clone(), modifiers, code, action, editor })
+        .collect())
+}
+
+/// 内置绑定 (emacs 或 vi insert 默认键位之上的修改): 单行时 Tab 打开补全菜单；
+/// 多行时 Tab 不做补全，Ctrl+S / Ctrl+Enter 直接提交 (Ctrl+Enter 需要终端能区分修饰键)
+pub fn default_bindings() -> Vec<KeyBinding> {
+    [
+        ("tab", KeyAction::Complete, KeyEditor::Single),
+        ("tab", KeyAction::None, KeyEditor::Multiline),
+        ("ctrl-s", KeyAction::Submit, KeyEditor::Multiline),
+        ("ctrl-enter", KeyAction::Submit, KeyEditor::Multiline),
+    ]
+    .into_iter()
+    .filter_map(|(key, action, editor)| {
+        let (modifiers, code, key) = parse_key(key).ok()?;
+        Some(KeyBinding { key, modifiers, code, action, editor })
+    })
+    .collect()
+}
+
+/// 生效的绑定: 内置绑定被配置文件中同一编辑器、同一按键的绑定替换，其余追加在后面。
+/// 第二个值表示这条绑定是否来自配置文件
+pub fn active_bindings(custom: &[KeyBinding]) -> Vec<(KeyBinding, bool)> {
+    let mut bindings: Vec<(KeyBinding, bool)> = default_bindings()
+        .into_iter()
+        .filter(|d| !custom.iter().any(|c| same_key(c, d)))
+        .map(|d| (d, false))
+        .collect();
+    for binding in custom {
+        // 配置文件中同一按键写了多次时以最后一次为准
+        bindings.retain(|(b, _)| !same_key(b, binding));
+        bindings.push((binding.clone(), true));
+    }
+    bindings
+}
+
+fn same_key(a: &KeyBinding, b: &KeyBinding) -> bool {
+    a.editor == b.editor && a.modifiers == b.modifiers && a.code == b.code
+}
+
+/// 把生效的绑定加到 reedline 的键位表中 (单行和多行编辑器创建时都经过这里)
+pub fn apply(keybindings: &mut Keybindings, custom: &[KeyBinding], editor: KeyEditor) {
+    for (binding, _) in active_bindings(custom) {
+        if binding.editor == editor {
+            keybindings.add_binding(binding.modifiers, binding.code, binding.action.event());
+        }
+    }
+}
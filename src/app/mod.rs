@@ -1,2 +1,16 @@
 pub mod state;
-pub mod snippet_manager; 
\ No newline at end of file
+pub mod snippet_manager;
+pub mod settings;
+pub mod output;
+pub mod context;
+pub mod session;
+pub mod usage;
+pub mod config;
+pub mod editor;
+pub mod keys;
+pub mod report;
+pub mod watch;
+pub mod presets;
+pub mod prompt_templates;
+pub mod pager;
+pub mod style;
@@ -0,0 +1,557 @@
+use std::io::{self, IsTerminal, Write};
+use std::path::Path;
+use std::sync::atomic::{AtomicU8, AtomicUsize, Ordering};
+use std::time::{Duration, Instant};
+
+use tokio::sync::mpsc::UnboundedReceiver;
+
+use crate::app::{settings::BinaryTransition, state::AppState, style::{paint, Style}};
+use crate::core::{overlap::Overlap, progress::ProgressEvent};
+
+/// 命令输出的级别
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Level {
+    /// `(Note) ...` 一类的提示
+    Info,
+    /// `(Warning) ...`、用法提示和无效参数
+    Warn,
+    /// 命令失败
+    Error,
+    /// 命令本身的结果 (列表、统计、文件内容等)
+    Result,
+}
+
+/// executor 的文字输出: REPL 使用 TerminalOutput 打印到终端，测试用 CapturedOutput 收集后断言。
+/// 每次调用是完整的一行 (可以含换行符)，文本与直接打印时完全相同
+pub trait Output: Send {
+    fn emit(&mut self, level: Level, text: String);
+
+    /// 是否直接显示在交互终端上 (REPL)；只有这时长输出才分页 (app::pager)
+    fn is_interactive(&self) -> bool {
+        false
+    }
+
+    /// 向用户提一个是/否问题 (见 confirm_with)；默认从 stdin 读取回答，REPL 改为通过编辑器读取。
+    /// 使用固定回答或默认值时的提示经由本输出显示
+    fn confirm(&mut self, question: &str, default: bool) -> bool {
+        confirm_with(&mut StdinInput, question, default, &mut |note| self.info(note))
+    }
+
+    fn info(&mut self, text: String) {
+        self.emit(Level::Info, text);
+    }
+
+    fn warn(&mut self, text: String) {
+        self.emit(Level::Warn, text);
+    }
+
+    fn error(&mut self, text: String) {
+        self.emit(Level::Error, text);
+    }
+
+    fn result(&mut self, text: String) {
+        self.emit(Level::Result, text);
+    }
+
+    /// 按文本自带的前缀选择级别: `(Warning)` 为 Warn，`(Error)` 为 Error，其余为 Info
+    fn tagged(&mut self, text: String) {
+        let level = if text.starts_with("(Warning)") {
+            Level::Warn
+        } else if text.starts_with("(Error)") {
+            Level::Error
+        } else {
+            Level::Info
+        };
+        self.emit(level, text);
+    }
+}
+
+/// 打印到终端: Error 写到 stderr，其余写到 stdout。启用颜色时提示变暗，警告黄色，错误红色
+pub struct TerminalOutput;
+
+impl Output for TerminalOutput {
+    fn emit(&mut self, level: Level, text: String) {
+        match level {
+            Level::Info => println!("{}", paint(Style::Dim, &text)),
+            Level::Warn => println!("{}", paint(Style::Yellow, &text)),
+            Level::Error => eprintln!("{}", paint(Style::Red, &text)),
+            Level::Result => println!("{}", text),
+        }
+    }
+
+    fn is_interactive(&self) -> bool {
+        io::stdout().is_terminal()
+    }
+}
+
+/// 非交互模式 (命令行 `--add` / `--copy` 等) 的输出: 记录是否有命令报错，决定退出码。
+/// 警告和错误写到 stderr；to_stderr 时全部写到 stderr，stdout 只留给 `--stdout` 的 payload
+#[derive(Debug, Default)]
+pub struct ScriptOutput {
+    pub to_stderr: bool,
+    pub failed: bool,
+}
+
+impl Output for ScriptOutput {
+    fn emit(&mut self, level: Level, text: String) {
+        if level == Level::Error {
+            self.failed = true;
+        }
+        if self.to_stderr || matches!(level, Level::Warn | Level::Error) {
+            eprintln!("{}", text);
+        } else {
+            println!("{}", text);
+        }
+    }
+}
+
+/// 收集所有输出，供测试断言 (或 /dashboard 这类自己展示结果的界面丢弃)。
+/// 确认提示按 stdin 不是终端处理 (不提问，使用默认值或 `--yes` / `--no`)，因此不会等待输入
+#[derive(Debug, Default)]
+pub struct CapturedOutput {
+    pub lines: Vec<(Level, String)>,
+}
+
+impl CapturedOutput {
+    /// 全部输出，每次调用一行
+    pub fn text(&self) -> String {
+        self.lines.iter().map(|(_, text)| text.as_str()).collect::<Vec<_>>().join("\n")
+    }
+
+    /// 某个级别的输出
+    pub fn at(&self, level: Level) -> Vec<&str> {
+        self.lines.iter().filter(|(l, _)| *l == level).map(|(_, text)| text.as_str()).collect()
+    }
+}
+
+impl Output for CapturedOutput {
+    fn emit(&mut self, level: Level, text: String) {
+        self.lines.push((level, text));
+    }
+
+    fn confirm(&mut self, question: &str, default: bool) -> bool {
+        confirm_with(&mut PipeInput, question, default, &mut |note| self.info(note))
+    }
+}
+
+/// 命令行 `--yes` / `--no` 设置的全局回答: 0 = 未设置, 1 = yes, 2 = no
+static CONFIRM_OVERRIDE: AtomicU8 = AtomicU8::new(0);
+
+/// 设置 (或用 None 清除) 所有确认提示的固定回答
+pub fn set_confirm_override(answer: Option<bool>) {
+    let v = match answer {
+        None => 0,
+        Some(true) => 1,
+        Some(false) => 2,
+    };
+    CONFIRM_OVERRIDE.store(v, Ordering::Relaxed);
+}
+
+fn confirm_override() -> Option<bool> {
+    match CONFIRM_OVERRIDE.load(Ordering::Relaxed) {
+        1 => Some(true),
+        2 => Some(false),
+        _ => None,
+    }
+}
+
+/// 确认提示读取回答的来源；抽象出来以便替换 stdin (例如模拟终端或管道)
+pub trait ConfirmInput {
+    /// 是否连接到交互终端；否则不提问，直接使用默认值
+    fn is_terminal(&self) -> bool;
+    /// 显示 prompt 并读取一行回答，EOF、取消或出错时返回 None
+    fn read_answer(&mut self, prompt: &str) -> Option<String>;
+}
+
+/// 进程的标准输入
+struct StdinInput;
+
+impl ConfirmInput for StdinInput {
+    fn is_terminal(&self) -> bool {
+        io::stdin().is_terminal()
+    }
+
+    fn read_answer(&mut self, prompt: &str) -> Option<String> {
+        print!("{}", prompt);
+        let _ = io::stdout().flush();
+        let mut answer = String::new();
+        match io::stdin().read_line(&mut answer) {
+            Ok(0) | Err(_) => None,
+            Ok(_) => Some(answer),
+        }
+    }
+}
+
+/// 不是终端的输入 (相当于管道)，从不读取回答
+struct PipeInput;
+
+impl ConfirmInput for PipeInput {
+    fn is_terminal(&self) -> bool {
+        false
+    }
+
+    fn read_answer(&mut self, _prompt: &str) -> Option<String> {
+        None
+    }
+}
+
+/// 向用户提一个是/否问题，直接回车使用默认值，从 stdin 读取回答
+///
+/// 只应在 REPL 读取输入之外调用 (executor 执行命令期间)，此时终端处于普通模式，
+/// 可以安全地从 stdin 读取一行。executor 应调用 `Output::confirm`，REPL 中由编辑器读取回答
+pub fn confirm(question: &str, default: bool) -> bool {
+    confirm_with(&mut StdinInput, question, default, &mut |note| println!("{}", note))
+}
+
+/// 同 confirm，但从给定的来源读取回答。
+///  - 设置了 `--yes` / `--no` 时直接使用该回答
+///  - 输入不是终端 (管道、/dev/null) 时不提问，直接使用默认值，保证脚本不会卡在看不见的提示上
+///
+/// 这两种情况下不提问，而是把采用的回答作为一条 `(Note)` 交给 note
+pub fn confirm_with(
+    input: &mut dyn ConfirmInput,
+    question: &str,
+    default: bool,
+    note: &mut dyn FnMut(String),
+) -> bool {
+    let yes_no = |b: bool| if b { "yes" } else { "no" };
+    if let Some(answer) = confirm_override() {
+        note(format!("(Note) {} -> {} (--{})", question, yes_no(answer), yes_no(answer)));
+        return answer;
+    }
+    if !input.is_terminal() {
+        log::info!("stdin is not a terminal, answering {:?} with the default", question);
+        note(format!("(Note) {} -> {} (stdin is not a terminal)", question, yes_no(default)));
+        return default;
+    }
+
+    let hint = if default { "[Y/n]" } else { "[y/N]" };
+    match input.read_answer(&format!("{} {} ", question, hint)) {
+        None => default,
+        Some(answer) => match answer.trim().to_lowercase().as_str() {
+            "y" | "yes" => true,
+            "n" | "no" => false,
+            _ => default,
+        },
+    }
+}
+
+/// 把字节数格式化为易读的形式，例如 `12.3 MB`
+pub fn format_bytes(bytes: usize) -> String {
+    const UNITS: [&str; 4] = ["B", "KB", "MB", "GB"];
+    let mut value = bytes as f64;
+    let mut unit = 0;
+    while value >= 1024.0 && unit < UNITS.len() - 1 {
+        value /= 1024.0;
+        unit += 1;
+    }
+    if unit == 0 {
+        format!("{} {}", bytes, UNITS[0])
+    } else {
+        format!("{:.1} {}", value, UNITS[unit])
+    }
+}
+
+/// 把 token 数格式化为紧凑形式: 小于 1000 原样显示，否则如 `84.2k`
+pub fn format_token_count(tokens: usize) -> String {
+    if tokens < 1000 {
+        tokens.to_string()
+    } else {
+        format!("{:.1}k", tokens as f64 / 1000.0)
+    }
+}
+
+/// 缓存的终端宽度 (列数)；0 表示尚未获取
+static TERMINAL_WIDTH: AtomicUsize = AtomicUsize::new(0);
+
+/// 终端宽度取不到时 (例如输出被重定向) 使用的默认值
+const FALLBACK_WIDTH: usize = 80;
+
+/// 当前终端宽度。读取缓存值，只在首次调用或窗口大小变化后才查询终端
+pub fn terminal_width() -> usize {
+    match TERMINAL_WIDTH.load(Ordering::Relaxed) {
+        0 => refresh_terminal_width(),
+        w => w,
+    }
+}
+
+/// 重新查询终端宽度并更新缓存 (窗口大小变化时调用)
+pub fn refresh_terminal_width() -> usize {
+    let width = crossterm::terminal::size()
+        .map(|(cols, _)| cols as usize)
+        .ok()
+        .filter(|w| *w > 0)
+        .unwrap_or(FALLBACK_WIDTH);
+    TERMINAL_WIDTH.store(width, Ordering::Relaxed);
+    width
+}
+
+/// 监听窗口大小变化 (SIGWINCH)，在后台线程中刷新缓存的终端宽度。
+/// reedline 自己处理补全菜单和提示符的重绘；这里只保证我们自己的按宽度排版使用最新的值。
+#[cfg(unix)]
+pub fn watch_terminal_resize() {
+    use signal_hook::{consts::SIGWINCH, iterator::Signals};
+
+    match Signals::new([SIGWINCH]) {
+        Ok(mut signals) => {
+            std::thread::spawn(move || {
+                for _ in signals.forever() {
+                    refresh_terminal_width();
+                }
+            });
+        }
+        Err(e) => log::warn!("无法监听窗口大小变化: {:?}", e),
+    }
+}
+
+/// 非 Unix 平台没有 SIGWINCH，宽度在首次使用时获取
+#[cfg(not(unix))]
+pub fn watch_terminal_resize() {}
+
+/// 把单行文本截断到 width 列以内 (超出部分以 `…` 结尾)，按字符计数
+pub fn fit_to_width(text: &str, width: usize) -> String {
+    if text.chars().count() <= width {
+        return text.to_string();
+    }
+    let mut out: String = text.chars().take(width.saturating_sub(1)).collect();
+    out.push('…');
+    out
+}
+
+/// 命令结束后的一行状态栏，例如 `✔ 37 files · 84.2k tokens · prompt 412 · budget 66%`；
+/// 没有设置预算 (/budget off) 时不显示最后一段
+///
+/// 只读取 AppState 中已缓存的计数 (与提示符相同)，不做任何额外计算。
+/// 按当前终端宽度截断，避免在窄窗口中折行；需要截断时不加粗 token 数
+pub fn status_footer(st: &AppState) -> String {
+    let tokens = format_token_count(st.token_count);
+    let budget = match st.settings.token_budget {
+        0 => String::new(),
+        budget => format!(" · budget {}%", st.token_count * 100 / budget),
+    };
+    let footer = format!("✔ {} files · {} tokens · prompt {}{}", st.file_count, tokens, st.prompt_tokens, budget);
+    let fitted = fit_to_width(&footer, terminal_width());
+    if fitted != footer {
+        return fitted;
+    }
+    format!(
+        "✔ {} files · {} tokens · prompt {}{}",
+        st.file_count,
+        paint(Style::Bold, &tokens),
+        st.prompt_tokens,
+        budget
+    )
+}
+
+/// 带千位分隔符的整数，例如 `45,210`
+pub fn format_thousands(n: usize) -> String {
+    let digits = n.to_string();
+    let mut out = String::with_capacity(digits.len() + digits.len() / 3);
+    for (i, c) in digits.chars().enumerate() {
+        if i > 0 && (digits.len() - i).is_multiple_of(3) {
+            out.push(',');
+        }
+        out.push(c);
+    }
+    out
+}
+
+/// 带符号的变化量，例如 `+8,431`、`-120`、`+0`
+pub fn format_delta(before: usize, after: usize) -> String {
+    if after >= before {
+        format!("+{}", format_thousands(after - before))
+    } else {
+        format!("-{}", format_thousands(before - after))
+    }
+}
+
+/// /add、/remove 之后的一行变化摘要，例如 `(Note) +12 files, +8,431 tokens (total 45,210)`
+pub fn delta_summary(files_before: usize, files_after: usize, tokens_before: usize, tokens_after: usize) -> String {
+    format!(
+        "(Note) {} files, {} tokens (total {})",
+        format_delta(files_before, files_after),
+        format_delta(tokens_before, tokens_after),
+        paint(Style::Bold, &format_thousands(tokens_after))
+    )
+}
+
+/// 预算的紧凑形式: 与 format_token_count 相同，但整千不带小数，例如 `128k`
+pub fn format_budget(budget: usize) -> String {
+    if budget >= 1000 && budget.is_multiple_of(1000) {
+        format!("{}k", budget / 1000)
+    } else {
+        format_token_count(budget)
+    }
+}
+
+/// 超过预算 BUDGET_WARN_PERCENT% 时发出警告
+pub const BUDGET_WARN_PERCENT: usize = 80;
+
+/// token 数接近或超出预算时的提示 (黄色警告 / 红色错误)；未设预算或用量正常时返回 None
+pub fn budget_warning(tokens: usize, budget: usize) -> Option<String> {
+    if budget == 0 || tokens * 100 <= budget * BUDGET_WARN_PERCENT {
+        return None;
+    }
+    let percent = tokens * 100 / budget;
+    let usage = format!("{} / {} tokens ({}%)", format_token_count(tokens), format_budget(budget), percent);
+    if tokens > budget {
+        Some(paint(Style::Red, &format!("(Error) Over the token budget: {}. Remove or /mute files, or raise it with /budget <n>", usage)))
+    } else {
+        Some(paint(Style::Yellow, &format!("(Warning) Close to the token budget: {}", usage)))
+    }
+}
+
+/// 命令失败时的状态栏: 红色 `✘` 加错误摘要
+pub fn failure_footer(error: &str) -> String {
+    paint(Style::Red, &format!("✘ {}", error))
+}
+
+/// 重复内容提示，例如 `~3.1k tokens of cargo test duplicate selected file content (src/a.rs)`
+pub fn overlap_summary(overlap: &Overlap) -> String {
+    let files: Vec<String> = overlap.files.iter().map(|p| p.display().to_string()).collect();
+    format!(
+        "~{} tokens of {} duplicate selected file content ({}); consider /mute on those files or trimming the capture",
+        format_token_count(overlap.approx_tokens()),
+        overlap.document.display(),
+        files.join(", ")
+    )
+}
+
+/// 重新读取时发现已被删除、从上下文中移除的文件
+pub fn pruned_summary(paths: &[std::path::PathBuf]) -> String {
+    let list: Vec<String> = paths.iter().map(|p| p.display().to_string()).collect();
+    format!("(Note) Removed {} deleted files from the context: {}", paths.len(), list.join(", "))
+}
+
+/// 已选文件变成二进制时的警告，例如 `(Warning) build/out.txt now looks binary; ...`
+pub fn binary_warning(path: &Path, kind: BinaryTransition) -> String {
+    let action = match kind {
+        BinaryTransition::KeepLast => "keeping its last text version",
+        BinaryTransition::Placeholder => "its content is replaced by a placeholder",
+        BinaryTransition::Drop => "removed it from the context",
+    };
+    format!(
+        "(Warning) {} now looks binary; {} (see /set binary_transition)",
+        path.display(),
+        action
+    )
+}
+
+/// 少于这么多个文件时不显示进度，避免小的 /add 刷屏
+const PROGRESS_QUIET_BELOW: usize = 50;
+
+/// 进度行最多每隔这么久重绘一次
+const PROGRESS_REDRAW: Duration = Duration::from_millis(100);
+
+/// 进度事件对应的 (文本, 是否值得显示, 是否跳过节流)；shown 为之前是否已经显示过进度行
+fn progress_line(event: ProgressEvent, shown: bool) -> (String, bool, bool) {
+    match event {
+        ProgressEvent::Scanned(n) => (format!("scanned {} files", n), n >= PROGRESS_QUIET_BELOW, false),
+        ProgressEvent::Read { done, total } => {
+            (format!("read {}/{} files", done, total), total >= PROGRESS_QUIET_BELOW, done == total)
+        }
+        ProgressEvent::Tokenizing => ("tokenizing…".to_string(), shown, true),
+    }
+}
+
+/// 把进度事件画在终端的同一行上 (`\r` 覆盖)，所有发送端 drop 后清除该行。
+///  - 文件数少于 PROGRESS_QUIET_BELOW 时保持安静；stdout 不是终端时什么都不画
+pub async fn render_progress(mut events: UnboundedReceiver<ProgressEvent>) {
+    let interactive = io::stdout().is_terminal();
+    let mut shown = false;
+    let mut last_draw: Option<Instant> = None;
+    while let Some(event) = events.recv().await {
+        let (line, visible, force) = progress_line(event, shown);
+        if !interactive || !visible {
+            continue;
+        }
+        if !force && last_draw.is_some_and(|t| t.elapsed() < PROGRESS_REDRAW) {
+            continue;
+        }
+        print!("\r\x1b[2K({})", line);
+        let _ = io::stdout().flush();
+        shown = true;
+        last_draw = Some(Instant::now());
+    }
+    if shown {
+        print!("\r\x1b[2K");
+        let _ = io::stdout().flush();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// 依次处理事件，返回会显示的进度行 (不考虑终端和节流)
+    fn visible_lines(events: &[ProgressEvent]) -> Vec<String> {
+        let mut shown = false;
+        let mut lines = Vec::new();
+        for event in events {
+            let (line, visible, _) = progress_line(*event, shown);
+            if visible {
+                shown = true;
+                lines.push(line);
+            }
+        }
+        lines
+    }
+
+    #[test]
+    fn small_adds_stay_silent() {
+        let total = PROGRESS_QUIET_BELOW - 1;
+        let mut events = vec![ProgressEvent::Scanned(total)];
+        events.extend((1..=total).map(|done| ProgressEvent::Read { done, total }));
+        events.push(ProgressEvent::Tokenizing);
+        assert!(visible_lines(&events).is_empty());
+    }
+
+    #[test]
+    fn large_adds_show_every_stage() {
+        let total = PROGRESS_QUIET_BELOW;
+        let events = [
+            ProgressEvent::Scanned(total),
+            ProgressEvent::Read { done: 1, total },
+            ProgressEvent::Read { done: total, total },
+            ProgressEvent::Tokenizing,
+        ];
+        assert_eq!(visible_lines(&events), ["scanned 50 files", "read 1/50 files", "read 50/50 files", "tokenizing…"]);
+        // 最后一个文件读完时总是重绘，不受节流影响
+        assert!(progress_line(ProgressEvent::Read { done: total, total }, true).2);
+        assert!(!progress_line(ProgressEvent::Read { done: 1, total }, true).2);
+    }
+
+    /// 固定回答的输入，记录显示过的提示
+    struct Scripted {
+        answers: Vec<Option<&'static str>>,
+        prompts: Vec<String>,
+    }
+
+    impl ConfirmInput for Scripted {
+        fn is_terminal(&self) -> bool {
+            true
+        }
+
+        fn read_answer(&mut self, prompt: &str) -> Option<String> {
+            self.prompts.push(prompt.to_string());
+            self.answers.remove(0).map(str::to_string)
+        }
+    }
+
+    #[test]
+    fn confirm_reads_the_answer_after_the_hint() {
+        let mut input = Scripted {
+            answers: vec![Some("y"), Some(" YES \n"), Some("n"), Some(""), None, Some("maybe")],
+            prompts: Vec::new(),
+        };
+        assert!(confirm_with(&mut input, "Quit anyway?", false, &mut |_| {}));
+        assert!(confirm_with(&mut input, "Quit anyway?", false, &mut |_| {}));
+        assert!(!confirm_with(&mut input, "Follow?", true, &mut |_| {}));
+        // 直接回车、取消 (Ctrl+C / EOF) 和无法识别的回答都使用默认值
+        assert!(confirm_with(&mut input, "Follow?", true, &mut |_| {}));
+        assert!(!confirm_with(&mut input, "Quit anyway?", false, &mut |_| {}));
+        assert!(!confirm_with(&mut input, "Quit anyway?", false, &mut |_| {}));
+        assert_eq!(input.prompts[0], "Quit anyway? [y/N] ");
+        assert_eq!(input.prompts[2], "Follow? [Y/n] ");
+    }
+}
@@ -0,0 +1,136 @@
+// src/app/pager.rs
+
+use std::io::{self, Write};
+
+use ratatui::crossterm::{
+    event::{self, Event, KeyCode, KeyEventKind, KeyModifiers},
+    terminal::{self, disable_raw_mode, enable_raw_mode},
+};
+
+use crate::app::{output::{Level, Output}, style::{paint, Style}};
+
+/// 分页输出 (/context、/list、/show、/last --show): 包装另一个 Output，交互终端中每打印满一屏暂停，
+/// Enter / 空格继续，q 放弃剩余的输出。非交互输出 (命令行模式、管道、测试) 原样转发
+pub struct Pager<'a> {
+    inner: &'a mut dyn Output,
+    /// 每屏的行数，0 表示不分页
+    page: usize,
+    /// 当前这一屏已经打印的行数
+    shown: usize,
+    /// 已按 q，之后的输出全部丢弃
+    quit: bool,
+    /// 一屏打印满后等待用户，返回 false 表示停止
+    wait: Box<dyn FnMut() -> bool + Send + 'a>,
+}
+
+impl<'a> Pager<'a> {
+    /// 按终端高度分页 (留一行给「更多」提示)，inner 不是交互终端时不分页
+    pub fn new(inner: &'a mut dyn Output) -> Self {
+        let page = if inner.is_interactive() {
+            terminal::size().map(|(_, rows)| rows as usize).unwrap_or(0).saturating_sub(1)
+        } else {
+            0
+        };
+        Self::with_wait(inner, page, wait_for_key)
+    }
+
+    /// 指定每屏行数和等待方式
+    ///
+    /// ```
+    /// use rustprompt::app::{output::{CapturedOutput, Output}, pager::Pager};
+    ///
+    /// let mut out = CapturedOutput::default();
+    /// let mut answers = vec![false, true]; // 第一屏之后继续，第二屏之后按 q
+    /// let mut pager = Pager::with_wait(&mut out, 4, move || answers.pop().unwrap_or(false));
+    /// pager.result((1..=10).map(|i| i.to_string()).collect::<Vec<_>>().join("\n"));
+    /// pager.result("after".to_string());
+    /// drop(pager);
+    /// assert_eq!(out.lines.len(), 3);
+    /// assert!(out.text().starts_with("1\n2\n3\n4\n5\n6\n7\n8\n(Note)"));
+    /// assert!(!out.text().contains("after"));
+    /// ```
+    pub fn with_wait<F>(inner: &'a mut dyn Output, page: usize, wait: F) -> Self
+    where
+        F: FnMut() -> bool + Send + 'a,
+    {
+        Self { inner, page, shown: 0, quit: false, wait: Box::new(wait) }
+    }
+}
+
+impl Output for Pager<'_> {
+    fn emit(&mut self, level: Level, text: String) {
+        // 错误总是显示，即使已经停止
+        if self.page == 0 || level == Level::Error {
+            self.inner.emit(level, text);
+            return;
+        }
+        if self.quit {
+            return;
+        }
+        let lines: Vec<&str> = text.split('\n').collect();
+        let mut start = 0;
+        while start < lines.len() {
+            if self.shown == self.page {
+                if !(self.wait)() {
+                    self.quit = true;
+                    self.inner.info("(Note) Rest of the output skipped".to_string());
+                    return;
+                }
+                self.shown = 0;
+            }
+            let take = (self.page - self.shown).min(lines.len() - start);
+            self.inner.emit(level, lines[start..start + take].join("\n"));
+            self.shown += take;
+            start += take;
+        }
+    }
+
+    fn is_interactive(&self) -> bool {
+        self.inner.is_interactive()
+    }
+}
+
+/// 分页打印一段长文本 (例如文档内容)
+pub fn print_paged(text: &str, out: &mut dyn Output) {
+    Pager::new(out).result(text.to_string());
+}
+
+/// 离开等待时 (包括出错返回) 恢复终端，reedline 下一次读取输入时再自己进入 raw mode
+struct RawModeGuard;
+
+impl Drop for RawModeGuard {
+    fn drop(&mut self) {
+        let _ = disable_raw_mode();
+    }
+}
+
+/// 打印「更多」提示并在 raw mode 下读取一个按键: Enter / 空格继续，q / Esc / Ctrl+C 停止；
+/// 无法读取终端时停止
+fn wait_for_key() -> bool {
+    print!("{}", paint(Style::Dim, "-- more -- (Enter/space: next page, q: stop)"));
+    let _ = io::stdout().flush();
+    let proceed = read_key();
+    // 擦掉提示行，后面的输出从行首开始
+    print!("\r\x1b[2K");
+    let _ = io::stdout().flush();
+    proceed
+}
+
+fn read_key() -> bool {
+    if enable_raw_mode().is_err() {
+        return false;
+    }
+    let _guard = RawModeGuard;
+    loop {
+        match event::read() {
+            Ok(Event::Key(key)) if key.kind == KeyEventKind::Press => match key.code {
+                KeyCode::Enter | KeyCode::Char(' ') => return true,
+                KeyCode::Char('c') if key.modifiers.contains(KeyModifiers::CONTROL) => return false,
+                KeyCode::Char('q') | KeyCode::Esc => return false,
+                _ => {}
+            },
+            Ok(_) => {}
+            Err(_) => return false,
+        }
+    }
+}
@@ -0,0 +1,77 @@
+// src/app/presets.rs
+
+use serde::Deserialize;
+
+use crate::core::ignore_rules::IgnoreConfig;
+
+/// /preset 的一个预设: 一次设置扩展名过滤 (/only) 并追加忽略模式 (/ignore add)。
+/// 字段都是普通数据 (可反序列化)，之后可以从配置文件读取自定义预设
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+pub struct Preset {
+    pub name: String,
+    /// 扩展名过滤 (小写，不带 `.`)
+    pub extensions: Vec<String>,
+    /// 追加的忽略模式 (gitignore 语法)
+    #[serde(default)]
+    pub ignore: Vec<String>,
+}
+
+impl Preset {
+    fn new(name: &str, extensions: &[&str], ignore: &[&str]) -> Self {
+        Self {
+            name: name.to_string(),
+            extensions: extensions.iter().map(|s| s.to_string()).collect(),
+            ignore: ignore.iter().map(|s| s.to_string()).collect(),
+        }
+    }
+
+    /// 应用到忽略规则: 扩展名过滤整体替换，忽略模式只追加还没有的；返回新追加的模式
+    pub fn apply(&self, ignore: &mut IgnoreConfig) -> Vec<String> {
+        ignore.extensions = self.extensions.clone();
+        let mut added = Vec::new();
+        for pattern in &self.ignore {
+            if !ignore.patterns.contains(pattern) {
+                ignore.patterns.push(pattern.clone());
+                added.push(pattern.clone());
+            }
+        }
+        added
+    }
+
+    /// 当前规则是否就是这个预设设置的: 扩展名过滤相同 (不计顺序)，忽略模式都还在
+    pub fn is_active(&self, ignore: &IgnoreConfig) -> bool {
+        let mut mine = self.extensions.clone();
+        let mut current = ignore.extensions.clone();
+        mine.sort();
+        current.sort();
+        mine == current && self.ignore.iter().all(|p| ignore.patterns.contains(p))
+    }
+}
+
+/// 内置预设
+pub fn builtin() -> Vec<Preset> {
+    vec![
+        Preset::new("rust", &["rs", "toml"], &["target"]),
+        Preset::new("web", &["js", "jsx", "ts", "tsx", "css", "html"], &["node_modules", "dist"]),
+        Preset::new("python", &["py", "pyi", "toml"], &["venv", ".venv", "__pycache__"]),
+    ]
+}
+
+/// 按名称查找预设 (不区分大小写)
+///
+/// ```
+/// use rustprompt::app::presets;
+/// use rustprompt::core::ignore_rules::IgnoreConfig;
+///
+/// let rust = presets::find("Rust").unwrap();
+/// let mut ignore = IgnoreConfig::default();
+/// assert_eq!(rust.apply(&mut ignore), ["target"]);
+/// assert_eq!(ignore.extensions, ["rs", "toml"]);
+/// assert!(rust.is_active(&ignore));
+/// // 再次应用不会重复追加模式
+/// assert!(rust.apply(&mut ignore).is_empty());
+/// assert!(presets::find("cobol").is_none());
+/// ```
+pub fn find(name: &str) -> Option<Preset> {
+    builtin().into_iter().find(|p| p.name.eq_ignore_ascii_case(name))
+}
@@ -0,0 +1,111 @@
+// src/app/prompt_templates.rs
+
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+
+use crate::core::dirs;
+
+/// 保存 prompt 模板的文件 (用户配置目录下，所有项目共用)
+const TEMPLATES_FILE: &str = "prompt_templates.json";
+
+/// /template 保存的命名 prompt 文本: 名字 -> prompt_text。
+/// 每次操作都重新读取文件，同时运行的多个 REPL 看到的是同一份模板
+#[derive(Debug, Clone, Default)]
+pub struct PromptTemplates {
+    path: Option<PathBuf>,
+    templates: BTreeMap<String, String>,
+}
+
+impl PromptTemplates {
+    /// 读取用户配置目录下的模板文件；不存在或无法解析时为空
+    pub fn load() -> Self {
+        Self::load_from(dirs::config_dir().map(|dir| dir.join(TEMPLATES_FILE)))
+    }
+
+    /// 读取指定的模板文件 (None 表示没有可用的配置目录，模板只能读不能写)
+    ///
+    /// ```
+    /// use rustprompt::app::prompt_templates::PromptTemplates;
+    ///
+    /// let path = std::env::temp_dir().join(format!("rustprompt-templates-{}.json", std::process::id()));
+    /// let mut templates = PromptTemplates::load_from(Some(path.clone()));
+    /// templates.save("review", "You are reviewing a Rust crate.").unwrap();
+    ///
+    /// // 重新读取: 模板已经写入文件
+    /// let mut reloaded = PromptTemplates::load_from(Some(path.clone()));
+    /// assert_eq!(reloaded.names(), ["review"]);
+    /// assert_eq!(reloaded.get("review"), Some("You are reviewing a Rust crate."));
+    /// assert!(reloaded.delete("review").unwrap());
+    /// assert!(!reloaded.delete("review").unwrap());
+    /// std::fs::remove_file(&path).unwrap();
+    /// ```
+    pub fn load_from(path: Option<PathBuf>) -> Self {
+        let templates = path.as_deref().map(read_templates).unwrap_or_default();
+        Self { path, templates }
+    }
+
+    /// 模板名，按字母顺序
+    pub fn names(&self) -> Vec<String> {
+        self.templates.keys().cloned().collect()
+    }
+
+    pub fn get(&self, name: &str) -> Option<&str> {
+        self.templates.get(name).map(String::as_str)
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = (&String, &String)> {
+        self.templates.iter()
+    }
+
+    /// /template list 中显示的预览: 第一行的前 max_chars 个字符 (按字符计，不会截断多字节字符)，
+    /// 以及是否省略了内容 (第一行更长，或者还有其它行)
+    ///
+    /// ```
+    /// use rustprompt::app::prompt_templates::PromptTemplates;
+    ///
+    /// assert_eq!(PromptTemplates::preview("审查这段代码", 6), ("审查这段代码".to_string(), false));
+    /// assert_eq!(PromptTemplates::preview("审查这段代码。", 6), ("审查这段代码".to_string(), true));
+    /// assert_eq!(PromptTemplates::preview("Review\nthis", 60), ("Review".to_string(), true));
+    /// ```
+    pub fn preview(text: &str, max_chars: usize) -> (String, bool) {
+        let first = text.lines().next().unwrap_or("");
+        let preview: String = first.chars().take(max_chars).collect();
+        let truncated = first.chars().count() > max_chars || !text[first.len()..].trim().is_empty();
+        (preview, truncated)
+    }
+
+    /// 保存 (同名时覆盖) 并写回文件
+    pub fn save(&mut self, name: &str, text: &str) -> std::io::Result<()> {
+        self.templates.insert(name.to_string(), text.to_string());
+        self.write()
+    }
+
+    /// 删除并写回文件；返回模板是否存在
+    pub fn delete(&mut self, name: &str) -> std::io::Result<bool> {
+        if self.templates.remove(name).is_none() {
+            return Ok(false);
+        }
+        self.write().map(|()| true)
+    }
+
+    /// 先写临时文件再改名，写到一半退出也不会留下损坏的模板文件
+    fn write(&self) -> std::io::Result<()> {
+        let Some(path) = &self.path else {
+            return Err(std::io::Error::other("no user config directory ($HOME is not set)"));
+        };
+        if let Some(dir) = path.parent() {
+            std::fs::create_dir_all(dir)?;
+        }
+        let json = serde_json::to_string_pretty(&self.templates).map_err(std::io::Error::other)?;
+        let tmp = path.with_extension("json.tmp");
+        std::fs::write(&tmp, json + "\n")?;
+        std::fs::rename(&tmp, path)
+    }
+}
+
+fn read_templates(path: &Path) -> BTreeMap<String, String> {
+    std::fs::read_to_string(path)
+        .ok()
+        .and_then(|text| serde_json::from_str(&text).ok())
+        .unwrap_or_default()
+}
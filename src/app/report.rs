@@ -0,0 +1,195 @@
+// src/app/report.rs
+
+use std::time::UNIX_EPOCH;
+
+use serde::Serialize;
+
+use crate::app::snippet_manager::{InstructionPlacement, ManifestDocument, PayloadManifest, SnippetManager};
+use crate::app::state::{AppState, ReplMode, PROJECT_TREE_VIRTUAL_PATH};
+use crate::core::file_stamp::FileStamp;
+
+/// 当前 JSON 格式的版本号；只增加字段时不变，删除或改变字段含义时递增
+pub const REPORT_VERSION: u32 = 1;
+
+/// `/context --json` 和命令行 `rustprompt context --json` 输出的上下文状态，供编辑器插件和脚本读取。
+///
+/// 格式 (版本 1):
+///
+/// ```json
+/// {
+///   "version": 1,
+///   "mode": "manual",
+///   "files": [
+///     { "path": "src/main.rs", "tokens": 412, "bytes": 1830, "mtime": 1760000000, "muted": false, "truncated": false }
+///   ],
+///   "file_count": 1,
+///   "total_tokens": 498,
+///   "prompt_chars": 0,
+///   "prompt_tokens": 0,
+///   "token_budget": 128000,
+///   "manifest": {
+///     "format": "xml",
+///     "documents": [
+///       { "id": "D1", "source": "(project tree)", "tokens": 86, "truncated": false },
+///       { "id": "D2", "source": "src/main.rs", "tokens": 412, "truncated": false }
+///     ],
+///     "muted": [],
+///     "grouped_by_language": false,
+///     "instruction": "none",
+///     "instruction_after": null,
+///     "prompt_tokens": 0,
+///     "total_tokens": 498,
+///     "approx_bytes": 2104
+///   }
+/// }
+/// ```
+///
+/// - `mode`: `"manual"` 或 `"prompt"`
+/// - `files`: 已选文件，按 path 排序；path 与 `<source>` 相同 (相对于项目根目录)
+/// - `tokens`: 该文件 snippet 的 token 数，`bytes` / `mtime` (Unix 秒) 取自最近一次读取时的记录，未知时为 null
+/// - `total_tokens`: 整个 payload (包括项目树和 prompt) 的 token 数
+/// - `prompt_chars`: prompt 的字符数
+/// - `token_budget`: `/budget` 设置的上限，未设置时为 null
+/// - `truncated`: 超过 max_file_bytes、只读取了开头部分
+/// - `manifest`: 与 `/preview` 相同的 payload 结构，见 ManifestReport
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct ContextReport {
+    pub version: u32,
+    pub mode: &'static str,
+    pub files: Vec<FileReport>,
+    pub file_count: usize,
+    pub total_tokens: usize,
+    pub prompt_chars: usize,
+    pub prompt_tokens: usize,
+    pub token_budget: Option<usize>,
+    pub manifest: ManifestReport,
+}
+
+/// ContextReport 中的一个已选文件
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct FileReport {
+    pub path: String,
+    pub tokens: Option<usize>,
+    pub bytes: Option<u64>,
+    pub mtime: Option<u64>,
+    pub muted: bool,
+    pub truncated: bool,
+}
+
+/// payload 的清单 (SnippetManager::manifest)，按 payload 中的顺序列出文档
+///
+/// - `source`: 与 `<source>` 相同，项目树为 `"(project tree)"`
+/// - `muted`: 仍被选中但不进入 payload 的文件，没有 id
+/// - `instruction`: `"none"` (prompt 为空)、`"after_document"` 或 `"template"` (由自定义模板决定)；
+///   `instruction_after` 是 instruction 前面那个文档的 id
+/// - `total_tokens`: 主体 + prompt；`approx_bytes` 不含合并时的标记
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct ManifestReport {
+    pub format: String,
+    pub documents: Vec<ManifestEntry>,
+    pub muted: Vec<ManifestEntry>,
+    pub grouped_by_language: bool,
+    pub instruction: &'static str,
+    pub instruction_after: Option<String>,
+    pub prompt_tokens: usize,
+    pub total_tokens: usize,
+    pub approx_bytes: usize,
+}
+
+/// ManifestReport 中的一个文档
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct ManifestEntry {
+    #[serde(skip_serializing_if = "String::is_empty")]
+    pub id: String,
+    pub source: String,
+    pub tokens: usize,
+    pub truncated: bool,
+}
+
+impl ManifestReport {
+    pub fn from_manifest(manifest: &PayloadManifest, st: &AppState) -> Self {
+        let entry = |doc: &ManifestDocument| ManifestEntry {
+            id: doc.id.clone(),
+            source: if doc.source.as_os_str() == PROJECT_TREE_VIRTUAL_PATH {
+                "(project tree)".to_string()
+            } else {
+                st.display_path(&doc.source).to_string_lossy().into_owned()
+            },
+            tokens: doc.tokens,
+            truncated: doc.truncated.is_some(),
+        };
+        let (instruction, instruction_after) = match &manifest.instruction {
+            InstructionPlacement::None => ("none", None),
+            InstructionPlacement::AfterDocument(id) => ("after_document", id.clone()),
+            InstructionPlacement::Template => ("template", None),
+        };
+        Self {
+            format: manifest.format.clone(),
+            documents: manifest.documents.iter().map(entry).collect(),
+            muted: manifest.muted.iter().map(entry).collect(),
+            grouped_by_language: manifest.grouped_by_language,
+            instruction,
+            instruction_after,
+            prompt_tokens: manifest.prompt_tokens,
+            total_tokens: manifest.total_tokens,
+            approx_bytes: manifest.approx_bytes,
+        }
+    }
+}
+
+impl ContextReport {
+    pub fn from_state(st: &AppState) -> Self {
+        let mut files: Vec<FileReport> = st
+            .selected_paths
+            .iter()
+            .map(|p| {
+                let stamp = st.file_stamps.get(p).copied().or_else(|| FileStamp::of(p));
+                FileReport {
+                    path: st.display_path(p).to_string_lossy().into_owned(),
+                    tokens: st.doc_tokens.get(p).copied(),
+                    bytes: stamp.map(|s| s.len),
+                    mtime: stamp
+                        .and_then(|s| s.modified)
+                        .and_then(|t| t.duration_since(UNIX_EPOCH).ok())
+                        .map(|d| d.as_secs()),
+                    muted: st.muted.contains(p),
+                    truncated: st.truncated.contains_key(p),
+                }
+            })
+            .collect();
+        files.sort_by(|a, b| a.path.cmp(&b.path));
+
+        Self {
+            version: REPORT_VERSION,
+            mode: match st.mode {
+                ReplMode::Manual => "manual",
+                ReplMode::Prompt => "prompt",
+            },
+            file_count: files.len(),
+            files,
+            total_tokens: st.token_count,
+            prompt_chars: st.prompt_text.chars().count(),
+            prompt_tokens: st.prompt_tokens,
+            token_budget: Some(st.settings.token_budget).filter(|&b| b > 0),
+            manifest: ManifestReport::from_manifest(&SnippetManager::manifest(st), st),
+        }
+    }
+
+    /// 序列化为 JSON: 默认缩进排版，compact 时输出单行
+    ///
+    /// ```
+    /// use rustprompt::app::{report::ContextReport, state::AppState};
+    ///
+    /// let json = ContextReport::from_state(&AppState::new()).to_json(true);
+    /// assert!(json.starts_with(r#"{"version":1,"mode":"manual","files":[],"file_count":0,"#));
+    /// ```
+    pub fn to_json(&self, compact: bool) -> String {
+        let json = if compact {
+            serde_json::to_string(self)
+        } else {
+            serde_json::to_string_pretty(self)
+        };
+        // 字段都是字符串和数字，不会序列化失败
+        json.unwrap_or_default()
+    }
+}
@@ -0,0 +1,144 @@
+// src/app/session.rs
+
+use std::path::{Path, PathBuf};
+
+use anyhow::anyhow;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    app::state::{AppState, ReplMode},
+    core::{ignore_rules::IgnoreConfig, template::OutputFormat},
+    error::AppError,
+};
+
+/// 会话文件格式的版本，格式不兼容地变化时递增
+const SESSION_VERSION: u32 = 1;
+
+/// 会话文件所在目录 (项目根目录下)
+const SESSION_DIR: &str = ".rustprompt";
+
+/// 一次 /add 的参数及其展开结果
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SavedRoot {
+    pub path: PathBuf,
+    pub files: Vec<PathBuf>,
+}
+
+/// /save 写入、/load 读取的内容: 重建上下文所需的 AppState 子集 (不含 snippet，加载时重新生成)
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Session {
+    pub version: u32,
+    pub selected_paths: Vec<PathBuf>,
+    #[serde(default)]
+    pub add_roots: Vec<SavedRoot>,
+    #[serde(default)]
+    pub muted: Vec<PathBuf>,
+    #[serde(default)]
+    pub pinned: Vec<PathBuf>,
+    #[serde(default)]
+    pub prompt_text: String,
+    /// "manual" 或 "prompt"
+    pub mode: String,
+    /// /sort 策略名
+    #[serde(default)]
+    pub sort_strategy: String,
+    /// 自定义模板名；为空表示 XML
+    #[serde(default)]
+    pub template: String,
+    /// token 编码名
+    #[serde(default)]
+    pub token_encoding: String,
+    /// /model 选择的模型名 (决定 /copy 的上下文窗口上限)
+    #[serde(default)]
+    pub model: Option<String>,
+    /// 扫描目录时使用的忽略规则
+    pub ignore: IgnoreConfig,
+}
+
+impl Session {
+    /// 从当前状态生成会话 (路径排序，保证文件内容稳定，便于提交或比较)
+    pub fn capture(st: &AppState) -> Self {
+        let mut selected_paths: Vec<PathBuf> = st.selected_paths.iter().cloned().collect();
+        selected_paths.sort();
+        let mut muted: Vec<PathBuf> = st.muted.iter().cloned().collect();
+        muted.sort();
+        let mut pinned: Vec<PathBuf> = st.pinned.iter().cloned().collect();
+        pinned.sort();
+        let add_roots = st
+            .add_roots
+            .iter()
+            .map(|r| SavedRoot {
+                path: r.path.clone(),
+                files: r.files.iter().cloned().collect(),
+            })
+            .collect();
+        let template = match &st.output_format {
+            OutputFormat::Xml => String::new(),
+            OutputFormat::Custom { name, .. } => name.clone(),
+        };
+        Self {
+            version: SESSION_VERSION,
+            selected_paths,
+            add_roots,
+            muted,
+            pinned,
+            prompt_text: st.prompt_text.clone(),
+            mode: match st.mode {
+                ReplMode::Manual => "manual",
+                ReplMode::Prompt => "prompt",
+            }
+            .to_string(),
+            sort_strategy: st.sort_strategy.as_str().to_string(),
+            template,
+            token_encoding: st.token_encoding.name().to_string(),
+            model: st.model.clone(),
+            ignore: st.ignore.clone(),
+        }
+    }
+
+    pub fn mode(&self) -> ReplMode {
+        match self.mode.as_str() {
+            "prompt" => ReplMode::Prompt,
+            _ => ReplMode::Manual,
+        }
+    }
+}
+
+/// 会话文件的位置: 不带名字时为 `.rustprompt/session.json`，否则为 `.rustprompt/sessions/<name>.json`
+pub fn session_path(name: Option<&str>) -> PathBuf {
+    match name {
+        None => Path::new(SESSION_DIR).join("session.json"),
+        Some(name) => Path::new(SESSION_DIR).join("sessions").join(format!("{}.json", name)),
+    }
+}
+
+/// 写入会话文件 (必要时创建目录)
+pub async fn save(path: &Path, session: &Session) -> Result<(), AppError> {
+    let json = serde_json::to_string_pretty(session)
+        .map_err(|e| AppError::General(anyhow!("Failed to serialize session: {}", e)))?;
+    if let Some(dir) = path.parent() {
+        tokio::fs::create_dir_all(dir).await.map_err(|e| AppError::io(dir, e))?;
+    }
+    tokio::fs::write(path, json + "\n").await.map_err(|e| AppError::io(path, e))
+}
+
+/// 读取会话文件
+pub async fn load(path: &Path) -> Result<Session, AppError> {
+    let text = match tokio::fs::read_to_string(path).await {
+        Ok(text) => text,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => {
+            return Err(AppError::General(anyhow!("No saved session at {}", path.display())));
+        }
+        Err(e) => return Err(AppError::io(path, e)),
+    };
+    let session: Session = serde_json::from_str(&text)
+        .map_err(|e| AppError::General(anyhow!("Invalid session file {}: {}", path.display(), e)))?;
+    if session.version > SESSION_VERSION {
+        return Err(AppError::General(anyhow!(
+            "{} was saved by a newer version (session format {})",
+            path.display(),
+            session.version
+        )));
+    }
+    Ok(session)
+}
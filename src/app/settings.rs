@@ -0,0 +1,530 @@
+use crate::core::clipboard::ClipboardBackend;
+use crate::core::xml::MergeOptions;
+
+/// /refresh 发现疑似改名的文件时的处理策略
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub enum FollowRenames {
+    /// 直接跟随改名
+    Auto,
+    /// 逐个询问
+    #[default]
+    Ask,
+    /// 不做改名检测
+    Off,
+}
+
+/// /copy 之前 (或定期) 自动刷新已修改文件的策略
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub enum AutoRefresh {
+    /// /copy 前检查指纹，只重新读取发生变化的文件
+    #[default]
+    BeforeCopy,
+    /// 后台每隔 N 秒检查一次
+    Interval(u64),
+    /// 不自动刷新
+    Off,
+}
+
+/// 已添加的文本文件在重新读取时变成二进制 (例如被构建产物覆盖) 的处理方式
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub enum BinaryTransition {
+    /// 保留最后一次作为文本读取到的 snippet
+    KeepLast,
+    /// 用一段说明替换其内容
+    #[default]
+    Placeholder,
+    /// 从上下文中移除
+    Drop,
+}
+
+/// 新加入的文件超过 max_file_bytes 时的处理方式
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub enum Oversize {
+    /// 不加入，/add 列出被跳过的文件
+    #[default]
+    Skip,
+    /// 只读取开头 max_file_bytes 字节，并在内容末尾注明原始大小
+    Truncate,
+}
+
+/// 上下键、提示和 Ctrl+R 搜索使用哪些项目的历史
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub enum HistoryScope {
+    /// 全部历史，但来自其它项目且引用了此处不存在路径的条目排在后面
+    #[default]
+    Prefer,
+    /// 隐藏来自其它项目且引用了此处不存在路径的条目
+    Project,
+    /// 不区分项目
+    All,
+}
+
+/// REPL 的键位: emacs (默认) 或 vi (Esc 进入 normal 模式，提示符显示当前模式)
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum EditMode {
+    #[default]
+    Emacs,
+    Vi,
+}
+
+impl EditMode {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            EditMode::Emacs => "emacs",
+            EditMode::Vi => "vi",
+        }
+    }
+
+    pub fn parse(value: &str) -> Option<Self> {
+        match value.to_lowercase().as_str() {
+            "emacs" => Some(EditMode::Emacs),
+            "vi" | "vim" => Some(EditMode::Vi),
+            _ => None,
+        }
+    }
+}
+
+/// 运行时可调整的选项，通过 `/set <key> <value>` 修改
+#[derive(Debug, Clone)]
+pub struct Settings {
+    /// 实验性: 生成 snippet 时把行首空格压缩为制表符
+    pub indent_compression: bool,
+    /// 按语言对 payload 中的文档分组
+    pub group_by_language: bool,
+    /// 分组时的语言优先级 (逗号分隔的语言名)
+    pub language_priority: Vec<String>,
+    /// /refresh 时如何处理被改名的已选文件
+    pub follow_renames: FollowRenames,
+    /// 是否常驻保存合并后的 XML；关闭后在 /copy 时按需拼装，以时间换内存
+    pub cache_merged: bool,
+    /// 内存占用软上限 (MB)，超过时给出警告；0 表示不限制
+    pub memory_cap_mb: usize,
+    /// 修改上下文的命令执行后是否打印一行状态栏
+    pub status_footer: bool,
+    /// 自动刷新策略
+    pub auto_refresh: AutoRefresh,
+    /// /copy 前是否校验 payload 为良构 XML
+    pub validate_xml: bool,
+    /// token 预算 (目标模型的上下文窗口)，用于提示符、/dashboard 的进度条和超出预算的警告 (/budget)；0 表示不设预算
+    pub token_budget: usize,
+    /// `/format custom` 未指定名字时使用的模板 (名字或文件路径)
+    pub template: String,
+    /// /refresh 时重新展开 /add 过的目录和 glob，把之后新建的文件加入上下文
+    pub expand_roots_on_refresh: bool,
+    /// 历史记录按项目区分的方式
+    pub history_scope: HistoryScope,
+    /// 最多保留的历史条数
+    pub history_capacity: usize,
+    /// 已选文件变成二进制时的处理方式
+    pub binary_transition: BinaryTransition,
+    /// 在本地记录使用统计 (/usage)；默认关闭
+    pub usage_stats: bool,
+    /// 单个文件的大小上限 (字节)，超过时按 oversize 处理；0 表示不限制。
+    /// 已选文件重新读取时超过上限一律截断，不会从上下文中消失
+    pub max_file_bytes: usize,
+    /// /add 遇到超过 max_file_bytes 的文件时跳过还是截断
+    pub oversize: Oversize,
+    /// /grep 最多打印的匹配数，其余只报告数量；0 表示不限制
+    pub grep_max_hits: usize,
+    /// Prompt 模式下输入的 `@path` 自动 /add 对应的文件 (关闭后 `@` 仍然可以补全路径)
+    pub auto_add_mentions: bool,
+    /// 提示符中分开显示文件 (包括项目树) 和 prompt 的 token 数，例如 `[12.3k+412] tokens`
+    pub token_breakdown: bool,
+    /// /copy 等使用的复制方式: auto (clip.exe / wl-copy → 系统剪贴板 → OSC 52 → 临时文件) 或固定为其中一种
+    pub clipboard_backend: ClipboardBackend,
+    /// 终端输出使用颜色 (NO_COLOR、--no-color 或 stdout 不是终端时总是关闭)
+    pub color: bool,
+    /// 路径补全没有前缀匹配时，在项目根目录下的所有文件中模糊匹配 (例如 `eng` → `src/repl/engine.rs`)
+    pub fuzzy_completion: bool,
+    /// REPL 的键位 (emacs|vi)；修改后立即重建编辑器
+    pub edit_mode: EditMode,
+    /// 输入时用灰色提示历史中的后续内容 (/remove 提示最近添加的路径)，按 Right / End 接受
+    pub hints: bool,
+    /// /reset 和 (prompt 没有保存时的) /quit 之前确认；--force 跳过
+    pub confirm_destructive: bool,
+}
+
+impl Default for Settings {
+    fn default() -> Self {
+        Self {
+            indent_compression: false,
+            group_by_language: false,
+            language_priority: Vec::new(),
+            follow_renames: FollowRenames::default(),
+            cache_merged: true,
+            memory_cap_mb: 256,
+            status_footer: true,
+            auto_refresh: AutoRefresh::default(),
+            validate_xml: true,
+            token_budget: 128_000,
+            template: String::new(),
+            expand_roots_on_refresh: false,
+            history_scope: HistoryScope::default(),
+            history_capacity: 1000,
+            binary_transition: BinaryTransition::default(),
+            usage_stats: false,
+            max_file_bytes: 512 * 1024,
+            oversize: Oversize::default(),
+            grep_max_hits: 50,
+            auto_add_mentions: true,
+            token_breakdown: false,
+            clipboard_backend: ClipboardBackend::default(),
+            color: true,
+            fuzzy_completion: true,
+            edit_mode: EditMode::default(),
+            hints: true,
+            confirm_destructive: true,
+        }
+    }
+}
+
+impl Settings {
+    /// 所有可用的 key，按 `/set` 列表的展示顺序
+    pub const KEYS: &'static [&'static str] = &[
+        "indent_compression",
+        "group_by_language",
+        "language_priority",
+        "follow_renames",
+        "cache_merged",
+        "memory_cap_mb",
+        "status_footer",
+        "auto_refresh",
+        "validate_xml",
+        "token_budget",
+        "template",
+        "expand_roots_on_refresh",
+        "history_scope",
+        "history_capacity",
+        "binary_transition",
+        "usage_stats",
+        "max_file_bytes",
+        "oversize",
+        "grep_max_hits",
+        "auto_add_mentions",
+        "token_breakdown",
+        "clipboard_backend",
+        "color",
+        "fuzzy_completion",
+        "edit_mode",
+        "hints",
+        "confirm_destructive",
+    ];
+
+    /// key 的其它写法，例如 `/set editmode vi`
+    const KEY_ALIASES: &'static [(&'static str, &'static str)] = &[("editmode", "edit_mode")];
+
+    /// 把 key 的其它写法换成 KEYS 中的名字，其余原样返回
+    ///
+    /// ```
+    /// use rustprompt::app::settings::Settings;
+    ///
+    /// assert_eq!(Settings::canonical_key("editmode"), "edit_mode");
+    /// assert_eq!(Settings::canonical_key("color"), "color");
+    /// ```
+    pub fn canonical_key(key: &str) -> &str {
+        Self::KEY_ALIASES.iter().find(|(alias, _)| *alias == key).map_or(key, |(_, name)| name)
+    }
+
+    /// 读取某个 key 的当前值 (以字符串形式)，未知 key 返回 None
+    pub fn get(&self, key: &str) -> Option<String> {
+        match key {
+            "indent_compression" => Some(on_off(self.indent_compression)),
+            "group_by_language" => Some(on_off(self.group_by_language)),
+            "language_priority" => Some(self.language_priority.join(",")),
+            "follow_renames" => Some(
+                match self.follow_renames {
+                    FollowRenames::Auto => "auto",
+                    FollowRenames::Ask => "ask",
+                    FollowRenames::Off => "off",
+                }
+                .to_string(),
+            ),
+            "cache_merged" => Some(on_off(self.cache_merged)),
+            "memory_cap_mb" => Some(self.memory_cap_mb.to_string()),
+            "status_footer" => Some(on_off(self.status_footer)),
+            "auto_refresh" => Some(match self.auto_refresh {
+                AutoRefresh::BeforeCopy => "before_copy".to_string(),
+                AutoRefresh::Interval(secs) => format!("interval:{}", secs),
+                AutoRefresh::Off => "off".to_string(),
+            }),
+            "validate_xml" => Some(on_off(self.validate_xml)),
+            "token_budget" => Some(self.token_budget.to_string()),
+            "template" => Some(self.template.clone()),
+            "expand_roots_on_refresh" => Some(on_off(self.expand_roots_on_refresh)),
+            "history_scope" => Some(
+                match self.history_scope {
+                    HistoryScope::Prefer => "prefer",
+                    HistoryScope::Project => "project",
+                    HistoryScope::All => "all",
+                }
+                .to_string(),
+            ),
+            "binary_transition" => Some(
+                match self.binary_transition {
+                    BinaryTransition::KeepLast => "keep-last",
+                    BinaryTransition::Placeholder => "placeholder",
+                    BinaryTransition::Drop => "drop",
+                }
+                .to_string(),
+            ),
+            "usage_stats" => Some(on_off(self.usage_stats)),
+            "history_capacity" => Some(self.history_capacity.to_string()),
+            "max_file_bytes" => Some(format_size(self.max_file_bytes)),
+            "oversize" => Some(
+                match self.oversize {
+                    Oversize::Skip => "skip",
+                    Oversize::Truncate => "truncate",
+                }
+                .to_string(),
+            ),
+            "grep_max_hits" => Some(self.grep_max_hits.to_string()),
+            "auto_add_mentions" => Some(on_off(self.auto_add_mentions)),
+            "token_breakdown" => Some(on_off(self.token_breakdown)),
+            "clipboard_backend" => Some(self.clipboard_backend.as_str().to_string()),
+            "color" => Some(on_off(self.color)),
+            "fuzzy_completion" => Some(on_off(self.fuzzy_completion)),
+            "edit_mode" => Some(self.edit_mode.as_str().to_string()),
+            "hints" => Some(on_off(self.hints)),
+            "confirm_destructive" => Some(on_off(self.confirm_destructive)),
+            _ => None,
+        }
+    }
+
+    /// 修改某个 key，返回面向用户的错误信息
+    pub fn set(&mut self, key: &str, value: &str) -> Result<(), String> {
+        match key {
+            "indent_compression" => {
+                self.indent_compression = parse_bool(value)?;
+                Ok(())
+            }
+            "group_by_language" => {
+                self.group_by_language = parse_bool(value)?;
+                Ok(())
+            }
+            "language_priority" => {
+                self.language_priority = parse_list(value);
+                Ok(())
+            }
+            "follow_renames" => {
+                self.follow_renames = match value.to_lowercase().as_str() {
+                    "auto" => FollowRenames::Auto,
+                    "ask" => FollowRenames::Ask,
+                    "off" => FollowRenames::Off,
+                    _ => return Err(format!("Invalid value: {} (expected auto|ask|off)", value)),
+                };
+                Ok(())
+            }
+            "cache_merged" => {
+                self.cache_merged = parse_bool(value)?;
+                Ok(())
+            }
+            "memory_cap_mb" => {
+                self.memory_cap_mb = parse_usize(value)?;
+                Ok(())
+            }
+            "status_footer" => {
+                self.status_footer = parse_bool(value)?;
+                Ok(())
+            }
+            "template" => {
+                self.template = value.trim().to_string();
+                Ok(())
+            }
+            "expand_roots_on_refresh" => {
+                self.expand_roots_on_refresh = parse_bool(value)?;
+                Ok(())
+            }
+            "history_scope" => {
+                self.history_scope = match value.to_lowercase().as_str() {
+                    "prefer" => HistoryScope::Prefer,
+                    "project" => HistoryScope::Project,
+                    "all" => HistoryScope::All,
+                    _ => return Err(format!("Invalid value: {} (expected prefer|project|all)", value)),
+                };
+                Ok(())
+            }
+            "binary_transition" => {
+                self.binary_transition = match value.to_lowercase().as_str() {
+                    "keep-last" => BinaryTransition::KeepLast,
+                    "placeholder" => BinaryTransition::Placeholder,
+                    "drop" => BinaryTransition::Drop,
+                    _ => return Err(format!("Invalid value: {} (expected keep-last|placeholder|drop)", value)),
+                };
+                Ok(())
+            }
+            "history_capacity" => {
+                let capacity = parse_usize(value)?;
+                if capacity == 0 {
+                    return Err(format!("Invalid value: {} (expected a positive number)", value));
+                }
+                self.history_capacity = capacity;
+                Ok(())
+            }
+            "usage_stats" => {
+                self.usage_stats = parse_bool(value)?;
+                Ok(())
+            }
+            "max_file_bytes" => {
+                self.max_file_bytes = parse_size(value)?;
+                Ok(())
+            }
+            "oversize" => {
+                self.oversize = match value.to_lowercase().as_str() {
+                    "skip" => Oversize::Skip,
+                    "truncate" => Oversize::Truncate,
+                    _ => return Err(format!("Invalid value: {} (expected skip|truncate)", value)),
+                };
+                Ok(())
+            }
+            "grep_max_hits" => {
+                self.grep_max_hits = parse_usize(value)?;
+                Ok(())
+            }
+            "auto_add_mentions" => {
+                self.auto_add_mentions = parse_bool(value)?;
+                Ok(())
+            }
+            "token_breakdown" => {
+                self.token_breakdown = parse_bool(value)?;
+                Ok(())
+            }
+            "clipboard_backend" => {
+                self.clipboard_backend = ClipboardBackend::parse(value)
+                    .ok_or_else(|| format!("Invalid value: {} (expected auto|native|wsl|wayland|osc52|file)", value))?;
+                Ok(())
+            }
+            "color" => {
+                self.color = parse_bool(value)?;
+                Ok(())
+            }
+            "fuzzy_completion" => {
+                self.fuzzy_completion = parse_bool(value)?;
+                Ok(())
+            }
+            "hints" => {
+                self.hints = parse_bool(value)?;
+                Ok(())
+            }
+            "confirm_destructive" => {
+                self.confirm_destructive = parse_bool(value)?;
+                Ok(())
+            }
+            "edit_mode" => {
+                self.edit_mode =
+                    EditMode::parse(value).ok_or_else(|| format!("Invalid value: {} (expected emacs|vi)", value))?;
+                Ok(())
+            }
+            "token_budget" => {
+                self.token_budget = parse_token_count(value)?;
+                Ok(())
+            }
+            "validate_xml" => {
+                self.validate_xml = parse_bool(value)?;
+                Ok(())
+            }
+            "auto_refresh" => {
+                let v = value.to_lowercase();
+                self.auto_refresh = match v.as_str() {
+                    "before_copy" => AutoRefresh::BeforeCopy,
+                    "off" => AutoRefresh::Off,
+                    _ => match v.strip_prefix("interval:").map(|s| s.trim().parse::<u64>()) {
+                        Some(Ok(secs)) if secs > 0 => AutoRefresh::Interval(secs),
+                        _ => {
+                            return Err(format!(
+                                "Invalid value: {} (expected before_copy|interval:<secs>|off)",
+                                value
+                            ))
+                        }
+                    },
+                };
+                Ok(())
+            }
+            _ => Err(format!(
+                "Unknown setting: {} (available: {})",
+                key,
+                Self::KEYS.join(", ")
+            )),
+        }
+    }
+
+    /// merge 阶段需要的选项
+    pub fn merge_options(&self) -> MergeOptions {
+        MergeOptions {
+            group_by_language: self.group_by_language,
+            language_priority: self.language_priority.clone(),
+            ..MergeOptions::default()
+        }
+    }
+}
+
+fn on_off(v: bool) -> String {
+    if v { "on" } else { "off" }.to_string()
+}
+
+pub fn parse_bool(value: &str) -> Result<bool, String> {
+    match value.to_lowercase().as_str() {
+        "on" | "true" | "yes" | "1" => Ok(true),
+        "off" | "false" | "no" | "0" => Ok(false),
+        _ => Err(format!("Invalid value: {} (expected on|off)", value)),
+    }
+}
+
+fn parse_list(value: &str) -> Vec<String> {
+    value
+        .split(',')
+        .map(|s| s.trim().to_lowercase())
+        .filter(|s| !s.is_empty())
+        .collect()
+}
+
+/// token 数，可以带 k / m 后缀 (例如 `128k`、`1m`)
+pub fn parse_token_count(value: &str) -> Result<usize, String> {
+    let lower = value.trim().to_lowercase();
+    let (digits, scale) = match lower.strip_suffix('k') {
+        Some(d) => (d, 1_000.0),
+        None => match lower.strip_suffix('m') {
+            Some(d) => (d, 1_000_000.0),
+            None => (lower.as_str(), 1.0),
+        },
+    };
+    digits
+        .parse::<f64>()
+        .ok()
+        .filter(|n| n.is_finite() && *n >= 0.0)
+        .map(|n| (n * scale).round() as usize)
+        .ok_or_else(|| format!("Invalid value: {} (expected a token count such as 128000 or 128k)", value))
+}
+
+/// 字节数，可以带 k / kb / m / mb 后缀 (按 1024 计，例如 `512k`)
+fn parse_size(value: &str) -> Result<usize, String> {
+    let lower = value.trim().to_lowercase();
+    let (digits, scale) = match lower.strip_suffix('b').unwrap_or(&lower) {
+        d if d.ends_with('k') => (&d[..d.len() - 1], 1024),
+        d if d.ends_with('m') => (&d[..d.len() - 1], 1024 * 1024),
+        d => (d, 1),
+    };
+    digits
+        .trim()
+        .parse::<usize>()
+        .ok()
+        .and_then(|n| n.checked_mul(scale))
+        .ok_or_else(|| format!("Invalid value: {} (expected a size such as 524288, 512k or 1m)", value))
+}
+
+/// parse_size 的逆操作: 整 MB 显示为 `1m`，整 KB 显示为 `512k`，否则为字节数
+fn format_size(bytes: usize) -> String {
+    const KB: usize = 1024;
+    match bytes {
+        0 => "0".to_string(),
+        b if b.is_multiple_of(KB * KB) => format!("{}m", b / (KB * KB)),
+        b if b.is_multiple_of(KB) => format!("{}k", b / KB),
+        b => b.to_string(),
+    }
+}
+
+fn parse_usize(value: &str) -> Result<usize, String> {
+    value
+        .trim()
+        .parse()
+        .map_err(|_| format!("Invalid value: {} (expected a number)", value))
+}
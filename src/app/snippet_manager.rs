@@ -1,22 +1,342 @@
+use std::collections::{HashMap, HashSet};
 use std::path::{Path, PathBuf};
 use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use anyhow::anyhow;
+use tokio_util::sync::CancellationToken;
 use tokio::fs;
+use tokio::io::AsyncReadExt;
 
 use crate::{
-    app::state::{AppState, PROJECT_TREE_VIRTUAL_PATH},
+    app::{
+        output::{binary_warning, format_bytes, pruned_summary},
+        settings::{BinaryTransition, Settings},
+        state::{AppState, PROJECT_TREE_VIRTUAL_PATH},
+    },
     core::{
+        indent::compress_indentation,
+        renames::ContentFingerprint,
+        file_stamp::FileStamp,
         tokenizer::calculate_tokens_in_string,
-        xml::{generate_single_file_snippet, merge_all_snippets},
+        imports::{extract_imports, resolve_imports, ImportRef},
+        ordering::{order_paths, OrderingInputs, SortStrategy},
+        overlap::{find_overlaps, Overlap},
+        language::{detect_language, language_title},
+        decode::{decode_with_encoding, Decoded},
+        paths,
+        progress::{Progress, ProgressEvent},
+        templating::{self, Expansion},
+        template::{render as render_template, OutputFormat, TemplateContext, TemplateDocument, TemplateMetadata},
+        xml::{generate_single_file_snippet, generate_single_file_snippet_with_attrs, document_groups, document_ids, estimate_merged_tokens, instruction_snippet, merge_all_snippets, snippet_content, MergeOptions},
         tree_builder::generate_project_tree_string, // 使用 tree_builder
         ignore_rules::IgnoreConfig,
     },
-    error::AppError,
+    error::{io_reason, AppError},
 };
 
+/// 二进制文件的一行占位说明，例如 `[binary file, 204.0 KB]`
+fn binary_placeholder(len: usize) -> String {
+    format!("[binary file, {}]", format_bytes(len))
+}
+
+/// 只读取文件开头 limit 字节；读取失败时返回空内容 (与 fs::read 失败时相同)
+async fn read_prefix(path: &Path, limit: usize) -> std::io::Result<Vec<u8>> {
+    let mut bytes = Vec::with_capacity(limit);
+    let file = fs::File::open(path).await?;
+    file.take(limit as u64).read_to_end(&mut bytes).await?;
+    Ok(bytes)
+}
+
 /// 提供对 snippet 的公共操作，如增量更新、全量刷新、更新项目树、重建合并等。
 pub struct SnippetManager;
 
+/// 在锁外为一个文件生成的 snippet 及其附属记录，随后在锁内一次性写入 AppState
+struct SnippetRecord {
+    path: PathBuf,
+    snippet: String,
+    fingerprint: ContentFingerprint,
+    tokens: usize,
+    imports: Vec<ImportRef>,
+    /// 内容是二进制文件的占位说明
+    placeholder: bool,
+    /// 超过 max_file_bytes 被截断时的原始大小
+    truncated: Option<u64>,
+}
+
+impl SnippetRecord {
+    fn store(self, st: &mut AppState) {
+        st.fingerprints.insert(self.path.clone(), self.fingerprint);
+        if let Some(stamp) = FileStamp::of(&self.path) {
+            st.file_stamps.insert(self.path.clone(), stamp);
+        }
+        if self.placeholder {
+            st.binary_files.insert(self.path.clone(), BinaryTransition::Placeholder);
+        } else {
+            st.binary_files.remove(&self.path);
+        }
+        match self.truncated {
+            Some(size) => st.truncated.insert(self.path.clone(), size),
+            None => st.truncated.remove(&self.path),
+        };
+        st.doc_tokens.insert(self.path.clone(), self.tokens);
+        st.imports.insert(self.path.clone(), self.imports);
+        st.partial_docs.insert(self.path, self.snippet);
+    }
+}
+
+/// 重新读取一个文件的结果
+enum ReadOutcome {
+    Store(SnippetRecord),
+    /// 文件变成了二进制，保留现有 snippet (binary_transition=keep-last)，只更新指纹和 mtime
+    KeepLast { path: PathBuf, fingerprint: ContentFingerprint },
+    /// 文件变成了二进制，从上下文中移除 (binary_transition=drop)
+    Drop(PathBuf),
+    /// 文件已被删除，从上下文中移除并记入 pruned_files
+    Missing(PathBuf),
+}
+
+impl ReadOutcome {
+    fn path(&self) -> &Path {
+        match self {
+            Self::Store(record) => &record.path,
+            Self::KeepLast { path, .. } | Self::Drop(path) | Self::Missing(path) => path,
+        }
+    }
+}
+
+/// 读取前文件在上下文中的状态: 是否已有 snippet，以及是否已按二进制处理
+#[derive(Debug, Clone, Copy)]
+enum PreviousState {
+    Text,
+    Binary,
+}
+
+/// payload 中的一个文档 (只有元数据，不含内容)
+#[derive(Debug, Clone, PartialEq)]
+pub struct ManifestDocument {
+    pub id: String,
+    pub source: PathBuf,
+    pub tokens: usize,
+    /// 超过 max_file_bytes 被截断时为原始大小
+    pub truncated: Option<u64>,
+}
+
+/// instruction (prompt) 在 payload 中的位置
+#[derive(Debug, Clone, PartialEq)]
+pub enum InstructionPlacement {
+    /// prompt 为空，不插入 instruction
+    None,
+    /// 紧跟在该 ID 的文档之后、`</documents>` 之前 (没有文档时为 None)
+    AfterDocument(Option<String>),
+    /// 由自定义模板决定
+    Template,
+}
+
+/// /copy 将要生成的 payload 的结构: 文档顺序、静音的文件、instruction 的位置和总大小
+#[derive(Debug, Clone)]
+pub struct PayloadManifest {
+    pub format: String,
+    pub documents: Vec<ManifestDocument>,
+    /// 仍被选中但不进入 payload 的文件
+    pub muted: Vec<ManifestDocument>,
+    pub grouped_by_language: bool,
+    pub instruction: InstructionPlacement,
+    pub prompt_tokens: usize,
+    /// 主体 + prompt 的 token 数
+    pub total_tokens: usize,
+    /// 各 snippet 与 prompt 的字节数之和 (不含合并时的标记)
+    pub approx_bytes: usize,
+}
+
+/// /copy --split 拆分出的一个部分
+#[derive(Debug, Clone)]
+pub struct PayloadPart {
+    /// 完整的 payload: 项目树 + 这一部分的文件 + instruction
+    pub text: String,
+    pub tokens: usize,
+    /// 这一部分的文件数
+    pub documents: usize,
+}
+
+/// /copy --split 的结果
+#[derive(Debug, Clone)]
+pub struct SplitPayload {
+    pub parts: Vec<PayloadPart>,
+    /// 单独就超过上限、各自占一个部分的文件及其 token 数
+    pub oversized: Vec<(PathBuf, usize)>,
+}
+
+/// 拆分时估算每个文档的标记 (id 属性、换行、分组标签) 占用的 token，实际大小在渲染后校验
+const PART_DOCUMENT_MARKUP: usize = 8;
+
 impl SnippetManager {
+    /// 根据当前 settings 为真实文件生成 snippet (例如按需进行缩进压缩)
+    fn render_file_snippet(path: &Path, content: &str, settings: &Settings) -> String {
+        if settings.indent_compression {
+            if let Some(compressed) = compress_indentation(content) {
+                let attrs = [("indent_unit", compressed.unit.to_string())];
+                return generate_single_file_snippet_with_attrs(path, &compressed.content, 0, &attrs);
+            }
+        }
+        generate_single_file_snippet(path, content, 0)
+    }
+
+    /// 读取文件内容并生成 snippet 记录
+    ///  - 识别 BOM 并按 UTF-8 / UTF-16 解码；非法字节按 U+FFFD 替换，而不是像 read_to_string 那样整份丢成空串
+    ///  - 之前作为文本加入的文件现在看起来是二进制时，按 settings.binary_transition 处理，
+    ///    并记录一条待报告的警告 (已经按二进制处理过的文件不重复警告)
+    ///  - 首次加入的二进制文件 (只有 /ignore binary off 时才会到这里) 写入一行占位说明，例如 `[binary file, 204.0 KB]`
+    ///  - 超过 settings.max_file_bytes 的文件只读取开头部分，内容末尾注明原始大小 (`[truncated, original 40.2 MB]`)；
+    ///    /add 在 oversize=skip 时已经把新文件中超限的去掉，这里遇到的是 oversize=truncate 或重新读取时变大的已选文件
+    ///  - 指纹基于原始字节 (截断时为读取到的部分加上完整长度)，保证与磁盘上的文件 (find_by_fingerprint) 可比
+    ///  - `<source>` 写入相对于项目根目录 root 的路径 (见 `paths::display_path`)
+    ///  - 文件已被删除时返回 Missing，由 store_outcomes 移出上下文；其它读取错误 (例如没有权限) 保留选择，
+    ///    内容为一行说明，例如 `[unreadable: permission denied]`
+    async fn read_file(
+        path: &Path,
+        settings: &Settings,
+        root: &Path,
+        previous: Option<PreviousState>,
+    ) -> (ReadOutcome, bool) {
+        let limit = settings.max_file_bytes;
+        let size = fs::metadata(path).await.map(|m| m.len()).unwrap_or(0);
+        let truncated = (limit > 0 && size > limit as u64).then_some(size);
+        let read = match truncated {
+            Some(_) => read_prefix(path, limit).await,
+            None => fs::read(path).await,
+        };
+        let source = paths::display_path(path, root);
+        let mut bytes = match read {
+            Ok(bytes) => bytes,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => return (ReadOutcome::Missing(path.to_path_buf()), false),
+            Err(e) => {
+                let note = format!("[unreadable: {}]", io_reason(&e));
+                let mut record = Self::placeholder_record(path, &source, &note, ContentFingerprint::of(&[]));
+                // 不是二进制文件，不记入 binary_files
+                record.placeholder = false;
+                return (ReadOutcome::Store(record), false);
+            }
+        };
+        let fingerprint = match truncated {
+            Some(size) => ContentFingerprint::of_prefix(&bytes, size),
+            None => ContentFingerprint::of(&bytes),
+        };
+        // 截断处可能落在多字节字符中间: 去掉结尾不完整的 UTF-8 序列，否则整份内容会被当成其它编码解码
+        if truncated.is_some() {
+            if let Err(e) = std::str::from_utf8(&bytes) {
+                if e.error_len().is_none() {
+                    bytes.truncate(e.valid_up_to());
+                }
+            }
+        }
+        let (decoded, encoding) = decode_with_encoding(&bytes);
+        if encoding.is_unusual() && matches!(decoded, Decoded::Text(_)) {
+            log::warn!("{:?} 不是 UTF-8 编码，按 {} 解码", path, encoding);
+        }
+        let content = match (decoded, previous) {
+            (Decoded::Text(s), _) => match truncated {
+                Some(size) => format!("{}\n[truncated, original {}]", s, format_bytes(size as usize)),
+                None => s,
+            },
+            (Decoded::Binary { len, .. }, None) => {
+                let len = truncated.map_or(len, |size| size as usize);
+                let record = Self::placeholder_record(path, &source, &binary_placeholder(len), fingerprint);
+                return (ReadOutcome::Store(record), false);
+            }
+            (Decoded::Binary { len, .. }, Some(previous)) => {
+                let newly = matches!(previous, PreviousState::Text);
+                let outcome = match settings.binary_transition {
+                    BinaryTransition::KeepLast => ReadOutcome::KeepLast { path: path.to_path_buf(), fingerprint },
+                    BinaryTransition::Drop => ReadOutcome::Drop(path.to_path_buf()),
+                    BinaryTransition::Placeholder => {
+                        let note = if newly {
+                            format!(
+                                "(binary content omitted: this file was text when it was added but now looks binary, {} bytes)",
+                                len
+                            )
+                        } else {
+                            binary_placeholder(len)
+                        };
+                        ReadOutcome::Store(Self::placeholder_record(path, &source, &note, fingerprint))
+                    }
+                };
+                return (outcome, newly);
+            }
+        };
+        let snippet = Self::render_file_snippet(&source, &content, settings);
+        let tokens = calculate_tokens_in_string(&snippet).unwrap_or(0);
+        let imports = extract_imports(path, &content);
+        let record = SnippetRecord { path: path.to_path_buf(), snippet, fingerprint, tokens, imports, placeholder: false, truncated };
+        (ReadOutcome::Store(record), false)
+    }
+
+    /// 内容为占位说明的二进制文件记录
+    fn placeholder_record(path: &Path, source: &Path, note: &str, fingerprint: ContentFingerprint) -> SnippetRecord {
+        let snippet = generate_single_file_snippet(source, note, 0);
+        let tokens = calculate_tokens_in_string(&snippet).unwrap_or(0);
+        SnippetRecord {
+            path: path.to_path_buf(),
+            snippet,
+            fingerprint,
+            tokens,
+            imports: Vec::new(),
+            placeholder: true,
+            truncated: None,
+        }
+    }
+
+    /// 已有 snippet 的文件在读取前的状态
+    fn previous_states(st: &AppState, paths: &[PathBuf]) -> HashMap<PathBuf, PreviousState> {
+        paths
+            .iter()
+            .filter(|p| st.partial_docs.contains_key(*p))
+            .map(|p| {
+                let previous = if st.binary_files.contains_key(p) {
+                    PreviousState::Binary
+                } else {
+                    PreviousState::Text
+                };
+                (p.clone(), previous)
+            })
+            .collect()
+    }
+
+    /// 在锁内写入读取结果；newly 为 true 的二进制转换记入 binary_warnings
+    fn store_outcomes(st: &mut AppState, outcomes: Vec<(ReadOutcome, bool)>) {
+        let pruned_before = st.pruned_files.len();
+        for (outcome, newly) in outcomes {
+            if newly || matches!(outcome, ReadOutcome::Drop(_)) {
+                st.binary_warnings.push((outcome.path().to_path_buf(), st.settings.binary_transition));
+            }
+            match outcome {
+                ReadOutcome::Store(record) => record.store(st),
+                ReadOutcome::KeepLast { path, fingerprint } => {
+                    st.fingerprints.insert(path.clone(), fingerprint);
+                    if let Some(stamp) = FileStamp::of(&path) {
+                        st.file_stamps.insert(path.clone(), stamp);
+                    }
+                    st.binary_files.insert(path, BinaryTransition::KeepLast);
+                }
+                ReadOutcome::Drop(path) => {
+                    st.selected_paths.remove(&path);
+                    st.forget_file(&path);
+                    st.file_count = st.selected_paths.len();
+                }
+                ReadOutcome::Missing(path) => {
+                    st.selected_paths.remove(&path);
+                    st.forget_file(&path);
+                    st.file_count = st.selected_paths.len();
+                    st.pruned_files.push(path);
+                }
+            }
+        }
+        // 只包含被删除文件的 /add root 不再显示
+        if st.pruned_files.len() > pruned_before {
+            let selected = st.selected_paths.clone();
+            st.add_roots.prune(|f| selected.contains(f));
+        }
+    }
+
     /// 增量添加文件 snippet
     ///  - 先在锁外读取文件内容，生成 snippet
     ///  - 然后在锁内写入 partial_docs
@@ -24,62 +344,531 @@ impl SnippetManager {
         state: Arc<Mutex<AppState>>,
         files: Vec<PathBuf>,
     ) -> Result<(), AppError> {
+        Self::add_files_snippet_with_progress(state, files, &Progress::none(), &CancellationToken::new()).await
+    }
+
+    /// 同 add_files_snippet，每读完一个文件发送一次 `ProgressEvent::Read`；
+    /// 每个文件之前检查 cancel，被取消时不写入任何 snippet，返回 `AppError::Cancelled`
+    pub async fn add_files_snippet_with_progress(
+        state: Arc<Mutex<AppState>>,
+        files: Vec<PathBuf>,
+        progress: &Progress,
+        cancel: &CancellationToken,
+    ) -> Result<(), AppError> {
+        let (settings, root, previous) = {
+            let st = state.lock().unwrap();
+            (st.settings.clone(), st.project_root.clone(), Self::previous_states(&st, &files))
+        };
+
         // 1) 读取文件内容(在锁外, 避免阻塞 REPL)
         let mut new_snips = Vec::with_capacity(files.len());
         for f in &files { // Borrow files instead of consuming
+            if cancel.is_cancelled() {
+                return Err(AppError::Cancelled);
+            }
             // 可以考虑 tokio::task::spawn_blocking，如果文件很多或很大
-            let content = fs::read_to_string(f).await.unwrap_or_default();
-            let snippet = generate_single_file_snippet(f, &content, 0);
-            new_snips.push((f.clone(), snippet)); // Clone f here
+            new_snips.push(Self::read_file(f, &settings, &root, previous.get(f).copied()).await);
+            progress.send(ProgressEvent::Read { done: new_snips.len(), total: files.len() });
         }
 
         // 2) 上锁: 将结果写入 partial_docs
-        {
-            let mut st = state.lock().unwrap();
-            for (path, snip) in new_snips {
-                st.partial_docs.insert(path, snip);
-            }
-        }
+        Self::store_outcomes(&mut state.lock().unwrap(), new_snips);
 
         Ok(())
     }
 
-    /// 更新/重新生成项目树 snippet，并存入 partial_docs
-    ///  - tree_builder 本身可能比较耗时, 可以考虑 spawn_blocking
-    pub fn update_project_tree_snippet(
+    /// 增量刷新: 只重新读取 mtime/大小与记录不一致的已选文件，返回重新读取的文件数
+    ///  - stat 很廉价，未变化的文件不会被读取
+    ///  - 调用方负责随后更新项目树并 rebuild
+    pub async fn refresh_dirty(state: Arc<Mutex<AppState>>) -> Result<usize, AppError> {
+        let dirty = Self::dirty_files(&state.lock().unwrap());
+
+        if !dirty.is_empty() {
+            log::info!("refresh_dirty: {} files changed on disk", dirty.len());
+            Self::add_files_snippet(state, dirty.clone()).await?;
+        }
+        Ok(dirty.len())
+    }
+
+    /// mtime/大小与记录不一致 (或已被删除) 的已选文件
+    pub fn dirty_files(st: &AppState) -> Vec<PathBuf> {
+        st.selected_paths
+            .iter()
+            .filter(|p| FileStamp::of(p) != st.file_stamps.get(*p).copied())
+            .cloned()
+            .collect()
+    }
+
+    /// 启动 auto_refresh=interval 的后台任务: 定期执行增量刷新，
+    /// 有文件被刷新时通过 notify 回调报告 (REPL 中使用 reedline 的 ExternalPrinter)
+    pub fn spawn_auto_refresh<F>(
+        state: Arc<Mutex<AppState>>,
+        secs: u64,
+        notify: F,
+    ) -> tokio::task::JoinHandle<()>
+    where
+        F: Fn(String) + Send + 'static,
+    {
+        tokio::spawn(async move {
+            let mut ticker = tokio::time::interval(Duration::from_secs(secs));
+            ticker.tick().await; // 第一次 tick 立即完成，跳过
+            loop {
+                ticker.tick().await;
+                let refreshed = match Self::refresh_dirty(state.clone()).await {
+                    Ok(n) => n,
+                    Err(e) => {
+                        log::warn!("auto refresh failed: {:?}", e);
+                        continue;
+                    }
+                };
+                if refreshed == 0 {
+                    continue;
+                }
+                let ignore_config = state.lock().unwrap().ignore.clone();
+                let result = match Self::update_project_tree_snippet(state.clone(), &ignore_config).await {
+                    Ok(()) => Self::rebuild_and_recalc(state.clone()).await,
+                    Err(e) => Err(e),
+                };
+                let (warnings, pruned) = {
+                    let mut st = state.lock().unwrap();
+                    (std::mem::take(&mut st.binary_warnings), std::mem::take(&mut st.pruned_files))
+                };
+                for (path, kind) in warnings {
+                    notify(binary_warning(&path, kind));
+                }
+                if !pruned.is_empty() {
+                    notify(pruned_summary(&pruned));
+                }
+                match result {
+                    Ok(()) => notify(format!("(auto-refresh) {} changed files refreshed", refreshed)),
+                    Err(e) => log::warn!("auto refresh rebuild failed: {:?}", e),
+                }
+            }
+        })
+    }
+
+    /// 更新/重新生成项目树 snippet (project_root 下的目录树)，并存入 partial_docs
+    ///  - 遍历目录在 spawn_blocking 中进行 (大仓库可能要几秒)，期间不持有锁，也不阻塞 REPL
+    pub async fn update_project_tree_snippet(
         state: Arc<Mutex<AppState>>,
         ignore_config: &IgnoreConfig,
     ) -> Result<(), AppError> {
-        let current_dir = std::env::current_dir().unwrap_or_else(|_| PathBuf::from("."));
-        // 如果项目很大, generate_project_tree_string 可能耗时
-        // 考虑优化: let tree_txt = tokio::task::spawn_blocking(...).await??;
-        let tree_txt = generate_project_tree_string(&current_dir, ignore_config)
-            .unwrap_or_else(|e| {
-                log::error!("生成项目树失败: {:?}", e);
-                "".to_string()
-            });
-
-        let snippet = generate_single_file_snippet(Path::new(PROJECT_TREE_VIRTUAL_PATH), &tree_txt, 0);
+        let root = state.lock().unwrap().project_root.clone();
+        let config = ignore_config.clone();
+        let snippet = run_blocking("生成项目树", move || {
+            let tree_txt = generate_project_tree_string(&root, &config)
+                .unwrap_or_else(|e| {
+                    log::error!("生成项目树失败: {:?}", e);
+                    "".to_string()
+                });
+            generate_single_file_snippet(Path::new(PROJECT_TREE_VIRTUAL_PATH), &tree_txt, 0)
+        })
+        .await?;
+        Self::store_tree_snippet(&state, snippet).await
+    }
 
+    /// 写入项目树 snippet 及其 token 数；树没有变化时 (大多数 /add 和 /remove) 不重新分词
+    async fn store_tree_snippet(state: &Arc<Mutex<AppState>>, snippet: String) -> Result<(), AppError> {
+        let tree_key = PathBuf::from(PROJECT_TREE_VIRTUAL_PATH);
         {
-            let mut st = state.lock().unwrap();
-            st.partial_docs.insert(PathBuf::from(PROJECT_TREE_VIRTUAL_PATH), snippet);
+            let st = state.lock().unwrap();
+            if st.partial_docs.get(&tree_key) == Some(&snippet) && st.doc_tokens.contains_key(&tree_key) {
+                return Ok(());
+            }
         }
-
+        let (snippet, tokens) = run_blocking("计算项目树 token", move || {
+            let tokens = calculate_tokens_in_string(&snippet).unwrap_or(0);
+            (snippet, tokens)
+        })
+        .await?;
+        let mut st = state.lock().unwrap();
+        st.doc_tokens.insert(tree_key.clone(), tokens);
+        st.partial_docs.insert(tree_key, snippet);
         Ok(())
     }
 
     /// 重建合并 + 计算token
-    ///  - 在锁内进行，合并和token计算本身不算大IO
-    pub fn rebuild_and_recalc(state: Arc<Mutex<AppState>>) -> Result<(), AppError> {
+    ///  - 在锁内复制拼装所需的状态 (AppState::render_view)，合并和分词在 spawn_blocking 中进行，期间不持有锁
+    ///  - 写回前检查 rebuild_generation: 期间又开始了新的 rebuild (例如后台自动刷新) 时丢弃本次结果
+    ///  - XML 的 token 数由缓存的每个 snippet 的 token 数累加得到，不对整个 XML 重新分词；
+    ///    自定义模板的输出无法这样拆分，仍然整体分词
+    ///  - settings.cache_merged 关闭时只保留 token 数，不常驻合并结果
+    pub async fn rebuild_and_recalc(state: Arc<Mutex<AppState>>) -> Result<(), AppError> {
+        Self::rebuild_and_recalc_with_cancel(state, &CancellationToken::new()).await
+    }
+
+    /// 同 rebuild_and_recalc；还没有 token 数的 snippet 逐个分词，每个文档之前检查 cancel (REPL 中为 Ctrl+C)。
+    /// 被取消时不写回任何结果 (cached_xml、token 数保持上一次 rebuild 的值)，返回 `AppError::Cancelled`；
+    /// /copy 之前总会重新 rebuild，不会复制过期的内容
+    pub async fn rebuild_and_recalc_with_cancel(
+        state: Arc<Mutex<AppState>>,
+        cancel: &CancellationToken,
+    ) -> Result<(), AppError> {
+        let (mut view, generation) = {
+            let mut st = state.lock().unwrap();
+            st.rebuild_generation += 1;
+            (st.render_view(), st.rebuild_generation)
+        };
+        let cancel = cancel.clone();
+        let (merged, tokens, split, doc_ids) = run_blocking("重建 payload", move || -> Result<_, AppError> {
+            let uncounted: Vec<PathBuf> =
+                view.partial_docs.keys().filter(|p| !view.doc_tokens.contains_key(*p)).cloned().collect();
+            for path in uncounted {
+                if cancel.is_cancelled() {
+                    return Err(AppError::Cancelled);
+                }
+                let tokens = calculate_tokens_in_string(&view.partial_docs[&path]).unwrap_or(0);
+                view.doc_tokens.insert(path, tokens);
+            }
+            if cancel.is_cancelled() {
+                return Err(AppError::Cancelled);
+            }
+            let options = Self::merge_options(&view);
+            let merged = Self::render_body(&view)?;
+            let tokens = match view.output_format {
+                OutputFormat::Xml => estimate_merged_tokens(&view.partial_docs, &view.doc_tokens, &options),
+                OutputFormat::Custom { .. } => calculate_tokens_in_string(&merged)?,
+            };
+            Ok((merged, tokens, view.snippet_token_split(), document_ids(&view.partial_docs, &options)))
+        })
+        .await??;
+
         let mut st = state.lock().unwrap();
-        let merged = merge_all_snippets(&st.partial_docs);
-        let tokens = calculate_tokens_in_string(&merged)?;
-        st.cached_xml = merged;
+        if st.rebuild_generation != generation {
+            return Ok(());
+        }
+        st.doc_ids = doc_ids;
+        st.cached_xml = if st.settings.cache_merged { merged } else { String::new() };
         st.token_count = tokens;
+        (st.file_tokens, st.tree_tokens) = split;
+        Ok(())
+    }
+
+    /// 切换编码后重新计算所有 snippet 和 prompt 的 token 数 (在 spawn_blocking 中、锁外分词)；调用方随后 rebuild。
+    /// 每个文档之前检查 cancel，被取消时保留原来的 token 数，返回 `AppError::Cancelled`
+    pub async fn recount_tokens(state: Arc<Mutex<AppState>>, cancel: &CancellationToken) -> Result<(), AppError> {
+        let (snippets, prompt) = {
+            let st = state.lock().unwrap();
+            (st.partial_docs.clone(), st.prompt_text.clone())
+        };
+        let cancel = cancel.clone();
+        let (doc_tokens, prompt_tokens) = run_blocking("重新计算 token", move || -> Result<_, AppError> {
+            let mut doc_tokens = HashMap::with_capacity(snippets.len());
+            for (path, snippet) in snippets {
+                if cancel.is_cancelled() {
+                    return Err(AppError::Cancelled);
+                }
+                doc_tokens.insert(path, calculate_tokens_in_string(&snippet)?);
+            }
+            Ok((doc_tokens, calculate_tokens_in_string(&prompt)?))
+        })
+        .await??;
+
+        let mut st = state.lock().unwrap();
+        st.doc_tokens = doc_tokens;
+        st.prompt_tokens = prompt_tokens;
         Ok(())
     }
 
+    /// 获取 payload 主体 (不含 instruction): 有缓存时直接使用，否则按需拼装 (与缓存结果完全一致)
+    ///  - 自定义模板会用到 prompt，而 prompt 变化时不会 rebuild，所以总是重新渲染
+    fn merged_body(st: &AppState) -> Result<String, AppError> {
+        match st.output_format {
+            OutputFormat::Xml if st.settings.cache_merged && !st.cached_xml.is_empty() => Ok(st.cached_xml.clone()),
+            _ => Self::render_body(st),
+        }
+    }
+
+    /// 按当前输出格式拼装 payload 主体: `<documents>` XML 或自定义模板的渲染结果
+    pub fn render_body(st: &AppState) -> Result<String, AppError> {
+        match &st.output_format {
+            OutputFormat::Xml => Ok(merge_all_snippets(&st.partial_docs, &Self::merge_options(st))),
+            OutputFormat::Custom { name, source } => render_template(name, source, &Self::template_context(st))
+                .map_err(|e| AppError::General(anyhow!("{}", e))),
+        }
+    }
+
+    /// 自定义模板的输入: 与 XML 相同的文档顺序 (含静音、排序规则)，内容为反转义后的原文
+    fn template_context(st: &AppState) -> TemplateContext {
+        let tree_key = PathBuf::from(PROJECT_TREE_VIRTUAL_PATH);
+        let documents: Vec<TemplateDocument> = document_groups(&st.partial_docs, &Self::merge_options(st))
+            .into_iter()
+            .filter(|(_, path, _)| *path != tree_key)
+            .map(|(id, path, group)| TemplateDocument {
+                id,
+                path: st.display_path(&path).to_string_lossy().to_string(),
+                language: detect_language(&path),
+                content: st.partial_docs.get(&path).map(|s| snippet_content(s)).unwrap_or_default(),
+                tokens: st.doc_tokens.get(&path).copied().unwrap_or(0),
+                group: group.map(|language| language_title(&language)),
+            })
+            .collect();
+        let metadata = TemplateMetadata {
+            document_count: documents.len(),
+            file_tokens: documents.iter().map(|d| d.tokens).sum(),
+            format: st.output_format.describe(),
+        };
+        TemplateContext {
+            documents,
+            tree: st.partial_docs.get(&tree_key).map(|s| snippet_content(s)).unwrap_or_default(),
+            prompt: Self::expand_prompt(st).text,
+            metadata,
+        }
+    }
+
+    /// settings 中的拼装选项 + 当前 /sort 策略计算出的文件顺序
+    fn merge_options(st: &AppState) -> MergeOptions {
+        let mut options = st.settings.merge_options();
+        options.excluded = st.muted.clone();
+        if st.sort_strategy != SortStrategy::Path {
+            options.order = Self::document_order(st);
+        }
+        options
+    }
+
+    /// 按当前 /sort 策略计算真实文件的顺序
+    pub fn document_order(st: &AppState) -> Vec<PathBuf> {
+        let paths: Vec<PathBuf> = st
+            .partial_docs
+            .keys()
+            .filter(|p| p.as_os_str() != PROJECT_TREE_VIRTUAL_PATH)
+            .cloned()
+            .collect();
+        let dependencies = match st.sort_strategy {
+            SortStrategy::Dependency => Some(resolve_imports(&st.imports, &paths)),
+            _ => None,
+        };
+        let inputs = OrderingInputs {
+            tokens: Some(&st.doc_tokens),
+            stamps: Some(&st.file_stamps),
+            dependencies: dependencies.as_ref(),
+        };
+        let order = order_paths(&paths, st.sort_strategy, &inputs);
+        log::debug!("document order ({}): {:?}", st.sort_strategy.as_str(), order);
+        order
+    }
+
+    /// 虚拟文档 (不对应已选文件的 snippet，项目树除外) 与已选文件内容的重复情况
+    pub fn overlaps(st: &AppState) -> Vec<Overlap> {
+        let tree_key = PathBuf::from(PROJECT_TREE_VIRTUAL_PATH);
+        let mut files = Vec::new();
+        let mut virtual_docs = Vec::new();
+        for (path, snippet) in &st.partial_docs {
+            if st.selected_paths.contains(path) {
+                if !st.muted.contains(path) {
+                    files.push((path, snippet.as_str()));
+                }
+            } else if *path != tree_key {
+                virtual_docs.push((path, snippet.as_str()));
+            }
+        }
+        find_overlaps(&files, &virtual_docs)
+    }
+
+    /// 拼装最终 payload: 合并后的 XML + (可选) 放在 </documents> 之前的 instruction
+    ///  - /copy 和 /validate 共用，保证校验的内容就是实际复制的内容
+    ///  - 自定义模板自己决定 prompt 的位置，不再额外插入 instruction
+    pub fn assemble_payload(st: &AppState) -> Result<String, AppError> {
+        let mut final_xml = Self::merged_body(st)?;
+        if st.output_format == OutputFormat::Xml && !st.prompt_text.is_empty() {
+            let instruction_tag = instruction_snippet(&Self::expand_prompt(st).text);
+            if let Some(idx) = final_xml.rfind("</documents>") {
+                final_xml.insert_str(idx, &instruction_tag);
+            } else {
+                final_xml.push_str(&instruction_tag);
+                final_xml.push_str("\n</documents>");
+            }
+        }
+        Ok(final_xml)
+    }
+
+    /// 把 payload 按文档拆成多个不超过 limit 个 token 的部分 (只支持 XML 格式)。
+    /// 每一部分都是独立的 `<documents part="i" of="n">`，项目树和 instruction 在每一部分重复；
+    /// 单个文件不会被拆开，本身超过上限的文件独占一部分 (记录在 oversized 中):
+    ///
+    /// ```
+    /// use std::sync::{Arc, Mutex};
+    /// use rustprompt::{
+    ///     app::{output::CapturedOutput, snippet_manager::SnippetManager, state::AppState},
+    ///     command::{definition::Command, executor},
+    /// };
+    /// use tokio_util::sync::CancellationToken;
+    ///
+    /// # tokio::runtime::Runtime::new().unwrap().block_on(async {
+    /// let dir = std::env::temp_dir().join(format!("rustprompt-split-{}", std::process::id()));
+    /// std::fs::create_dir_all(&dir).unwrap();
+    /// for (name, words) in [("a.txt", 200), ("b.txt", 200), ("c.txt", 1000)] {
+    ///     std::fs::write(dir.join(name), "word ".repeat(words)).unwrap();
+    /// }
+    ///
+    /// let mut st = AppState::new();
+    /// st.project_root = dir.clone();
+    /// let state = Arc::new(Mutex::new(st));
+    /// let add = Command::Add(vec![dir.clone()], Default::default());
+    /// executor::execute(add, state.clone(), &CancellationToken::new(), &mut CapturedOutput::default()).await.unwrap();
+    ///
+    /// // 每一部分都重复项目树 (当前目录的树)，上限要留出它的大小
+    /// let st = state.lock().unwrap();
+    /// let limit = st.tree_tokens + 300;
+    /// let split = SnippetManager::split_payload(&st, limit).unwrap();
+    /// assert_eq!(split.parts.len(), 3);
+    /// assert!(split.parts[0].text.starts_with(r#"<documents part="1" of="3">"#));
+    /// assert!(split.parts[..2].iter().all(|p| p.tokens <= limit && p.documents == 1));
+    /// assert_eq!(split.oversized.len(), 1);
+    /// assert!(split.oversized[0].0.ends_with("c.txt"));
+    /// std::fs::remove_dir_all(&dir).unwrap();
+    /// # });
+    /// ```
+    pub fn split_payload(st: &AppState, limit: usize) -> Result<SplitPayload, AppError> {
+        if st.output_format != OutputFormat::Xml {
+            return Err(AppError::General(anyhow!("splitting only supports the XML format (/format xml)")));
+        }
+        let tree_key = PathBuf::from(PROJECT_TREE_VIRTUAL_PATH);
+        let files: Vec<PathBuf> = document_ids(&st.partial_docs, &Self::merge_options(st))
+            .into_iter()
+            .map(|(_, path)| path)
+            .filter(|path| *path != tree_key)
+            .collect();
+        let base = calculate_tokens_in_string(&Self::render_part(st, &[], 1, 1)?)?;
+        if base >= limit {
+            return Err(AppError::General(anyhow!(
+                "the project tree and instruction alone are {} tokens, not under the split limit of {}",
+                base,
+                limit
+            )));
+        }
+
+        // 先按缓存的 token 数贪心分组，再渲染校验；超出的部分把最后一个文件移到下一部分。
+        // 估算偏大，估算超出上限时先渲染确认，正好等于上限的部分不会被拆开
+        let mut groups: Vec<Vec<PathBuf>> = Vec::new();
+        let mut current: Vec<PathBuf> = Vec::new();
+        let mut estimate = base;
+        for path in files {
+            let tokens = st.doc_tokens.get(&path).copied().unwrap_or(0) + PART_DOCUMENT_MARKUP;
+            if !current.is_empty() && estimate + tokens > limit {
+                current.push(path.clone());
+                let index = groups.len() + 1;
+                let actual = calculate_tokens_in_string(&Self::render_part(st, &current, index, index)?)?;
+                current.pop();
+                if actual <= limit {
+                    current.push(path);
+                    estimate = actual;
+                    continue;
+                }
+                groups.push(std::mem::take(&mut current));
+                estimate = base;
+            }
+            current.push(path);
+            estimate += tokens;
+        }
+        if !current.is_empty() || groups.is_empty() {
+            groups.push(current);
+        }
+
+        loop {
+            let count = groups.len();
+            let mut parts = Vec::with_capacity(count);
+            for (i, group) in groups.iter().enumerate() {
+                let text = Self::render_part(st, group, i + 1, count)?;
+                let tokens = calculate_tokens_in_string(&text)?;
+                parts.push(PayloadPart { text, tokens, documents: group.len() });
+            }
+            match parts.iter().position(|p| p.tokens > limit && p.documents > 1) {
+                Some(i) => {
+                    let moved = groups[i].pop().unwrap_or_default();
+                    match groups.get_mut(i + 1) {
+                        Some(next) => next.insert(0, moved),
+                        None => groups.push(vec![moved]),
+                    }
+                }
+                None => {
+                    let oversized = groups
+                        .iter()
+                        .zip(&parts)
+                        .filter(|(_, part)| part.tokens > limit)
+                        .filter_map(|(group, part)| group.first().map(|path| (path.clone(), part.tokens)))
+                        .collect();
+                    return Ok(SplitPayload { parts, oversized });
+                }
+            }
+        }
+    }
+
+    /// 只包含 files (以及项目树) 的 payload，根标签标出这是第 index / count 部分
+    fn render_part(st: &AppState, files: &[PathBuf], index: usize, count: usize) -> Result<String, AppError> {
+        let tree_key = PathBuf::from(PROJECT_TREE_VIRTUAL_PATH);
+        let mut view = st.render_view();
+        // 占位符 ({{file_count}} 等) 仍然描述整个上下文
+        view.file_count = st.file_count;
+        view.token_count = st.token_count;
+        let files: HashSet<&PathBuf> = files.iter().collect();
+        view.partial_docs.retain(|path, _| *path == tree_key || files.contains(path));
+        let payload = Self::assemble_payload(&view)?;
+        Ok(payload.replacen("<documents>", &format!("<documents part=\"{}\" of=\"{}\">", index, count), 1))
+    }
+
+    /// 拼装时替换 prompt 中的占位符: `{{file_count}}`、`{{token_count}}`、`{{tree}}` (项目树文本)、`{{date}}` (UTC)；
+    /// 未知的占位符原样保留，由调用方根据 `unknown` 警告
+    pub fn expand_prompt(st: &AppState) -> Expansion {
+        let tree_key = PathBuf::from(PROJECT_TREE_VIRTUAL_PATH);
+        templating::expand(&st.prompt_text, |name| match name {
+            "file_count" => Some(st.file_count.to_string()),
+            "token_count" => Some(st.token_count.to_string()),
+            "tree" => Some(st.partial_docs.get(&tree_key).map(|s| snippet_content(s)).unwrap_or_default()),
+            "date" => Some(templating::today()),
+            _ => None,
+        })
+    }
+
+    /// payload 的清单: 使用与 assemble_payload 相同的编号和排序规则，但只读缓存的 token 数，不拼装内容
+    pub fn manifest(st: &AppState) -> PayloadManifest {
+        let entry = |id: String, source: PathBuf| ManifestDocument {
+            id,
+            tokens: st.doc_tokens.get(&source).copied().unwrap_or(0),
+            truncated: st.truncated.get(&source).copied(),
+            source,
+        };
+        let documents: Vec<ManifestDocument> = document_ids(&st.partial_docs, &Self::merge_options(st))
+            .into_iter()
+            .map(|(id, source)| entry(id, source))
+            .collect();
+        let mut muted: Vec<ManifestDocument> = st
+            .muted
+            .iter()
+            .filter(|p| st.partial_docs.contains_key(*p))
+            .map(|p| entry(String::new(), p.clone()))
+            .collect();
+        muted.sort_by(|a, b| a.source.cmp(&b.source));
+
+        let instruction = match st.output_format {
+            OutputFormat::Custom { .. } => InstructionPlacement::Template,
+            OutputFormat::Xml if st.prompt_text.is_empty() => InstructionPlacement::None,
+            OutputFormat::Xml => InstructionPlacement::AfterDocument(documents.last().map(|d| d.id.clone())),
+        };
+        // 自定义模板的 token 数是对整个渲染结果 (已含 prompt) 分词得到的
+        let total_tokens = match instruction {
+            InstructionPlacement::AfterDocument(_) => st.token_count + st.prompt_tokens,
+            _ => st.token_count,
+        };
+        let approx_bytes = documents
+            .iter()
+            .filter_map(|d| st.partial_docs.get(&d.source))
+            .map(String::len)
+            .sum::<usize>()
+            + st.prompt_text.len();
+
+        PayloadManifest {
+            format: st.output_format.describe(),
+            documents,
+            muted,
+            grouped_by_language: st.settings.group_by_language,
+            instruction,
+            prompt_tokens: st.prompt_tokens,
+            total_tokens,
+            approx_bytes,
+        }
+    }
+
     /// 全量刷新: 清空除项目树外的 snippet -> 重新生成 -> 更新树 -> 计算 token
     ///  - 在锁外进行文件IO
     pub async fn full_refresh(
@@ -87,44 +876,54 @@ impl SnippetManager {
         all_paths: Vec<PathBuf>,
         ignore_config: &IgnoreConfig,
     ) -> Result<(), AppError> {
-        // 1) 先清空 old snippet (在锁内，快速操作)
-        {
-            let mut st = state.lock().unwrap();
-            st.partial_docs.clear(); // 清空所有真实文件 snippet
-            // 暂时不写回 tree snippet，等文件IO完成后再统一处理
-        }
+        // 1) 记下读取前的状态 (旧 snippet 留到写回时再清空，binary_transition=keep-last 需要它们)
+        let (settings, root, previous) = {
+            let st = state.lock().unwrap();
+            (st.settings.clone(), st.project_root.clone(), Self::previous_states(&st, &all_paths))
+        };
 
         // 2) 读取文件IO (锁外)
         let mut new_snips = Vec::with_capacity(all_paths.len());
         for f in &all_paths { // Borrow all_paths
-            let content = fs::read_to_string(f).await.unwrap_or_default();
-            let snippet = generate_single_file_snippet(f, &content, 0);
-            new_snips.push((f.clone(), snippet)); // Clone path here
-        }
-
-        // 3) 更新项目树 (同样可能耗时，锁外执行，但目前是同步函数)
-        //    为了简化，先在锁外生成树文本和 snippet
-        let current_dir = std::env::current_dir().unwrap_or_else(|_| PathBuf::from("."));
-        let tree_txt = generate_project_tree_string(&current_dir, ignore_config)
-            .unwrap_or_else(|e| {
-                log::error!("生成项目树失败: {:?}", e);
-                "".to_string()
-            });
-        let tree_snippet = generate_single_file_snippet(Path::new(PROJECT_TREE_VIRTUAL_PATH), &tree_txt, 0);
-
-        // 4) 上锁一次性写回所有 snippets (包括新的项目树)
+            new_snips.push(Self::read_file(f, &settings, &root, previous.get(f).copied()).await);
+        }
+
+        // 3) 上锁: 清空旧 snippet (保留 keep-last 的文件)，一次性写回所有 snippets
         {
             let mut st = state.lock().unwrap();
-            for (path, snip) in new_snips {
-                st.partial_docs.insert(path, snip);
+            let kept: Vec<(PathBuf, String, usize, Vec<ImportRef>)> = new_snips
+                .iter()
+                .filter(|(outcome, _)| matches!(outcome, ReadOutcome::KeepLast { .. }))
+                .filter_map(|(outcome, _)| {
+                    let path = outcome.path().to_path_buf();
+                    let snippet = st.partial_docs.remove(&path)?;
+                    let tokens = st.doc_tokens.get(&path).copied().unwrap_or(0);
+                    let imports = st.imports.remove(&path).unwrap_or_default();
+                    Some((path, snippet, tokens, imports))
+                })
+                .collect();
+            st.forget_all_files(); // 清空所有真实文件 snippet
+            for (path, snippet, tokens, imports) in kept {
+                st.partial_docs.insert(path.clone(), snippet);
+                st.doc_tokens.insert(path.clone(), tokens);
+                st.imports.insert(path, imports);
             }
-            // 写入新的或恢复的树 snippet
-            st.partial_docs.insert(PathBuf::from(PROJECT_TREE_VIRTUAL_PATH), tree_snippet);
+            Self::store_outcomes(&mut st, new_snips);
         }
 
-        // 5) rebuild & recalc (锁内)
-        Self::rebuild_and_recalc(state)?;
+        // 4) 重新生成项目树 (锁外，spawn_blocking)
+        Self::update_project_tree_snippet(state.clone(), ignore_config).await?;
+
+        // 5) rebuild & recalc (锁外，spawn_blocking)
+        Self::rebuild_and_recalc(state).await?;
 
         Ok(())
     }
-} 
\ No newline at end of file
+}
+
+/// 在阻塞线程池中执行耗时的同步工作 (遍历目录、拼装 payload、分词)，不占用 REPL 所在的线程
+async fn run_blocking<T: Send + 'static>(task: &str, f: impl FnOnce() -> T + Send + 'static) -> Result<T, AppError> {
+    tokio::task::spawn_blocking(f)
+        .await
+        .map_err(|e| AppError::General(anyhow!("{}任务失败: {:?}", task, e)))
+}
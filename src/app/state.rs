@@ -1,5 +1,18 @@
-use std::collections::{HashMap, HashSet};
-use std::path::PathBuf;
+use std::collections::{BTreeMap, HashMap, HashSet, VecDeque};
+use std::path::{Path, PathBuf};
+
+use crate::app::settings::{BinaryTransition, Settings};
+use crate::app::watch::WatchHandle;
+use crate::core::{file_stamp::FileStamp, imports::ImportRef, ordering::SortStrategy, renames::ContentFingerprint};
+use crate::core::tokenizer::{self, calculate_tokens_in_string, Encoding};
+use crate::core::template::OutputFormat;
+use crate::core::roots::AddRoots;
+use crate::core::paths;
+use crate::core::ignore_rules::IgnoreConfig;
+use crate::core::xml::document_id;
+use crate::app::config::ConfigSource;
+use crate::app::keys::KeyBinding;
+use crate::app::usage::UsageRecorder;
 
 /// 虚拟路径常量，用作项目目录树的唯一 key
 pub const PROJECT_TREE_VIRTUAL_PATH: &str = "__PROJECT_TREE__";
@@ -10,6 +23,42 @@ pub enum ReplMode {
     Prompt,
 }
 
+/// activity 中最多保留的条数
+const ACTIVITY_LIMIT: usize = 50;
+
+/// undo_stack 中最多保留的快照数
+const UNDO_LIMIT: usize = 20;
+
+/// /undo 用的上下文快照: 只记录选择和 prompt，snippet 在恢复时按需重新读取
+#[derive(Debug, Clone)]
+pub struct ContextSnapshot {
+    /// 快照之后执行的命令 (例如 `/add .`)
+    pub label: String,
+    pub selected_paths: HashSet<PathBuf>,
+    pub add_roots: AddRoots,
+    pub muted: HashSet<PathBuf>,
+    pub pinned: HashSet<PathBuf>,
+    pub prompt_text: String,
+}
+
+impl ContextSnapshot {
+    /// 两个快照描述的上下文是否相同 (不比较 label)
+    pub fn same_context(&self, other: &ContextSnapshot) -> bool {
+        self.selected_paths == other.selected_paths
+            && self.muted == other.muted
+            && self.prompt_text == other.prompt_text
+    }
+}
+
+/// 已选文件的 snippet 生成之后磁盘上的变化 (/context 标注，/refresh 处理)
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Staleness {
+    /// mtime 或大小与读取时记录的不同
+    Modified,
+    /// 文件已被删除 (或无法访问)
+    Missing,
+}
+
 /// 用于区分 REPL 编辑器的状态
 #[derive(Debug, Clone, Copy, PartialEq)]
 pub enum ReplEditorMode {
@@ -19,42 +68,422 @@ pub enum ReplEditorMode {
 
 /// 全局共享状态
 pub struct AppState {
-    /// 已选中的真实文件路径
+    /// 已选中的真实文件路径 (规整后的 key，见 `paths::selection_key`)
     pub selected_paths: HashSet<PathBuf>,
 
+    /// 项目根目录 (绝对路径)，`<source>` 和列表中的路径相对于它显示；默认是启动时的当前目录，可用 `--root` 指定
+    pub project_root: PathBuf,
+
+    /// 每次 /add 的原始参数 (目录、glob 等) 及其展开结果，用于 /context 分组显示和 /refresh 重新展开
+    pub add_roots: AddRoots,
+
     /// 已选文件数
     pub file_count: usize,
 
     /// 当前合并后 XML 的 Token 数
     pub token_count: usize,
 
+    /// token_count 中已选文件 snippet 的部分 (不含静音文件和 XML 标记)，rebuild 时按 snippet 累加
+    pub file_tokens: usize,
+
+    /// token_count 中项目树 snippet 的部分
+    pub tree_tokens: usize,
+
     /// 每个「文件」(包括虚拟文件) -> 其 `<document index="x"> ... </document>` 片段
     pub partial_docs: HashMap<PathBuf, String>,
 
+    /// 每个真实文件生成 snippet 时的内容指纹，用于检测改名
+    pub fingerprints: HashMap<PathBuf, ContentFingerprint>,
+
+    /// 每个真实文件生成 snippet 时的 mtime + 大小，用于快速判断哪些 snippet 已过期
+    pub file_stamps: HashMap<PathBuf, FileStamp>,
+
+    /// 每个 snippet 的 token 数 (生成 snippet 时计算，包括项目树)，rebuild 时累加得到总数
+    pub doc_tokens: HashMap<PathBuf, usize>,
+
+    /// 每个真实文件中提取到的 import 引用，用于 /sort dependency
+    pub imports: HashMap<PathBuf, Vec<ImportRef>>,
+
+    /// 添加后变成二进制的已选文件及当时采取的处理方式 (keep-last 或 placeholder)
+    pub binary_files: HashMap<PathBuf, BinaryTransition>,
+
+    /// 超过 max_file_bytes、只读取了开头部分的已选文件及其原始大小 (字节)
+    pub truncated: HashMap<PathBuf, u64>,
+
+    /// 尚未报告给用户的「文件变成二进制」事件，由执行命令的一方取出并打印
+    pub binary_warnings: Vec<(PathBuf, BinaryTransition)>,
+
+    /// 重新读取时发现已被删除、从选择中移除但尚未报告给用户的文件
+    pub pruned_files: Vec<PathBuf>,
+
+    /// 最近一次拼装 payload 时分配的文档 ID (D1, D2, ...)，按 payload 顺序排列
+    pub doc_ids: Vec<(String, PathBuf)>,
+
+    /// 被静音的已选文件: 保持选中，但不进入 payload (/mute)
+    pub muted: HashSet<PathBuf>,
+
+    /// 固定的已选文件: /reset 时保留 (/pin、/unpin；/reset --hard 也清除它们)
+    pub pinned: HashSet<PathBuf>,
+
+    /// add/remove/reset/prompt 修改之前的快照，/undo 从末尾弹出 (最多 UNDO_LIMIT 个)
+    pub undo_stack: VecDeque<ContextSnapshot>,
+
+    /// 最近执行的修改类命令，供 /dashboard 显示 (最多 ACTIVITY_LIMIT 条)
+    pub activity: VecDeque<String>,
+
+    /// payload 的输出格式 (/format)
+    pub output_format: OutputFormat,
+
+    /// 计算 token 使用的编码 (/model)；与 tokenizer 的当前编码保持一致
+    pub token_encoding: Encoding,
+
+    /// /model 选择的模型名 (小写)，有已知上下文窗口时 /copy 据此检查；选择的是编码名时为 None
+    pub model: Option<String>,
+
+    /// 最近一次成功复制的完整 payload (包括 instruction)，/last 不读取文件直接重新复制；/reset 清除
+    pub last_copied: Option<String>,
+    /// 最近一次 /save、/copy、/export 或 /load 时的 prompt；与 prompt_text 不同时 /quit 先确认
+    pub saved_prompt: String,
+
+    /// 最近一次 /copy --split 的上限 (token)，/copy --part 按它重新拆分
+    pub split_limit: Option<usize>,
+
+    /// 当前的文件排序策略 (/sort)
+    pub sort_strategy: SortStrategy,
+
+    /// auto_refresh=interval 时的后台刷新任务
+    pub auto_refresh_task: Option<tokio::task::JoinHandle<()>>,
+
+    /// /watch on 时的文件监视，设为 None 即停止
+    pub watch: Option<WatchHandle>,
+
     /// 最终合并得到的完整XML
     pub cached_xml: String,
 
+    /// 每次 rebuild 开始时递增；rebuild 在锁外进行，写回时据此丢弃已被后一次 rebuild 取代的结果
+    pub rebuild_generation: u64,
+
     /// 当前模式: manual or prompt
     pub mode: ReplMode,
 
     /// prompt模式下收集到的提示词 (可多行或单行)
     pub prompt_text: String,
 
+    /// prompt_text 的 token 数 (在 prompt 变化时更新，供状态栏直接读取)
+    pub prompt_tokens: usize,
+
     /// 编辑器模式：单行或多行
     pub editor_mode: ReplEditorMode,
+
+    /// 通过 /set 调整的运行时选项
+    pub settings: Settings,
+
+    /// 扫描目录和生成项目树时使用的忽略规则 (/ignore、/unignore)；修改后只影响之后的扫描，不移除已选文件
+    pub ignore: IgnoreConfig,
+
+    /// 本次会话中执行过的命令 (输入的原文，不含 prompt 文本)，/history 列出，`!!` / `!<n>` 重新执行；只增不减，编号在会话内不变
+    pub command_log: Vec<String>,
+
+    /// 正在执行的 /source 嵌套层数 (见 script::MAX_SOURCE_DEPTH)
+    pub source_depth: usize,
+
+    /// 用户定义的命令别名 (/alias 或配置文件的 [aliases])，例如 `/asrc` -> `/add src --ext rs`；
+    /// REPL 在解析命令之前展开 (parser::expand_aliases)
+    pub aliases: BTreeMap<String, String>,
+    /// 配置文件的 on_start: 启动时 (进入 REPL 或执行命令行给出的命令之前) 依次执行的命令。
+    /// /reload-config 只更新这个列表，不会再次执行
+    pub on_start: Vec<String>,
+    /// 配置文件 `[[keybindings]]` 中的绑定，覆盖内置绑定 (keys::active_bindings)；/keys 列出生效的绑定
+    pub keybindings: Vec<KeyBinding>,
+
+    /// 配置项 (见 config::CONFIG_KEYS) 的来源；不在表中的为默认值
+    pub config_sources: HashMap<&'static str, ConfigSource>,
+
+    /// 本地使用统计 (/usage)；是否记录由 settings.usage_stats 决定
+    pub usage: UsageRecorder,
+}
+
+impl Default for AppState {
+    fn default() -> Self {
+        Self::new()
+    }
 }
 
 impl AppState {
     pub fn new() -> Self {
         Self {
             selected_paths: HashSet::new(),
+            project_root: std::env::current_dir().map(|d| paths::normalize(&d)).unwrap_or_default(),
+            add_roots: AddRoots::default(),
             file_count: 0,
             token_count: 0,
+            file_tokens: 0,
+            tree_tokens: 0,
             partial_docs: HashMap::new(),
+            fingerprints: HashMap::new(),
+            file_stamps: HashMap::new(),
+            doc_tokens: HashMap::new(),
+            imports: HashMap::new(),
+            binary_files: HashMap::new(),
+            truncated: HashMap::new(),
+            binary_warnings: Vec::new(),
+            pruned_files: Vec::new(),
+            doc_ids: Vec::new(),
+            muted: HashSet::new(),
+            pinned: HashSet::new(),
+            undo_stack: VecDeque::new(),
+            activity: VecDeque::new(),
+            output_format: OutputFormat::default(),
+            token_encoding: tokenizer::active_encoding(),
+            model: None,
+            last_copied: None,
+            saved_prompt: String::new(),
+            split_limit: None,
+            sort_strategy: SortStrategy::default(),
+            auto_refresh_task: None,
+            watch: None,
             cached_xml: String::new(),
+            rebuild_generation: 0,
             mode: ReplMode::Manual,
             prompt_text: String::new(),
+            prompt_tokens: 0,
             editor_mode: ReplEditorMode::SingleLine,
+            settings: Settings::default(),
+            ignore: IgnoreConfig::default(),
+            command_log: Vec::new(),
+            source_depth: 0,
+            aliases: BTreeMap::new(),
+            on_start: Vec::new(),
+            keybindings: Vec::new(),
+            config_sources: HashMap::new(),
+            usage: UsageRecorder::new(),
+        }
+    }
+
+    /// 已选文件的 snippet 是否已经过期 (按 file_stamps 中记录的 mtime + 大小判断)；未过期返回 None
+    pub fn staleness(&self, path: &Path) -> Option<Staleness> {
+        match FileStamp::of(path) {
+            None => Some(Staleness::Missing),
+            Some(stamp) if self.file_stamps.get(path) != Some(&stamp) => Some(Staleness::Modified),
+            Some(_) => None,
+        }
+    }
+
+    /// 丢弃某个文件的 snippet 及其所有附属记录 (指纹、mtime、token 数、import)
+    pub fn forget_file(&mut self, path: &PathBuf) {
+        self.partial_docs.remove(path);
+        self.fingerprints.remove(path);
+        self.file_stamps.remove(path);
+        self.doc_tokens.remove(path);
+        self.imports.remove(path);
+        self.binary_files.remove(path);
+        self.truncated.remove(path);
+        self.muted.remove(path);
+        self.pinned.remove(path);
+    }
+
+    /// 清空所有真实文件的 snippet 及附属记录 (项目树 snippet 一并清除)，/context 的 token 分项归零
+    pub fn forget_all_files(&mut self) {
+        self.partial_docs.clear();
+        self.file_tokens = 0;
+        self.tree_tokens = 0;
+        self.fingerprints.clear();
+        self.file_stamps.clear();
+        self.doc_tokens.clear();
+        self.imports.clear();
+        self.binary_files.clear();
+        self.truncated.clear();
+    }
+
+    /// 把用户输入的 `D14` / `d14` / `14` 解析为 (index, 路径)
+    pub fn resolve_doc_id(&self, raw: &str) -> Option<(usize, &PathBuf)> {
+        let raw = raw.trim();
+        let index: usize = raw.strip_prefix(['D', 'd']).unwrap_or(raw).parse().ok()?;
+        let id = document_id(index);
+        self.doc_ids.iter().find(|(i, _)| *i == id).map(|(_, p)| (index, p))
+    }
+
+    /// 合并指向同一文件的已选路径 (例如旧会话中的 `./src/a.rs` 与 `src/a.rs`，或指向同一目标的符号链接)。
+    /// 每组保留与 `paths::file_key` 相同的那一项 (没有则保留字典序最小的)，其余从选择中移除；
+    /// 返回 (被移除的路径, 保留的路径)。调用方负责重建 snippet。
+    pub fn merge_duplicate_selections(&mut self) -> Vec<(PathBuf, PathBuf)> {
+        let cwd = std::env::current_dir().unwrap_or_else(|_| PathBuf::from("."));
+        let mut groups: HashMap<PathBuf, Vec<PathBuf>> = HashMap::new();
+        for p in &self.selected_paths {
+            groups.entry(paths::file_key(p, &cwd)).or_default().push(p.clone());
+        }
+
+        let mut merged = Vec::new();
+        for (key, mut group) in groups {
+            if group.len() < 2 {
+                continue;
+            }
+            group.sort();
+            let kept = if group.contains(&key) { key } else { group[0].clone() };
+            for dup in group.into_iter().filter(|p| *p != kept) {
+                if self.muted.contains(&dup) {
+                    self.muted.insert(kept.clone());
+                }
+                if self.pinned.contains(&dup) {
+                    self.pinned.insert(kept.clone());
+                }
+                self.selected_paths.remove(&dup);
+                self.forget_file(&dup);
+                self.add_roots.rename(&dup, &kept);
+                merged.push((dup, kept.clone()));
+            }
+        }
+        self.file_count = self.selected_paths.len();
+        merged.sort();
+        merged
+    }
+
+    /// 只含拼装 payload 所需字段的副本，供 rebuild 在锁外使用
+    pub fn render_view(&self) -> AppState {
+        AppState {
+            project_root: self.project_root.clone(),
+            partial_docs: self.partial_docs.clone(),
+            file_stamps: self.file_stamps.clone(),
+            doc_tokens: self.doc_tokens.clone(),
+            imports: self.imports.clone(),
+            muted: self.muted.clone(),
+            output_format: self.output_format.clone(),
+            sort_strategy: self.sort_strategy,
+            prompt_text: self.prompt_text.clone(),
+            settings: self.settings.clone(),
+            ..AppState::new()
+        }
+    }
+
+    /// 位于目录 dir 之下的已选文件，按路径排序。只比较路径，不访问文件系统 (目录已被删除时也能使用)；
+    /// dir 为相对路径时相对于当前目录，没有匹配时再相对于 project_root
+    ///
+    /// ```
+    /// use std::path::{Path, PathBuf};
+    /// use rustprompt::app::state::AppState;
+    ///
+    /// let mut st = AppState::new();
+    /// st.project_root = PathBuf::from("/home/me/proj");
+    /// for f in ["src/main.rs", "src/repl/engine.rs", "src/repl/prompt.rs", "srcs/x.rs"] {
+    ///     st.selected_paths.insert(st.project_root.join(f));
+    /// }
+    /// let under = st.selected_under(Path::new("src/repl/"));
+    /// assert_eq!(under, [st.project_root.join("src/repl/engine.rs"), st.project_root.join("src/repl/prompt.rs")]);
+    /// assert_eq!(st.selected_under(Path::new("/home/me/proj/src")).len(), 3);
+    /// assert!(st.selected_under(Path::new("src/main.rs")).is_empty());
+    /// ```
+    pub fn selected_under(&self, dir: &Path) -> Vec<PathBuf> {
+        let cwd = std::env::current_dir().unwrap_or_else(|_| PathBuf::from("."));
+        let under = |base: &Path| -> Vec<PathBuf> {
+            let dir = paths::normalize(&base.join(dir));
+            let mut found: Vec<PathBuf> = self
+                .selected_paths
+                .iter()
+                .filter(|p| {
+                    let absolute = paths::normalize(&cwd.join(p));
+                    absolute != dir && absolute.starts_with(&dir)
+                })
+                .cloned()
+                .collect();
+            found.sort();
+            found
+        };
+        let found = under(&cwd);
+        if found.is_empty() && dir.is_relative() {
+            under(&self.project_root)
+        } else {
+            found
         }
     }
-} 
\ No newline at end of file
+
+    /// prompt 不为空，且在最近一次保存或复制之后修改过
+    ///
+    /// ```
+    /// use rustprompt::app::state::AppState;
+    ///
+    /// let mut st = AppState::new();
+    /// assert!(!st.prompt_unsaved());
+    /// st.prompt_text = "Explain this".to_string();
+    /// assert!(st.prompt_unsaved());
+    /// st.saved_prompt = st.prompt_text.clone();
+    /// assert!(!st.prompt_unsaved());
+    /// ```
+    pub fn prompt_unsaved(&self) -> bool {
+        !self.prompt_text.is_empty() && self.prompt_text != self.saved_prompt
+    }
+
+    /// 已选文件对外展示的路径 (相对于 project_root)
+    pub fn display_path(&self, path: &Path) -> PathBuf {
+        paths::display_path(path, &self.project_root)
+    }
+
+    /// 当前上下文的快照
+    pub fn snapshot(&self, label: String) -> ContextSnapshot {
+        ContextSnapshot {
+            label,
+            selected_paths: self.selected_paths.clone(),
+            add_roots: self.add_roots.clone(),
+            muted: self.muted.clone(),
+            pinned: self.pinned.clone(),
+            prompt_text: self.prompt_text.clone(),
+        }
+    }
+
+    /// 记录一个可以 /undo 的快照，超出上限时丢弃最旧的
+    pub fn push_undo(&mut self, snapshot: ContextSnapshot) {
+        if self.undo_stack.len() >= UNDO_LIMIT {
+            self.undo_stack.pop_front();
+        }
+        self.undo_stack.push_back(snapshot);
+    }
+
+    /// 记录一条活动日志，超出上限时丢弃最旧的
+    pub fn log_activity(&mut self, entry: String) {
+        if self.activity.len() >= ACTIVITY_LIMIT {
+            self.activity.pop_front();
+        }
+        self.activity.push_back(entry);
+    }
+
+    /// prompt_text 变化后重新计算其 token 数
+    pub fn recalc_prompt_tokens(&mut self) {
+        self.prompt_tokens = calculate_tokens_in_string(&self.prompt_text).unwrap_or(0);
+    }
+
+    /// 在 prompt 末尾追加一行，只对新增部分 (连同换行) 分词并累加到 prompt_tokens。
+    /// 拼接处的分词可能与整体重新计算相差一两个 token，/model 切换编码等场景仍由 recalc_prompt_tokens 校准
+    pub fn append_prompt_line(&mut self, line: &str) {
+        let added = if self.prompt_text.is_empty() {
+            line.to_string()
+        } else {
+            format!("\n{}", line)
+        };
+        self.prompt_text.push_str(&added);
+        self.prompt_tokens += calculate_tokens_in_string(&added).unwrap_or(0);
+    }
+
+    /// 按 snippet 累加的 token 数: (已选文件, 项目树)；静音文件不进入 payload，不计入
+    pub fn snippet_token_split(&self) -> (usize, usize) {
+        let mut files = 0;
+        let mut tree = 0;
+        for (path, snippet) in &self.partial_docs {
+            let tokens = self
+                .doc_tokens
+                .get(path)
+                .copied()
+                .unwrap_or_else(|| calculate_tokens_in_string(snippet).unwrap_or(0));
+            if path.as_os_str() == PROJECT_TREE_VIRTUAL_PATH {
+                tree += tokens;
+            } else if !self.muted.contains(path) {
+                files += tokens;
+            }
+        }
+        (files, tree)
+    }
+
+    /// 上下文占用的内存 (字节): 所有 snippet + 缓存的合并 XML
+    pub fn memory_usage(&self) -> usize {
+        let snippets: usize = self.partial_docs.values().map(|s| s.len()).sum();
+        snippets + self.cached_xml.len()
+    }
+}
@@ -0,0 +1,106 @@
+// src/app/style.rs
+
+use std::io::{self, IsTerminal};
+use std::sync::atomic::{AtomicBool, Ordering};
+
+/// 结束样式 (SGR reset)
+const RESET: &str = "\x1b[0m";
+
+/// 终端输出使用的样式
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Style {
+    /// `(Note)` 一类的提示
+    Dim,
+    /// 警告、接近预算
+    Yellow,
+    /// 错误、超出预算
+    Red,
+    /// token 数
+    Bold,
+    /// 文件路径
+    Cyan,
+    /// 匹配到的文本 (/grep)
+    Highlight,
+}
+
+impl Style {
+    fn code(self) -> &'static str {
+        match self {
+            Self::Dim => "2",
+            Self::Yellow => "33",
+            Self::Red => "31",
+            Self::Bold => "1",
+            Self::Cyan => "36",
+            Self::Highlight => "1;31",
+        }
+    }
+}
+
+/// 启动时决定: stdout 是终端、没有设置 NO_COLOR、也没有 --no-color
+static TERMINAL: AtomicBool = AtomicBool::new(false);
+/// /set color
+static WANTED: AtomicBool = AtomicBool::new(true);
+
+/// 启动时调用一次。没有调用时 (测试、doc 示例) 不输出颜色
+pub fn init(no_color: bool) {
+    // https://no-color.org: 设置为非空值时关闭颜色
+    let no_color_env = std::env::var_os("NO_COLOR").is_some_and(|v| !v.is_empty());
+    TERMINAL.store(!no_color && !no_color_env && io::stdout().is_terminal(), Ordering::Relaxed);
+}
+
+/// /set color on|off；终端不支持颜色时打开也没有效果
+pub fn set_enabled(on: bool) {
+    WANTED.store(on, Ordering::Relaxed);
+}
+
+pub fn enabled() -> bool {
+    TERMINAL.load(Ordering::Relaxed) && WANTED.load(Ordering::Relaxed)
+}
+
+/// 启用颜色时给 text 加上样式，否则原样返回
+pub fn paint(style: Style, text: &str) -> String {
+    if enabled() {
+        apply(style, text)
+    } else {
+        text.to_string()
+    }
+}
+
+/// 总是给 text 加上样式。text 中已有的样式结束后恢复外层样式，所以可以嵌套
+/// (例如整行变暗的提示中加粗的 token 数)
+///
+/// ```
+/// use rustprompt::app::style::{apply, strip, Style};
+///
+/// let inner = apply(Style::Bold, "1,270");
+/// assert_eq!(inner, "\x1b[1m1,270\x1b[0m");
+/// let line = apply(Style::Dim, &format!("(Note) total {}", inner));
+/// assert_eq!(line, "\x1b[2m(Note) total \x1b[1m1,270\x1b[0m\x1b[2m\x1b[0m");
+/// assert_eq!(strip(&line), "(Note) total 1,270");
+/// ```
+pub fn apply(style: Style, text: &str) -> String {
+    let start = format!("\x1b[{}m", style.code());
+    let body = text.replace(RESET, &format!("{}{}", RESET, start));
+    format!("{}{}{}", start, body, RESET)
+}
+
+/// 去掉样式 (SGR 序列)，用于写进活动日志等不直接显示在终端上的地方
+pub fn strip(text: &str) -> String {
+    let mut out = String::with_capacity(text.len());
+    let mut chars = text.chars();
+    while let Some(c) = chars.next() {
+        if c != '\x1b' {
+            out.push(c);
+            continue;
+        }
+        // ESC [ 参数 m
+        let rest = chars.as_str();
+        match rest.strip_prefix('[').and_then(|r| r.find('m').map(|end| (r, end))) {
+            Some((r, end)) if r[..end].chars().all(|c| c.is_ascii_digit() || c == ';') => {
+                chars = r[end + 1..].chars();
+            }
+            _ => out.push(c),
+        }
+    }
+    out
+}
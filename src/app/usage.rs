@@ -0,0 +1,307 @@
+// src/app/usage.rs
+
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+use std::time::{Instant, SystemTime, UNIX_EPOCH};
+
+use serde::{Deserialize, Serialize};
+
+use crate::core::dirs;
+
+/// payload 大小 (token 数) 直方图各桶的上界 (不含)；最后一个桶收纳不小于最后一个上界的 payload
+pub const SIZE_BUCKETS: [usize; 5] = [1_000, 8_000, 32_000, 128_000, 512_000];
+
+/// 攒够这么多条记录后写一次磁盘 (退出时总会写)
+const FLUSH_EVERY: usize = 20;
+
+/// 本地使用统计，只写入用户数据目录下的 `usage.json`，不会发送到任何地方
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct UsageStats {
+    /// 第一条记录的时间 (Unix 秒)；0 表示还没有记录
+    #[serde(default)]
+    pub since: u64,
+    /// 每个命令的调用次数
+    #[serde(default)]
+    pub commands: BTreeMap<String, u64>,
+    /// /copy、/export 的 payload 大小分布，下标对应 SIZE_BUCKETS 划分的桶
+    #[serde(default)]
+    pub payload_sizes: Vec<u64>,
+    #[serde(default)]
+    pub payload_tokens_total: u64,
+    #[serde(default)]
+    pub payload_tokens_max: u64,
+    #[serde(default)]
+    pub sessions: u64,
+    #[serde(default)]
+    pub session_secs: u64,
+}
+
+impl UsageStats {
+    pub fn is_empty(&self) -> bool {
+        *self == Self::default()
+    }
+
+    pub fn record_command(&mut self, name: &str) {
+        self.touch();
+        *self.commands.entry(name.to_string()).or_insert(0) += 1;
+    }
+
+    pub fn record_payload(&mut self, tokens: usize) {
+        self.touch();
+        let bucket = Self::bucket(tokens);
+        if self.payload_sizes.len() <= bucket {
+            self.payload_sizes.resize(SIZE_BUCKETS.len() + 1, 0);
+        }
+        self.payload_sizes[bucket] += 1;
+        self.payload_tokens_total += tokens as u64;
+        self.payload_tokens_max = self.payload_tokens_max.max(tokens as u64);
+    }
+
+    pub fn record_session(&mut self, secs: u64) {
+        self.touch();
+        self.sessions += 1;
+        self.session_secs += secs;
+    }
+
+    /// 并入另一份统计 (磁盘上的 + 本次会话尚未写入的)
+    pub fn merge(&mut self, other: &UsageStats) {
+        if other.since != 0 && (self.since == 0 || other.since < self.since) {
+            self.since = other.since;
+        }
+        for (name, count) in &other.commands {
+            *self.commands.entry(name.clone()).or_insert(0) += count;
+        }
+        if self.payload_sizes.len() < other.payload_sizes.len() {
+            self.payload_sizes.resize(other.payload_sizes.len(), 0);
+        }
+        for (mine, theirs) in self.payload_sizes.iter_mut().zip(&other.payload_sizes) {
+            *mine += theirs;
+        }
+        self.payload_tokens_total += other.payload_tokens_total;
+        self.payload_tokens_max = self.payload_tokens_max.max(other.payload_tokens_max);
+        self.sessions += other.sessions;
+        self.session_secs += other.session_secs;
+    }
+
+    /// 记录过的 payload 数
+    pub fn payload_count(&self) -> u64 {
+        self.payload_sizes.iter().sum()
+    }
+
+    /// token 数落在哪个桶
+    pub fn bucket(tokens: usize) -> usize {
+        SIZE_BUCKETS.iter().position(|upper| tokens < *upper).unwrap_or(SIZE_BUCKETS.len())
+    }
+
+    /// 桶的说明，例如 `1k-8k`、`>=512k`
+    pub fn bucket_label(bucket: usize) -> String {
+        let k = |n: usize| format!("{}k", n / 1000);
+        match bucket {
+            0 => format!("<{}", k(SIZE_BUCKETS[0])),
+            b if b >= SIZE_BUCKETS.len() => format!(">={}", k(SIZE_BUCKETS[SIZE_BUCKETS.len() - 1])),
+            b => format!("{}-{}", k(SIZE_BUCKETS[b - 1]), k(SIZE_BUCKETS[b])),
+        }
+    }
+
+    fn touch(&mut self) {
+        if self.since == 0 {
+            self.since = now_secs();
+        }
+    }
+}
+
+/// 使用统计的记录器: 在内存中累积，每 FLUSH_EVERY 条或退出时与磁盘上的统计合并后整体写回。
+/// 是否记录 (`/set usage_stats`) 由调用方判断。
+pub struct UsageRecorder {
+    path: Option<PathBuf>,
+    pending: UsageStats,
+    pending_events: usize,
+    started: Instant,
+}
+
+impl Default for UsageRecorder {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl UsageRecorder {
+    pub fn new() -> Self {
+        Self {
+            path: dirs::data_dir().map(|dir| dir.join("usage.json")),
+            pending: UsageStats::default(),
+            pending_events: 0,
+            started: Instant::now(),
+        }
+    }
+
+    pub fn record_command(&mut self, name: &str) {
+        self.pending.record_command(name);
+        self.pending_events += 1;
+    }
+
+    pub fn record_payload(&mut self, tokens: usize) {
+        self.pending.record_payload(tokens);
+        self.pending_events += 1;
+    }
+
+    /// 攒够 FLUSH_EVERY 条时写盘
+    pub fn flush_if_due(&mut self) {
+        if self.pending_events >= FLUSH_EVERY {
+            self.flush();
+        }
+    }
+
+    /// 退出时调用: 记录本次会话的时长 (recording 为 false 时只写入已有的记录)
+    pub fn finish(&mut self, recording: bool) {
+        if recording {
+            self.pending.record_session(self.started.elapsed().as_secs());
+        }
+        self.flush();
+    }
+
+    /// 与磁盘上的统计合并后写回；失败只记日志，不影响正常使用
+    pub fn flush(&mut self) {
+        if self.pending.is_empty() {
+            return;
+        }
+        let Some(path) = &self.path else { return };
+        let mut stats = read_stats(path);
+        stats.merge(&self.pending);
+        match write_stats(path, &stats) {
+            Ok(()) => {
+                self.pending = UsageStats::default();
+                self.pending_events = 0;
+            }
+            Err(e) => log::warn!("写入使用统计失败: {:?}", e),
+        }
+    }
+
+    /// 磁盘上的统计加上本次会话尚未写入的部分
+    pub fn report(&self) -> UsageStats {
+        let mut stats = self.path.as_deref().map(read_stats).unwrap_or_default();
+        stats.merge(&self.pending);
+        stats
+    }
+
+    /// 清空所有统计 (包括磁盘上的文件)
+    pub fn reset(&mut self) -> std::io::Result<()> {
+        self.pending = UsageStats::default();
+        self.pending_events = 0;
+        match &self.path {
+            Some(path) => match std::fs::remove_file(path) {
+                Err(e) if e.kind() != std::io::ErrorKind::NotFound => Err(e),
+                _ => Ok(()),
+            },
+            None => Ok(()),
+        }
+    }
+}
+
+fn now_secs() -> u64 {
+    SystemTime::now().duration_since(UNIX_EPOCH).map(|d| d.as_secs()).unwrap_or(0)
+}
+
+fn read_stats(path: &Path) -> UsageStats {
+    std::fs::read_to_string(path)
+        .ok()
+        .and_then(|text| serde_json::from_str(&text).ok())
+        .unwrap_or_default()
+}
+
+/// 先写临时文件再改名，写到一半退出也不会留下损坏的统计文件
+fn write_stats(path: &Path, stats: &UsageStats) -> std::io::Result<()> {
+    if let Some(dir) = path.parent() {
+        std::fs::create_dir_all(dir)?;
+    }
+    let json = serde_json::to_string_pretty(stats).map_err(std::io::Error::other)?;
+    let tmp = path.with_extension("json.tmp");
+    std::fs::write(&tmp, json + "\n")?;
+    std::fs::rename(&tmp, path)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn buckets_split_at_their_upper_bound() {
+        assert_eq!(UsageStats::bucket(0), 0);
+        assert_eq!(UsageStats::bucket(999), 0);
+        assert_eq!(UsageStats::bucket(1_000), 1);
+        assert_eq!(UsageStats::bucket(7_999), 1);
+        assert_eq!(UsageStats::bucket(511_999), 4);
+        assert_eq!(UsageStats::bucket(512_000), 5);
+        assert_eq!(UsageStats::bucket(10_000_000), 5);
+        let labels: Vec<String> = (0..=SIZE_BUCKETS.len()).map(UsageStats::bucket_label).collect();
+        assert_eq!(labels, ["<1k", "1k-8k", "8k-32k", "32k-128k", "128k-512k", ">=512k"]);
+    }
+
+    #[test]
+    fn payloads_fill_the_histogram_and_totals() {
+        let mut stats = UsageStats::default();
+        for tokens in [999, 1_000, 512_000, 600_000] {
+            stats.record_payload(tokens);
+        }
+        assert_eq!(stats.payload_sizes, [1, 1, 0, 0, 0, 2]);
+        assert_eq!(stats.payload_count(), 4);
+        assert_eq!(stats.payload_tokens_total, 999 + 1_000 + 512_000 + 600_000);
+        assert_eq!(stats.payload_tokens_max, 600_000);
+        assert_ne!(stats.since, 0);
+    }
+
+    #[test]
+    fn merge_sums_two_stats() {
+        let mut a = UsageStats::default();
+        a.record_command("/add");
+        a.record_command("/add");
+        a.record_payload(500);
+        a.record_session(30);
+        a.since = 200;
+        // 旧版本写入的统计可能没有直方图
+        let mut b = UsageStats { since: 100, ..Default::default() };
+        b.record_command("/add");
+        b.record_command("/copy");
+        b.record_payload(2_000);
+        b.record_payload(700_000);
+        b.record_session(90);
+
+        a.merge(&b);
+        assert_eq!(a.since, 100);
+        assert_eq!(a.commands, BTreeMap::from([("/add".to_string(), 3), ("/copy".to_string(), 1)]));
+        assert_eq!(a.payload_sizes, [1, 1, 0, 0, 0, 1]);
+        assert_eq!(a.payload_tokens_total, 702_500);
+        assert_eq!(a.payload_tokens_max, 700_000);
+        assert_eq!((a.sessions, a.session_secs), (2, 120));
+
+        let mut empty = UsageStats::default();
+        empty.merge(&a);
+        assert_eq!(empty, a);
+    }
+
+    #[test]
+    fn flush_merges_with_the_file_and_reset_removes_it() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("usage.json");
+        let recorder = || UsageRecorder { path: Some(path.clone()), ..UsageRecorder::new() };
+
+        let mut first = recorder();
+        first.record_command("/add");
+        first.flush();
+        let mut second = recorder();
+        second.record_command("/add");
+        second.record_payload(1_000);
+        assert_eq!(second.report().commands["/add"], 2);
+        second.finish(false);
+
+        let stats = read_stats(&path);
+        assert_eq!(stats.commands["/add"], 2);
+        assert_eq!(stats.payload_sizes[1], 1);
+        assert_eq!(stats.sessions, 0);
+        assert!(!path.with_extension("json.tmp").exists());
+
+        second.reset().unwrap();
+        assert!(!path.exists());
+        assert!(second.report().is_empty());
+    }
+}
@@ -0,0 +1,172 @@
+// src/app/watch.rs
+
+use std::collections::HashSet;
+use std::path::PathBuf;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use notify::{EventKind, RecommendedWatcher, RecursiveMode, Watcher};
+use tokio::sync::mpsc;
+
+use crate::app::{output::binary_warning, snippet_manager::SnippetManager, state::AppState};
+use crate::core::{file_stamp::FileStamp, paths};
+use crate::error::AppError;
+
+/// 一批文件事件结束后再等这么久没有新事件才处理 (保存文件、git checkout 往往连续产生很多事件)
+const DEBOUNCE: Duration = Duration::from_millis(300);
+
+/// /watch on 启动的文件监视: 持有 notify 的 watcher 和处理事件的后台任务，drop 时两者一起停止
+pub struct WatchHandle {
+    _watcher: RecommendedWatcher,
+    task: tokio::task::JoinHandle<()>,
+}
+
+impl Drop for WatchHandle {
+    fn drop(&mut self) {
+        self.task.abort();
+    }
+}
+
+/// 一批事件中需要处理的内容
+#[derive(Debug, Default)]
+struct Batch {
+    /// 内容可能变化的路径
+    paths: HashSet<PathBuf>,
+    /// 有文件被创建、删除或改名，项目树需要重新生成
+    structure: bool,
+}
+
+/// 开始监视: 项目根目录递归监视 (已选文件和项目树)，根目录之外的已选文件单独监视。
+/// 已选文件变化时重新生成它的 snippet 并重新计算 token，通过 notify 回调报告 (REPL 中使用 ExternalPrinter)
+pub fn spawn<F>(state: Arc<Mutex<AppState>>, notify: F) -> Result<WatchHandle, AppError>
+where
+    F: Fn(String) + Send + 'static,
+{
+    let (tx, mut rx) = mpsc::unbounded_channel();
+    let mut watcher = notify::recommended_watcher(move |event: notify::Result<notify::Event>| {
+        match event {
+            Ok(event) => {
+                let _ = tx.send(event);
+            }
+            Err(e) => log::warn!("watch error: {}", e),
+        }
+    })
+    .map_err(watch_error)?;
+
+    let (root, outside) = {
+        let st = state.lock().unwrap();
+        let outside: Vec<PathBuf> = st
+            .selected_paths
+            .iter()
+            .filter(|p| paths::display_path(p, &st.project_root).is_absolute())
+            .cloned()
+            .collect();
+        (st.project_root.clone(), outside)
+    };
+    watcher.watch(&root, RecursiveMode::Recursive).map_err(watch_error)?;
+    for path in &outside {
+        if let Err(e) = watcher.watch(path, RecursiveMode::NonRecursive) {
+            log::warn!("cannot watch {}: {}", path.display(), e);
+        }
+    }
+
+    let task = tokio::spawn(async move {
+        while let Some(first) = rx.recv().await {
+            let mut batch = Batch::default();
+            batch.push(first);
+            loop {
+                match tokio::time::timeout(DEBOUNCE, rx.recv()).await {
+                    Ok(Some(event)) => batch.push(event),
+                    Ok(None) => return,
+                    Err(_) => break,
+                }
+            }
+            for message in apply(&state, batch).await {
+                notify(message);
+            }
+        }
+    });
+
+    Ok(WatchHandle { _watcher: watcher, task })
+}
+
+impl Batch {
+    fn push(&mut self, event: notify::Event) {
+        match event.kind {
+            EventKind::Access(_) => return,
+            EventKind::Create(_) | EventKind::Remove(_) | EventKind::Modify(notify::event::ModifyKind::Name(_)) => {
+                self.structure = true;
+            }
+            _ => {}
+        }
+        self.paths.extend(event.paths);
+    }
+}
+
+/// 处理一批事件，返回要打印的提示
+async fn apply(state: &Arc<Mutex<AppState>>, batch: Batch) -> Vec<String> {
+    let cwd = std::env::current_dir().unwrap_or_else(|_| PathBuf::from("."));
+    // 只重新读取确实变化了的已选文件 (/copy 等已经刷新过的文件 mtime/大小与记录一致)
+    let (changed, tokens_before, ignore, structure) = {
+        let st = state.lock().unwrap();
+        let mut changed: Vec<PathBuf> = batch
+            .paths
+            .iter()
+            .map(|p| paths::file_key(p, &cwd))
+            .filter(|key| st.selected_paths.contains(key))
+            .filter(|key| FileStamp::of(key) != st.file_stamps.get(key).copied())
+            .collect();
+        changed.sort();
+        changed.dedup();
+        let tokens_before: Vec<usize> = changed.iter().map(|p| st.doc_tokens.get(p).copied().unwrap_or(0)).collect();
+        // 被忽略的路径 (例如 node_modules 或 /ignore 的模式) 不影响项目树
+        let structure = batch.structure && batch.paths.iter().any(|p| !st.ignore.should_ignore_path(p));
+        (changed, tokens_before, st.ignore.clone(), structure)
+    };
+    if changed.is_empty() && !structure {
+        return Vec::new();
+    }
+
+    let total_before = state.lock().unwrap().token_count;
+    let result = async {
+        if !changed.is_empty() {
+            SnippetManager::add_files_snippet(state.clone(), changed.clone()).await?;
+        }
+        SnippetManager::update_project_tree_snippet(state.clone(), &ignore).await?;
+        SnippetManager::rebuild_and_recalc(state.clone()).await
+    }
+    .await;
+    if let Err(e) = result {
+        log::warn!("watch refresh failed: {:?}", e);
+        return Vec::new();
+    }
+
+    let mut st = state.lock().unwrap();
+    let mut messages: Vec<String> = std::mem::take(&mut st.binary_warnings)
+        .into_iter()
+        .map(|(path, kind)| binary_warning(&path, kind))
+        .collect();
+    // 已删除的文件已经移出上下文，下面逐个报告
+    st.pruned_files.clear();
+    for (path, before) in changed.iter().zip(tokens_before) {
+        let after = st.doc_tokens.get(path).copied().unwrap_or(0);
+        let verb = if st.selected_paths.contains(path) { "updated" } else { "deleted, removed from the context" };
+        messages.push(format!(
+            "(watch) {} {}, {:+} tokens",
+            st.display_path(path).display(),
+            verb,
+            after as i64 - before as i64
+        ));
+    }
+    if changed.is_empty() && st.token_count != total_before {
+        messages.push(format!(
+            "(watch) project tree updated, {:+} tokens",
+            st.token_count as i64 - total_before as i64
+        ));
+    }
+    messages
+}
+
+fn watch_error(e: notify::Error) -> AppError {
+    AppError::General(anyhow::anyhow!("cannot start the file watcher: {}", e))
+}
@@ -1,15 +1,96 @@
 use std::path::PathBuf;
 
+use crate::core::ignore_rules::IgnoreConfig;
+
+/// /add 的单次选项: 只对这一次扫描修改忽略规则，不修改 /ignore 和 /only 的全局设置
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct AddOptions {
+    /// --hidden: 包含隐藏文件
+    pub hidden: bool,
+    /// --no-ignore (或 --force / -f): 不应用任何忽略规则 (隐藏文件、.gitignore、node_modules、/ignore 的模式)
+    pub no_ignore: bool,
+    /// --ext rs,toml: 只加入这些扩展名的文件 (已规整，见 ignore_rules::parse_extensions)，代替 /only 的过滤
+    pub ext: Vec<String>,
+}
+
+impl AddOptions {
+    /// 这一次扫描使用的忽略规则: 全局规则按选项修改后的副本
+    pub fn apply(&self, config: &IgnoreConfig) -> IgnoreConfig {
+        let mut config = if self.no_ignore { config.permissive() } else { config.clone() };
+        if self.hidden {
+            config.ignore_hidden = false;
+        }
+        if !self.ext.is_empty() {
+            config.extensions = self.ext.clone();
+        }
+        config
+    }
+
+    /// 还原为 /add 的参数，例如 `--hidden `；没有选项时为空串
+    pub fn flags(&self) -> String {
+        let mut flags = String::new();
+        if self.hidden {
+            flags.push_str("--hidden ");
+        }
+        if self.no_ignore {
+            flags.push_str("--no-ignore ");
+        }
+        if !self.ext.is_empty() {
+            flags.push_str(&format!("--ext {} ", self.ext.join(",")));
+        }
+        flags
+    }
+}
+
+/// /copy 的选项
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct CopyOptions {
+    /// --refresh: 重新读取所有已选文件，而不是只读取发生变化的文件
+    pub refresh: bool,
+    /// --force (或 -f): 超过模型的上下文窗口时仍然复制
+    pub force: bool,
+    /// --split <n>: 拆成每部分不超过 n 个 token 的多个部分，复制第 1 部分 (未校验的原始参数)
+    pub split: Option<String>,
+    /// --part <i>: 按上一次 --split 的上限重新拆分，复制第 i 部分 (未校验的原始参数)
+    pub part: Option<String>,
+    /// --backend <name>: 这一次使用指定的复制方式，代替 /set clipboard_backend (未校验的原始参数)
+    pub backend: Option<String>,
+}
+
+impl CopyOptions {
+    /// 还原为 /copy 之后的参数，例如 ` --split 100k`；没有选项时为空串
+    pub fn flags(&self) -> String {
+        let mut flags = String::new();
+        if self.refresh {
+            flags.push_str(" --refresh");
+        }
+        if self.force {
+            flags.push_str(" --force");
+        }
+        if let Some(limit) = &self.split {
+            flags.push_str(&format!(" --split {}", limit));
+        }
+        if let Some(part) = &self.part {
+            flags.push_str(&format!(" --part {}", part));
+        }
+        if let Some(backend) = &self.backend {
+            flags.push_str(&format!(" --backend {}", backend));
+        }
+        flags
+    }
+}
+
 /// 我们支持的命令列表
 #[derive(Debug, Clone)] // 确保 Clone trait 已添加
 pub enum Command {
-    Add(PathBuf),
-    Remove(PathBuf),
+    Add(Vec<PathBuf>, AddOptions),
+    Remove(Vec<PathBuf>),
     ShowContext,
-    Copy,
-    Reset,
-    Help,
-    Quit,
+    ContextJson(bool), // /context --json [--compact]: 以 JSON 输出上下文状态 (见 report::ContextReport)，true 时输出单行
+    Copy(CopyOptions), // /copy [--refresh] [--force] [--split <n> | --part <i>]
+    Reset(bool, bool), // /reset [--hard] [--force]: 第一个为 true 时连固定 (/pin) 的文件一起清除，第二个为 true 时不确认
+    Help(Option<String>), // /help [command]: 有参数时显示这个命令的详细说明
+    Quit(bool), // /quit [--force]: true 时不确认 (prompt 没有保存时会询问)
     Unknown(String),
 
     // 新增: 模式切换 /mode
@@ -23,4 +104,130 @@ pub enum Command {
 
     // 新增: /resetprompt 命令
     ResetPrompt,
+
+    // /set [key] [value]: 查看或修改运行时选项
+    Set(Option<String>, Option<String>),
+
+    // /refresh: 重新读取所有已选文件 (并跟随被改名的文件)
+    Refresh,
+
+    // /stats: 查看上下文统计 (token、内存占用等)
+    Stats,
+
+    // /validate: 校验当前 payload 是否为良构的 XML
+    Validate,
+
+    // /sort [strategy]: 查看或切换文件排序策略
+    Sort(Option<String>),
+
+    // /list: 列出已选文件及各自的 token 数
+    ListFiles,
+
+    // /which <id>: 根据文档 ID (例如 D14) 查找对应的路径
+    Which(String),
+
+    // /show <id>: 显示文档 ID 对应的文档 (与 payload 中的内容一致)
+    Show(String),
+
+    // /mute <path>: 切换已选文件的静音状态 (保持选中但不进入 payload)
+    Mute(PathBuf),
+
+    // /dashboard: 全屏仪表盘视图
+    Dashboard,
+
+    // /format [xml|custom [name]]: 查看或切换 payload 的输出格式
+    Format(Option<String>, Option<String>),
+
+    // /model [encoding]  查看或切换计算 token 使用的编码
+    Model(Option<String>),
+
+    // /preview (或 /copy --outline-preview): 显示 /copy 将生成的 payload 的结构，不输出内容
+    Preview,
+
+    // /export [--force] <path>: 把 /copy 的内容写入文件；bool 为 --force (允许覆盖已有文件)
+    Export(PathBuf, bool),
+
+    // /save [name]: 把已选文件、prompt、模式等保存到 .rustprompt/ 下的会话文件
+    Save(Option<String>),
+
+    // /load [name]: 从会话文件恢复上下文 (重新读取所有文件)
+    Load(Option<String>),
+
+    // /usage [--reset]: 查看本地使用统计；bool 为 --reset (清空统计)
+    Usage(bool),
+
+    // /undo: 撤销上一次 add/remove/reset/prompt 修改 (可连续撤销)
+    Undo,
+
+    // /ignore [list|hidden on|off|gitignore on|off|node_modules on|off|add <pattern>]: 查看或修改扫描时的忽略规则
+    Ignore(Option<String>, Option<String>),
+
+    // /unignore <pattern>: 删除 /ignore add 添加的模式
+    Unignore(String),
+
+    // /config: 显示生效的配置项及其来源 (默认值、.rustprompt.toml 或本次会话中的命令)
+    ShowConfig,
+
+    // /reload-config: 重新读取 .rustprompt.toml (本次会话中用命令修改过的值保持不变)
+    ReloadConfig,
+
+    // /budget [n|off]: 查看或设置 token 预算 (settings.token_budget)；off 取消预算
+    Budget(Option<String>),
+
+    // /peek <path> [--lines N]: 预览一个文件 (开头 N 行、大小和 token 数)，不修改上下文
+    Peek(PathBuf, Option<String>),
+
+    // /grep [--regex] <pattern>: 在已选文件的内容中查找 (默认不区分大小写的子串)
+    Grep(String),
+
+    // /pick (或不带参数的 /add): 打开模糊查找器，从项目中选择要加入的文件
+    Pick,
+
+    // /prompt edit 或 /edit: 用外部编辑器 ($VISUAL / $EDITOR) 编辑 prompt_text
+    EditPrompt,
+
+    // /watch [on|off]: 监视已选文件，变化时立即重新生成 snippet
+    Watch(Option<String>),
+
+    // /only [ext,...|off]: 只扫描这些扩展名的文件 (也作用于项目树)；None 表示查看当前过滤
+    Only(Option<String>),
+
+    // /preset [name]: 套用内置预设 (扩展名过滤和忽略模式)；None 表示列出预设
+    Preset(Option<String>),
+
+    // /pin <path>: 固定一个已选文件，/reset 时保留
+    Pin(PathBuf),
+
+    // /unpin <path>: 取消固定
+    Unpin(PathBuf),
+
+    // /template list|save <name>|use <name> [--append]|delete <name>: 命名的 prompt 模板 (保存在用户配置目录)；第三个字段为 --append
+    Template(Option<String>, Option<String>, bool),
+
+    // 多行编辑提交的 prompt 中的 @path 提及 (由 REPL 发出，不从输入解析)
+    AddMentions(String),
+
+    // /copyprompt: 只复制 prompt 文本 (原样，不替换占位符)
+    CopyPrompt,
+
+    // /copytree: 只复制重新生成的项目树
+    CopyTree,
+
+    // /last [--show]: 重新复制最近一次 /copy 的内容；true 时分页打印而不是复制
+    Last(bool),
+
+    // /alias [name [expansion]]: 列出、查看或定义用户别名 (例如 /alias asrc add src --ext rs)
+    Alias(Option<String>, Option<String>),
+
+    // /unalias <name>: 删除用户别名
+    Unalias(String),
+
+    // /history: 列出本次会话中执行过的命令 (编号供 !<n> 使用)
+    History,
+
+    // /source [--continue] <file>: 依次执行文件中的命令 (每行一条)；true 时遇到错误继续执行后面的行
+    Source(PathBuf, bool),
+
+    // /keys: 列出生效的键位绑定
+    Keys,
 } 
\ No newline at end of file
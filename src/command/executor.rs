@@ -1,66 +1,879 @@
 use std::sync::{Arc, Mutex};
-use std::path::PathBuf;
+use std::collections::HashSet;
+use std::io::IsTerminal;
+use std::path::{Path, PathBuf};
 
 use log::info;
-use anyhow::Result;
+use anyhow::{anyhow, Result};
+use tokio_util::sync::CancellationToken;
 
 use crate::{
-    app::state::{AppState, ReplMode},
-    app::snippet_manager::SnippetManager,
-    command::definition::Command,
-    core::{files_scanner, ignore_rules::IgnoreConfig, clipboard},
-    error::AppError,
-    repl::engine::ReplEngine,
+    app::{
+        output::{
+            Output, binary_warning, budget_warning, pruned_summary, delta_summary, format_budget, format_bytes, format_delta,
+            format_thousands, format_token_count, overlap_summary, render_progress, status_footer,
+        },
+        pager::{print_paged, Pager},
+        settings::{self, AutoRefresh, BinaryTransition, FollowRenames, Oversize, Settings},
+        state::{AppState, ReplMode, Staleness, PROJECT_TREE_VIRTUAL_PATH},
+        style::{self, paint, Style},
+    },
+    app::session::{self, Session},
+    app::usage::UsageStats,
+    app::config::{self, ConfigSource},
+    app::presets,
+    app::prompt_templates::PromptTemplates,
+    app::editor,
+    app::keys::{self, KeyEditor},
+    app::report::ContextReport,
+    app::snippet_manager::{InstructionPlacement, SnippetManager},
+    command::{definition::{AddOptions, Command}, parser, registry, script},
+    core::{files_scanner::{self, ScanResult}, ignore_rules::{self, IgnoreConfig, IgnoreReason}, progress::{Progress, ProgressEvent}, clipboard::{self, ClipboardBackend, Copied}, ordering::SortStrategy, renames, tokenizer::{self, Encoding}, template::{self, OutputFormat}, validate::validate_xml, xml::{place_document, snippet_content}, decode::{decode_with_encoding, Decoded}, search, paths},
+    error::{io_reason, AppError},
+    repl::{dashboard, picker},
 };
 
-// [ADDED] 定义一个函数，用于判断给定 Command 是否在指定模式下可用
+/// 给定 Command 是否在指定模式下可用: 由 command::registry 决定；输入的 prompt 文本只在 Prompt 模式下有效
 fn is_command_valid_in_mode(cmd: &Command, mode: &ReplMode) -> bool {
-    match mode {
-        ReplMode::Manual => {
-            match cmd {
-                Command::Add(_) 
-                | Command::Remove(_) 
-                | Command::ShowContext
-                | Command::Copy
-                | Command::Reset
-                | Command::Help
-                | Command::Quit
-                | Command::Mode(_)
-                | Command::ResetPrompt  // 允许在 Manual 模式下使用
-                | Command::Prompt  // 允许在 Manual 模式下使用
-                => true,
-
-                Command::AppendPromptText(_)
-                | Command::Unknown(_) => false,
-            }
-        }
-        ReplMode::Prompt => {
-            match cmd {
-                Command::Mode(_)
-                | Command::Prompt
-                | Command::ShowContext
-                | Command::Copy
-                | Command::Help
-                | Command::Quit
-                | Command::AppendPromptText(_)
-                | Command::ResetPrompt  // 允许在 Prompt 模式下使用
-                => true,
-
-                Command::Add(_)
-                | Command::Remove(_)
-                | Command::Reset
-                | Command::Unknown(_) => false,
+    match cmd {
+        Command::AppendPromptText(_) => *mode == ReplMode::Prompt,
+        // 提交 prompt 后由 REPL 触发，两种模式下都可以
+        Command::AddMentions(_) => true,
+        Command::Unknown(_) => false,
+        other => registry::find(command_name(other)).is_some_and(|spec| spec.valid_in(mode)),
+    }
+}
+
+/// 命令执行后需要 REPL 处理的动作 (executor 不直接操作 ReplEngine)
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Effect {
+    None,
+    /// 切换到多行编辑器编辑 prompt (/prompt)
+    EnterMultiline,
+    /// 结束 REPL (/quit)
+    Quit,
+    /// 启动定期自动刷新 (秒)；旧任务已经停止，新任务的提示通过 REPL 的输出通道打印
+    StartAutoRefresh(u64),
+    /// 开始监视文件变化 (/watch on)；提示同样通过 REPL 的输出通道打印
+    StartWatch,
+    /// 键位设置 (edit_mode) 或配置文件中的绑定变了，按新设置重建编辑器
+    RebuildEditor,
+}
+
+/// 脚本结束时 (包括出错和被 Ctrl+C 丢弃) 减少 AppState.source_depth
+struct SourceDepthGuard(Arc<Mutex<AppState>>);
+
+impl Drop for SourceDepthGuard {
+    fn drop(&mut self) {
+        self.0.lock().unwrap().source_depth -= 1;
+    }
+}
+
+/// 依次执行脚本中的行 (/source，以及启动时的 -c 和 on_start)。行的处理与 REPL 相同: 先展开别名，
+/// Prompt 模式下不以 / 开头的是 prompt 文本。某行失败 (包括未知命令) 时报告 `来源:行号`，
+/// keep_going 时继续，否则停止并返回错误。返回最后一个需要 REPL 处理的 Effect；/quit 结束脚本
+pub async fn run_script(
+    source: &str,
+    lines: Vec<script::ScriptLine>,
+    keep_going: bool,
+    state: Arc<Mutex<AppState>>,
+    cancel: &CancellationToken,
+    out: &mut dyn Output,
+) -> Result<Effect, AppError> {
+    let mut effect = Effect::None;
+    let (mut ran, mut failed) = (0, 0);
+    for line in lines {
+        let location = format!("{}:{}", source, line.number);
+        let (mode, aliases) = {
+            let st = state.lock().unwrap();
+            (st.mode.clone(), st.aliases.clone())
+        };
+        let parsed = if mode == ReplMode::Prompt && !line.text.starts_with('/') {
+            Ok(Command::AppendPromptText(line.text.clone()))
+        } else {
+            parser::expand_aliases(&line.text, &aliases).and_then(|text| parser::parse(&text).map_err(|e| error_message(&e)))
+        };
+        // 嵌套的 /source 失败时，错误信息中已经有内层脚本的位置
+        let nested = matches!(parsed, Ok(Command::Source(..)));
+        let result = match parsed {
+            Ok(Command::Unknown(text)) => Err(format!("Unknown command: {}", text)),
+            Ok(Command::Prompt | Command::EditPrompt) => {
+                Err("multi-line prompt editing can't run from a script; write the prompt text on a line in prompt mode instead".to_string())
+            }
+            Ok(cmd) => Box::pin(execute(cmd, state.clone(), cancel, out)).await.map_err(|e| error_message(&e)),
+            Err(e) => Err(e),
+        };
+        ran += 1;
+        match result {
+            Ok(Effect::Quit) => return Ok(Effect::Quit),
+            Ok(Effect::None) => {}
+            Ok(other) => effect = other,
+            Err(e) if keep_going => {
+                failed += 1;
+                out.error(format!("✘ {}: {}", location, e));
+            }
+            Err(e) if nested => return Err(AppError::General(anyhow!(e))),
+            Err(e) => return Err(AppError::General(anyhow!("{}: {}", location, e))),
+        }
+    }
+    if failed > 0 {
+        out.warn(format!("(Warning) Ran {} commands from {}, {} failed", ran, source, failed));
+    } else {
+        out.info(format!("(Note) Ran {} commands from {}", ran, source));
+    }
+    Ok(effect)
+}
+
+/// 面向用户的一行错误信息 (REPL 的 ✘ 状态行和 /dashboard 的活动日志使用)
+pub fn error_message(e: &AppError) -> String {
+    let message = match e {
+        AppError::PathNotFound(path) => format!("No such file or directory: {}", path.display()),
+        AppError::Io { path, source } => format!("{}: {}", path.display(), io_reason(source)),
+        AppError::General(e) => format!("{:#}", e),
+        other => other.to_string(),
+    };
+    message.lines().map(str::trim).filter(|l| !l.is_empty()).collect::<Vec<_>>().join(" ")
+}
+
+/// /peek 默认显示的行数
+const PEEK_LINES: usize = 40;
+
+/// 命令名 (例如 `/add`)，用于提示信息和使用统计
+pub fn command_name(cmd: &Command) -> &'static str {
+    match cmd {
+        Command::Add(..) => "/add",
+        Command::Remove(_) => "/remove",
+        Command::ShowContext | Command::ContextJson(_) => "/context",
+        Command::Copy(..) => "/copy",
+        Command::Reset(..) => "/reset",
+        Command::Help(_) => "/help",
+        Command::Quit(_) => "/quit",
+        Command::Mode(_) => "/mode",
+        Command::Prompt => "/prompt",
+        Command::AppendPromptText(_) => "(text input)",
+        Command::ResetPrompt => "/resetprompt",
+        Command::Set(..) => "/set",
+        Command::Refresh => "/refresh",
+        Command::Stats => "/stats",
+        Command::Validate => "/validate",
+        Command::Sort(_) => "/sort",
+        Command::ListFiles => "/list",
+        Command::Which(_) => "/which",
+        Command::Show(_) => "/show",
+        Command::Mute(_) => "/mute",
+        Command::Dashboard => "/dashboard",
+        Command::Format(..) => "/format",
+        Command::Model(_) => "/model",
+        Command::Preview => "/preview",
+        Command::Export(..) => "/export",
+        Command::Save(_) => "/save",
+        Command::Load(_) => "/load",
+        Command::Usage(_) => "/usage",
+        Command::Undo => "/undo",
+        Command::Ignore(..) => "/ignore",
+        Command::Unignore(_) => "/unignore",
+        Command::ShowConfig => "/config",
+        Command::ReloadConfig => "/reload-config",
+        Command::Budget(_) => "/budget",
+        Command::Peek(..) => "/peek",
+        Command::Grep(_) => "/grep",
+        Command::Pick => "/pick",
+        Command::EditPrompt => "/edit",
+        Command::Watch(_) => "/watch",
+        Command::Only(_) => "/only",
+        Command::Preset(_) => "/preset",
+        Command::Pin(_) => "/pin",
+        Command::Unpin(_) => "/unpin",
+        Command::Template(..) => "/template",
+        Command::AddMentions(_) => "(prompt mentions)",
+        Command::CopyPrompt => "/copyprompt",
+        Command::CopyTree => "/copytree",
+        Command::Last(_) => "/last",
+        Command::Alias(..) => "/alias",
+        Command::Unalias(_) => "/unalias",
+        Command::History => "/history",
+        Command::Source(..) => "/source",
+        Command::Keys => "/keys",
+        Command::Unknown(_) => "unknown",
+    }
+}
+
+/// /ignore list: 当前的忽略规则
+fn print_ignore_rules(ignore: &IgnoreConfig, out: &mut dyn Output) {
+    let on_off = |v: bool| if v { "on" } else { "off" };
+    out.result("Ignore rules (apply to /add and the project tree):".to_string());
+    out.result(format!("  hidden        {}", on_off(ignore.ignore_hidden)));
+    out.result(format!("  gitignore     {}", on_off(ignore.use_gitignore)));
+    out.result(format!("  node_modules  {}", on_off(ignore.ignore_node_modules)));
+    out.result(format!("  binary        {}", on_off(ignore.ignore_binary)));
+    if ignore.extensions.is_empty() {
+        out.result("  extensions    (all; limit with /only rs,md)".to_string());
+    } else {
+        out.result(format!("  extensions    {}", extension_list(&ignore.extensions)));
+    }
+    if ignore.patterns.is_empty() {
+        out.result("  patterns      (none; add with /ignore add <pattern>)".to_string());
+    } else {
+        for pattern in &ignore.patterns {
+            out.result(format!("  pattern       {}", pattern));
+        }
+    }
+}
+
+/// /context 和 /list 中固定文件的标记
+const PIN_MARK: &str = " 📌";
+
+/// 扩展名过滤的显示形式，例如 `.rs, .toml`
+fn extension_list(extensions: &[String]) -> String {
+    extensions.iter().map(|e| format!(".{}", e)).collect::<Vec<_>>().join(", ")
+}
+
+/// 会修改上下文 (文件、prompt 或设置) 的命令，执行成功后打印状态栏
+fn is_mutating(cmd: &Command) -> bool {
+    matches!(
+        cmd,
+        Command::Add(..)
+            | Command::Remove(_)
+            | Command::Reset(..)
+            | Command::Refresh
+            | Command::Copy(..)
+            | Command::Export(..)
+            | Command::ResetPrompt
+            | Command::AppendPromptText(_)
+            | Command::Set(_, Some(_))
+            | Command::Sort(Some(_))
+            | Command::Mute(_)
+            | Command::Format(Some(_), _)
+            | Command::Model(Some(_))
+            | Command::Load(_)
+            | Command::Undo
+            | Command::Ignore(Some(_), Some(_))
+            | Command::Unignore(_)
+            | Command::ReloadConfig
+            | Command::Budget(Some(_))
+            | Command::EditPrompt
+            | Command::Only(Some(_))
+            | Command::Preset(Some(_))
+            | Command::Pin(_)
+            | Command::Unpin(_)
+            | Command::AddMentions(_)
+    ) || is_template_use(cmd)
+}
+
+/// 执行前保存快照、可以用 /undo 撤销的命令
+fn is_undoable(cmd: &Command) -> bool {
+    match cmd {
+        // 没有路径的 /add 转给 /pick，由选出路径之后的 /add 记录
+        Command::Add(paths, _) => !paths.is_empty(),
+        Command::Remove(_) | Command::Reset(..) | Command::ResetPrompt | Command::AppendPromptText(_) | Command::EditPrompt
+        | Command::Refresh => true,
+        _ => is_template_use(cmd),
+    }
+}
+
+/// `/template use <name>`: 修改 prompt 的 /template 子命令
+fn is_template_use(cmd: &Command) -> bool {
+    matches!(cmd, Command::Template(Some(sub), Some(_), _) if sub == "use")
+}
+
+/// 修改类命令在活动日志中的简短描述
+fn activity_label(cmd: &Command) -> String {
+    match cmd {
+        Command::Add(ps, options) => format!("/add {}{}", options.flags(), join_paths(ps)),
+        Command::Remove(ps) => format!("/remove {}", join_paths(ps)),
+        Command::Mute(p) => format!("/mute {}", p.display()),
+        Command::Pin(p) => format!("/pin {}", p.display()),
+        Command::Unpin(p) => format!("/unpin {}", p.display()),
+        Command::Template(_, Some(name), append) => format!("/template use {}{}", name, if *append { " --append" } else { "" }),
+        Command::Set(k, Some(v)) => format!("/set {} {}", k.as_deref().unwrap_or(""), v),
+        Command::Sort(Some(s)) => format!("/sort {}", s),
+        Command::Model(Some(m)) => format!("/model {}", m),
+        Command::Load(name) => format!("/load {}", name.as_deref().unwrap_or("")).trim_end().to_string(),
+        Command::Ignore(sub, value) => format!("/ignore {} {}", sub.as_deref().unwrap_or(""), value.as_deref().unwrap_or("")),
+        Command::Unignore(pattern) => format!("/unignore {}", pattern),
+        Command::Only(Some(list)) => format!("/only {}", list),
+        Command::Preset(Some(name)) => format!("/preset {}", name),
+        Command::Reset(hard, _) => if *hard { "/reset --hard" } else { "/reset" }.to_string(),
+        Command::Undo => "/undo".to_string(),
+        Command::Refresh => "/refresh".to_string(),
+        Command::Copy(options) => format!("/copy{}", options.flags()),
+        Command::Export(p, _) => format!("/export {}", p.display()),
+        Command::ResetPrompt => "/resetprompt".to_string(),
+        Command::AppendPromptText(_) => "prompt text appended".to_string(),
+        Command::EditPrompt => "/prompt edit".to_string(),
+        Command::ReloadConfig => "/reload-config".to_string(),
+        Command::Budget(Some(b)) => format!("/budget {}", b),
+        other => format!("{:?}", other),
+    }
+}
+
+/// 命令可能修改的配置项 (config::CONFIG_KEYS)；值真的变化时记为本次会话的覆盖，/reload-config 不再改写
+fn config_overrides(cmd: &Command) -> &'static [&'static str] {
+    match cmd {
+        Command::Set(Some(k), Some(_)) if k == "token_budget" => &["token_budget"],
+        Command::Set(Some(k), Some(_)) if k == "edit_mode" => &["edit_mode"],
+        Command::Set(Some(k), Some(_)) if k == "hints" => &["hints"],
+        Command::Set(Some(k), Some(_)) if k == "confirm_destructive" => &["confirm_destructive"],
+        Command::Budget(Some(_)) => &["token_budget"],
+        Command::Format(Some(_), _) => &["format"],
+        Command::Ignore(Some(sub), Some(_)) => match sub.as_str() {
+            "hidden" => &["ignore.hidden"],
+            "gitignore" => &["ignore.gitignore"],
+            "node_modules" => &["ignore.node_modules"],
+            "binary" => &["ignore.binary"],
+            "add" => &["ignore.patterns"],
+            _ => &[],
+        },
+        Command::Unignore(_) | Command::Preset(Some(_)) => &["ignore.patterns"],
+        Command::Alias(Some(_), Some(_)) | Command::Unalias(_) => &["aliases"],
+        Command::AppendPromptText(_) | Command::ResetPrompt | Command::Undo => &["prompt"],
+        cmd if is_template_use(cmd) => &["prompt"],
+        Command::Load(_) => &[
+            "ignore.hidden",
+            "ignore.gitignore",
+            "ignore.node_modules",
+            "ignore.binary",
+            "ignore.patterns",
+            "prompt",
+            "format",
+        ],
+        _ => &[],
+    }
+}
+
+fn join_paths(paths: &[PathBuf]) -> String {
+    paths.iter().map(|p| p.display().to_string()).collect::<Vec<_>>().join(" ")
+}
+
+/// /pin、/unpin、/mute 参数对应的已选文件 key；与 /remove 一样，当前目录下不存在时按项目根目录解析
+fn pin_key(state: &Arc<Mutex<AppState>>, path: &Path) -> PathBuf {
+    let cwd = std::env::current_dir().unwrap_or_else(|_| PathBuf::from("."));
+    let root = state.lock().unwrap().project_root.clone();
+    paths::file_key(&paths::resolve_in_root(path, &cwd, &root), &cwd)
+}
+
+/// 内存占用超过软上限时提醒用户
+fn warn_if_over_memory_cap(state: &Arc<Mutex<AppState>>, out: &mut dyn Output) {
+    let st = state.lock().unwrap();
+    let cap = st.settings.memory_cap_mb;
+    let usage = st.memory_usage();
+    if cap > 0 && usage > cap * 1024 * 1024 {
+        out.warn(format!(
+            "(Warning) Context holds {} in memory, above the {} MB soft cap (see /set cache_merged off)",
+            format_bytes(usage),
+            cap
+        ));
+    }
+}
+
+/// 会话名只允许字母、数字、`-` 和 `_`，避免写到 .rustprompt/sessions/ 之外
+fn is_session_name(name: &str) -> bool {
+    !name.is_empty() && name.chars().all(|c| c.is_alphanumeric() || c == '-' || c == '_')
+}
+
+/// /usage 的报告: 会话、payload 大小分布和命令使用次数
+fn print_usage_report(stats: &UsageStats, out: &mut dyn Output) {
+    let days = std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .map(|d| d.as_secs().saturating_sub(stats.since) / 86_400)
+        .unwrap_or(0);
+    out.result(format!("Usage statistics (local only, first recorded {} days ago):", days));
+
+    let minutes = stats.session_secs / 60;
+    let average = minutes.checked_div(stats.sessions).unwrap_or(0);
+    out.result(format!("  {:<10}: {} ({} min in total, {} min average)", "Sessions", stats.sessions, minutes, average));
+
+    let payloads = stats.payload_count();
+    if payloads > 0 {
+        out.result(format!(
+            "  {:<10}: {} copied or exported (average {} tokens, largest {})",
+            "Payloads",
+            payloads,
+            format_token_count((stats.payload_tokens_total / payloads) as usize),
+            format_token_count(stats.payload_tokens_max as usize)
+        ));
+        let widest = stats.payload_sizes.iter().copied().max().unwrap_or(1).max(1);
+        for (bucket, count) in stats.payload_sizes.iter().enumerate() {
+            let bar = "#".repeat(((count * 30).div_ceil(widest)) as usize);
+            let line = format!("    {:<10} {:>5}  {}", UsageStats::bucket_label(bucket), count, bar);
+            out.result(line.trim_end().to_string());
+        }
+    }
+
+    let mut commands: Vec<(&String, &u64)> = stats.commands.iter().collect();
+    commands.sort_by(|a, b| b.1.cmp(a.1).then_with(|| a.0.cmp(b.0)));
+    out.result("  Commands:".to_string());
+    for (name, count) in commands {
+        out.result(format!("    {:<14} {:>6}", name, count));
+    }
+}
+
+/// 打印并清空刷新过程中记录的「文件变成二进制」警告和因文件被删除而移出上下文的文件
+fn report_binary_warnings(state: &Arc<Mutex<AppState>>, out: &mut dyn Output) {
+    let (warnings, pruned) = {
+        let mut st = state.lock().unwrap();
+        (std::mem::take(&mut st.binary_warnings), std::mem::take(&mut st.pruned_files))
+    };
+    for (path, kind) in warnings {
+        out.warn(binary_warning(&path, kind));
+    }
+    if !pruned.is_empty() {
+        out.info(pruned_summary(&pruned));
+    }
+}
+
+/// /copy 和 /export 共用: 按需刷新已修改的文件 (force_refresh 时全部重新读取)、重建，然后拼装并校验最终 payload。
+/// 返回 (payload, 重新读取的文件数)；action 用于校验失败时的提示
+async fn prepare_payload(
+    state: &Arc<Mutex<AppState>>,
+    ignore_config: &IgnoreConfig,
+    action: &str,
+    force_refresh: bool,
+    out: &mut dyn Output,
+) -> Result<(String, usize), AppError> {
+    // auto_refresh=before_copy: 只重新读取磁盘上发生变化的文件 (mtime/大小变化)，其余沿用缓存的 snippet
+    let auto_refresh = state.lock().unwrap().settings.auto_refresh;
+    let refreshed = if force_refresh {
+        let paths: Vec<PathBuf> = state.lock().unwrap().selected_paths.iter().cloned().collect();
+        SnippetManager::full_refresh(state.clone(), paths.clone(), ignore_config).await?;
+        paths.len()
+    } else if auto_refresh == AutoRefresh::BeforeCopy {
+        SnippetManager::refresh_dirty(state.clone()).await?
+    } else {
+        0
+    };
+    report_binary_warnings(state, out);
+    SnippetManager::update_project_tree_snippet(state.clone(), ignore_config).await?;
+    SnippetManager::rebuild_and_recalc(state.clone()).await?;
+
+    let (payload, is_xml, unknown) = {
+        let st = state.lock().unwrap();
+        let unknown = SnippetManager::expand_prompt(&st).unknown;
+        (SnippetManager::assemble_payload(&st)?, st.output_format == OutputFormat::Xml, unknown)
+    };
+    for name in unknown {
+        out.warn(format!("(Warning) Unknown placeholder {{{{{}}}}} left as is in the prompt (write \\{{\\{{ for a literal {{{{)", name));
+    }
+
+    for overlap in SnippetManager::overlaps(&state.lock().unwrap()) {
+        out.info(format!("(Note) {}", overlap_summary(&overlap)));
+    }
+
+    // 输出前确认 payload 是良构的 XML (自定义模板的输出不是 XML，跳过)
+    let validate = state.lock().unwrap().settings.validate_xml;
+    if validate && is_xml {
+        if let Err(e) = validate_xml(&payload) {
+            return Err(AppError::Xml(format!("refusing to {}: {}", action, e)));
+        }
+    }
+    Ok((payload, refreshed))
+}
+
+/// 按 /set clipboard_backend (或 /copy --backend 给出的 backend) 复制文本: 成功时打印 success
+/// (经 OSC 52 或临时文件时另加说明)，失败时打印尝试过的方式和原因；返回是否成功
+fn copy_text(
+    state: &Arc<Mutex<AppState>>,
+    text: &str,
+    backend: Option<ClipboardBackend>,
+    success: String,
+    out: &mut dyn Output,
+) -> bool {
+    let backend = backend.unwrap_or_else(|| state.lock().unwrap().settings.clipboard_backend);
+    match clipboard::copy_to_clipboard(text, backend) {
+        Ok(Copied::Native) => out.info(success),
+        Ok(Copied::Command(command)) => out.info(format!("{} (via {})", success, command)),
+        Ok(Copied::Osc52) => {
+            out.info(success);
+            out.info("(Note) Sent through the terminal (OSC 52); the terminal must allow clipboard access".to_string());
+        }
+        Ok(Copied::File(path)) if backend == ClipboardBackend::File => {
+            out.info(format!("(Note) Written to {} (clipboard_backend = file)", path.display()));
+        }
+        Ok(Copied::File(path)) => {
+            out.warn(format!("(Warning) No clipboard available, the content was written to {} instead", path.display()));
+        }
+        Err(e) => {
+            out.error(format!("Failed to copy to clipboard: {}", error_message(&e)));
+            return false;
+        }
+    }
+    true
+}
+
+/// /model 选择的模型有已知的上下文窗口时，按最终 payload (包括 instruction，与剪贴板上的内容一致) 的
+/// token 数检查是否超出；超出时列出最大的几个文件和移除建议，并拒绝复制
+fn check_context_window(state: &Arc<Mutex<AppState>>, payload: &str, out: &mut dyn Output) -> Result<(), AppError> {
+    let (model, window, mut files) = {
+        let st = state.lock().unwrap();
+        let Some((model, window)) = st.model.as_ref().and_then(|m| tokenizer::context_window(m).map(|w| (m.clone(), w))) else {
+            return Ok(());
+        };
+        let files: Vec<(PathBuf, usize)> = st
+            .selected_paths
+            .iter()
+            .filter(|p| !st.muted.contains(*p))
+            .map(|p| (st.display_path(p), st.doc_tokens.get(p).copied().unwrap_or(0)))
+            .collect();
+        (model, window, files)
+    };
+    let tokens = tokenizer::calculate_tokens_in_string(payload)?;
+    if tokens <= window {
+        return Ok(());
+    }
+
+    files.sort_by(|a, b| b.1.cmp(&a.1).then_with(|| a.0.cmp(&b.0)));
+    out.warn(format!(
+        "(Warning) The payload is {} tokens, {} over the {} context window of {}",
+        format_thousands(tokens),
+        format_thousands(tokens - window),
+        format_budget(window),
+        model
+    ));
+    out.warn("Biggest files:".to_string());
+    for (path, file_tokens) in files.iter().take(5) {
+        out.warn(format!("  {:>8}  {}", format_thousands(*file_tokens), path.display()));
+    }
+    // 从最大的文件开始，直到移除的 token 足够抵消超出的部分
+    let mut excess = tokens - window;
+    let mut candidates = Vec::new();
+    for (path, file_tokens) in &files {
+        if excess == 0 {
+            break;
+        }
+        candidates.push(path.display().to_string());
+        excess = excess.saturating_sub(*file_tokens);
+    }
+    if excess == 0 && !candidates.is_empty() {
+        out.info(format!("(Note) Removing the largest files would make it fit: /remove {}", candidates.join(" ")));
+    }
+    Err(AppError::General(anyhow!(
+        "refusing to copy: payload exceeds the {} context window (/copy --force copies anyway)",
+        model
+    )))
+}
+
+/// 拼装最终 payload 但不复制到剪贴板 (命令行 `--stdout` 使用)，与 /copy 一样按需刷新并校验。
+/// 加入之后被删除的文件在刷新时移出上下文:
+///
+/// ```
+/// use std::sync::{Arc, Mutex};
+/// use rustprompt::{
+///     app::{output::CapturedOutput, state::AppState},
+///     command::{definition::Command, executor},
+/// };
+/// use tokio_util::sync::CancellationToken;
+///
+/// # tokio::runtime::Runtime::new().unwrap().block_on(async {
+/// let dir = std::env::temp_dir().join(format!("rustprompt-prune-{}", std::process::id()));
+/// std::fs::create_dir_all(&dir).unwrap();
+/// let (kept, deleted) = (dir.join("kept.txt"), dir.join("deleted.txt"));
+/// std::fs::write(&kept, "still here").unwrap();
+/// std::fs::write(&deleted, "about to go").unwrap();
+///
+/// let state = Arc::new(Mutex::new(AppState::new()));
+/// let mut out = CapturedOutput::default();
+/// let add = Command::Add(vec![kept.clone(), deleted.clone()], Default::default());
+/// executor::execute(add, state.clone(), &CancellationToken::new(), &mut out).await.unwrap();
+/// assert_eq!(state.lock().unwrap().file_count, 2);
+///
+/// std::fs::remove_file(&deleted).unwrap();
+/// let payload = executor::render_payload(state.clone(), &mut out).await.unwrap();
+/// assert!(payload.contains("still here") && !payload.contains("deleted.txt"));
+/// assert_eq!(state.lock().unwrap().file_count, 1);
+/// assert!(out.text().contains("Removed 1 deleted files from the context"));
+/// std::fs::remove_dir_all(&dir).unwrap();
+/// # });
+/// ```
+pub async fn render_payload(state: Arc<Mutex<AppState>>, out: &mut dyn Output) -> Result<String, AppError> {
+    let ignore_config = state.lock().unwrap().ignore.clone();
+    let (payload, _) = prepare_payload(&state, &ignore_config, "print", false, out).await?;
+    Ok(payload)
+}
+
+/// prompt 文本中 `@path` 提及的、尚未选中的文件通过 /add 的流程加入 (`/set auto_add_mentions off` 关闭)。
+/// 不存在的路径和目录不处理 (`@` 也常用于其它用途)；被忽略规则跳过时打印与 /add 相同的提示
+async fn add_mentions(
+    state: &Arc<Mutex<AppState>>,
+    text: &str,
+    cancel: &CancellationToken,
+    out: &mut dyn Output,
+) -> Result<(), AppError> {
+    let (enabled, ignore_config) = {
+        let st = state.lock().unwrap();
+        (st.settings.auto_add_mentions, st.ignore.clone())
+    };
+    if !enabled {
+        return Ok(());
+    }
+    let cwd = std::env::current_dir().unwrap_or_else(|_| PathBuf::from("."));
+    let root = state.lock().unwrap().project_root.clone();
+    let mut added = false;
+    for path in parser::mentions(text) {
+        // prompt 中提到的路径通常与 <source> 一样相对于项目根目录
+        let path = paths::resolve_in_root(&path, &cwd, &root);
+        let key = paths::file_key(&path, &cwd);
+        if !path.is_file() || state.lock().unwrap().selected_paths.contains(&key) {
+            continue;
+        }
+        let tokens_before = state.lock().unwrap().token_count;
+        let Some(notes) = add_paths(state, std::slice::from_ref(&path), &ignore_config, Progress::none(), cancel).await? else {
+            out.info("(Note) add cancelled, context unchanged".to_string());
+            return Ok(());
+        };
+        let st = state.lock().unwrap();
+        if st.selected_paths.contains(&key) {
+            added = true;
+            out.info(format!(
+                "(Note) auto-added {} ({} tokens)",
+                st.display_path(&key).display(),
+                format_delta(tokens_before, st.token_count)
+            ));
+        } else {
+            for note in notes {
+                out.tagged(note);
+            }
+        }
+    }
+    if added {
+        warn_if_over_memory_cap(state, out);
+    }
+    Ok(())
+}
+
+/// /add 的主体: 逐个扫描参数、读取新文件并重建，过程中通过 progress 报告进度。
+/// 返回要打印的提示 (等进度行清除后由调用方打印，避免与进度行混在一起)；被 cancel 取消时返回 None。
+///  - 扫描和读取期间不修改上下文，全部读完后才在一次加锁中写入，所以取消后上下文保持不变
+async fn add_paths(
+    state: &Arc<Mutex<AppState>>,
+    paths: &[PathBuf],
+    ignore_config: &IgnoreConfig,
+    progress: Progress,
+    cancel: &CancellationToken,
+) -> Result<Option<Vec<String>>, AppError> {
+    // 逐个扫描；单个路径出错只报告，不中断整个命令
+    let mut notes = Vec::new();
+    let mut roots = Vec::new();
+    let mut scanned = Vec::new();
+    let mut skipped_binary = 0;
+    for path in paths {
+        match files_scanner::scan_dir_with_progress(path, ignore_config, &progress, cancel).await {
+            Ok(scan) if scan.files.is_empty() && scan.skipped_binary == 0 && files_scanner::is_glob(&path.to_string_lossy()) => {
+                notes.push(format!("(Note) No files matched {}", path.display()));
+            }
+            Ok(scan) if scan.files.is_empty() && path.is_file() && !ignore_config.matches_extension(path) => {
+                notes.push(format!(
+                    "(Note) Skipped {} (only {} files are added)",
+                    path.display(),
+                    extension_list(&ignore_config.extensions)
+                ));
+            }
+            Ok(scan) if scan.files.is_empty() && path.is_file() && ignore_config.should_ignore_path(path) => {
+                let reason = ignore_config.ignore_reason(path);
+                let flag = if reason == Some(IgnoreReason::Hidden) { "--hidden" } else { "--no-ignore" };
+                notes.push(format!(
+                    "(Note) Skipped {} ({}); use /add {} {} to add it anyway",
+                    path.display(),
+                    reason.map(|r| r.to_string()).unwrap_or_default(),
+                    flag,
+                    path.display()
+                ));
+            }
+            Ok(ScanResult { files, skipped_binary: skipped }) => {
+                skipped_binary += skipped;
+                // 记住原始参数，/context 按它分组，/refresh 可以重新展开
+                if !files.is_empty() {
+                    roots.push((path, files.clone()));
+                }
+                scanned.extend(files);
             }
+            Err(AppError::Cancelled) => return Ok(None),
+            Err(e) if paths.len() == 1 => return Err(e),
+            Err(e) => notes.push(format!("(Warning) {}", e)),
         }
     }
+    info!("  -> Scanned {} files", scanned.len());
+    if skipped_binary > 0 {
+        notes.push(format!(
+            "(Note) Skipped {} binary files (/ignore binary off adds them as one-line placeholders)",
+            skipped_binary
+        ));
+    }
+
+    // oversize=skip: 超过 max_file_bytes 的新文件不加入 (已选文件保持选中，重新读取时截断)
+    let (limit, skip_oversized, fresh) = {
+        let st = state.lock().unwrap();
+        let fresh: Vec<PathBuf> = scanned.iter().filter(|f| !st.selected_paths.contains(*f)).cloned().collect();
+        (st.settings.max_file_bytes, st.settings.oversize == Oversize::Skip, fresh)
+    };
+    if limit > 0 && skip_oversized {
+        let oversized: Vec<(PathBuf, u64)> = fresh
+            .into_iter()
+            .filter_map(|f| {
+                let size = std::fs::metadata(&f).ok()?.len();
+                (size > limit as u64).then_some((f, size))
+            })
+            .collect();
+        if !oversized.is_empty() {
+            notes.push(format!(
+                "(Warning) Skipped {} files larger than {} (raise it with /set max_file_bytes, or /set oversize truncate):",
+                oversized.len(),
+                format_bytes(limit)
+            ));
+            for (f, size) in &oversized {
+                notes.push(format!(" - {} ({})", f.display(), format_bytes(*size as usize)));
+            }
+            let skipped: HashSet<PathBuf> = oversized.into_iter().map(|(f, _)| f).collect();
+            scanned.retain(|f| !skipped.contains(f));
+            for (_, files) in &mut roots {
+                files.retain(|f| !skipped.contains(f));
+            }
+            roots.retain(|(_, files)| !files.is_empty());
+        }
+    }
+    if scanned.is_empty() {
+        return Ok(Some(notes));
+    }
+
+    let (new_files, tokens_before) = {
+        let st = state.lock().unwrap();
+        let new_files: HashSet<&PathBuf> = scanned.iter().filter(|f| !st.selected_paths.contains(*f)).collect();
+        (new_files.len(), st.token_count)
+    };
+    if new_files > 0 {
+        match SnippetManager::add_files_snippet_with_progress(state.clone(), scanned.clone(), &progress, cancel).await {
+            Err(AppError::Cancelled) => return Ok(None),
+            result => result?,
+        }
+    }
+
+    let (files_before, num_added) = {
+        let mut st = state.lock().unwrap();
+        for (path, files) in roots {
+            st.add_roots.record(path, files);
+        }
+        let init_count = st.selected_paths.len();
+        st.selected_paths.extend(scanned);
+        let final_count = st.selected_paths.len();
+        st.file_count = final_count;
+        info!("  -> selected_paths increased from {} to {}", init_count, final_count);
+        (init_count, final_count - init_count)
+    };
+
+    if num_added > 0 {
+        progress.send(ProgressEvent::Tokenizing);
+        SnippetManager::update_project_tree_snippet(state.clone(), ignore_config).await?;
+        // 文件已经加入；此时取消只跳过 token 计算 (保留上一次的结果)，由调用方报告
+        SnippetManager::rebuild_and_recalc_with_cancel(state.clone(), cancel).await?;
+    } else {
+        info!("  -> No new files added, skipping snippet update");
+    }
+    let tokens_after = state.lock().unwrap().token_count;
+    notes.push(delta_summary(files_before, files_before + num_added, tokens_before, tokens_after));
+    Ok(Some(notes))
 }
 
+/// 执行一条命令。只读写 `state`，文字输出全部写到 `out`，需要 REPL 配合的部分 (进入多行编辑、退出、
+/// 带输出通道的后台任务) 以 [`Effect`] 返回，由调用方处理；`cancel` 被触发时支持取消的命令 (例如 /add) 尽快停止。
+///
+/// 不依赖 ReplEngine，可以直接对一个新的 AppState 执行:
+///
+/// ```
+/// use std::sync::{Arc, Mutex};
+/// use tokio_util::sync::CancellationToken;
+/// use rustprompt::{
+///     app::{output::CapturedOutput, state::{AppState, ReplMode}},
+///     command::{definition::Command, executor::{self, Effect}},
+/// };
+///
+/// # tokio::runtime::Runtime::new().unwrap().block_on(async {
+/// let state = Arc::new(Mutex::new(AppState::new()));
+/// let cancel = CancellationToken::new();
+/// let mut out = CapturedOutput::default();
+///
+/// let effect = executor::execute(Command::Mode(Some("prompt".into())), state.clone(), &cancel, &mut out).await?;
+/// assert_eq!(effect, Effect::None);
+/// assert_eq!(state.lock().unwrap().mode, ReplMode::Prompt);
+///
+/// executor::execute(Command::AppendPromptText("Explain this".into()), state.clone(), &cancel, &mut out).await?;
+/// assert_eq!(state.lock().unwrap().prompt_text, "Explain this");
+///
+/// assert_eq!(executor::execute(Command::Prompt, state.clone(), &cancel, &mut out).await?, Effect::EnterMultiline);
+/// // prompt 还没有保存或复制: /quit 先确认 (这里固定回答 no)，--force 直接退出
+/// rustprompt::app::output::set_confirm_override(Some(false));
+/// assert_eq!(executor::execute(Command::Quit(false), state.clone(), &cancel, &mut out).await?, Effect::None);
+/// assert_eq!(executor::execute(Command::Quit(true), state, &cancel, &mut out).await?, Effect::Quit);
+/// # Ok::<(), rustprompt::error::AppError>(())
+/// # }).unwrap();
+/// ```
+///
+/// 收集到的输出与终端上打印的文本相同，可以逐条断言:
+///
+/// ```
+/// use std::sync::{Arc, Mutex};
+/// use tokio_util::sync::CancellationToken;
+/// use rustprompt::{
+///     app::{output::{CapturedOutput, Level}, state::AppState},
+///     command::{definition::Command, executor},
+/// };
+///
+/// # tokio::runtime::Runtime::new().unwrap().block_on(async {
+/// let state = Arc::new(Mutex::new(AppState::new()));
+/// let cancel = CancellationToken::new();
+///
+/// let mut out = CapturedOutput::default();
+/// executor::execute(Command::Add(vec!["Cargo.toml".into()], Default::default()), state.clone(), &cancel, &mut out).await?;
+/// assert!(out.at(Level::Info)[0].starts_with("(Note) +1 files, +"));
+///
+/// let mut out = CapturedOutput::default();
+/// executor::execute(Command::ShowContext, state.clone(), &cancel, &mut out).await?;
+/// assert!(out.text().contains("Selected files:\n - \"Cargo.toml\""));
+///
+/// let mut out = CapturedOutput::default();
+/// executor::execute(Command::Mode(Some("bogus".into())), state.clone(), &cancel, &mut out).await?;
+/// assert_eq!(out.at(Level::Warn), ["Unknown mode: bogus (available: manual, prompt)"]);
+///
+/// let mut out = CapturedOutput::default();
+/// executor::execute(Command::Unknown("/cpoy".into()), state.clone(), &cancel, &mut out).await?;
+/// assert_eq!(out.at(Level::Warn), ["Unknown command: /cpoy — did you mean /copy?"]);
+///
+/// let mut out = CapturedOutput::default();
+/// executor::execute(Command::Help(Some("add".into())), state.clone(), &cancel, &mut out).await?;
+/// assert!(out.text().starts_with("/add <path>\n  Add files or directories to context\n"));
+/// assert!(out.text().contains("--no-ignore") && out.text().contains("Available in manual mode only"));
+///
+/// // 失败的命令返回 Err，由调用方报告
+/// let result = executor::execute(Command::Add(vec!["missing.rs".into()], Default::default()), state, &cancel, &mut out).await;
+/// assert!(result.is_err());
+/// # Ok::<(), rustprompt::error::AppError>(())
+/// # }).unwrap();
+/// ```
 pub async fn execute(
-    cmd: Command, 
+    cmd: Command,
     state: Arc<Mutex<AppState>>,
-    engine: &mut ReplEngine,
-) -> Result<(), AppError> {
-    let ignore_config = IgnoreConfig::default();
+    cancel: &CancellationToken,
+    out: &mut dyn Output,
+) -> Result<Effect, AppError> {
+    // 执行前的快照只在命令成功、且确实改变了选择或 prompt 时才保存，供 /undo 恢复
+    // (取消的 /reset、失败的 /add、只打印用法的命令都不留下撤销步骤)
+    let before = is_undoable(&cmd).then(|| state.lock().unwrap().snapshot(activity_label(&cmd)));
+    let result = dispatch(cmd, state.clone(), cancel, out).await;
+    if let (Ok(_), Some(before)) = (&result, before) {
+        let mut st = state.lock().unwrap();
+        if !before.same_context(&st.snapshot(String::new())) {
+            st.push_undo(before);
+        }
+    }
+    result
+}
+
+async fn dispatch(
+    cmd: Command,
+    state: Arc<Mutex<AppState>>,
+    cancel: &CancellationToken,
+    out: &mut dyn Output,
+) -> Result<Effect, AppError> {
+    let mut effect = Effect::None;
+    let ignore_config = state.lock().unwrap().ignore.clone();
 
     // [ADDED] Check the compatibility between current mode and command
     let current_mode = {
@@ -70,143 +883,570 @@ pub async fn execute(
 
     // [MODIFIED] Handle Unknown command specially, prompt before match
     if let Command::Unknown(u) = &cmd {
-        println!("Unknown command: {}", u);
-        return Ok(());
+        // 输错的命令提示相近的命令；Manual 模式下不以 / 开头的输入多半是想写 prompt
+        let hint = match registry::similar(u).as_slice() {
+            [] if !u.starts_with('/') => " — to write prompt text, switch with /mode prompt".to_string(),
+            [] => String::new(),
+            [one] => format!(" — did you mean {}?", one),
+            many => format!(" — did you mean one of {}?", many.join(", ")),
+        };
+        out.warn(format!("Unknown command: {}{}", u, hint));
+        return Ok(Effect::None);
     }
 
     // [MODIFIED] Check validity of other commands
     if !is_command_valid_in_mode(&cmd, &current_mode) {
-        // Get simple command name for printing
-        let cmd_name = match &cmd {
-             Command::Add(_) => "/add",
-             Command::Remove(_) => "/remove",
-             Command::ShowContext => "/context",
-             Command::Copy => "/copy",
-             Command::Reset => "/reset",
-             Command::Help => "/help",
-             Command::Quit => "/quit",
-             Command::Mode(_) => "/mode",
-             Command::Prompt => "/prompt",
-             Command::AppendPromptText(_) => "(text input)",
-             Command::ResetPrompt => "/resetprompt",
-             Command::Unknown(_) => "unknown",
-        };
-        println!("(Note) Command {} is not available in {:?} mode!", cmd_name, current_mode);
-        return Ok(()); 
+        let cmd_name = command_name(&cmd);
+        out.info(format!("(Note) Command {} is not available in {:?} mode!", cmd_name, current_mode));
+        return Ok(Effect::None);
     }
 
+    let mutating = is_mutating(&cmd);
+    let label = if mutating { activity_label(&cmd) } else { String::new() };
+    let name = command_name(&cmd);
+    let budget_before = {
+        let st = state.lock().unwrap();
+        (st.token_count, st.settings.token_budget)
+    };
+    let overrides: Vec<(&str, String)> = {
+        let st = state.lock().unwrap();
+        config_overrides(&cmd).iter().map(|&key| (key, config::current_value(&st, key))).collect()
+    };
+
     match cmd {
-        Command::Add(path) => {
-            info!("Executing /add: {:?}", path);
+        Command::Add(paths, options) if paths.is_empty() && options != AddOptions::default() => {
+            out.warn("Usage: /add [--hidden] [--no-ignore] [--ext rs,toml] <path> [more paths...]".to_string());
+        }
 
-            let scanned = files_scanner::scan_dir(&path, &ignore_config).await?;
-            info!("  -> Scanned {} files", scanned.len());
+        Command::Add(paths, _) if paths.is_empty() && !std::io::stdin().is_terminal() => {
+            out.warn("Usage: /add <path> [more paths...]".to_string());
+        }
 
-            let num_added = {
-                let mut st = state.lock().unwrap();
-                let init_count = st.selected_paths.len();
-                for f in &scanned {
-                    st.selected_paths.insert(f.clone());
-                }
-                let final_count = st.selected_paths.len();
-                st.file_count = final_count;
-                info!("  -> selected_paths increased from {} to {}", init_count, final_count);
-                final_count - init_count
-            };
+        Command::Add(paths, _) if paths.is_empty() => {
+            return Box::pin(execute(Command::Pick, state, cancel, out)).await;
+        }
 
-            if num_added > 0 || scanned.is_empty() {
-                SnippetManager::add_files_snippet(state.clone(), scanned).await?;
-                SnippetManager::update_project_tree_snippet(state.clone(), &ignore_config)?;
-                SnippetManager::rebuild_and_recalc(state.clone())?;
-            } else {
-                info!("  -> No new files added, skipping snippet update");
+        Command::Add(paths, options) => {
+            info!("Executing /add: {:?} ({:?})", paths, options);
+
+            // --hidden / --no-ignore 只作用于这一次扫描
+            let ignore_config = options.apply(&ignore_config);
+
+            // 进度由这里展示: 扫描与读取只负责发送事件；所有发送端 drop 后渲染任务清除进度行并结束
+            let (progress, events) = Progress::channel();
+            let renderer = tokio::spawn(render_progress(events));
+            let result = add_paths(&state, &paths, &ignore_config, progress, cancel).await;
+            let _ = renderer.await;
+            match result? {
+                Some(notes) => {
+                    for note in notes {
+                        out.tagged(note);
+                    }
+                    warn_if_over_memory_cap(&state, out);
+                }
+                None => out.info("(Note) add cancelled, context unchanged".to_string()),
             }
         }
 
-        Command::Remove(path) => {
-            info!("Executing /remove: {:?}", path);
+        Command::Remove(paths) if paths.is_empty() => {
+            out.warn("Usage: /remove <path|glob> [more...]".to_string());
+        }
+
+        Command::Remove(paths) => {
+            info!("Executing /remove: {:?}", paths);
 
-            let scanned = files_scanner::scan_dir(&path, &ignore_config).await?;
-            info!("  -> Scanned {} files (to be removed)", scanned.len());
+            // glob 直接匹配已选路径，不需要扫描文件系统；普通路径照旧扫描
+            // (不跳过二进制文件: /ignore binary off 时加入的二进制文件也要能移除)
+            let scan_config = IgnoreConfig { ignore_binary: false, ..ignore_config.clone() };
+            let cwd = std::env::current_dir().unwrap_or_default();
+            let root = state.lock().unwrap().project_root.clone();
+            let mut to_remove: Vec<PathBuf> = Vec::new();
+            for path in &paths {
+                // 正好是某次 /add 的参数: 删除这个 root，但保留同时被其它 root 覆盖的文件
+                let root_files = state.lock().unwrap().add_roots.remove(path);
+                if let Some((exclusive, shared)) = root_files {
+                    let st = state.lock().unwrap();
+                    let kept = shared.iter().filter(|f| st.selected_paths.contains(*f)).count();
+                    if kept > 0 {
+                        out.info(format!(
+                            "(Note) Kept {} files under {} that are also covered by another /add",
+                            kept,
+                            path.display()
+                        ));
+                    }
+                    to_remove.extend(exclusive);
+                    continue;
+                }
+                if files_scanner::is_glob(&path.to_string_lossy()) {
+                    let matcher = files_scanner::glob_matcher(path)?;
+                    let st = state.lock().unwrap();
+                    let matched: Vec<PathBuf> = st
+                        .selected_paths
+                        .iter()
+                        .filter(|p| matcher.is_match(files_scanner::glob_candidate(p)))
+                        .cloned()
+                        .collect();
+                    if matched.is_empty() {
+                        out.info(format!("(Note) No selected files matched {}", path.display()));
+                    }
+                    to_remove.extend(matched);
+                } else {
+                    // 补全插入的是相对于项目根目录的路径，当前目录不是根目录时按根目录解析
+                    let path = &paths::resolve_in_root(path, &cwd, &root);
+                    // 目录 (包括已被删除的目录): 直接移除其下所有已选文件，不重新扫描
+                    if !path.is_file() {
+                        let under = state.lock().unwrap().selected_under(path);
+                        if !under.is_empty() {
+                            out.info(format!("(Note) Removing {} selected files under {}", under.len(), path.display()));
+                            to_remove.extend(under);
+                            continue;
+                        }
+                    }
+                    // 单个文件不按忽略规则过滤: /add --force 加入的被忽略文件也要能移除
+                    let config = if path.is_file() { scan_config.permissive() } else { scan_config.clone() };
+                    match files_scanner::scan_dir(path, &config).await {
+                        Ok(files) => to_remove.extend(files),
+                        Err(e) if paths.len() == 1 => return Err(e),
+                        Err(e) => out.warn(format!("(Warning) {}", e)),
+                    }
+                }
+            }
+            info!("  -> {} files to be removed", to_remove.len());
 
-            let num_removed = {
+            let (num_removed, tokens_before) = {
                 let mut st = state.lock().unwrap();
                 let init_count = st.selected_paths.len();
-                for f in &scanned {
+                for f in &to_remove {
                     st.selected_paths.remove(f);
-                    st.partial_docs.remove(f);
+                    st.forget_file(f);
                 }
                 let final_count = st.selected_paths.len();
                 st.file_count = final_count;
+                let AppState { add_roots, selected_paths, .. } = &mut *st;
+                add_roots.prune(|f| selected_paths.contains(f));
                 info!("  -> selected_paths decreased from {} to {}", init_count, final_count);
-                init_count - final_count
+                (init_count - final_count, st.token_count)
             };
 
             if num_removed > 0 {
-                SnippetManager::update_project_tree_snippet(state.clone(), &ignore_config)?;
-                SnippetManager::rebuild_and_recalc(state.clone())?;
+                SnippetManager::update_project_tree_snippet(state.clone(), &ignore_config).await?;
+                SnippetManager::rebuild_and_recalc(state.clone()).await?;
+                let (files_after, tokens_after) = {
+                    let st = state.lock().unwrap();
+                    (st.selected_paths.len(), st.token_count)
+                };
+                out.info(delta_summary(files_after + num_removed, files_after, tokens_before, tokens_after));
             } else {
                 info!("  -> No files removed, skipping snippet update");
             }
         }
 
         Command::ShowContext => {
+            // 文件很多时按屏分页 (只在 REPL 的终端上)
+            let mut pager = Pager::new(out);
+            let out = &mut pager;
+            // 旧会话或旧版本留下的重复路径 (同一文件的不同写法、符号链接) 在这里合并
+            let merged = state.lock().unwrap().merge_duplicate_selections();
+            if !merged.is_empty() {
+                SnippetManager::update_project_tree_snippet(state.clone(), &ignore_config).await?;
+                SnippetManager::rebuild_and_recalc(state.clone()).await?;
+                out.info(format!("(Note) Merged {} duplicate selections:", merged.len()));
+                for (dup, kept) in &merged {
+                    out.result(format!(" - {} -> {}", dup.display(), kept.display()));
+                }
+            }
             let st = state.lock().unwrap();
-            println!("Current file_count={}, token_count={}", st.file_count, st.token_count);
-            println!("Selected files:");
+            out.result(format!("Current file_count={}, token_count={}", st.file_count, st.token_count));
+            // token_count 之外的 prompt 单独计数；剩余部分是 XML 标记或自定义模板的文本
+            out.result(format!(
+                "Token breakdown: files {} · project tree {} · markup {} · prompt {} (total {})",
+                format_thousands(st.file_tokens),
+                format_thousands(st.tree_tokens),
+                format_thousands(st.token_count.saturating_sub(st.file_tokens + st.tree_tokens)),
+                format_thousands(st.prompt_tokens),
+                format_thousands(st.token_count + st.prompt_tokens)
+            ));
+            if !st.ignore.extensions.is_empty() {
+                out.result(format!("Extension filter: {} (/only off removes it)", extension_list(&st.ignore.extensions)));
+            }
+            // 按 /add 的原始参数分组，重叠的 root 标出与谁重叠
+            let summaries = st.add_roots.summaries(|f| st.selected_paths.contains(f));
+            if !summaries.is_empty() {
+                out.result("Added roots:".to_string());
+                for s in &summaries {
+                    let mut line = format!(" - from /add {} ({} files", s.path.display(), s.selected);
+                    if s.shared > 0 {
+                        let others: Vec<String> = s.overlaps_with.iter().map(|p| p.display().to_string()).collect();
+                        line.push_str(&format!(", {} also under {}", s.shared, others.join(", ")));
+                    }
+                    line.push(')');
+                    out.result(line.to_string());
+                }
+                let uncovered = st.add_roots.uncovered(&st.selected_paths);
+                if uncovered > 0 {
+                    out.result(format!(" - {} files not under any /add (e.g. followed renames)", uncovered));
+                }
+            }
+            out.result("Selected files:".to_string());
+            let mut stale = 0;
             for p in &st.selected_paths {
-                println!(" - {:?}", p);
+                let shown = paint(Style::Cyan, &format!("{:?}", st.display_path(p)));
+                let mut line = match (st.binary_files.get(p), st.truncated.get(p)) {
+                    (Some(BinaryTransition::KeepLast), _) => format!(" - {} (now binary; last text version kept)", shown),
+                    (Some(_), _) => format!(" - {} (now binary; content replaced by a placeholder)", shown),
+                    (None, Some(size)) => format!(" - {} (truncated; original {})", shown, format_bytes(*size as usize)),
+                    (None, None) => format!(" - {}", shown),
+                };
+                if st.pinned.contains(p) {
+                    line.push_str(PIN_MARK);
+                }
+                // 读取之后在磁盘上被修改或删除的文件
+                let staleness = st.staleness(p);
+                match staleness {
+                    Some(Staleness::Modified) => line.push_str(" (modified)"),
+                    Some(Staleness::Missing) => line.push_str(" (missing)"),
+                    None => {}
+                }
+                stale += staleness.is_some() as usize;
+                out.result(line);
+            }
+            if stale > 0 {
+                out.info(format!("(Note) {} files changed on disk since they were read; /refresh re-reads them", stale));
+            }
+            if !st.pinned.is_empty() {
+                out.info(format!("(Note){} marks pinned files, which /reset keeps", PIN_MARK));
+            }
+        }
+
+        Command::Copy(options) => {
+            info!("Executing /copy{}", options.flags());
+
+            // --split / --part: 参数有误时不刷新、不复制
+            let split_limit = match (&options.split, &options.part) {
+                (Some(_), Some(_)) => {
+                    out.warn("Usage: /copy --split <n_tokens> | --part <i>".to_string());
+                    return Ok(Effect::None);
+                }
+                (Some(limit), None) => match settings::parse_token_count(limit) {
+                    Ok(limit) if limit > 0 => Some(limit),
+                    _ => {
+                        out.warn("Usage: /copy --split <n_tokens> (e.g. /copy --split 100k)".to_string());
+                        return Ok(Effect::None);
+                    }
+                },
+                (None, Some(_)) => match state.lock().unwrap().split_limit {
+                    Some(limit) => Some(limit),
+                    None => {
+                        out.info("(Note) Nothing split yet; use /copy --split <n_tokens> first".to_string());
+                        return Ok(Effect::None);
+                    }
+                },
+                (None, None) => None,
+            };
+            // --backend: 只对这一次复制生效，用于排查某种复制方式
+            let backend = match options.backend.as_deref().map(ClipboardBackend::parse) {
+                None => None,
+                Some(Some(backend)) => Some(backend),
+                Some(None) => {
+                    out.warn("Usage: /copy --backend auto|native|wsl|wayland|osc52|file".to_string());
+                    return Ok(Effect::None);
+                }
+            };
+            let part_index = match options.part.as_deref().map(str::parse::<usize>) {
+                None => 1,
+                Some(Ok(i)) if i > 0 => i,
+                Some(_) => {
+                    out.warn("Usage: /copy --part <i> (parts are numbered from 1)".to_string());
+                    return Ok(Effect::None);
+                }
+            };
+
+            let (mut xml_to_copy, refreshed) = prepare_payload(&state, &ignore_config, "copy", options.refresh, out).await?;
+            let mut part_note = None;
+            if let Some(limit) = split_limit {
+                // 每次都按当前上下文重新拆分，--part 复制的总是最新的内容
+                let (split, oversized, validate) = {
+                    let mut st = state.lock().unwrap();
+                    st.split_limit = Some(limit);
+                    let split = SnippetManager::split_payload(&st, limit)?;
+                    let oversized: Vec<String> = split
+                        .oversized
+                        .iter()
+                        .map(|(path, tokens)| {
+                            format!(
+                                "(Warning) {} alone is {} tokens, over the split limit of {}; it gets a part of its own",
+                                st.display_path(path).display(),
+                                format_thousands(*tokens),
+                                format_budget(limit)
+                            )
+                        })
+                        .collect();
+                    (split, oversized, st.settings.validate_xml)
+                };
+                for warning in oversized {
+                    out.warn(warning);
+                }
+                let count = split.parts.len();
+                let Some(part) = split.parts.into_iter().nth(part_index - 1) else {
+                    out.warn(format!("(Warning) There are only {} parts", count));
+                    return Ok(Effect::None);
+                };
+                if validate {
+                    if let Err(e) = validate_xml(&part.text) {
+                        return Err(AppError::Xml(format!("refusing to copy part {}: {}", part_index, e)));
+                    }
+                }
+                let next = if part_index < count {
+                    format!("; /copy --part {} copies the next one", part_index + 1)
+                } else {
+                    String::new()
+                };
+                part_note = Some(format!(
+                    "(Note) Copied part {} of {} ({} files, {} tokens){}",
+                    part_index,
+                    count,
+                    part.documents,
+                    format_thousands(part.tokens),
+                    next
+                ));
+                xml_to_copy = part.text;
+            }
+            if !options.force {
+                check_context_window(&state, &xml_to_copy, out)?;
+            }
+
+            let copied = copy_text(
+                &state,
+                &xml_to_copy,
+                backend,
+                format!(
+                    "(Note) Content (including project tree + instruction) has been copied to clipboard! ({} files re-read from disk)",
+                    refreshed
+                ),
+                out,
+            );
+            if copied {
+                {
+                    let mut st = state.lock().unwrap();
+                    st.last_copied = Some(xml_to_copy);
+                    st.saved_prompt = st.prompt_text.clone();
+                }
+                if let Some(note) = part_note {
+                    out.info(note);
+                }
             }
         }
 
-        Command::Copy => {
-            info!("Executing /copy (full refresh)");
+        Command::Refresh => {
+            info!("Executing /refresh");
 
-            let paths: Vec<PathBuf> = {
+            let (paths, fingerprints, follow, root) = {
                 let st = state.lock().unwrap();
-                st.selected_paths.iter().cloned().collect()
+                let paths: Vec<PathBuf> = st.selected_paths.iter().cloned().collect();
+                (paths, st.fingerprints.clone(), st.settings.follow_renames, st.project_root.clone())
             };
 
-            SnippetManager::full_refresh(state.clone(), paths, &ignore_config).await?;
+            let missing: Vec<PathBuf> = paths.iter().filter(|p| !p.exists()).cloned().collect();
+            let mut still_missing = Vec::new();
 
-            let xml_to_copy = {
-                let mut st = state.lock().unwrap();
-                let mut final_xml = st.cached_xml.clone();
+            if !missing.is_empty() && follow != FollowRenames::Off {
+                let cwd = std::env::current_dir().unwrap_or_else(|_| PathBuf::from("."));
+                // 优先使用 git 的改名记录，其次按内容指纹扫描项目目录
+                let git_renames = renames::detect_git_renames(&root);
+                let selected_abs: HashSet<PathBuf> = paths.iter().map(|p| renames::absolute_path(p)).collect();
+
+                for old in &missing {
+                    let candidate = git_renames
+                        .get(&renames::absolute_path(old))
+                        .filter(|p| p.exists())
+                        .cloned()
+                        .or_else(|| {
+                            fingerprints.get(old).and_then(|fp| {
+                                renames::find_by_fingerprint(&root, *fp, &ignore_config, &selected_abs)
+                            })
+                        });
 
-                if !st.prompt_text.is_empty() {
-                    let instruction_tag = format!("\n<instruction>\n{}\n</instruction>", st.prompt_text);
+                    let Some(new_path) = candidate else {
+                        still_missing.push(old.clone());
+                        continue;
+                    };
+                    // 换算成已选文件使用的 key (cwd 之下为相对路径)
+                    let new_path = paths::file_key(&new_path, &cwd);
 
-                    if let Some(idx) = final_xml.rfind("</documents>") {
-                        final_xml.insert_str(idx, &instruction_tag);
+                    let follow_it = match follow {
+                        FollowRenames::Auto => {
+                            out.info(format!("(Note) {} appears renamed to {}, following.", old.display(), new_path.display()));
+                            true
+                        }
+                        FollowRenames::Ask => out.confirm(
+                            &format!("{} appears renamed to {} — follow?", old.display(), new_path.display()),
+                            true,
+                        ),
+                        FollowRenames::Off => false,
+                    };
+
+                    if follow_it {
+                        let mut st = state.lock().unwrap();
+                        st.selected_paths.remove(old);
+                        if st.pinned.contains(old) {
+                            st.pinned.insert(new_path.clone());
+                        }
+                        st.forget_file(old);
+                        st.add_roots.rename(old, &new_path);
+                        st.selected_paths.insert(new_path);
+                        st.file_count = st.selected_paths.len();
                     } else {
-                        final_xml.push_str(&instruction_tag);
-                        final_xml.push_str("\n</documents>");
+                        still_missing.push(old.clone());
                     }
-                    st.cached_xml = final_xml.clone();
                 }
-                final_xml
+            } else {
+                still_missing = missing;
+            }
+
+            // expand_roots_on_refresh: 重新展开 /add 过的目录和 glob，加入之后新建的文件
+            let (expand, roots) = {
+                let st = state.lock().unwrap();
+                let roots: Vec<PathBuf> = st.add_roots.iter().map(|r| r.path.clone()).collect();
+                (st.settings.expand_roots_on_refresh, roots)
             };
+            if expand {
+                let mut picked_up = 0;
+                for root in &roots {
+                    // 目录被删掉等情况: 保留 root 原有的展开结果
+                    let Ok(files) = files_scanner::scan_dir(root, &ignore_config).await else {
+                        continue;
+                    };
+                    let mut st = state.lock().unwrap();
+                    for f in st.add_roots.reexpand(root, files) {
+                        if st.selected_paths.insert(f) {
+                            picked_up += 1;
+                        }
+                    }
+                    st.file_count = st.selected_paths.len();
+                }
+                if picked_up > 0 {
+                    out.info(format!("(Note) Picked up {} new files under added directories.", picked_up));
+                }
+            }
+
+            // 仍然找不到的文件从选择中移除
+            if !still_missing.is_empty() {
+                let mut st = state.lock().unwrap();
+                for p in &still_missing {
+                    st.selected_paths.remove(p);
+                    st.forget_file(p);
+                }
+                let selected = st.selected_paths.clone();
+                st.add_roots.prune(|f| selected.contains(f));
+                st.file_count = st.selected_paths.len();
+                out.info(format!("(Note) Removed {} missing files: {}", still_missing.len(), join_paths(&still_missing)));
+            }
 
-            match clipboard::copy_to_clipboard(&xml_to_copy) {
-                Ok(_) => println!("(Note) Content (including project tree + instruction) has been copied to clipboard!"),
-                Err(e) => eprintln!("Failed to copy to clipboard: {:?}", e),
+            // 只重新读取过期的 snippet (包括改名后的新路径和新加入的文件)；/copy --refresh 重新读取全部
+            let (stale, total) = {
+                let st = state.lock().unwrap();
+                (SnippetManager::dirty_files(&st), st.selected_paths.len())
+            };
+            if !stale.is_empty() {
+                SnippetManager::add_files_snippet(state.clone(), stale.clone()).await?;
             }
+            SnippetManager::update_project_tree_snippet(state.clone(), &ignore_config).await?;
+            SnippetManager::rebuild_and_recalc(state.clone()).await?;
+            out.info(format!(
+                "(Note) Refreshed {} stale files ({} unchanged).",
+                stale.len(),
+                total - stale.len()
+            ));
+            report_binary_warnings(&state, out);
+            warn_if_over_memory_cap(&state, out);
         }
 
-        Command::Reset => {
-            info!("Executing /reset");
-            let mut st = state.lock().unwrap();
-            st.selected_paths.clear();
-            st.file_count = 0;
-            st.token_count = 0;
-            st.partial_docs.clear();
-            st.cached_xml.clear();
-            st.prompt_text.clear();
+        Command::Reset(hard, force) => {
+            info!("Executing /reset (hard={})", hard);
+            let (lost_files, prompt_tokens, ask) = {
+                let st = state.lock().unwrap();
+                let lost = st.selected_paths.iter().filter(|p| hard || !st.pinned.contains(*p)).count();
+                (lost, st.prompt_tokens, !force && st.settings.confirm_destructive)
+            };
+            if ask && (lost_files > 0 || prompt_tokens > 0) {
+                let question = format!(
+                    "This clears {} files and the prompt ({} tokens) from the context. Continue?",
+                    lost_files, prompt_tokens
+                );
+                // 必须回答 y (直接回车为 no)；REPL 中回答经过编辑器读取，但不会进入命令历史
+                if !out.confirm(&question, false) {
+                    out.info("(Note) Reset cancelled (/reset --force skips this question)".to_string());
+                    return Ok(Effect::None);
+                }
+            }
+            let kept = {
+                let mut st = state.lock().unwrap();
+                if hard {
+                    st.pinned.clear();
+                }
+                if st.pinned.is_empty() {
+                    st.selected_paths.clear();
+                    st.add_roots.clear();
+                    st.file_count = 0;
+                    st.token_count = 0;
+                    st.forget_all_files();
+                    st.muted.clear();
+                } else {
+                    // 固定的文件连同 snippet 一起保留，其余文件与 /remove 一样丢弃
+                    let dropped: Vec<PathBuf> =
+                        st.selected_paths.iter().filter(|p| !st.pinned.contains(*p)).cloned().collect();
+                    for p in &dropped {
+                        st.selected_paths.remove(p);
+                        st.forget_file(p);
+                    }
+                    let selected = st.selected_paths.clone();
+                    st.add_roots.prune(|f| selected.contains(f));
+                    st.file_count = st.selected_paths.len();
+                }
+                st.doc_ids.clear();
+                st.cached_xml.clear();
+                st.prompt_text.clear();
+                st.prompt_tokens = 0;
+                st.last_copied = None;
+                if st.watch.take().is_some() {
+                    out.info("(Note) Stopped watching files".to_string());
+                }
+                st.pinned.len()
+            };
+            if kept > 0 {
+                SnippetManager::rebuild_and_recalc(state.clone()).await?;
+                out.info(format!("(Note) Kept {} pinned files; /reset --hard clears them too", kept));
+            }
+
+            info!("  -> Context cleared except {} pinned files", kept);
+        }
 
-            info!("  -> All context cleared (files, partial_docs, token_count, prompt_text)");
+        Command::Help(Some(topic)) if registry::find_topic(&topic).is_some() => {
+            let spec = registry::find_topic(&topic).unwrap();
+            out.result(spec.usage.to_string());
+            out.result(format!("  {}", spec.description));
+            if !spec.help.is_empty() {
+                out.result(String::new());
+                for line in spec.help.lines() {
+                    out.result(format!("  {}", line));
+                }
+            }
+            let modes = match (spec.manual, spec.prompt) {
+                (true, true) => "manual and prompt mode",
+                (true, false) => "manual mode only",
+                _ => "prompt mode only",
+            };
+            out.result(String::new());
+            out.result(format!("  Available in {}", modes));
+            if !spec.aliases.is_empty() {
+                out.result(format!("  Aliases: {}", spec.aliases.join(", ")));
+            }
         }
 
-        Command::Help => {
+        Command::Help(topic) => {
+            if let Some(topic) = topic {
+                out.info(format!("(Note) No command named {}; showing all commands", topic));
+            }
             // [MODIFIED] Show commands for different modes with aligned descriptions
             let st = state.lock().unwrap();
             let mode = st.mode.clone();
@@ -215,34 +1455,36 @@ pub async fn execute(
             // [ADDED] Define alignment width
             let width = 25;
 
-            match mode {
-                ReplMode::Manual => {
-                    println!("Available commands (Manual mode):");
-                    println!("  {:<width$} - {}", "/add <path>", "Add files or directories to context", width=width);
-                    println!("  {:<width$} - {}", "/remove <path>", "Remove files or directories from context", width=width);
-                    println!("  {:<width$} - {}", "/context", "Show current context info (file count, token count)", width=width);
-                    println!("  {:<width$} - {}", "/copy", "Copy current context (with project tree and prompt) to clipboard", width=width);
-                    println!("  {:<width$} - {}", "/reset", "Clear all context and prompt", width=width);
-                    println!("  {:<width$} - {}", "/mode [manual|prompt]", "View or switch modes", width=width);
-                    println!("  {:<width$} - {}", "/help", "Show this help message", width=width);
-                    println!("  {:<width$} - {}", "/quit", "Exit program", width=width);
-                }
-                ReplMode::Prompt => {
-                    println!("Available commands (Prompt mode):");
-                    println!("  {:<width$} - {}", "/mode [manual|prompt]", "View or switch modes", width=width);
-                    println!("  {:<width$} - {}", "/prompt", "View current accumulated prompt", width=width);
-                    println!("  {:<width$} - {}", "/context", "Show current context info (file count, token count)", width=width);
-                    println!("  {:<width$} - {}", "/copy", "Copy current context (with project tree and prompt) to clipboard", width=width);
-                    println!("  {:<width$} - {}", "/help", "Show this help message", width=width);
-                    println!("  {:<width$} - {}", "/quit", "Exit program", width=width);
-                    println!("\nIn prompt mode:");
-                    println!("  Direct input (not starting with '/') will be appended to the prompt.");
-                }
+            let title = match mode {
+                ReplMode::Manual => "Available commands (Manual mode):",
+                ReplMode::Prompt => "Available commands (Prompt mode):",
+            };
+            out.result(title.to_string());
+            for spec in registry::for_mode(&mode) {
+                out.result(format!("  {:<width$} - {}", spec.usage, spec.description, width = width));
             }
+            if mode == ReplMode::Prompt {
+                out.result("\nIn prompt mode:".to_string());
+                out.result("  Direct input (not starting with '/') will be appended to the prompt.".to_string());
+            }
+            out.result("\nRun /help <command> for options and examples.".to_string());
         }
 
-        Command::Quit => {
-            println!("(Note) Exiting...");
+        Command::Quit(force) => {
+            let (unsaved, prompt_tokens, ask) = {
+                let st = state.lock().unwrap();
+                (st.prompt_unsaved(), st.prompt_tokens, !force && st.settings.confirm_destructive)
+            };
+            if ask && unsaved {
+                let question = format!("The prompt ({} tokens) has not been saved or copied. Quit anyway?", prompt_tokens);
+                if !out.confirm(&question, false) {
+                    out.info("(Note) Quit cancelled; keep it with /save or /copy (/quit --force skips this question)".to_string());
+                    return Ok(Effect::None);
+                }
+            }
+            state.lock().unwrap().watch = None;
+            out.info("(Note) Exiting...".to_string());
+            effect = Effect::Quit;
         }
 
         Command::Mode(opt) => {
@@ -250,20 +1492,20 @@ pub async fn execute(
             match opt {
                 None => {
                     match st.mode {
-                        ReplMode::Manual => println!("Current mode: manual"),
-                        ReplMode::Prompt => println!("Current mode: prompt"),
+                        ReplMode::Manual => out.result("Current mode: manual".to_string()),
+                        ReplMode::Prompt => out.result("Current mode: prompt".to_string()),
                     }
                 }
                 Some(m) => {
                     let mode_str = m.to_lowercase();
                     if mode_str == "manual" {
                         st.mode = ReplMode::Manual;
-                        println!("Switched to manual mode");
+                        out.result("Switched to manual mode".to_string());
                     } else if mode_str == "prompt" {
                         st.mode = ReplMode::Prompt;
-                        println!("Switched to prompt mode");
+                        out.result("Switched to prompt mode".to_string());
                     } else {
-                        println!("Unknown mode: {} (available: manual, prompt)", m);
+                        out.warn(format!("Unknown mode: {} (available: manual, prompt)", m));
                     }
                 }
             }
@@ -274,36 +1516,1350 @@ pub async fn execute(
             {
                 let mut st = state.lock().unwrap();
                 if st.mode == ReplMode::Manual {
-                    println!("(Note) Currently in manual mode, automatically switching to prompt mode...");
+                    out.info("(Note) Currently in manual mode, automatically switching to prompt mode...".to_string());
                     st.mode = ReplMode::Prompt;
                 }
             }
-            // Enter multiline edit mode
-            engine.enter_multiline_mode()?;
-            println!("(Note) Entering multiline edit mode. Type :submit and press Enter to finish editing.");
+            // 多行编辑由 REPL 切换
+            effect = Effect::EnterMultiline;
         }
 
         Command::ResetPrompt => {
             let mut st = state.lock().unwrap();
             st.prompt_text.clear();
-            println!("(Note) Prompt cache has been cleared.");
+            st.prompt_tokens = 0;
+            out.info("(Note) Prompt cache has been cleared.".to_string());
         }
 
         Command::AppendPromptText(line) => {
+            {
+                let mut st = state.lock().unwrap();
+                if st.mode != ReplMode::Prompt {
+                    out.error("Internal error: Attempting to append prompt text in non-prompt mode.".to_string());
+                    return Ok(Effect::None);
+                }
+                let tokens_before = st.prompt_tokens;
+                st.append_prompt_line(&line);
+                out.info(format!(
+                    "(Note) Added to prompt: {} tokens (prompt total {})",
+                    format_delta(tokens_before, st.prompt_tokens),
+                    format_thousands(st.prompt_tokens)
+                ));
+            }
+            // 提到的文件 (@path) 随 prompt 一起加入，/undo 一并撤销
+            add_mentions(&state, &line, cancel, out).await?;
+        }
+
+        Command::AddMentions(text) => {
+            add_mentions(&state, &text, cancel, out).await?;
+        }
+        Command::Set(key, value) => {
+            match (key, value) {
+                (None, _) => {
+                    let st = state.lock().unwrap();
+                    out.result("Settings:".to_string());
+                    for k in Settings::KEYS {
+                        out.result(format!("  {:<20} = {}", k, st.settings.get(k).unwrap_or_default()));
+                    }
+                }
+                (Some(k), None) => {
+                    let st = state.lock().unwrap();
+                    match st.settings.get(&k) {
+                        Some(v) => out.result(format!("{} = {}", k, v)),
+                        None => out.warn(format!("Unknown setting: {} (available: {})", k, Settings::KEYS.join(", "))),
+                    }
+                }
+                (Some(k), Some(v)) => {
+                    let (result, tokens_before, paths) = {
+                        let mut st = state.lock().unwrap();
+                        let result = st.settings.set(&k, &v);
+                        let paths: Vec<PathBuf> = st.selected_paths.iter().cloned().collect();
+                        (result, st.token_count, paths)
+                    };
+                    if let Err(msg) = result {
+                        out.warn(msg);
+                        return Ok(Effect::None);
+                    }
+                    out.info(format!("(Note) {} = {}", k, v));
+
+                    // 影响 snippet 内容的选项需要重新生成; 影响拼装方式的只需重新 merge
+                    let regenerated = match k.as_str() {
+                        "indent_compression" if !paths.is_empty() => {
+                            SnippetManager::full_refresh(state.clone(), paths, &ignore_config).await?;
+                            report_binary_warnings(&state, out);
+                            true
+                        }
+                        "group_by_language" | "language_priority" if !paths.is_empty() => {
+                            SnippetManager::rebuild_and_recalc(state.clone()).await?;
+                            true
+                        }
+                        _ => false,
+                    };
+                    if k == "color" {
+                        style::set_enabled(state.lock().unwrap().settings.color);
+                    }
+                    if k == "edit_mode" {
+                        effect = Effect::RebuildEditor;
+                    }
+                    if k == "auto_refresh" {
+                        // 无论新值是什么，先停止旧的后台任务
+                        let mut st = state.lock().unwrap();
+                        if let Some(task) = st.auto_refresh_task.take() {
+                            task.abort();
+                        }
+                        if let AutoRefresh::Interval(secs) = st.settings.auto_refresh {
+                            // 后台任务的输出要经过 REPL 的 ExternalPrinter，由 REPL 启动
+                            effect = Effect::StartAutoRefresh(secs);
+                        }
+                    }
+                    if k == "cache_merged" {
+                        // 关闭缓存时立即释放合并结果; 重新开启时重建
+                        SnippetManager::rebuild_and_recalc(state.clone()).await?;
+                    }
+                    if regenerated {
+                        let tokens_after = state.lock().unwrap().token_count;
+                        let delta = tokens_after as i64 - tokens_before as i64;
+                        out.info(format!("(Note) Tokens: {} -> {} ({:+})", tokens_before, tokens_after, delta));
+                    }
+                }
+            }
+        }
+
+        Command::Stats => {
+            let st = state.lock().unwrap();
+            let snippet_bytes: usize = st.partial_docs.values().map(|s| s.len()).sum();
+            let cap = st.settings.memory_cap_mb;
+
+            out.result("Context statistics:".to_string());
+            out.result(format!("  {:<16}: {}", "Files", st.file_count));
+            out.result(format!("  {:<16}: {} (including project tree)", "Documents", st.partial_docs.len()));
+            out.result(format!("  {:<16}: {} ({})", "Tokens", st.token_count, st.token_encoding.name()));
+            out.result(format!("  {:<16}: {}", "Sort order", st.sort_strategy.as_str()));
+            out.result(format!("  {:<16}: {}", "Format", st.output_format.describe()));
+            out.result(format!("  {:<16}: {}", "Snippet memory", format_bytes(snippet_bytes)));
+            if st.settings.cache_merged {
+                out.result(format!("  {:<16}: {} (cached)", "Merged XML", format_bytes(st.cached_xml.len())));
+            } else {
+                out.result(format!("  {:<16}: not cached (assembled on demand)", "Merged XML"));
+            }
+            if cap > 0 {
+                out.result(format!("  {:<16}: {} (soft cap {} MB)", "Total memory", format_bytes(st.memory_usage()), cap));
+            } else {
+                out.result(format!("  {:<16}: {}", "Total memory", format_bytes(st.memory_usage())));
+            }
+            for overlap in SnippetManager::overlaps(&st) {
+                out.result(format!("  {:<16}: {}", "Duplication", overlap_summary(&overlap)));
+            }
+        }
+
+        Command::Validate => {
+            let payload = {
+                let st = state.lock().unwrap();
+                if let OutputFormat::Custom { name, .. } = &st.output_format {
+                    out.info(format!("(Note) Output format is the custom template {}; XML validation does not apply.", name));
+                    return Ok(Effect::None);
+                }
+                SnippetManager::assemble_payload(&st)?
+            };
+            match validate_xml(&payload) {
+                Ok(report) => {
+                    out.info("(Note) XML is well-formed.".to_string());
+                    out.result(format!("  {:<12}: {}", "Documents", report.documents));
+                    out.result(format!("  {:<12}: {} (all balanced)", "Elements", report.elements));
+                    out.result(format!("  {:<12}: {}", "Max depth", report.max_depth));
+                    out.result(format!("  {:<12}: {}", "Size", format_bytes(payload.len())));
+                }
+                Err(e) => out.warn(format!("(Warning) {}", e)),
+            }
+        }
+
+        Command::Sort(None) => {
+            let st = state.lock().unwrap();
+            out.result(format!("Sort order: {} (available: {})", st.sort_strategy.as_str(), SortStrategy::NAMES.join(", ")));
+        }
+
+        Command::Sort(Some(name)) => {
+            let strategy = match SortStrategy::parse(&name) {
+                Some(s) => s,
+                None => {
+                    out.warn(format!("Unknown sort strategy: {} (available: {})", name, SortStrategy::NAMES.join(", ")));
+                    return Ok(Effect::None);
+                }
+            };
+            state.lock().unwrap().sort_strategy = strategy;
+            SnippetManager::rebuild_and_recalc(state.clone()).await?;
+            out.info(format!("(Note) Sort order: {}", strategy.as_str()));
+        }
+
+        Command::ListFiles => {
+            let mut pager = Pager::new(out);
+            let out = &mut pager;
+            let st = state.lock().unwrap();
+            if st.selected_paths.is_empty() {
+                out.info("(Note) No files selected.".to_string());
+                return Ok(Effect::None);
+            }
+            // token 数在生成 snippet 时已缓存，这里不读文件也不重新计算
+            let mut rows: Vec<(&PathBuf, usize)> = st
+                .selected_paths
+                .iter()
+                .map(|p| (p, st.doc_tokens.get(p).copied().unwrap_or(0)))
+                .collect();
+            rows.sort_by(|a, b| b.1.cmp(&a.1).then_with(|| a.0.cmp(b.0)));
+
+            let total: usize = rows.iter().map(|(_, t)| t).sum();
+            let width = total.to_string().len();
+            for (path, tokens) in &rows {
+                let muted = if st.muted.contains(*path) { "  (muted)" } else { "" };
+                let pin = if st.pinned.contains(*path) { PIN_MARK } else { "" };
+                out.result(format!(
+                    "  {}  {}{}{}",
+                    paint(Style::Bold, &format!("{:>width$}", tokens, width = width)),
+                    paint(Style::Cyan, &st.display_path(path).display().to_string()),
+                    pin,
+                    muted
+                ));
+            }
+            out.result(format!("  {:>width$}  total ({} files; payload incl. tree and markup: {} tokens)", total, rows.len(), st.token_count, width = width));
+        }
+
+        Command::Which(id) | Command::Show(id) if id.is_empty() => {
+            out.warn("Usage: /which <id> or /show <id> (e.g. D14)".to_string());
+        }
+
+        Command::Which(id) => {
+            let st = state.lock().unwrap();
+            match st.resolve_doc_id(&id) {
+                Some((_, p)) if p.as_os_str() == PROJECT_TREE_VIRTUAL_PATH => out.result(format!("{} -> project tree", id)),
+                Some((_, p)) => out.result(format!("{} -> {}", id, p.display())),
+                None => match (st.doc_ids.first(), st.doc_ids.last()) {
+                    (Some((first, _)), Some((last, _))) => {
+                        out.info(format!("(Note) No document with ID {} (IDs run {}..{})", id, first, last))
+                    }
+                    _ => out.info("(Note) No documents in the context yet.".to_string()),
+                },
+            }
+        }
+
+        Command::Show(id) => {
+            let document = {
+                let st = state.lock().unwrap();
+                st.resolve_doc_id(&id)
+                    .and_then(|(i, p)| st.partial_docs.get(p).map(|s| place_document(s, i)))
+            };
+            match document {
+                Some(document) => print_paged(&document, out),
+                None => out.info(format!("(Note) No document with ID {}", id)),
+            }
+        }
+
+        Command::Mute(path) => {
+            // `./a.rs`、`sub/../a.rs` 或相对于项目根目录的写法都指向同一个已选文件
+            let path = pin_key(&state, &path);
+            let now_muted = {
+                let mut st = state.lock().unwrap();
+                if !st.selected_paths.contains(&path) {
+                    out.info(format!("(Note) {} is not in the context.", path.display()));
+                    return Ok(Effect::None);
+                }
+                let now_muted = !st.muted.remove(&path);
+                if now_muted {
+                    st.muted.insert(path.clone());
+                }
+                now_muted
+            };
+            SnippetManager::rebuild_and_recalc(state.clone()).await?;
+            out.info(format!(
+                "(Note) {} {}",
+                if now_muted { "Muted" } else { "Unmuted" },
+                path.display()
+            ));
+        }
+
+        Command::Dashboard => {
+            dashboard::run(state.clone()).await?;
+        }
+
+        Command::Format(None, _) => {
+            let st = state.lock().unwrap();
+            out.result(format!("Format: {}", st.output_format.describe()));
+            out.result(format!("Templates: {}", template::available_templates().join(", ")));
+        }
+
+        Command::Format(Some(kind), name) => {
+            let format = match kind.as_str() {
+                "xml" => OutputFormat::Xml,
+                "custom" => {
+                    let name = name.unwrap_or_else(|| state.lock().unwrap().settings.template.clone());
+                    if name.is_empty() {
+                        out.warn("Usage: /format custom <name> (or /set template <name>)".to_string());
+                        return Ok(Effect::None);
+                    }
+                    match template::find_template(&name) {
+                        Ok((name, source)) => OutputFormat::Custom { name, source },
+                        Err(msg) => {
+                            out.warn(msg);
+                            return Ok(Effect::None);
+                        }
+                    }
+                }
+                other => {
+                    out.warn(format!("Unknown format: {} (available: xml, custom <name>)", other));
+                    return Ok(Effect::None);
+                }
+            };
+
+            // 先试渲染一次，模板有错误时保留原来的格式
+            let previous = {
+                let mut st = state.lock().unwrap();
+                std::mem::replace(&mut st.output_format, format)
+            };
+            if let Err(e) = SnippetManager::rebuild_and_recalc(state.clone()).await {
+                state.lock().unwrap().output_format = previous;
+                SnippetManager::rebuild_and_recalc(state.clone()).await?;
+                out.warn(format!("(Warning) {}", e));
+                return Ok(Effect::None);
+            }
+            let st = state.lock().unwrap();
+            out.info(format!("(Note) Format: {} ({} tokens)", st.output_format.describe(), st.token_count));
+        }
+
+        Command::Model(None) => {
+            let (current, model) = {
+                let st = state.lock().unwrap();
+                (st.token_encoding, st.model.clone())
+            };
+            out.result("Token encodings:".to_string());
+            for encoding in Encoding::ALL {
+                let marker = if encoding == current { "*" } else { " " };
+                out.result(format!("  {} {:<12} ({})", marker, encoding.name(), encoding.models()));
+            }
+            if let Some(window) = model.as_deref().and_then(tokenizer::context_window) {
+                out.result(format!(
+                    "Model: {} (context window {}; /copy refuses larger payloads)",
+                    model.as_deref().unwrap_or_default(),
+                    format_budget(window)
+                ));
+            }
+        }
+
+        Command::Model(Some(name)) => {
+            let Some(encoding) = Encoding::parse(&name) else {
+                let names: Vec<&str> = Encoding::ALL.iter().map(|e| e.name()).collect();
+                out.warn(format!("Unknown model or encoding: {} (available: {})", name, names.join(", ")));
+                return Ok(Effect::None);
+            };
+            // 模型名决定 /copy 的上下文窗口上限；编码名没有上限
+            let model = tokenizer::context_window(&name).map(|_| name.trim().to_lowercase());
+            let window = model.as_deref().and_then(tokenizer::context_window);
+            let window_note = window.map(|w| format!("; context window {}", format_budget(w))).unwrap_or_default();
+            let tokens_before = {
+                let mut st = state.lock().unwrap();
+                st.model = model;
+                if st.token_encoding == encoding {
+                    out.info(format!("(Note) Already using {}{}", encoding.name(), window_note));
+                    return Ok(Effect::None);
+                }
+                st.token_count
+            };
+            // 先加载词表，失败时保持原来的编码
+            tokenizer::tokenizer_for(encoding)?;
+            tokenizer::set_active_encoding(encoding);
+            state.lock().unwrap().token_encoding = encoding;
+            SnippetManager::recount_tokens(state.clone(), cancel).await?;
+            SnippetManager::rebuild_and_recalc_with_cancel(state.clone(), cancel).await?;
+            let tokens_after = state.lock().unwrap().token_count;
+            out.info(format!(
+                "(Note) Token encoding: {} (tokens: {} -> {}){}",
+                encoding.name(),
+                tokens_before,
+                tokens_after,
+                window_note
+            ));
+        }
+
+        Command::Preview => {
+            let st = state.lock().unwrap();
+            let manifest = SnippetManager::manifest(&st);
+            if manifest.documents.is_empty() && manifest.muted.is_empty() {
+                out.info("(Note) No documents in the context yet.".to_string());
+                return Ok(Effect::None);
+            }
+
+            let grouped = if manifest.grouped_by_language { ", grouped by language" } else { "" };
+            out.result(format!(
+                "Payload preview ({}, {} documents{}):",
+                manifest.format,
+                manifest.documents.len(),
+                grouped
+            ));
+            let width = manifest.documents.iter().map(|d| d.id.len()).max().unwrap_or(2);
+            for doc in &manifest.documents {
+                let source = if doc.source.as_os_str() == PROJECT_TREE_VIRTUAL_PATH {
+                    "(project tree)".to_string()
+                } else {
+                    doc.source.display().to_string()
+                };
+                let flag = match doc.truncated {
+                    Some(size) => format!("  (truncated, original {})", format_bytes(size as usize)),
+                    None => String::new(),
+                };
+                out.result(format!("  {:<width$}  {:>7}  {}{}", doc.id, doc.tokens, source, flag, width = width));
+            }
+            for doc in &manifest.muted {
+                out.result(format!("  {:<width$}  {:>7}  {}  (muted, excluded)", "-", doc.tokens, doc.source.display(), width = width));
+            }
+
+            match &manifest.instruction {
+                InstructionPlacement::None => out.result("  Instruction: none (prompt is empty)".to_string()),
+                InstructionPlacement::AfterDocument(Some(id)) => out.result(format!(
+                    "  Instruction: after {}, before </documents> ({} tokens)",
+                    id, manifest.prompt_tokens
+                )),
+                InstructionPlacement::AfterDocument(None) => {
+                    out.result(format!("  Instruction: inside empty <documents> ({} tokens)", manifest.prompt_tokens))
+                }
+                InstructionPlacement::Template => {
+                    out.result(format!("  Instruction: placed by the template ({} tokens)", manifest.prompt_tokens))
+                }
+            }
+            out.result(format!(
+                "  Total: {} tokens, ~{} ({})",
+                manifest.total_tokens,
+                format_bytes(manifest.approx_bytes),
+                st.token_encoding.name()
+            ));
+
+            let dirty = SnippetManager::dirty_files(&st).len();
+            if dirty > 0 && st.settings.auto_refresh == AutoRefresh::BeforeCopy {
+                out.info(format!("(Note) {} files changed on disk; /copy will re-read them first.", dirty));
+            }
+        }
+
+        Command::Export(path, _) if path.as_os_str().is_empty() => {
+            out.warn("Usage: /export [--force] <path>".to_string());
+        }
+
+        Command::Export(path, force) => {
+            info!("Executing /export: {:?}", path);
+            if !force && tokio::fs::try_exists(&path).await.unwrap_or(false) {
+                return Err(AppError::General(anyhow!(
+                    "{} already exists (use /export --force {} to overwrite)",
+                    path.display(),
+                    path.display()
+                )));
+            }
+
+            let (payload, refreshed) = prepare_payload(&state, &ignore_config, "export", false, out).await?;
+            if let Some(parent) = path.parent().filter(|p| !p.as_os_str().is_empty()) {
+                tokio::fs::create_dir_all(parent).await.map_err(|e| AppError::io(parent, e))?;
+            }
+            tokio::fs::write(&path, &payload).await.map_err(|e| AppError::io(&path, e))?;
+            {
+                let mut st = state.lock().unwrap();
+                st.saved_prompt = st.prompt_text.clone();
+            }
+
+            let tokens = tokenizer::calculate_tokens_in_string(&payload)?;
+            out.info(format!(
+                "(Note) Wrote context ({}, {} tokens) to {} ({} files re-read from disk)",
+                format_bytes(payload.len()),
+                tokens,
+                path.display(),
+                refreshed
+            ));
+        }
+
+        Command::Save(Some(name)) | Command::Load(Some(name)) if !is_session_name(&name) => {
+            out.info(format!("(Note) Invalid session name: {} (use letters, digits, '-' and '_')", name));
+        }
+
+        Command::Save(name) => {
+            let path = session::session_path(name.as_deref());
+            let saved = Session::capture(&state.lock().unwrap());
+            session::save(&path, &saved).await?;
+            state.lock().unwrap().saved_prompt = saved.prompt_text.clone();
+            out.info(format!(
+                "(Note) Saved session ({} files) to {}",
+                saved.selected_paths.len(),
+                path.display()
+            ));
+        }
+
+        Command::Load(name) => {
+            info!("Executing /load: {:?}", name);
+            let path = session::session_path(name.as_deref());
+            let saved = session::load(&path).await?;
+
+            let current = state.lock().unwrap().selected_paths.len();
+            if current > 0
+                && !out.confirm(
+                    &format!("Replace the current context ({} files) with {}?", current, path.display()),
+                    true,
+                )
+            {
+                out.info("(Note) Load cancelled.".to_string());
+                return Ok(Effect::None);
+            }
+
+            // 编码和模板在修改状态之前解析，无法使用时给出警告并回到默认值
+            let encoding = match Encoding::parse(&saved.token_encoding) {
+                Some(encoding) => encoding,
+                None => {
+                    if !saved.token_encoding.is_empty() {
+                        out.warn(format!("(Warning) Unknown token encoding {}, keeping the current one", saved.token_encoding));
+                    }
+                    state.lock().unwrap().token_encoding
+                }
+            };
+            tokenizer::tokenizer_for(encoding)?;
+            let format = if saved.template.is_empty() {
+                OutputFormat::Xml
+            } else {
+                match template::find_template(&saved.template) {
+                    Ok((name, source)) => OutputFormat::Custom { name, source },
+                    Err(msg) => {
+                        out.warn(format!("(Warning) {}; using xml", msg));
+                        OutputFormat::Xml
+                    }
+                }
+            };
+
+            let (paths, missing): (Vec<PathBuf>, Vec<PathBuf>) =
+                saved.selected_paths.iter().cloned().partition(|p| p.is_file());
+            for p in &missing {
+                out.warn(format!("(Warning) Skipping missing file: {}", p.display()));
+            }
+
+            tokenizer::set_active_encoding(encoding);
+            {
+                let mut st = state.lock().unwrap();
+                st.selected_paths = paths.iter().cloned().collect();
+                st.file_count = st.selected_paths.len();
+                st.add_roots.clear();
+                for root in &saved.add_roots {
+                    let files: Vec<PathBuf> =
+                        root.files.iter().filter(|f| st.selected_paths.contains(*f)).cloned().collect();
+                    if !files.is_empty() {
+                        st.add_roots.record(&root.path, files);
+                    }
+                }
+                st.muted = saved.muted.iter().filter(|p| st.selected_paths.contains(*p)).cloned().collect();
+                st.pinned = saved.pinned.iter().filter(|p| st.selected_paths.contains(*p)).cloned().collect();
+                st.doc_ids.clear();
+                st.cached_xml.clear();
+                st.token_encoding = encoding;
+                st.model = saved.model.clone();
+                st.output_format = format;
+                st.sort_strategy = SortStrategy::parse(&saved.sort_strategy).unwrap_or_default();
+                st.mode = saved.mode();
+                st.prompt_text = saved.prompt_text.clone();
+                st.saved_prompt = saved.prompt_text.clone();
+                st.recalc_prompt_tokens();
+                st.ignore = saved.ignore.clone();
+            }
+            SnippetManager::full_refresh(state.clone(), paths, &saved.ignore).await?;
+
+            let st = state.lock().unwrap();
+            let skipped = if missing.is_empty() {
+                String::new()
+            } else {
+                format!(", {} missing skipped", missing.len())
+            };
+            out.info(format!(
+                "(Note) Loaded {}: {} files, {} tokens{}",
+                path.display(),
+                st.file_count,
+                st.token_count,
+                skipped
+            ));
+        }
+
+        Command::Usage(true) => {
+            state.lock().unwrap().usage.reset().map_err(|e| AppError::General(anyhow!("Failed to reset usage statistics: {}", e)))?;
+            out.info("(Note) Usage statistics cleared.".to_string());
+        }
+
+        Command::Usage(false) => {
+            let (enabled, stats) = {
+                let st = state.lock().unwrap();
+                (st.settings.usage_stats, st.usage.report())
+            };
+            if !enabled {
+                out.info("(Note) Usage statistics are off; enable them with /set usage_stats on (stored locally only).".to_string());
+            }
+            if stats.is_empty() {
+                if enabled {
+                    out.info("(Note) Nothing recorded yet.".to_string());
+                }
+                return Ok(Effect::None);
+            }
+            print_usage_report(&stats, out);
+        }
+
+        Command::Undo => {
+            // 跳过与当前上下文相同的快照 (之后的命令又改回了同样的选择和 prompt)
+            let (snapshot, files_before, prompt_before) = {
+                let mut st = state.lock().unwrap();
+                let current = st.snapshot(String::new());
+                let mut found = None;
+                while let Some(snapshot) = st.undo_stack.pop_back() {
+                    if !snapshot.same_context(&current) {
+                        found = Some(snapshot);
+                        break;
+                    }
+                }
+                (found, current.selected_paths.len(), current.prompt_text)
+            };
+            let Some(snapshot) = snapshot else {
+                out.info("(Note) Nothing to undo.".to_string());
+                return Ok(Effect::None);
+            };
+
+            // 恢复选择；不在快照中的文件丢弃 snippet，重新选中的文件需要重新读取
+            let to_read: Vec<PathBuf> = {
+                let mut st = state.lock().unwrap();
+                let dropped: Vec<PathBuf> =
+                    st.selected_paths.difference(&snapshot.selected_paths).cloned().collect();
+                for p in &dropped {
+                    st.forget_file(p);
+                }
+                st.selected_paths = snapshot.selected_paths.clone();
+                st.file_count = st.selected_paths.len();
+                st.add_roots = snapshot.add_roots.clone();
+                st.muted = snapshot.muted.clone();
+                st.pinned = snapshot.pinned.clone();
+                st.prompt_text = snapshot.prompt_text.clone();
+                st.recalc_prompt_tokens();
+                st.selected_paths
+                    .iter()
+                    .filter(|p| !st.partial_docs.contains_key(*p))
+                    .cloned()
+                    .collect()
+            };
+            SnippetManager::add_files_snippet(state.clone(), to_read).await?;
+            SnippetManager::update_project_tree_snippet(state.clone(), &ignore_config).await?;
+            SnippetManager::rebuild_and_recalc(state.clone()).await?;
+
+            let prompt = if prompt_before == snapshot.prompt_text { "unchanged" } else { "restored" };
+            out.info(format!(
+                "(Note) Undid {} (files: {} -> {}, prompt {})",
+                snapshot.label,
+                files_before,
+                snapshot.selected_paths.len(),
+                prompt
+            ));
+        }
+
+        Command::Ignore(sub, value) => {
+            // 修改后只影响之后的扫描和项目树，已选文件保持不变
+            let changed = {
+                let mut st = state.lock().unwrap();
+                match (sub.as_deref().unwrap_or("list"), value) {
+                    ("list", _) => {
+                        print_ignore_rules(&st.ignore, out);
+                        false
+                    }
+                    (rule @ ("hidden" | "gitignore" | "node_modules" | "binary"), Some(v)) => match settings::parse_bool(&v) {
+                        Ok(on) => {
+                            match rule {
+                                "hidden" => st.ignore.ignore_hidden = on,
+                                "gitignore" => st.ignore.use_gitignore = on,
+                                "binary" => st.ignore.ignore_binary = on,
+                                _ => st.ignore.ignore_node_modules = on,
+                            }
+                            out.info(format!("(Note) ignore {} = {}", rule, if on { "on" } else { "off" }));
+                            true
+                        }
+                        Err(e) => {
+                            out.info(format!("(Note) {}", e));
+                            false
+                        }
+                    },
+                    ("add", Some(pattern)) => {
+                        if let Err(e) = IgnoreConfig::validate_pattern(&pattern) {
+                            out.info(format!("(Note) Invalid pattern {}: {}", pattern, e));
+                            false
+                        } else if st.ignore.patterns.contains(&pattern) {
+                            out.info(format!("(Note) {} is already ignored.", pattern));
+                            false
+                        } else {
+                            out.info(format!("(Note) Ignoring {}", pattern));
+                            st.ignore.patterns.push(pattern);
+                            true
+                        }
+                    }
+                    _ => {
+                        out.warn("Usage: /ignore [list] | /ignore hidden|gitignore|node_modules|binary on|off | /ignore add <pattern>".to_string());
+                        false
+                    }
+                }
+            };
+            if changed {
+                let ignore = state.lock().unwrap().ignore.clone();
+                SnippetManager::update_project_tree_snippet(state.clone(), &ignore).await?;
+                SnippetManager::rebuild_and_recalc(state.clone()).await?;
+                out.info("(Note) Already selected files are kept; the new rules apply to later scans and the project tree.".to_string());
+            }
+        }
+
+        Command::Unignore(pattern) if pattern.is_empty() => {
+            out.warn("Usage: /unignore <pattern>".to_string());
+        }
+
+        Command::Unignore(pattern) => {
+            let removed = {
+                let mut st = state.lock().unwrap();
+                let before = st.ignore.patterns.len();
+                st.ignore.patterns.retain(|p| *p != pattern);
+                st.ignore.patterns.len() != before
+            };
+            if removed {
+                let ignore = state.lock().unwrap().ignore.clone();
+                SnippetManager::update_project_tree_snippet(state.clone(), &ignore).await?;
+                SnippetManager::rebuild_and_recalc(state.clone()).await?;
+                out.info(format!("(Note) No longer ignoring {}", pattern));
+            } else {
+                out.info(format!("(Note) {} is not an ignore pattern (see /ignore list)", pattern));
+            }
+        }
+
+        Command::ShowConfig => {
+            let st = state.lock().unwrap();
+            out.result("Effective configuration:".to_string());
+            for key in config::CONFIG_KEYS {
+                let source = st.config_sources.get(key).cloned().unwrap_or(ConfigSource::Default);
+                out.result(format!("  {:<20} {:<30} ({})", key, config::current_value(&st, key), source.describe()));
+            }
+            match config::find_config(&st.project_root) {
+                Some(path) => out.result(format!("Config file: {}", path.display())),
+                None => out.info(format!("(Note) No {} in {} or its git repository root.", config::CONFIG_FILE, st.project_root.display())),
+            }
+        }
+
+        Command::ReloadConfig => {
+            let (messages, found, keys_changed) = {
+                let mut st = state.lock().unwrap();
+                let before = (st.settings.edit_mode, st.keybindings.clone());
+                let messages = config::reload(&mut st);
+                let keys_changed = before != (st.settings.edit_mode, st.keybindings.clone());
+                (messages, config::find_config(&st.project_root).is_some(), keys_changed)
+            };
+            if keys_changed {
+                effect = Effect::RebuildEditor;
+            }
+            for message in messages {
+                out.tagged(message);
+            }
+            if !found {
+                out.info(format!("(Note) No {} found; values from a previous config file were reset to defaults.", config::CONFIG_FILE));
+            }
+            // 忽略规则或输出格式可能变化；还没有项目树 (没有 /add 过) 时不生成
+            let (ignore, has_tree) = {
+                let st = state.lock().unwrap();
+                (st.ignore.clone(), st.partial_docs.contains_key(Path::new(PROJECT_TREE_VIRTUAL_PATH)))
+            };
+            if has_tree {
+                SnippetManager::update_project_tree_snippet(state.clone(), &ignore).await?;
+            }
+            SnippetManager::rebuild_and_recalc(state.clone()).await?;
+        }
+
+        Command::Budget(None) => {
+            let st = state.lock().unwrap();
+            match st.settings.token_budget {
+                0 => out.result("No token budget set (set one with /budget <n>, e.g. /budget 128k)".to_string()),
+                budget => out.result(format!(
+                    "Token budget: {} ({} tokens); current payload {} tokens ({}%)",
+                    format_budget(budget),
+                    budget,
+                    format_token_count(st.token_count),
+                    st.token_count * 100 / budget
+                )),
+            }
+        }
+
+        Command::Budget(Some(value)) => {
+            let parsed = if value == "off" { Ok(0) } else { settings::parse_token_count(&value) };
+            match parsed {
+                Ok(budget) => {
+                    state.lock().unwrap().settings.token_budget = budget;
+                    if budget == 0 {
+                        out.info("(Note) Token budget cleared".to_string());
+                    } else {
+                        out.info(format!("(Note) Token budget = {} ({} tokens)", format_budget(budget), budget));
+                    }
+                }
+                Err(e) => out.info(format!("(Note) {}", e)),
+            }
+        }
+
+        Command::Peek(path, _) if path.as_os_str().is_empty() => {
+            out.warn("Usage: /peek <path> [--lines N]".to_string());
+        }
+
+        Command::Peek(path, lines) => {
+            let lines = match lines.as_deref().map(str::parse::<usize>) {
+                None => PEEK_LINES,
+                Some(Ok(n)) if n > 0 => n,
+                Some(_) => {
+                    out.warn("Usage: /peek <path> [--lines N] (N is a positive number)".to_string());
+                    return Ok(Effect::None);
+                }
+            };
+            if path.is_dir() {
+                out.info(format!("(Note) {} is a directory; /peek takes a file (use /add to see what a directory would add)", path.display()));
+                return Ok(Effect::None);
+            }
+            let bytes = match tokio::fs::read(&path).await {
+                Ok(bytes) => bytes,
+                Err(e) => {
+                    out.info(format!("(Note) Cannot read {}: {}", path.display(), e));
+                    return Ok(Effect::None);
+                }
+            };
+            let (decoded, encoding) = decode_with_encoding(&bytes);
+            let content = match decoded {
+                Decoded::Text(s) => s,
+                Decoded::Binary { len, .. } => {
+                    out.result(format!("{} · {} · binary file, not shown", path.display(), format_bytes(len)));
+                    return Ok(Effect::None);
+                }
+            };
+            let tokens = tokenizer::calculate_tokens_in_string(&content)?;
+            let total_lines = content.lines().count();
+            let mut header = format!(
+                "{} · {} · ~{} tokens · {} lines",
+                path.display(),
+                format_bytes(bytes.len()),
+                format_token_count(tokens),
+                total_lines
+            );
+            if encoding.is_unusual() {
+                header.push_str(&format!(" · {}", encoding));
+            }
+            out.result(header.to_string());
+            let width = lines.min(total_lines).max(1).to_string().len();
+            for (i, line) in content.lines().take(lines).enumerate() {
+                out.result(format!("{:>width$} │ {}", i + 1, line, width = width));
+            }
+            if total_lines > lines {
+                out.result(format!("... {} more lines (/peek {} --lines {})", total_lines - lines, path.display(), total_lines));
+            }
+        }
+
+        Command::Grep(args) => {
+            let (regex, pattern) = match args.strip_prefix("--regex") {
+                Some(rest) if rest.is_empty() || rest.starts_with(' ') => (true, rest.trim()),
+                _ => (false, args.trim()),
+            };
+            if pattern.is_empty() {
+                out.warn("Usage: /grep [--regex] <pattern>".to_string());
+                return Ok(Effect::None);
+            }
+            let matcher = match search::build_matcher(pattern, regex) {
+                Ok(matcher) => matcher,
+                Err(e) => {
+                    out.warn(format!("(Warning) Invalid regex: {}", e));
+                    return Ok(Effect::None);
+                }
+            };
+            // 优先使用已生成的 snippet，没有时才读取磁盘
+            let (mut files, max_hits) = {
+                let st = state.lock().unwrap();
+                let files: Vec<(PathBuf, PathBuf, Option<String>)> = st
+                    .selected_paths
+                    .iter()
+                    .map(|p| (st.display_path(p), p.clone(), st.partial_docs.get(p).map(|s| snippet_content(s))))
+                    .collect();
+                (files, st.settings.grep_max_hits)
+            };
+            files.sort_by(|a, b| a.0.cmp(&b.0));
+            if files.is_empty() {
+                out.info("(Note) No files selected".to_string());
+                return Ok(Effect::None);
+            }
+
+            let mut total = 0;
+            let mut file_hits = 0;
+            for (shown, path, content) in files {
+                let content = match content {
+                    Some(content) => content,
+                    None => match tokio::fs::read(&path).await.map(|bytes| decode_with_encoding(&bytes).0) {
+                        Ok(Decoded::Text(content)) => content,
+                        _ => continue,
+                    },
+                };
+                let hits = search::search(&content, &matcher);
+                if hits.is_empty() {
+                    continue;
+                }
+                file_hits += 1;
+                for hit in hits {
+                    total += 1;
+                    if search::within_limit(total, max_hits) {
+                        let (before, matched, after) = hit.parts();
+                        out.result(format!(
+                            "{}:{}: {}{}{}",
+                            shown.display(),
+                            hit.line,
+                            before,
+                            paint(Style::Highlight, matched),
+                            after
+                        ));
+                    }
+                }
+            }
+            if total == 0 {
+                out.info(format!("(Note) No matches for {}", pattern));
+            } else if !search::within_limit(total, max_hits) {
+                out.result(format!("… and {} more ({} matches in {} files; /set grep_max_hits to show more)", total - max_hits, total, file_hits));
+            }
+        }
+
+        Command::Pick => {
+            if !std::io::stdin().is_terminal() {
+                out.info("(Note) /pick needs an interactive terminal; use /add <path>".to_string());
+                return Ok(Effect::None);
+            }
+            let root = state.lock().unwrap().project_root.clone();
+            let files = files_scanner::scan_dir(&root, &ignore_config).await?;
+            if files.is_empty() {
+                out.info(format!("(Note) No files to pick under {}", root.display()));
+                return Ok(Effect::None);
+            }
+            let cwd = std::env::current_dir().unwrap_or_default();
+            let candidates = {
+                let st = state.lock().unwrap();
+                let mut candidates: Vec<picker::Candidate> = files
+                    .into_iter()
+                    .map(|path| picker::Candidate {
+                        label: st.display_path(&path).display().to_string(),
+                        selected: st.selected_paths.contains(&path),
+                        // 交给 /add 的路径尽量相对于当前目录，与手动输入的一致
+                        path: path.strip_prefix(&cwd).map(Path::to_path_buf).unwrap_or(path),
+                    })
+                    .collect();
+                candidates.sort_by(|a, b| a.label.cmp(&b.label));
+                candidates
+            };
+            match picker::run(candidates)? {
+                None => out.info("(Note) Pick cancelled, context unchanged".to_string()),
+                Some(paths) if paths.is_empty() => out.info("(Note) Nothing picked".to_string()),
+                Some(paths) => return Box::pin(execute(Command::Add(paths, AddOptions::default()), state, cancel, out)).await,
+            }
+        }
+
+        Command::EditPrompt => {
+            let (editor, var) = editor::resolve_editor();
+            if var.is_none() {
+                out.info(format!("(Note) $VISUAL and $EDITOR are not set, using {}", editor[0]));
+            }
+            let text = state.lock().unwrap().prompt_text.clone();
+            // 编辑器独占终端直到退出；放到阻塞线程中等待，不占用 runtime 的工作线程
+            let result = tokio::task::spawn_blocking(move || editor::edit_text(&editor, &text))
+                .await
+                .map_err(|e| AppError::General(anyhow!("editor task failed: {}", e)))?;
+            let edited = match result {
+                Ok(edited) => edited,
+                Err(e) => {
+                    out.warn(format!("(Warning) {}; prompt unchanged", e));
+                    return Ok(Effect::None);
+                }
+            };
             let mut st = state.lock().unwrap();
-            if st.mode == ReplMode::Prompt {
-                if !st.prompt_text.is_empty() {
-                    st.prompt_text.push('\n');
+            if edited == st.prompt_text {
+                out.info(format!("(Note) Prompt unchanged ({} tokens)", format_thousands(st.prompt_tokens)));
+                return Ok(Effect::None);
+            }
+            st.prompt_text = edited;
+            let tokens_before = st.prompt_tokens;
+            st.recalc_prompt_tokens();
+            out.info(format!(
+                "(Note) Prompt updated: {} tokens (prompt total {})",
+                format_delta(tokens_before, st.prompt_tokens),
+                format_thousands(st.prompt_tokens)
+            ));
+        }
+
+        Command::ContextJson(compact) => {
+            let st = state.lock().unwrap();
+            out.result(ContextReport::from_state(&st).to_json(compact));
+        }
+
+        Command::Watch(arg) => {
+            let mut st = state.lock().unwrap();
+            match arg.as_deref() {
+                None => match st.watch {
+                    Some(_) => out.result("Watching selected files for changes (/watch off to stop)".to_string()),
+                    None => out.result("Not watching files (/watch on to start)".to_string()),
+                },
+                Some("on") if st.watch.is_some() => out.info("(Note) Already watching files".to_string()),
+                Some("on") => {
+                    out.info(format!(
+                        "(Note) Watching {} selected files and the project tree for changes (/watch off to stop)",
+                        st.selected_paths.len()
+                    ));
+                    // 后台任务的输出要经过 REPL 的 ExternalPrinter，由 REPL 启动
+                    effect = Effect::StartWatch;
                 }
-                st.prompt_text.push_str(&line);
-                println!("(Note) Added to prompt");
+                Some("off") => match st.watch.take() {
+                    Some(_) => out.info("(Note) Stopped watching files".to_string()),
+                    None => out.info("(Note) Not watching files".to_string()),
+                },
+                Some(other) => out.warn(format!("Usage: /watch [on|off] (got {})", other)),
+            }
+        }
+
+        Command::Only(arg) => {
+            // 与 /ignore 相同: 只影响之后的扫描和项目树，已选文件保持不变
+            let changed = {
+                let mut st = state.lock().unwrap();
+                match arg.as_deref() {
+                    None if st.ignore.extensions.is_empty() => {
+                        out.info("(Note) No extension filter; /only rs,md limits later scans to those extensions".to_string());
+                        false
+                    }
+                    None => {
+                        out.info(format!("(Note) Only {} files are scanned (/only off removes the filter)", extension_list(&st.ignore.extensions)));
+                        false
+                    }
+                    Some("off") if st.ignore.extensions.is_empty() => {
+                        out.info("(Note) No extension filter is active.".to_string());
+                        false
+                    }
+                    Some("off") => {
+                        st.ignore.extensions.clear();
+                        out.info("(Note) Extension filter removed".to_string());
+                        true
+                    }
+                    Some(list) => {
+                        let extensions = ignore_rules::parse_extensions(list);
+                        if extensions.is_empty() {
+                            out.warn("Usage: /only <ext>[,<ext>...] | /only off".to_string());
+                            false
+                        } else {
+                            out.info(format!("(Note) Only {} files are scanned from now on", extension_list(&extensions)));
+                            let changed = st.ignore.extensions != extensions;
+                            st.ignore.extensions = extensions;
+                            changed
+                        }
+                    }
+                }
+            };
+            if changed {
+                let ignore = state.lock().unwrap().ignore.clone();
+                SnippetManager::update_project_tree_snippet(state.clone(), &ignore).await?;
+                SnippetManager::rebuild_and_recalc(state.clone()).await?;
+                out.info("(Note) Already selected files are kept; the filter applies to later scans and the project tree.".to_string());
+            }
+        }
+
+        Command::Preset(None) => {
+            let st = state.lock().unwrap();
+            out.result("Presets (/preset <name> sets the extension filter and adds ignore patterns):".to_string());
+            let all = presets::builtin();
+            for preset in &all {
+                out.result(format!(
+                    "  {:<8} {}; ignores {}{}",
+                    preset.name,
+                    extension_list(&preset.extensions),
+                    preset.ignore.join(", "),
+                    if preset.is_active(&st.ignore) { " (active)" } else { "" }
+                ));
+            }
+            if !all.iter().any(|p| p.is_active(&st.ignore)) {
+                out.info("(Note) No preset is active".to_string());
+            }
+        }
+
+        Command::Preset(Some(name)) => {
+            let Some(preset) = presets::find(&name) else {
+                let names: Vec<String> = presets::builtin().into_iter().map(|p| p.name).collect();
+                out.info(format!("(Note) Unknown preset {}; available: {}", name, names.join(", ")));
+                return Ok(Effect::None);
+            };
+            let (added, ignore) = {
+                let mut st = state.lock().unwrap();
+                let added = preset.apply(&mut st.ignore);
+                (added, st.ignore.clone())
+            };
+            out.info(format!("(Note) Preset {}: only {} files are scanned", preset.name, extension_list(&preset.extensions)));
+            if added.is_empty() {
+                out.info(format!("(Note) Already ignoring {}", preset.ignore.join(", ")));
+            } else {
+                out.info(format!("(Note) Ignoring {}", added.join(", ")));
+            }
+            SnippetManager::update_project_tree_snippet(state.clone(), &ignore).await?;
+            SnippetManager::rebuild_and_recalc(state.clone()).await?;
+            out.info("(Note) Already selected files are kept; /only off and /unignore undo the preset.".to_string());
+        }
+
+        Command::Pin(path) | Command::Unpin(path) if path.as_os_str().is_empty() => {
+            out.warn("Usage: /pin <path> | /unpin <path>".to_string());
+        }
+
+        Command::Pin(path) => {
+            let key = pin_key(&state, &path);
+            let mut st = state.lock().unwrap();
+            if !st.selected_paths.contains(&key) {
+                out.info(format!("(Note) {} is not in the context; /add it first", path.display()));
+            } else if !st.pinned.insert(key) {
+                out.info(format!("(Note) {} is already pinned", path.display()));
+            } else {
+                out.info(format!("(Note) Pinned {}; /reset keeps it", path.display()));
+            }
+        }
+
+        Command::Unpin(path) => {
+            let key = pin_key(&state, &path);
+            if state.lock().unwrap().pinned.remove(&key) {
+                out.info(format!("(Note) Unpinned {}", path.display()));
+            } else {
+                out.info(format!("(Note) {} is not pinned", path.display()));
+            }
+        }
+
+        Command::Template(sub, name, append) => {
+            let mut templates = PromptTemplates::load();
+            match (sub.as_deref().unwrap_or("list"), name) {
+                ("list", _) if templates.names().is_empty() => {
+                    out.info("(Note) No prompt templates yet; /template save <name> stores the current prompt".to_string());
+                }
+                ("list", _) => {
+                    out.result("Prompt templates (/template use <name>):".to_string());
+                    let width = templates.names().iter().map(|n| n.chars().count()).max().unwrap_or(0);
+                    for (name, text) in templates.iter() {
+                        let (preview, truncated) = PromptTemplates::preview(text, 60);
+                        let more = if truncated { "…" } else { "" };
+                        out.result(format!("  {:<width$}  {}{}", name, preview, more, width = width));
+                    }
+                }
+                ("save", Some(name)) => {
+                    let text = state.lock().unwrap().prompt_text.clone();
+                    if text.trim().is_empty() {
+                        out.info("(Note) The prompt is empty; nothing to save".to_string());
+                        return Ok(Effect::None);
+                    }
+                    let existed = templates.get(&name).is_some();
+                    templates.save(&name, &text).map_err(|e| AppError::General(anyhow!("cannot save template {}: {}", name, e)))?;
+                    out.info(format!(
+                        "(Note) {} template {} ({} chars)",
+                        if existed { "Updated" } else { "Saved" },
+                        name,
+                        text.chars().count()
+                    ));
+                }
+                ("use", Some(name)) => {
+                    let Some(text) = templates.get(&name) else {
+                        out.info(format!("(Note) No template named {}; /template list shows them", name));
+                        return Ok(Effect::None);
+                    };
+                    let mut st = state.lock().unwrap();
+                    if append && !st.prompt_text.is_empty() {
+                        st.prompt_text.push('\n');
+                        st.prompt_text.push_str(text);
+                    } else {
+                        st.prompt_text = text.to_string();
+                    }
+                    let tokens_before = st.prompt_tokens;
+                    st.recalc_prompt_tokens();
+                    out.info(format!(
+                        "(Note) Prompt {} template {}: {} tokens (prompt total {})",
+                        if append { "extended with" } else { "set from" },
+                        name,
+                        format_delta(tokens_before, st.prompt_tokens),
+                        format_thousands(st.prompt_tokens)
+                    ));
+                }
+                ("delete", Some(name)) => {
+                    let deleted = templates
+                        .delete(&name)
+                        .map_err(|e| AppError::General(anyhow!("cannot delete template {}: {}", name, e)))?;
+                    if deleted {
+                        out.info(format!("(Note) Deleted template {}", name));
+                    } else {
+                        out.info(format!("(Note) No template named {}", name));
+                    }
+                }
+                _ => {
+                    out.warn("Usage: /template list | /template save <name> | /template use <name> [--append] | /template delete <name>".to_string());
+                }
+            }
+        }
+
+        Command::CopyPrompt => {
+            let (prompt, tokens) = {
+                let st = state.lock().unwrap();
+                (st.prompt_text.clone(), st.prompt_tokens)
+            };
+            if prompt.is_empty() {
+                out.info("(Note) The prompt is empty, nothing copied.".to_string());
+                return Ok(Effect::None);
+            }
+            copy_text(&state, &prompt, None, format!("(Note) Prompt copied to clipboard ({} tokens)", format_thousands(tokens)), out);
+        }
+
+        Command::CopyTree => {
+            // 按当前的忽略规则重新生成，顺便更新上下文中的项目树
+            SnippetManager::update_project_tree_snippet(state.clone(), &ignore_config).await?;
+            SnippetManager::rebuild_and_recalc(state.clone()).await?;
+            let tree = {
+                let st = state.lock().unwrap();
+                st.partial_docs
+                    .get(Path::new(PROJECT_TREE_VIRTUAL_PATH))
+                    .map(|s| snippet_content(s))
+                    .unwrap_or_default()
+            };
+            let tokens = tokenizer::calculate_tokens_in_string(&tree)?;
+            copy_text(&state, &tree, None, format!("(Note) Project tree copied to clipboard ({} tokens)", format_thousands(tokens)), out);
+        }
+
+        Command::Last(show) => {
+            let Some(payload) = state.lock().unwrap().last_copied.clone() else {
+                out.info("(Note) Nothing has been copied yet (or /reset cleared it).".to_string());
+                return Ok(Effect::None);
+            };
+            if show {
+                print_paged(&payload, out);
+            } else {
+                let tokens = tokenizer::calculate_tokens_in_string(&payload)?;
+                copy_text(
+                    &state,
+                    &payload,
+                    None,
+                    format!("(Note) Copied the last payload again ({} tokens)", format_thousands(tokens)),
+                    out,
+                );
+            }
+        }
+
+        Command::Alias(None, _) => {
+            let st = state.lock().unwrap();
+            if st.aliases.is_empty() {
+                out.info("(Note) No aliases defined; define one with /alias <name> <command> [args]".to_string());
             } else {
-                eprintln!("Internal error: Attempting to append prompt text in non-prompt mode.");
+                out.result("Aliases:".to_string());
+                for (name, expansion) in &st.aliases {
+                    out.result(format!("  {} = {}", name, expansion));
+                }
+            }
+            let builtin: Vec<String> = registry::COMMANDS
+                .iter()
+                .flat_map(|spec| spec.aliases.iter().map(move |alias| format!("{} -> {}", alias, spec.name)))
+                .collect();
+            out.result(format!("Built-in: {}", builtin.join(", ")));
+        }
+
+        Command::Alias(Some(name), None) => {
+            let key = format!("/{}", name.trim_start_matches('/'));
+            let st = state.lock().unwrap();
+            match (st.aliases.get(&key), registry::find(&key)) {
+                (Some(expansion), _) => out.result(format!("{} = {}", key, expansion)),
+                (None, Some(spec)) if spec.name != key => out.result(format!("{} -> {} (built-in)", key, spec.name)),
+                _ => out.info(format!("(Note) {} is not an alias (see /alias)", key)),
+            }
+        }
+
+        Command::Alias(Some(name), Some(expansion)) => {
+            let mut st = state.lock().unwrap();
+            match parser::normalize_alias(&name, &expansion, &st.aliases) {
+                Ok((key, expansion)) => {
+                    out.info(format!("(Note) {} = {}", key, expansion));
+                    st.aliases.insert(key, expansion);
+                }
+                Err(e) => out.warn(format!("(Warning) {}", e)),
+            }
+        }
+
+        Command::Unalias(name) if name.is_empty() => {
+            out.warn("Usage: /unalias <name>".to_string());
+        }
+
+        Command::Unalias(name) => {
+            let key = format!("/{}", name.trim_start_matches('/'));
+            match state.lock().unwrap().aliases.remove(&key) {
+                Some(expansion) => out.info(format!("(Note) Removed alias {} (was {})", key, expansion)),
+                None => out.info(format!("(Note) {} is not an alias (see /alias)", key)),
             }
         }
+
+        Command::History => {
+            let st = state.lock().unwrap();
+            if st.command_log.is_empty() {
+                out.info("(Note) No commands run yet in this session".to_string());
+            }
+            for (i, line) in st.command_log.iter().enumerate() {
+                out.result(format!("{:>4}  {}", i + 1, line));
+            }
+        }
+
+        Command::Source(path, _) if path.as_os_str().is_empty() => {
+            out.warn("Usage: /source [--continue] <file>".to_string());
+        }
+
+        Command::Source(path, keep_going) => {
+            let lines = script::read_script(&path)?;
+            {
+                let mut st = state.lock().unwrap();
+                if st.source_depth >= script::MAX_SOURCE_DEPTH {
+                    return Err(AppError::General(anyhow!(
+                        "{}: /source nested more than {} levels deep",
+                        path.display(),
+                        script::MAX_SOURCE_DEPTH
+                    )));
+                }
+                st.source_depth += 1;
+            }
+            let _depth = SourceDepthGuard(state.clone());
+            effect = run_script(&path.display().to_string(), lines, keep_going, state.clone(), cancel, out).await?;
+        }
+
+        Command::Keys => {
+            let st = state.lock().unwrap();
+            out.result(format!("Key bindings ({} mode):", st.settings.edit_mode.as_str()));
+            let bindings = keys::active_bindings(&st.keybindings);
+            for editor in [KeyEditor::Single, KeyEditor::Multiline] {
+                out.result(format!("  {}:", editor.as_str()));
+                for (binding, custom) in bindings.iter().filter(|(b, _)| b.editor == editor) {
+                    let origin = if *custom { "  (config)" } else { "" };
+                    out.result(format!("    {:<14} {}{}", binding.key, binding.action.as_str(), origin));
+                }
+            }
+            out.info(format!(
+                "(Note) Other keys keep the {} defaults. Rebind keys with [[keybindings]] in {} (key = \"ctrl-s\", action = \"submit\").",
+                st.settings.edit_mode.as_str(),
+                config::CONFIG_FILE
+            ));
+        }
+
         // [ADDED] Make sure all command variants are handled or explicitly ignored
         Command::Unknown(_) => { /* Already handled earlier */ }
     }
 
-    Ok(())
+    if !overrides.is_empty() {
+        let mut st = state.lock().unwrap();
+        for (key, before) in overrides {
+            if config::current_value(&st, key) != before {
+                st.config_sources.insert(key, ConfigSource::Command(name));
+            }
+        }
+    }
+
+    if mutating {
+        let mut st = state.lock().unwrap();
+        // 只在 token 数或预算变化后提醒，避免每个命令都重复
+        if (st.token_count, st.settings.token_budget) != budget_before {
+            if let Some(warning) = budget_warning(st.token_count, st.settings.token_budget) {
+                out.warn(warning);
+            }
+        }
+        let footer = status_footer(&st);
+        if st.settings.status_footer {
+            out.info(footer.clone());
+        }
+        st.log_activity(format!("{}  {}", label, style::strip(&footer)));
+    }
+
+    Ok(effect)
 } 
\ No newline at end of file
@@ -1,3 +1,5 @@
 pub mod definition;
 pub mod executor;
-pub mod parser; 
\ No newline at end of file
+pub mod parser;
+pub mod registry;
+pub mod script;
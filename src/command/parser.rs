@@ -1,9 +1,13 @@
+use std::collections::BTreeMap;
 use std::path::PathBuf;
 
 use anyhow::Result;
 
+use crate::app::settings::Settings;
 use crate::error::AppError;
-use crate::command::definition::Command;
+use crate::command::definition::{AddOptions, Command, CopyOptions};
+use crate::command::registry;
+use crate::core::ignore_rules;
 
 pub fn parse(input: &str) -> Result<Command, AppError> {
     // 必须以'/'开头，否则视为 Unknown
@@ -12,27 +16,48 @@ pub fn parse(input: &str) -> Result<Command, AppError> {
         return Ok(Command::Unknown(input.to_string()));
     }
 
-    // 按空格拆分: 第一个是命令, 剩下的是参数
-    let mut parts = input.trim().split_whitespace();
-    let cmd_str = parts.next().unwrap_or("");
-    let arg_str = parts.next(); // 可能是文件路径或模式名
+    // 按 shell 的规则拆分 (支持引号和 `\ ` 转义): 第一个是命令, 剩下的是参数
+    let tokens = tokenize(input);
+    let mut parts = tokens.iter().map(String::as_str);
+    // 别名 (例如 /exit) 按注册表换成命令名
+    let cmd_str = registry::canonical(parts.next().unwrap_or(""));
+    let arg_str = parts.clone().next(); // 可能是文件路径或模式名
 
     match cmd_str {
+        // /add 和 /remove 接受任意多个路径 (没有参数时为空列表)
         "/add" => {
-            // 如果没有参数，就先返回一个空路径
-            let p = arg_str.unwrap_or("").to_string();
-            Ok(Command::Add(PathBuf::from(p)))
-        }
-        "/remove" => {
-            let p = arg_str.unwrap_or("").to_string();
-            Ok(Command::Remove(PathBuf::from(p)))
+            let args: Vec<&str> = parts.collect();
+            let (options, paths) = split_add_flags(&args);
+            Ok(Command::Add(paths.iter().map(PathBuf::from).collect(), options))
         }
+        "/remove" => Ok(Command::Remove(parts.map(PathBuf::from).collect())),
+        "/context" if parts.clone().any(|a| a == "--json") => Ok(Command::ContextJson(parts.any(|a| a == "--compact"))),
         "/context" => Ok(Command::ShowContext),
-        "/copy" => Ok(Command::Copy),
-        "/reset" => Ok(Command::Reset),
-        "/help" => Ok(Command::Help),
-        "/quit" => Ok(Command::Quit),
+        "/copy" if arg_str == Some("--outline-preview") => Ok(Command::Preview),
+        "/copy" => {
+            let args: Vec<&str> = parts.collect();
+            // --split / --part / --backend 的值是下一个参数 (缺少时为空串，由 executor 提示用法)
+            let value_of = |flag: &str| {
+                args.iter()
+                    .position(|a| *a == flag)
+                    .map(|i| args.get(i + 1).map(|v| v.to_string()).unwrap_or_default())
+            };
+            Ok(Command::Copy(CopyOptions {
+                refresh: args.contains(&"--refresh"),
+                force: args.iter().any(|a| *a == "--force" || *a == "-f"),
+                split: value_of("--split"),
+                part: value_of("--part"),
+                backend: value_of("--backend"),
+            }))
+        }
+        "/reset" => {
+            let args: Vec<&str> = parts.collect();
+            Ok(Command::Reset(args.contains(&"--hard"), is_forced(&args)))
+        }
+        "/help" => Ok(Command::Help(arg_str.map(String::from))),
+        "/quit" => Ok(Command::Quit(is_forced(&parts.collect::<Vec<_>>()))),
         "/resetprompt" => Ok(Command::ResetPrompt),
+        "/refresh" => Ok(Command::Refresh),
 
         "/mode" => {
             // /mode 后可能无参数 => 查看当前模式
@@ -44,14 +69,522 @@ pub fn parse(input: &str) -> Result<Command, AppError> {
             }
         },
 
+        "/prompt" if arg_str == Some("edit") => Ok(Command::EditPrompt),
         "/prompt" => {
             Ok(Command::Prompt)
         },
 
+        "/set" => {
+            // /set => 列出全部选项; /set key => 查看; /set key value... => 修改
+            let key = arg_str.map(|k| Settings::canonical_key(k).to_string());
+            let rest: Vec<&str> = parts.skip(1).collect();
+            let value = if rest.is_empty() { None } else { Some(rest.join(" ")) };
+            Ok(Command::Set(key, value))
+        },
+
+        "/stats" => Ok(Command::Stats),
+
+        "/validate" => Ok(Command::Validate),
+
+        "/sort" => Ok(Command::Sort(arg_str.map(|s| s.to_string()))),
+
+        "/list" => Ok(Command::ListFiles),
+
+        "/which" => Ok(Command::Which(arg_str.unwrap_or("").to_string())),
+
+        "/show" => Ok(Command::Show(arg_str.unwrap_or("").to_string())),
+
+        "/mute" => Ok(Command::Mute(PathBuf::from(arg_str.unwrap_or("")))),
+
+        "/dashboard" => Ok(Command::Dashboard),
+
+        "/format" => {
+            let kind = arg_str.map(|s| s.to_string());
+            let name = parts.nth(1).map(|s| s.to_string());
+            Ok(Command::Format(kind, name))
+        },
+
+        "/model" => Ok(Command::Model(arg_str.map(|s| s.to_string()))),
+
+        "/preview" => Ok(Command::Preview),
+
+        "/export" => {
+            let force = parts.clone().any(|a| a == "--force");
+            let path = parts.find(|a| *a != "--force").map(PathBuf::from).unwrap_or_default();
+            Ok(Command::Export(path, force))
+        },
+
+        "/save" => Ok(Command::Save(arg_str.map(|s| s.to_string()))),
+
+        "/load" => Ok(Command::Load(arg_str.map(|s| s.to_string()))),
+
+        "/usage" => Ok(Command::Usage(arg_str == Some("--reset"))),
+
+        "/undo" => Ok(Command::Undo),
+
+        "/ignore" => {
+            let sub = arg_str.map(|s| s.to_string());
+            let rest: Vec<&str> = parts.skip(1).collect();
+            let value = if rest.is_empty() { None } else { Some(rest.join(" ")) };
+            Ok(Command::Ignore(sub, value))
+        },
+
+        "/unignore" => Ok(Command::Unignore(parts.collect::<Vec<_>>().join(" "))),
+
+        "/config" => Ok(Command::ShowConfig),
+
+        "/reload-config" => Ok(Command::ReloadConfig),
+
+        "/budget" => Ok(Command::Budget(arg_str.map(|s| s.to_string()))),
+
+        "/peek" => {
+            // --lines N 可以出现在路径前后；N 原样保留，由 executor 校验
+            let mut path = PathBuf::new();
+            let mut lines = None;
+            while let Some(arg) = parts.next() {
+                match arg {
+                    "--lines" | "-n" => lines = Some(parts.next().unwrap_or("").to_string()),
+                    _ if arg.starts_with("--lines=") => lines = Some(arg["--lines=".len()..].to_string()),
+                    _ => path = PathBuf::from(arg),
+                }
+            }
+            Ok(Command::Peek(path, lines))
+        },
+
+        "/grep" => Ok(Command::Grep(parts.collect::<Vec<_>>().join(" "))),
+
+        "/pick" => Ok(Command::Pick),
+
+        "/edit" => Ok(Command::EditPrompt),
+
+        "/watch" => Ok(Command::Watch(arg_str.map(|s| s.to_string()))),
+
+        "/only" => {
+            let rest: Vec<&str> = parts.collect();
+            Ok(Command::Only(if rest.is_empty() { None } else { Some(rest.join(",")) }))
+        },
+
+        "/preset" => Ok(Command::Preset(arg_str.map(|s| s.to_string()))),
+
+        "/pin" => Ok(Command::Pin(PathBuf::from(arg_str.unwrap_or("")))),
+        "/unpin" => Ok(Command::Unpin(PathBuf::from(arg_str.unwrap_or("")))),
+
+        "/template" => {
+            let args: Vec<&str> = parts.collect();
+            let append = args.contains(&"--append");
+            let mut rest = args.into_iter().filter(|a| *a != "--append");
+            Ok(Command::Template(rest.next().map(str::to_string), rest.next().map(str::to_string), append))
+        },
+
+        "/copyprompt" => Ok(Command::CopyPrompt),
+
+        "/copytree" => Ok(Command::CopyTree),
+
+        "/last" => Ok(Command::Last(arg_str == Some("--show"))),
+
+        "/alias" => {
+            // 展开内容保留原始输入 (包括引号)，执行时再按通常的规则拆分
+            let tokens = tokenize_spans(input);
+            let expansion = tokens.get(2).map(|t| input[t.start..].trim_end().to_string());
+            Ok(Command::Alias(tokens.get(1).map(|t| t.value.clone()), expansion))
+        }
+
+        "/unalias" => Ok(Command::Unalias(arg_str.unwrap_or("").to_string())),
+
+        "/history" => Ok(Command::History),
+
+        "/source" => {
+            let args: Vec<&str> = parts.collect();
+            let keep_going = args.contains(&"--continue");
+            let file = args.iter().find(|a| **a != "--continue").map(PathBuf::from).unwrap_or_default();
+            Ok(Command::Source(file, keep_going))
+        }
+
+        "/keys" => Ok(Command::Keys),
+
         // 其它未知命令
         _ => {
             // 依旧用 Unknown 表示
             Ok(Command::Unknown(input.to_string()))
         }
     }
-} 
\ No newline at end of file
+}
+
+/// 未加引号时可以用反斜杠转义的字符；其它反斜杠原样保留 (Windows 路径)
+const ESCAPABLE: &str = " '\"()[]{}&;!$#`";
+
+/// 命令行中的一个参数: 在原始输入中的字节范围 [start, end) 以及去掉引号/转义后的值
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Token {
+    pub start: usize,
+    pub end: usize,
+    pub value: String,
+}
+
+/// `--force` / `-f`: 跳过确认 (/reset、/quit)
+fn is_forced(args: &[&str]) -> bool {
+    args.iter().any(|a| *a == "--force" || *a == "-f")
+}
+
+/// /add 可以接受的选项 (补全时列出)
+pub const ADD_FLAGS: [&str; 4] = ["--hidden", "--no-ignore", "--force", "--ext"];
+
+/// 拆出 /add 参数中的选项 (`--hidden`、`--no-ignore`、`--force` / `-f`、`--ext rs,toml` / `--ext=rs,toml`)，
+/// 返回选项和其余的路径参数。选项可以写在路径前后；`--` 之后的参数都按路径处理 (用来加入以 `-` 开头的文件)
+///
+/// ```
+/// use rustprompt::command::parser::split_add_flags;
+///
+/// let (options, paths) = split_add_flags(&["--hidden", "src", "--ext", ".RS,toml", "--", "-x", "--no-ignore"]);
+/// assert!(options.hidden && !options.no_ignore);
+/// assert_eq!(options.ext, ["rs", "toml"]);
+/// assert_eq!(paths, ["src", "-x", "--no-ignore"]);
+/// ```
+pub fn split_add_flags<'a>(args: &[&'a str]) -> (AddOptions, Vec<&'a str>) {
+    let mut options = AddOptions::default();
+    let mut paths = Vec::new();
+    let mut args = args.iter().copied();
+    while let Some(arg) = args.next() {
+        match arg {
+            "--hidden" => options.hidden = true,
+            "--no-ignore" | "--force" | "-f" => options.no_ignore = true,
+            "--ext" => options.ext = ignore_rules::parse_extensions(args.next().unwrap_or("")),
+            "--" => paths.extend(args.by_ref()),
+            _ => match arg.strip_prefix("--ext=") {
+                Some(list) => options.ext = ignore_rules::parse_extensions(list),
+                None => paths.push(arg),
+            },
+        }
+    }
+    (options, paths)
+}
+
+/// prompt 文本中的 `@path` 提及: 以 `@` 开头的词 (前面可以有 `(`、引号或反引号)，去掉末尾的标点。
+/// `user@example.com` 这样 `@` 不在词首的不算
+///
+/// ```
+/// use std::path::PathBuf;
+/// use rustprompt::command::parser::mentions;
+///
+/// let found = mentions("look at @src/command/parser.rs, and (`@Cargo.toml`) — mail me@example.com @");
+/// assert_eq!(found, [PathBuf::from("src/command/parser.rs"), PathBuf::from("Cargo.toml")]);
+/// ```
+pub fn mentions(text: &str) -> Vec<PathBuf> {
+    let mut found: Vec<PathBuf> = Vec::new();
+    for word in text.split_whitespace() {
+        let word = word.trim_start_matches(['(', '"', '\'', '`']);
+        let Some(path) = word.strip_prefix('@') else { continue };
+        let path = path.trim_end_matches([',', '.', ';', ':', '!', '?', ')', ']', '}', '"', '\'', '`']);
+        if !path.is_empty() && !found.iter().any(|p| p.as_os_str() == path) {
+            found.push(PathBuf::from(path));
+        }
+    }
+    found
+}
+
+/// 别名最多展开的层数 (别名可以指向其它别名)
+const MAX_ALIAS_DEPTH: usize = 8;
+
+/// 展开用户别名 (AppState.aliases，key 和值都以 `/` 开头): 第一个词是别名时换成它的内容，
+/// 后面输入的参数接在内容之后；内容又是别名时继续展开。在 parse 之前调用，所以模式检查针对展开后的命令。
+/// 别名直接或间接指向自己时返回错误
+///
+/// ```
+/// use std::collections::BTreeMap;
+/// use rustprompt::command::parser::expand_aliases;
+///
+/// let mut aliases = BTreeMap::new();
+/// aliases.insert("/asrc".to_string(), "/add src --ext rs".to_string());
+/// aliases.insert("/cs".to_string(), "/asrc".to_string());
+/// assert_eq!(expand_aliases("/asrc", &aliases).unwrap(), "/add src --ext rs");
+/// assert_eq!(expand_aliases("/cs --hidden", &aliases).unwrap(), "/add src --ext rs --hidden");
+/// assert_eq!(expand_aliases("/add /asrc", &aliases).unwrap(), "/add /asrc");
+///
+/// aliases.insert("/loop".to_string(), "/again x".to_string());
+/// aliases.insert("/again".to_string(), "/loop".to_string());
+/// assert!(expand_aliases("/loop", &aliases).unwrap_err().contains("refers to itself"));
+/// ```
+pub fn expand_aliases(input: &str, aliases: &BTreeMap<String, String>) -> Result<String, String> {
+    let mut line = input.to_string();
+    let mut seen: Vec<String> = Vec::new();
+    loop {
+        let word = line.split_whitespace().next().unwrap_or("");
+        let Some(expansion) = aliases.get(word) else {
+            return Ok(line);
+        };
+        if seen.iter().any(|s| s == word) || seen.len() >= MAX_ALIAS_DEPTH {
+            return Err(format!("Alias {} refers to itself ({} -> {})", seen[0], seen.join(" -> "), word));
+        }
+        seen.push(word.to_string());
+        let rest = line.trim_start()[word.len()..].to_string();
+        line = format!("{}{}", expansion, rest);
+    }
+}
+
+/// `!!` (上一条命令) 或 `!<n>` (/history 中的第 n 条) 换成 log 中记录的命令；
+/// 不是这两种写法时返回 None，编号不存在时返回错误
+///
+/// ```
+/// use rustprompt::command::parser::expand_history;
+///
+/// let log = vec!["/add src".to_string(), "/copy --force".to_string()];
+/// assert_eq!(expand_history("!!", &log), Some(Ok("/copy --force".to_string())));
+/// assert_eq!(expand_history(" !1 ", &log), Some(Ok("/add src".to_string())));
+/// assert!(expand_history("!3", &log).unwrap().is_err());
+/// assert!(expand_history("!!", &[]).unwrap().is_err());
+/// assert_eq!(expand_history("!important", &log), None);
+/// assert_eq!(expand_history("/add !1", &log), None);
+/// ```
+pub fn expand_history(input: &str, log: &[String]) -> Option<Result<String, String>> {
+    let reference = input.trim().strip_prefix('!')?;
+    let index = if reference == "!" {
+        if log.is_empty() {
+            return Some(Err("No commands in history yet".to_string()));
+        }
+        log.len()
+    } else {
+        reference.parse::<usize>().ok()?
+    };
+    Some(match index.checked_sub(1).and_then(|i| log.get(i)) {
+        Some(line) => Ok(line.clone()),
+        None => Err(format!("No command !{} in history (see /history)", index)),
+    })
+}
+
+/// 检查并规整 /alias (或配置文件 [aliases]) 定义的别名，返回 (`/name`, `/command args`)。
+/// 名字和内容开头的 `/` 可以省略；名字不能与命令或内置别名相同，展开后必须是已知命令，也不能指向自己
+///
+/// ```
+/// use std::collections::BTreeMap;
+/// use rustprompt::command::parser::normalize_alias;
+///
+/// let mut aliases = BTreeMap::new();
+/// assert_eq!(normalize_alias("asrc", "add src --ext rs", &aliases).unwrap(), ("/asrc".to_string(), "/add src --ext rs".to_string()));
+/// assert!(normalize_alias("/rm", "/remove", &aliases).is_err());
+/// assert!(normalize_alias("x", "bogus", &aliases).is_err());
+/// aliases.insert("/b".to_string(), "/x".to_string());
+/// assert!(normalize_alias("x", "b", &aliases).is_err());
+/// ```
+pub fn normalize_alias(name: &str, expansion: &str, aliases: &BTreeMap<String, String>) -> Result<(String, String), String> {
+    let bare = name.trim_start_matches('/');
+    if bare.is_empty() || !bare.chars().all(|c| c.is_alphanumeric() || c == '-' || c == '_') {
+        return Err(format!("Invalid alias name: {} (use letters, digits, - and _)", name));
+    }
+    let key = format!("/{}", bare);
+    if registry::find(&key).is_some() {
+        return Err(format!("{} is already a command", key));
+    }
+    let expansion = expansion.trim();
+    if expansion.is_empty() {
+        return Err(format!("Alias {} needs a command to expand to", key));
+    }
+    let expansion = if expansion.starts_with('/') { expansion.to_string() } else { format!("/{}", expansion) };
+    let mut with_new = aliases.clone();
+    with_new.insert(key.clone(), expansion.clone());
+    let expanded = expand_aliases(&key, &with_new)?;
+    let command = expanded.split_whitespace().next().unwrap_or("");
+    if registry::find(command).is_none() {
+        return Err(format!("{} is not a command", command));
+    }
+    Ok((key, expansion))
+}
+
+/// 类似 shell 的拆分: 空白分隔参数，`"..."` 和 `'...'` 内的空白不分隔，
+/// 未加引号时 `\ ` 等转义还原为原字符。
+///  - 双引号内只有 `\"` 是转义，单引号内全部按字面处理
+///  - 没有闭合的引号一直延续到行尾 (补全时用户常常还没输入右引号)
+pub fn tokenize(input: &str) -> Vec<String> {
+    tokenize_spans(input).into_iter().map(|t| t.value).collect()
+}
+
+pub fn tokenize_spans(input: &str) -> Vec<Token> {
+    let mut tokens = Vec::new();
+    let mut chars = input.char_indices().peekable();
+    loop {
+        // 跳过分隔参数的空白
+        while chars.next_if(|(_, c)| c.is_whitespace()).is_some() {}
+        let Some(&(start, _)) = chars.peek() else { break };
+
+        let mut value = String::new();
+        let mut quote: Option<char> = None;
+        while let Some((_, c)) = chars.next_if(|(_, c)| quote.is_some() || !c.is_whitespace()) {
+            match (quote, c) {
+                (Some(q), _) if c == q => quote = None,
+                (None, '"' | '\'') => quote = Some(c),
+                (None, '\\') => match chars.next_if(|(_, n)| ESCAPABLE.contains(*n)) {
+                    Some((_, n)) => value.push(n),
+                    None => value.push(c),
+                },
+                (Some('"'), '\\') => match chars.next_if(|(_, n)| *n == '"') {
+                    Some((_, n)) => value.push(n),
+                    None => value.push(c),
+                },
+                _ => value.push(c),
+            }
+        }
+        let end = chars.peek().map(|(i, _)| *i).unwrap_or(input.len());
+        tokens.push(Token { start, end, value });
+    }
+    tokens
+}
+
+/// 把补全插入的值转成 tokenize 能还原的形式: 含空白或引号等特殊字符时加双引号，空串写成 `""`。
+/// 结尾的反斜杠放进单引号，否则它会和右引号 (`\"`) 或后面的空格 (`\ `) 组成转义
+///
+/// ```
+/// use rustprompt::command::parser::{quote_arg, tokenize};
+///
+/// assert_eq!(quote_arg("src/main.rs"), "src/main.rs");
+/// assert_eq!(quote_arg("My Documents/a \"b\".md"), r#""My Documents/a \"b\".md""#);
+/// assert_eq!(quote_arg(r"C:\My Files\"), r#""C:\My Files"'\'"#);
+/// let values = ["", "a b", r"dir\", r"dir with space\", r#"\"quoted\" \"#];
+/// let line: Vec<String> = values.iter().map(|v| quote_arg(v)).collect();
+/// assert_eq!(tokenize(&line.join(" ")), values);
+/// ```
+pub fn quote_arg(value: &str) -> String {
+    if value.is_empty() {
+        return "\"\"".to_string();
+    }
+    let body = value.trim_end_matches('\\');
+    let mut quoted = if body.chars().any(|c| c.is_whitespace() || ESCAPABLE.contains(c)) {
+        format!("\"{}\"", body.replace('"', "\\\""))
+    } else {
+        body.to_string()
+    };
+    if body.len() < value.len() {
+        quoted.push_str(&format!("'{}'", &value[body.len()..]));
+    }
+    quoted
+}
+
+/// 规范化用户粘贴 (或拖拽到终端) 的路径:
+///   - 去掉首尾空白 (部分终端会在末尾追加空格)
+///   - 去掉成对的单引号 (macOS) 或双引号 (Windows)，引号内的内容保持原样
+///   - 未加引号时，把 `\ `、`\(` 等 shell 转义还原 (macOS Terminal 的风格)；
+///     其它反斜杠原样保留，以免破坏 Windows 路径 `C:\Users\...`
+pub fn unquote_path(raw: &str) -> String {
+    let s = raw.trim();
+    for quote in ['\'', '"'] {
+        if s.len() >= 2 && s.starts_with(quote) && s.ends_with(quote) {
+            return s[1..s.len() - 1].to_string();
+        }
+    }
+
+    let mut out = String::with_capacity(s.len());
+    let mut chars = s.chars().peekable();
+    while let Some(c) = chars.next() {
+        if c == '\\' {
+            if let Some(&next) = chars.peek() {
+                if ESCAPABLE.contains(next) {
+                    out.push(next);
+                    chars.next();
+                    continue;
+                }
+            }
+        }
+        out.push(c);
+    }
+    out
+}
+
+/// 判断一行非命令输入是否是粘贴进来的、真实存在的文件/目录路径
+pub fn parse_pasted_path(input: &str) -> Option<PathBuf> {
+    let unquoted = unquote_path(input);
+    if unquoted.is_empty() {
+        return None;
+    }
+    let path = PathBuf::from(unquoted);
+    if path.exists() { Some(path) } else { None }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn paths(line: &str) -> Vec<PathBuf> {
+        match parse(line) {
+            Ok(Command::Add(paths, _)) | Ok(Command::Remove(paths)) => paths,
+            other => panic!("{:?}", other),
+        }
+    }
+
+    #[test]
+    fn quotes_group_whitespace() {
+        assert_eq!(tokenize(r#"/add "My Documents/a.md" 'b c.rs' d.rs"#), ["/add", "My Documents/a.md", "b c.rs", "d.rs"]);
+        assert_eq!(paths(r#"/remove "src/中文 目录/main.rs""#), [PathBuf::from("src/中文 目录/main.rs")]);
+        // 引号可以只包住参数的一部分
+        assert_eq!(tokenize(r#"a"b c"d"#), [r#"ab cd"#]);
+        assert_eq!(tokenize(r#""" ''"#), ["", ""]);
+    }
+
+    #[test]
+    fn escapes_outside_quotes() {
+        assert_eq!(paths(r"/add My\ Documents/a\(1\).md"), [PathBuf::from("My Documents/a(1).md")]);
+        // 不可转义的字符前的反斜杠保持原样 (Windows 路径)
+        assert_eq!(tokenize(r"C:\Users\me\a.rs"), [r"C:\Users\me\a.rs"]);
+        assert_eq!(tokenize(r"trailing\"), [r"trailing\"]);
+    }
+
+    #[test]
+    fn escapes_inside_quotes() {
+        assert_eq!(tokenize(r#""say \"hi\" \n""#), [r#"say "hi" \n"#]);
+        // 单引号内全部按字面处理
+        assert_eq!(tokenize(r#"'a\ b\"'"#), [r#"a\ b\""#]);
+        assert_eq!(tokenize(r#"'it"s'"#), [r#"it"s"#]);
+    }
+
+    #[test]
+    fn unterminated_quote_runs_to_end_of_line() {
+        assert_eq!(tokenize(r#"/add "My Documents/a b"#), ["/add", "My Documents/a b"]);
+        assert_eq!(tokenize("/add 'half open  "), ["/add", "half open  "]);
+        assert_eq!(paths(r#"/add a.rs "b c"#), [PathBuf::from("a.rs"), PathBuf::from("b c")]);
+    }
+
+    #[test]
+    fn spans_cover_the_raw_argument() {
+        let line = r#"/add "a b" c\ d"#;
+        let spans: Vec<(usize, usize)> = tokenize_spans(line).iter().map(|t| (t.start, t.end)).collect();
+        assert_eq!(spans, [(0, 4), (5, 10), (11, line.len())]);
+        assert_eq!(&line[5..10], r#""a b""#);
+    }
+
+    #[test]
+    fn unquotes_macos_drops() {
+        // Terminal.app: 反斜杠转义；iTerm2 等: 整体加单引号
+        assert_eq!(unquote_path(r"/Users/me/My\ Project/a\ \(copy\).rs"), "/Users/me/My Project/a (copy).rs");
+        assert_eq!(unquote_path("'/Users/me/My Project/a (copy).rs'"), "/Users/me/My Project/a (copy).rs");
+        assert_eq!(unquote_path(r"/Users/me/R\&D/notes\;v2.md"), "/Users/me/R&D/notes;v2.md");
+        // 引号内不再还原转义
+        assert_eq!(unquote_path(r"'/tmp/a\ b'"), r"/tmp/a\ b");
+    }
+
+    #[test]
+    fn unquotes_windows_drops() {
+        assert_eq!(unquote_path(r#""C:\Users\me\My Project\main.rs""#), r"C:\Users\me\My Project\main.rs");
+        // 没有空格时不加引号，反斜杠保持原样
+        assert_eq!(unquote_path(r"C:\Users\me\main.rs"), r"C:\Users\me\main.rs");
+        assert_eq!(unquote_path(r"\\server\share\a.txt"), r"\\server\share\a.txt");
+    }
+
+    #[test]
+    fn trims_trailing_spaces_from_drops() {
+        assert_eq!(unquote_path("/home/me/a.rs "), "/home/me/a.rs");
+        assert_eq!(unquote_path("'/Users/me/a b.rs' "), "/Users/me/a b.rs");
+        assert_eq!(unquote_path("  \"C:\\a b.rs\"\t"), r"C:\a b.rs");
+        assert_eq!(unquote_path("   "), "");
+    }
+
+    #[test]
+    fn pasted_paths_must_exist() {
+        let dir = tempfile::tempdir().unwrap();
+        let file = dir.path().join("My Project.rs");
+        std::fs::write(&file, "").unwrap();
+        let shown = file.display().to_string();
+
+        assert_eq!(parse_pasted_path(&format!("'{}' ", shown)), Some(file.clone()));
+        assert_eq!(parse_pasted_path(&format!("\"{}\"", shown)), Some(file.clone()));
+        assert_eq!(parse_pasted_path(&shown.replace(' ', r"\ ")), Some(file));
+        assert_eq!(parse_pasted_path(&format!("'{}'", dir.path().join("missing.rs").display())), None);
+        assert_eq!(parse_pasted_path("  "), None);
+    }
+}
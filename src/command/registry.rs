@@ -0,0 +1,370 @@
+// src/command/registry.rs
+
+use crate::app::state::ReplMode;
+use crate::core::ordering::SortStrategy;
+
+/// 命令参数的补全来源 (repl::completion 按命令的 CommandSpec::args 分派)
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ArgCompletion {
+    /// 不补全参数
+    None,
+    /// 文件系统中的路径 (/add 还补全 parser::ADD_FLAGS)
+    Paths,
+    /// 已选文件
+    SelectedPaths,
+    /// 第一个参数是这些词之一
+    Words(&'static [&'static str]),
+    /// 每个参数都可以是这些选项之一
+    Flags(&'static [&'static str]),
+    /// 模型名和编码名 (tokenizer::model_names)
+    Models,
+    /// 内置预设名
+    Presets,
+    /// 设置名 (Settings::KEYS)
+    Settings,
+    /// 先补全子命令，再按子命令补全后面的参数 (/ignore、/unignore、/template、/format)
+    Subcommands,
+    /// 命令名 (/help <command>)
+    Commands,
+}
+
+/// 注册表中的一个命令。parser 用它解析别名，executor 用它检查模式和生成 /help，
+/// 补全器用它列出命令 (带说明) 和补全参数，新增命令只需要在 COMMANDS 中加一项
+#[derive(Debug, Clone, Copy)]
+pub struct CommandSpec {
+    pub name: &'static str,
+    /// 与 name 等价的其它写法
+    pub aliases: &'static [&'static str],
+    /// /help 中的用法
+    pub usage: &'static str,
+    /// /help 和补全菜单中的说明
+    pub description: &'static str,
+    /// /help <command> 中显示的详细说明 (选项、示例)，可以为空
+    pub help: &'static str,
+    /// 在 Manual / Prompt 模式下是否可用
+    pub manual: bool,
+    pub prompt: bool,
+    pub args: ArgCompletion,
+}
+
+impl CommandSpec {
+    /// 两种模式下都可用、没有别名、不补全参数的命令
+    const fn new(name: &'static str, usage: &'static str, description: &'static str) -> Self {
+        Self { name, aliases: &[], usage, description, help: "", manual: true, prompt: true, args: ArgCompletion::None }
+    }
+
+    /// 只在 Manual 模式下可用 (修改文件选择的命令)
+    const fn manual_only(self) -> Self {
+        Self { prompt: false, ..self }
+    }
+
+    const fn aliases(self, aliases: &'static [&'static str]) -> Self {
+        Self { aliases, ..self }
+    }
+
+    const fn args(self, args: ArgCompletion) -> Self {
+        Self { args, ..self }
+    }
+
+    const fn help(self, help: &'static str) -> Self {
+        Self { help, ..self }
+    }
+
+    pub fn valid_in(&self, mode: &ReplMode) -> bool {
+        match mode {
+            ReplMode::Manual => self.manual,
+            ReplMode::Prompt => self.prompt,
+        }
+    }
+}
+
+/// 所有命令，按 /help 和补全菜单的展示顺序。名字和别名都必须能被 parser 识别，下面的示例会检查
+///
+/// ```
+/// use rustprompt::command::{definition::Command, parser, registry};
+///
+/// for spec in registry::COMMANDS {
+///     for name in std::iter::once(&spec.name).chain(spec.aliases) {
+///         assert!(!matches!(parser::parse(name).unwrap(), Command::Unknown(_)), "{} is not a command", name);
+///     }
+/// }
+/// assert_eq!(registry::find("/exit").unwrap().name, "/quit");
+/// assert_eq!(registry::canonical("/rm"), "/remove");
+/// ```
+pub const COMMANDS: &[CommandSpec] = &[
+    CommandSpec::new("/add", "/add <path>", "Add files or directories to context")
+        .aliases(&["/a"])
+        .manual_only()
+        .args(ArgCompletion::Paths)
+        .help(
+            "Globs such as src/**/*.rs are expanded relative to the current directory.\n\
+             --hidden      include hidden files\n\
+             --no-ignore   turn off all ignore rules for this command (alias --force)\n\
+             --ext rs,toml only add files with these extensions (replaces /only for this command)\n\
+             Without arguments opens the fuzzy finder (same as /pick).\n\
+             Examples: /add src   /add \"My Documents/notes.md\"   /add --ext rs,toml .",
+        ),
+    CommandSpec::new("/remove", "/remove <path>", "Remove files or directories from context")
+        .aliases(&["/rm"])
+        .manual_only()
+        .args(ArgCompletion::SelectedPaths)
+        .help(
+            "A directory removes every selected file under it, even if it was deleted;\n\
+             a glob is matched against the selected files.\n\
+             Examples: /remove src/repl/   /remove **/*_test.rs",
+        ),
+    CommandSpec::new("/context", "/context [--json [--compact]]", "Show current context info (file count, token count)")
+        .aliases(&["/ctx"])
+        .args(ArgCompletion::Flags(&["--json", "--compact"]))
+        .help(
+            "Files are grouped by the paths given to /add and marked (modified) or (missing)\n\
+             when they changed since they were read.\n\
+             --json        print the context as JSON (schema: ContextReport in src/app/report.rs)\n\
+             --compact     print the JSON on one line",
+        ),
+    CommandSpec::new(
+        "/copy",
+        "/copy [--refresh] [--force] [--split <n> | --part <i>] [--backend <name>]",
+        "Copy current context (with project tree and prompt) to clipboard; only changed files are re-read unless --refresh, --force copies even above the model's context window, --split copies part 1 of parts under n tokens",
+    )
+    .aliases(&["/c"])
+    .args(ArgCompletion::Flags(&["--refresh", "--force", "--split", "--part", "--backend", "--outline-preview"]))
+    .help(
+        "--refresh          re-read every selected file, not only the changed ones\n\
+         --force            copy even when larger than the model's context window\n\
+         --split <n>        split into parts of at most n tokens and copy part 1\n\
+         --part <i>         copy part i of the split\n\
+         --backend <name>   auto, native, wsl, wayland, osc52 or file for this copy\n\
+         --outline-preview  same as /preview\n\
+         Examples: /copy   /copy --split 100k   /copy --part 2",
+    ),
+    CommandSpec::new("/refresh", "/refresh", "Re-read selected files (follows renamed files)").help(
+        "Re-reads files whose size or modification time changed, follows renamed files\n\
+         and removes files that are still missing (undo with /undo).",
+    ),
+    CommandSpec::new("/reset", "/reset [--hard] [--force]", "Clear the context and prompt (pinned files stay unless --hard)")
+        .manual_only()
+        .args(ArgCompletion::Flags(&["--hard", "--force"]))
+        .help(
+            "--hard        also clear pinned files (see /pin)\n\
+             --force, -f   do not ask for confirmation (/set confirm_destructive off never asks)",
+        ),
+    CommandSpec::new("/mode", "/mode [manual|prompt]", "View or switch modes")
+        .args(ArgCompletion::Words(&["manual", "prompt"]))
+        .help(
+            "manual        lines are commands; /add, /remove and other file commands are available\n\
+             prompt        lines not starting with / are appended to the prompt, @path adds a file",
+        ),
+    CommandSpec::new("/prompt", "/prompt [edit]", "Edit the prompt in the multi-line editor (edit: in $VISUAL / $EDITOR)")
+        .args(ArgCompletion::Words(&["edit"]))
+        .help("In the multi-line editor Ctrl+S submits and Ctrl+C cancels."),
+    CommandSpec::new("/resetprompt", "/resetprompt", "Clear the prompt text"),
+    CommandSpec::new("/set", "/set [key] [value]", "View or change settings")
+        .args(ArgCompletion::Settings)
+        .help(
+            "Without arguments lists every setting; with a key shows its value.\n\
+             Examples: /set   /set token_budget 200k   /set color off",
+        ),
+    CommandSpec::new("/stats", "/stats", "Show context statistics (tokens, memory usage)"),
+    CommandSpec::new("/validate", "/validate", "Check that the generated XML is well-formed"),
+    CommandSpec::new("/sort", "/sort [strategy]", "View or change file ordering (path, tokens-desc, recency, dependency)")
+        .args(ArgCompletion::Words(SortStrategy::NAMES))
+        .help("dependency puts imported files before the files that use them."),
+    CommandSpec::new("/list", "/list", "List selected files with per-file token counts"),
+    CommandSpec::new("/which", "/which <id>", "Show which file a document ID (e.g. D14) refers to"),
+    CommandSpec::new("/show", "/show <id>", "Display the document with the given ID"),
+    CommandSpec::new("/mute", "/mute <path>", "Toggle excluding a selected file from the payload")
+        .manual_only()
+        .args(ArgCompletion::SelectedPaths),
+    CommandSpec::new("/dashboard", "/dashboard", "Open the full-screen dashboard (q to return)"),
+    CommandSpec::new("/format", "/format [xml|custom <name>]", "View or change the payload format (built-in or user templates)")
+        .args(ArgCompletion::Subcommands)
+        .help(
+            "Built-in templates: markdown, compact; user templates live in .rustprompt/templates/.\n\
+             Examples: /format xml   /format custom markdown",
+        ),
+    CommandSpec::new("/model", "/model [encoding]", "View or switch the token encoding (cl100k_base, o200k_base)")
+        .args(ArgCompletion::Models)
+        .help("A model name (gpt-4o, claude, ...) also sets the context window that /copy enforces."),
+    CommandSpec::new("/preview", "/preview", "Show the structure /copy would produce (documents, tokens, instruction) without content"),
+    CommandSpec::new("/export", "/export [--force] <path>", "Write the context (same as /copy) to a file")
+        .help("--force       overwrite an existing file"),
+    CommandSpec::new("/save", "/save [name]", "Save files, prompt and mode to a session file under .rustprompt/")
+        .help("Without a name saves to .rustprompt/session.json, otherwise to .rustprompt/sessions/<name>.json."),
+    CommandSpec::new("/load", "/load [name]", "Restore a session saved with /save (re-reads all files)").manual_only(),
+    CommandSpec::new("/usage", "/usage [--reset]", "Show local usage statistics (enable with /set usage_stats on)")
+        .args(ArgCompletion::Flags(&["--reset"])),
+    CommandSpec::new("/undo", "/undo", "Undo the last /add, /remove, /reset or prompt change")
+        .help("Repeat to step further back (up to 20 steps)."),
+    CommandSpec::new(
+        "/ignore",
+        "/ignore [list|hidden|gitignore|node_modules on|off|add <pattern>]",
+        "Show or change the ignore rules used by /add and the project tree",
+    )
+    .manual_only()
+    .args(ArgCompletion::Subcommands)
+    .help(
+        "Changes apply to later scans and the tree; selected files are kept.\n\
+         Examples: /ignore hidden off   /ignore binary off   /ignore add *.log",
+    ),
+    CommandSpec::new("/unignore", "/unignore <pattern>", "Remove a pattern added with /ignore add")
+        .manual_only()
+        .args(ArgCompletion::Subcommands),
+    CommandSpec::new("/config", "/config", "Show effective settings and where each value comes from"),
+    CommandSpec::new("/reload-config", "/reload-config", "Re-read .rustprompt.toml (values changed in this session are kept)"),
+    CommandSpec::new("/budget", "/budget [n|off]", "Show or set the token budget (e.g. 128k); warns above 80%")
+        .args(ArgCompletion::Words(&["off"]))
+        .help("Examples: /budget 128k   /budget off"),
+    CommandSpec::new("/peek", "/peek <path> [--lines N]", "Preview a file (first lines, size, tokens) without adding it")
+        .args(ArgCompletion::Paths)
+        .help("--lines N     show the first N lines instead of 40"),
+    CommandSpec::new("/grep", "/grep [--regex] <pattern>", "Search the contents of the selected files").help(
+        "Matches a case-insensitive substring; --regex treats the pattern as a regular expression.\n\
+         At most /set grep_max_hits matches are printed.",
+    ),
+    CommandSpec::new("/pick", "/pick", "Pick files to add with a fuzzy finder (same as /add without arguments)")
+        .help("Type to filter, space or Tab to mark, Enter to add, Esc to cancel."),
+    CommandSpec::new("/edit", "/edit", "Edit the prompt in $VISUAL / $EDITOR (same as /prompt edit)"),
+    CommandSpec::new("/watch", "/watch [on|off]", "Regenerate snippets as soon as selected files change on disk")
+        .args(ArgCompletion::Words(&["on", "off"])),
+    CommandSpec::new("/only", "/only [rs,md|off]", "Only scan files with these extensions (also filters the project tree)")
+        .manual_only()
+        .help("Examples: /only rs,toml   /only off"),
+    CommandSpec::new("/preset", "/preset [rust|web|python]", "Apply a language preset (extension filter and ignore patterns), or list presets")
+        .manual_only()
+        .args(ArgCompletion::Presets),
+    CommandSpec::new("/pin", "/pin <path>", "Keep a selected file when running /reset")
+        .manual_only()
+        .args(ArgCompletion::SelectedPaths),
+    CommandSpec::new("/unpin", "/unpin <path>", "Stop keeping a file on /reset")
+        .manual_only()
+        .args(ArgCompletion::SelectedPaths),
+    CommandSpec::new("/template", "/template list|save|use|delete", "Save the prompt under a name and reuse it in any project (use --append to add)")
+        .args(ArgCompletion::Subcommands)
+        .help(
+            "list                     show saved templates\n\
+             save <name>              save the current prompt\n\
+             use <name> [--append]    replace (or append to) the prompt\n\
+             delete <name>            delete a template",
+        ),
+    CommandSpec::new("/copyprompt", "/copyprompt", "Copy only the prompt text to the clipboard"),
+    CommandSpec::new("/copytree", "/copytree", "Copy only the project tree (regenerated now) to the clipboard"),
+    CommandSpec::new("/last", "/last [--show]", "Copy the last /copy payload again without re-reading files (--show prints it)")
+        .args(ArgCompletion::Flags(&["--show"])),
+    CommandSpec::new("/alias", "/alias [name [expansion]]", "List aliases, or define one (e.g. /alias asrc add src --ext rs)").help(
+        "The expansion is a command with arguments; the leading / is optional. Arguments typed\n\
+         after an alias are appended: with /alias asrc add src, /asrc --ext rs runs /add src --ext rs.\n\
+         Aliases may refer to other aliases but not to themselves. Aliases can also be set in\n\
+         the [aliases] table of .rustprompt.toml (e.g. asrc = \"add src --ext rs\").\n\
+         Examples: /alias   /alias asrc   /alias asrc add src --ext rs",
+    ),
+    CommandSpec::new("/unalias", "/unalias <name>", "Remove an alias defined with /alias").args(ArgCompletion::Subcommands),
+    CommandSpec::new("/history", "/history", "List the commands run in this session (rerun with !! or !<n>)").help(
+        "!!            run the last command again\n\
+         !<n>          run command n again (numbers stay the same for the whole session)\n\
+         The command being rerun is printed first. Prompt text is not recorded.",
+    ),
+    CommandSpec::new("/source", "/source [--continue] <file>", "Run the commands in a file, one per line (stops at the first error unless --continue)")
+        .args(ArgCompletion::Paths)
+        .help(
+            "Blank lines and lines starting with # are skipped; other lines run as if typed,\n\
+             so in prompt mode plain text is added to the prompt. Scripts may /source other\n\
+             scripts (up to 8 levels). /prompt and /edit cannot run from a script.\n\
+             --continue    run the remaining lines after a failed one\n\
+             Start-up scripts: rustprompt --source setup.rpt",
+        ),
+    CommandSpec::new("/keys", "/keys", "List the active key bindings (rebind them with [[keybindings]] in .rustprompt.toml)").help(
+        "[[keybindings]]\n\
+         key = \"ctrl-s\"       ctrl-, alt-, shift- plus a key: a letter, tab, enter, esc, space, up, f5, ...\n\
+         action = \"submit\"    complete, submit, newline, clear_line, clear_screen, history_search, cancel or none\n\
+         editor = \"multiline\" optional: single or multiline (default: both)\n\
+         Run /reload-config after editing the file.",
+    ),
+    CommandSpec::new("/help", "/help [command]", "Show this help message, or details for one command")
+        .aliases(&["/?"])
+        .args(ArgCompletion::Commands)
+        .help("Examples: /help   /help add"),
+    CommandSpec::new("/quit", "/quit [--force]", "Exit program (asks first if the prompt was not saved or copied)")
+        .aliases(&["/exit", "/q"])
+        .args(ArgCompletion::Flags(&["--force"]))
+        .help("--force, -f   quit without asking (/set confirm_destructive off never asks)"),
+];
+
+/// 按名字或别名查找命令
+pub fn find(name: &str) -> Option<&'static CommandSpec> {
+    COMMANDS.iter().find(|spec| spec.name == name || spec.aliases.contains(&name))
+}
+
+/// /help 的参数: 可以省略开头的 `/` (`/help add`)
+pub fn find_topic(topic: &str) -> Option<&'static CommandSpec> {
+    find(topic).or_else(|| find(&format!("/{}", topic)))
+}
+
+/// 别名换成命令名，不认识的名字原样返回
+pub fn canonical(name: &str) -> &str {
+    find(name).map_or(name, |spec| spec.name)
+}
+
+/// 某个模式下可用的命令
+pub fn for_mode(mode: &ReplMode) -> impl Iterator<Item = &'static CommandSpec> + '_ {
+    COMMANDS.iter().filter(move |spec| spec.valid_in(mode))
+}
+
+/// 未知命令最多提示的候选数
+const MAX_SUGGESTIONS: usize = 3;
+
+/// 与输错的命令相近的命令名 (包括别名对应的命令)，最接近的在前，最多 MAX_SUGGESTIONS 个。
+/// 相近是指编辑距离 (相邻字符交换算一次) 不超过 1 (输入较长时不超过 2)，或者输入是命令名的前缀
+///
+/// ```
+/// use rustprompt::command::registry::similar;
+///
+/// assert_eq!(similar("/cpoy"), ["/copy"]);
+/// assert_eq!(similar("/lisst"), ["/list", "/last"]);
+/// assert_eq!(similar("/exitt --now"), ["/quit", "/edit"]);
+/// assert_eq!(similar("/cop"), ["/copy", "/copytree", "/copyprompt"]);
+/// assert!(similar("/xyzzy").is_empty());
+/// assert!(similar("hello").is_empty());
+/// ```
+pub fn similar(input: &str) -> Vec<&'static str> {
+    let Some(typed) = input.split_whitespace().next().filter(|t| t.starts_with('/')) else {
+        return Vec::new();
+    };
+    let typed = typed.to_lowercase();
+    let max_distance = if typed.chars().count() > 5 { 2 } else { 1 };
+    let mut scored: Vec<(usize, &'static str)> = Vec::new();
+    for spec in COMMANDS {
+        let names = || std::iter::once(&spec.name).chain(spec.aliases);
+        let distance = names().map(|name| edit_distance(&typed, name)).min().unwrap_or(usize::MAX);
+        if distance <= max_distance {
+            scored.push((distance, spec.name));
+        } else if typed.len() > 2 && names().any(|name| name.starts_with(typed.as_str())) {
+            // 只是前缀的排在所有拼写相近的之后，短的命令在前
+            scored.push((max_distance + spec.name.len(), spec.name));
+        }
+    }
+    scored.sort();
+    scored.into_iter().take(MAX_SUGGESTIONS).map(|(_, name)| name).collect()
+}
+
+/// 编辑距离 (插入、删除、替换和相邻字符交换各算一次)
+fn edit_distance(a: &str, b: &str) -> usize {
+    let a: Vec<char> = a.chars().collect();
+    let b: Vec<char> = b.chars().collect();
+    // d[i][j]: a[..i] 与 b[..j] 的距离
+    let mut d = vec![vec![0; b.len() + 1]; a.len() + 1];
+    for (i, row) in d.iter_mut().enumerate() {
+        row[0] = i;
+    }
+    d[0] = (0..=b.len()).collect();
+    for i in 1..=a.len() {
+        for j in 1..=b.len() {
+            let cost = usize::from(a[i - 1] != b[j - 1]);
+            d[i][j] = (d[i - 1][j] + 1).min(d[i][j - 1] + 1).min(d[i - 1][j - 1] + cost);
+            if i > 1 && j > 1 && a[i - 1] == b[j - 2] && a[i - 2] == b[j - 1] {
+                d[i][j] = d[i][j].min(d[i - 2][j - 2] + 1);
+            }
+        }
+    }
+    d[a.len()][b.len()]
+}
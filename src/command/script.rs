@@ -0,0 +1,50 @@
+// src/command/script.rs
+
+use std::path::Path;
+
+use crate::error::AppError;
+
+/// /source 最多嵌套的层数 (脚本中的 /source 也算一层)
+pub const MAX_SOURCE_DEPTH: usize = 8;
+
+/// 脚本中的一行命令
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ScriptLine {
+    /// 在文件中的行号 (1 开始)，报告错误时使用
+    pub number: usize,
+    pub text: String,
+}
+
+/// 拆分脚本: 每行一条命令 (与 REPL 中输入的相同)，跳过空行和 `#` 开头的注释
+///
+/// ```
+/// use rustprompt::command::script::parse_script;
+///
+/// let lines = parse_script("# setup\n/add src\n\n  /only rs  \r\n#/copy\n");
+/// assert_eq!(lines.iter().map(|l| (l.number, l.text.as_str())).collect::<Vec<_>>(), [(2, "/add src"), (4, "/only rs")]);
+/// ```
+pub fn parse_script(text: &str) -> Vec<ScriptLine> {
+    text.lines()
+        .enumerate()
+        .map(|(i, line)| ScriptLine { number: i + 1, text: line.trim().to_string() })
+        .filter(|line| !line.text.is_empty() && !line.text.starts_with('#'))
+        .collect()
+}
+
+/// 命令行或配置文件给出的命令列表 (-c、on_start)，按顺序编号
+pub fn numbered(commands: &[String]) -> Vec<ScriptLine> {
+    commands
+        .iter()
+        .enumerate()
+        .map(|(i, text)| ScriptLine { number: i + 1, text: text.trim().to_string() })
+        .collect()
+}
+
+/// 读取并拆分脚本文件
+pub fn read_script(path: &Path) -> Result<Vec<ScriptLine>, AppError> {
+    if !path.exists() {
+        return Err(AppError::PathNotFound(path.to_path_buf()));
+    }
+    let text = std::fs::read_to_string(path).map_err(|e| AppError::io(path, e))?;
+    Ok(parse_script(&text))
+}
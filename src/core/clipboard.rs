@@ -1,13 +1,279 @@
+use std::fmt;
+use std::io::{IsTerminal, Write};
+use std::path::PathBuf;
+use std::process::{Command, Stdio};
+
 use crate::error::AppError;
 use arboard::Clipboard;
-use anyhow::anyhow; // 显式导入 anyhow
-
-pub fn copy_to_clipboard(xml: &str) -> Result<(), AppError> {
-    let mut clipboard = Clipboard::new()
-        .map_err(|e| AppError::General(anyhow!("Failed to create clipboard object: {:?}", e)))?;
-    
-    clipboard.set_text(xml.to_owned())
-        .map_err(|e| AppError::General(anyhow!("Failed to copy to clipboard: {:?}", e)))?;
-    
-    Ok(())
-} 
\ No newline at end of file
+
+/// 复制使用的方式 (/set clipboard_backend)
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum ClipboardBackend {
+    /// 依次尝试 clip.exe (WSL) 或 wl-copy (Wayland)、系统剪贴板、OSC 52、临时文件
+    #[default]
+    Auto,
+    /// 只使用系统剪贴板 (arboard)
+    Native,
+    /// WSL: 交给 Windows 的 clip.exe (arboard 在 WSL 中只会写到没人读取的 X 剪贴板)
+    Wsl,
+    /// Wayland: 交给 wl-copy
+    Wayland,
+    /// 只通过终端的 OSC 52 转义序列 (SSH 等没有系统剪贴板的场景)
+    Osc52,
+    /// 只写入临时文件并打印路径
+    File,
+}
+
+impl ClipboardBackend {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::Auto => "auto",
+            Self::Native => "native",
+            Self::Wsl => "wsl",
+            Self::Wayland => "wayland",
+            Self::Osc52 => "osc52",
+            Self::File => "file",
+        }
+    }
+
+    pub fn parse(value: &str) -> Option<Self> {
+        match value.trim().to_lowercase().as_str() {
+            "auto" => Some(Self::Auto),
+            "native" => Some(Self::Native),
+            "wsl" | "clip.exe" => Some(Self::Wsl),
+            "wayland" | "wl-copy" => Some(Self::Wayland),
+            "osc52" => Some(Self::Osc52),
+            "file" => Some(Self::File),
+            _ => None,
+        }
+    }
+}
+
+/// 实际完成复制的方式
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Copied {
+    Native,
+    /// 交给了外部命令 (clip.exe 或 wl-copy)
+    Command(&'static str),
+    /// 已把 OSC 52 序列写到终端；终端是否接受无法得知
+    Osc52,
+    /// 写入了这个文件
+    File(PathBuf),
+}
+
+/// 一种复制方式失败的原因
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ClipboardError {
+    /// 系统剪贴板 (arboard) 报告的错误
+    Native(String),
+    /// 外部命令不存在 (未安装或不在 PATH 中)
+    MissingCommand(&'static str),
+    /// 外部命令以非零状态退出
+    CommandFailed { command: &'static str, status: Option<i32>, stderr: String },
+    /// 与外部命令、终端或临时文件之间的读写错误
+    Io(String),
+    /// 没有可以写 OSC 52 序列的终端
+    NoTerminal(String),
+}
+
+impl fmt::Display for ClipboardError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Native(e) => write!(f, "{}", e),
+            Self::MissingCommand(command) => write!(f, "{} not found", command),
+            Self::CommandFailed { command, status, stderr } => {
+                match status {
+                    Some(code) => write!(f, "{} exited with status {}", command, code)?,
+                    None => write!(f, "{} was terminated by a signal", command)?,
+                }
+                if !stderr.is_empty() {
+                    write!(f, " ({})", stderr)?;
+                }
+                Ok(())
+            }
+            Self::Io(e) => write!(f, "{}", e),
+            Self::NoTerminal(e) => write!(f, "no terminal ({})", e),
+        }
+    }
+}
+
+/// 按 backend 复制文本；Auto 时前一种方式失败才尝试下一种，全部失败时错误信息列出每种方式失败的原因
+pub fn copy_to_clipboard(text: &str, backend: ClipboardBackend) -> Result<Copied, AppError> {
+    let attempts = match backend {
+        ClipboardBackend::Auto => auto_backends(),
+        forced => vec![forced],
+    };
+    let mut failures = Vec::new();
+    for attempt in attempts {
+        let result = match attempt {
+            ClipboardBackend::Native => copy_native(text).map(|_| Copied::Native),
+            ClipboardBackend::Wsl => copy_wsl(text).map(|_| Copied::Command("clip.exe")),
+            ClipboardBackend::Wayland => copy_wayland(text).map(|_| Copied::Command("wl-copy")),
+            ClipboardBackend::Osc52 => copy_osc52(text).map(|_| Copied::Osc52),
+            _ => copy_to_file(text).map(Copied::File),
+        };
+        match result {
+            Ok(copied) => return Ok(copied),
+            Err(reason) => failures.push(format!("{}: {}", attempt.as_str(), reason)),
+        }
+    }
+    Err(AppError::Clipboard(format!("tried {}", failures.join("; "))))
+}
+
+/// Auto 时按顺序尝试的方式: WSL 中先用 clip.exe，Wayland 会话中先用 wl-copy
+fn auto_backends() -> Vec<ClipboardBackend> {
+    let mut backends = Vec::new();
+    if is_wsl() {
+        backends.push(ClipboardBackend::Wsl);
+    }
+    if std::env::var_os("WAYLAND_DISPLAY").is_some() {
+        backends.push(ClipboardBackend::Wayland);
+    }
+    backends.extend([ClipboardBackend::Native, ClipboardBackend::Osc52, ClipboardBackend::File]);
+    backends
+}
+
+/// WSL 的内核版本字符串中带有 "microsoft" (WSL1 为 "Microsoft")
+fn is_wsl() -> bool {
+    std::fs::read_to_string("/proc/version").is_ok_and(|v| v.to_lowercase().contains("microsoft"))
+}
+
+fn copy_native(text: &str) -> Result<(), ClipboardError> {
+    let mut clipboard = Clipboard::new().map_err(|e| ClipboardError::Native(e.to_string()))?;
+    clipboard.set_text(text.to_owned()).map_err(|e| ClipboardError::Native(e.to_string()))
+}
+
+/// clip.exe 按系统代码页解释输入，带 BOM 的 UTF-16LE 才能保留非 ASCII 字符
+fn copy_wsl(text: &str) -> Result<(), ClipboardError> {
+    let mut input = vec![0xFF, 0xFE];
+    input.extend(text.encode_utf16().flat_map(u16::to_le_bytes));
+    pipe_to_command("clip.exe", &[], &input, true)
+}
+
+/// wl-copy 在后台继续提供剪贴板内容，子进程会继承 stderr，读取它会一直等到剪贴板被替换，所以不捕获
+fn copy_wayland(text: &str) -> Result<(), ClipboardError> {
+    pipe_to_command("wl-copy", &["--type", "text/plain;charset=utf-8"], text.as_bytes(), false)
+}
+
+/// 运行外部命令并把 input 写到它的 stdin；命令不存在、写入失败和非零退出分别映射为对应的错误
+fn pipe_to_command(command: &'static str, args: &[&str], input: &[u8], capture_stderr: bool) -> Result<(), ClipboardError> {
+    let mut child = Command::new(command)
+        .args(args)
+        .stdin(Stdio::piped())
+        .stdout(Stdio::null())
+        .stderr(if capture_stderr { Stdio::piped() } else { Stdio::null() })
+        .spawn()
+        .map_err(|e| match e.kind() {
+            std::io::ErrorKind::NotFound => ClipboardError::MissingCommand(command),
+            _ => ClipboardError::Io(format!("cannot run {}: {}", command, e)),
+        })?;
+    if let Some(mut stdin) = child.stdin.take() {
+        // 写完后 drop，关闭 stdin 让命令结束读取
+        stdin
+            .write_all(input)
+            .map_err(|e| ClipboardError::Io(format!("cannot write to {}: {}", command, e)))?;
+    }
+    let output = child
+        .wait_with_output()
+        .map_err(|e| ClipboardError::Io(format!("cannot wait for {}: {}", command, e)))?;
+    if output.status.success() {
+        return Ok(());
+    }
+    Err(ClipboardError::CommandFailed {
+        command,
+        status: output.status.code(),
+        stderr: String::from_utf8_lossy(&output.stderr).trim().to_string(),
+    })
+}
+
+/// 写到控制终端 (/dev/tty)，stdout 被重定向时也能到达终端；没有控制终端时退回到作为终端的 stdout
+fn copy_osc52(text: &str) -> Result<(), ClipboardError> {
+    let sequences = osc52_sequences(text, Multiplexer::detect());
+    let write_all = |w: &mut dyn Write| -> std::io::Result<()> {
+        for sequence in &sequences {
+            w.write_all(sequence.as_bytes())?;
+        }
+        w.flush()
+    };
+    match std::fs::OpenOptions::new().write(true).open("/dev/tty") {
+        Ok(mut tty) => write_all(&mut tty).map_err(|e| ClipboardError::Io(e.to_string())),
+        Err(e) if std::io::stdout().is_terminal() => {
+            log::debug!("cannot open /dev/tty ({}), writing OSC 52 to stdout", e);
+            write_all(&mut std::io::stdout().lock()).map_err(|e| ClipboardError::Io(e.to_string()))
+        }
+        Err(e) => Err(ClipboardError::NoTerminal(e.to_string())),
+    }
+}
+
+fn copy_to_file(text: &str) -> Result<PathBuf, ClipboardError> {
+    let path = std::env::temp_dir().join(format!("rustprompt-clipboard-{}.txt", std::process::id()));
+    std::fs::write(&path, text).map_err(|e| ClipboardError::Io(format!("{}: {}", path.display(), e)))?;
+    Ok(path)
+}
+
+/// OSC 52 序列需要穿过的终端复用器
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Multiplexer {
+    None,
+    /// tmux: 整个序列包进 DCS passthrough (需要 `set -g allow-passthrough on`)
+    Tmux,
+    /// GNU screen: DCS 字符串有长度限制，分成多段
+    Screen,
+}
+
+impl Multiplexer {
+    fn detect() -> Self {
+        if std::env::var_os("TMUX").is_some() {
+            Self::Tmux
+        } else if std::env::var_os("STY").is_some() {
+            Self::Screen
+        } else {
+            Self::None
+        }
+    }
+}
+
+/// GNU screen 每个 DCS 字符串的最大长度
+const SCREEN_CHUNK: usize = 768;
+
+/// 把文本设为剪贴板内容的 OSC 52 转义序列 (按顺序写出)
+///
+/// ```
+/// use rustprompt::core::clipboard::{osc52_sequences, Multiplexer};
+///
+/// assert_eq!(osc52_sequences("hi", Multiplexer::None), vec!["\x1b]52;c;aGk=\x07".to_string()]);
+/// assert_eq!(osc52_sequences("hi", Multiplexer::Tmux), vec!["\x1bPtmux;\x1b\x1b]52;c;aGk=\x07\x1b\\".to_string()]);
+/// let chunks = osc52_sequences(&"x".repeat(2000), Multiplexer::Screen);
+/// assert!(chunks.len() > 1 && chunks.iter().all(|c| c.starts_with("\x1bP") && c.ends_with("\x1b\\")));
+/// ```
+pub fn osc52_sequences(text: &str, mux: Multiplexer) -> Vec<String> {
+    let sequence = format!("\x1b]52;c;{}\x07", base64(text.as_bytes()));
+    match mux {
+        Multiplexer::None => vec![sequence],
+        Multiplexer::Tmux => vec![format!("\x1bPtmux;{}\x1b\\", sequence.replace('\x1b', "\x1b\x1b"))],
+        // 序列只含 ASCII，可以按字节切分
+        Multiplexer::Screen => sequence
+            .as_bytes()
+            .chunks(SCREEN_CHUNK)
+            .map(|chunk| format!("\x1bP{}\x1b\\", String::from_utf8_lossy(chunk)))
+            .collect(),
+    }
+}
+
+/// 标准 base64 (带 `=` 填充)
+fn base64(bytes: &[u8]) -> String {
+    const ALPHABET: &[u8; 64] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+    let mut out = String::with_capacity(bytes.len().div_ceil(3) * 4);
+    for chunk in bytes.chunks(3) {
+        let b = [chunk[0], chunk.get(1).copied().unwrap_or(0), chunk.get(2).copied().unwrap_or(0)];
+        let n = (b[0] as u32) << 16 | (b[1] as u32) << 8 | b[2] as u32;
+        for i in 0..4 {
+            if i <= chunk.len() {
+                out.push(ALPHABET[(n >> (18 - 6 * i) & 63) as usize] as char);
+            } else {
+                out.push('=');
+            }
+        }
+    }
+    out
+}
@@ -0,0 +1,222 @@
+// src/core/decode.rs
+
+use std::fmt;
+use std::io::Read;
+use std::path::Path;
+
+/// 判断是否为二进制时检查的前缀长度 (与 git 相同)
+const SNIFF_LEN: usize = 8000;
+
+/// 按编码解码后的文件内容
+#[derive(Debug, Clone, PartialEq)]
+pub enum Decoded {
+    Text(String),
+    /// 看起来是二进制 (含 NUL 字节，或大量无法按 UTF-8 解码的字节)；lossy 为按 U+FFFD 替换后的文本
+    Binary { lossy: String, len: usize },
+}
+
+/// 解码时识别出的编码，用于日志
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum SourceEncoding {
+    Utf8,
+    Utf8Bom,
+    Utf16Le,
+    Utf16Be,
+    /// 没有 BOM，但按 UTF-16 的字节模式识别出来 (大部分字符在 ASCII 范围内)
+    Utf16LeNoBom,
+    Utf16BeNoBom,
+    /// 基本是 UTF-8，少量非法字节按 U+FFFD 替换
+    Utf8Lossy,
+    /// 不含任何合法的 UTF-8 多字节序列，按 Latin-1 逐字节解码
+    Latin1,
+}
+
+impl SourceEncoding {
+    /// 是否是 UTF-8 (带或不带 BOM) 以外的编码，或有字节被替换
+    pub fn is_unusual(self) -> bool {
+        !matches!(self, Self::Utf8 | Self::Utf8Bom)
+    }
+}
+
+impl fmt::Display for SourceEncoding {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Self::Utf8 => "UTF-8",
+            Self::Utf8Bom => "UTF-8 (BOM)",
+            Self::Utf16Le => "UTF-16LE (BOM)",
+            Self::Utf16Be => "UTF-16BE (BOM)",
+            Self::Utf16LeNoBom => "UTF-16LE (no BOM)",
+            Self::Utf16BeNoBom => "UTF-16BE (no BOM)",
+            Self::Utf8Lossy => "UTF-8 with invalid bytes replaced",
+            Self::Latin1 => "Latin-1",
+        })
+    }
+}
+
+/// 同 decode_with_encoding，只返回内容
+pub fn decode(bytes: &[u8]) -> Decoded {
+    decode_with_encoding(bytes).0
+}
+
+/// 识别 BOM (UTF-8 / UTF-16 LE / UTF-16 BE) 并解码；没有 BOM 时:
+///  - 符合 UTF-16 字节模式 (每隔一个字节为 0) 的按 UTF-16 解码
+///  - 合法 UTF-8 原样使用
+///  - 不含任何合法多字节序列的 (例如 Latin-1 写的旧文件) 按 Latin-1 解码，重音字母保持可读
+///  - 其余按 UTF-8 处理，非法字节按 U+FFFD 替换，除非内容看起来是二进制
+///
+/// 二进制文件返回的编码没有意义 (Utf8Lossy)
+pub fn decode_with_encoding(bytes: &[u8]) -> (Decoded, SourceEncoding) {
+    if let Some(rest) = bytes.strip_prefix(b"\xEF\xBB\xBF") {
+        return (Decoded::Text(String::from_utf8_lossy(rest).into_owned()), SourceEncoding::Utf8Bom);
+    }
+    if let Some(rest) = bytes.strip_prefix(b"\xFF\xFE") {
+        return (Decoded::Text(decode_utf16(rest, u16::from_le_bytes)), SourceEncoding::Utf16Le);
+    }
+    if let Some(rest) = bytes.strip_prefix(b"\xFE\xFF") {
+        return (Decoded::Text(decode_utf16(rest, u16::from_be_bytes)), SourceEncoding::Utf16Be);
+    }
+
+    let sample = &bytes[..bytes.len().min(SNIFF_LEN)];
+    match utf16_without_bom(sample) {
+        Some(SourceEncoding::Utf16LeNoBom) => {
+            return (Decoded::Text(decode_utf16(bytes, u16::from_le_bytes)), SourceEncoding::Utf16LeNoBom);
+        }
+        Some(encoding) => return (Decoded::Text(decode_utf16(bytes, u16::from_be_bytes)), encoding),
+        None => {}
+    }
+    if sample.contains(&0) {
+        return (binary(bytes), SourceEncoding::Utf8Lossy);
+    }
+    match std::str::from_utf8(bytes) {
+        Ok(s) => (Decoded::Text(s.to_string()), SourceEncoding::Utf8),
+        Err(_) if looks_binary(sample) => (binary(bytes), SourceEncoding::Utf8Lossy),
+        Err(_) if !has_utf8_sequences(bytes) => {
+            (Decoded::Text(bytes.iter().map(|&b| b as char).collect()), SourceEncoding::Latin1)
+        }
+        Err(_) => (Decoded::Text(String::from_utf8_lossy(bytes).into_owned()), SourceEncoding::Utf8Lossy),
+    }
+}
+
+/// 文件开头的样本是否看起来是二进制 (与 decode 的判断相同):
+/// 带 BOM 或符合 UTF-16 字节模式的视为文本；含 NUL 字节，或超过一成的字节无法按 UTF-8 解码时视为二进制。
+/// 偶尔的坏字节 (例如 Latin-1 写的注释) 以及整份 Latin-1 的文本仍按文本处理
+pub fn looks_binary(sample: &[u8]) -> bool {
+    let sample = &sample[..sample.len().min(SNIFF_LEN)];
+    if [&b"\xEF\xBB\xBF"[..], b"\xFF\xFE", b"\xFE\xFF"].iter().any(|bom| sample.starts_with(bom)) {
+        return false;
+    }
+    if utf16_without_bom(sample).is_some() {
+        return false;
+    }
+    if sample.contains(&0) {
+        return true;
+    }
+    if std::str::from_utf8(sample).is_ok() {
+        return false;
+    }
+    // 单字节旧编码 (Latin-1 等) 的文本: 没有合法的 UTF-8 多字节序列，也没有空白以外的控制字符
+    if !has_utf8_sequences(sample) && !sample.iter().any(|&b| b < 0x20 && !b"\t\n\r\x0c\x1b".contains(&b)) {
+        return false;
+    }
+    let replaced = String::from_utf8_lossy(sample).chars().filter(|c| *c == '\u{FFFD}').count();
+    replaced * 10 > sample.len()
+}
+
+/// 只读取文件开头 SNIFF_LEN 字节判断是否为二进制；读取失败时返回 false (交给之后的读取报告)
+pub fn sniff_binary(path: &Path) -> bool {
+    let mut sample = Vec::with_capacity(SNIFF_LEN);
+    match std::fs::File::open(path) {
+        Ok(file) => file.take(SNIFF_LEN as u64).read_to_end(&mut sample).is_ok() && looks_binary(&sample),
+        Err(_) => false,
+    }
+}
+
+/// 没有 BOM 的 UTF-16: 九成以上的 2 字节单元一个字节为 0、另一个不为 0 (ASCII 范围的字符)，
+/// 且 0 总在同一侧。样本太短时不判断
+fn utf16_without_bom(sample: &[u8]) -> Option<SourceEncoding> {
+    let units = sample.len() / 2;
+    if units < 4 {
+        return None;
+    }
+    let pairs = sample.chunks_exact(2);
+    let le = pairs.clone().filter(|p| p[0] != 0 && p[1] == 0).count();
+    let be = pairs.filter(|p| p[0] == 0 && p[1] != 0).count();
+    if le * 10 >= units * 9 {
+        Some(SourceEncoding::Utf16LeNoBom)
+    } else if be * 10 >= units * 9 {
+        Some(SourceEncoding::Utf16BeNoBom)
+    } else {
+        None
+    }
+}
+
+/// 是否含有至少一个合法的 UTF-8 多字节序列 (有的话说明文件本意是 UTF-8，只是夹杂了坏字节)
+fn has_utf8_sequences(bytes: &[u8]) -> bool {
+    bytes
+        .utf8_chunks()
+        .any(|chunk| !chunk.valid().is_ascii())
+}
+
+fn binary(bytes: &[u8]) -> Decoded {
+    Decoded::Binary {
+        lossy: String::from_utf8_lossy(bytes).into_owned(),
+        len: bytes.len(),
+    }
+}
+
+fn decode_utf16(bytes: &[u8], unit: fn([u8; 2]) -> u16) -> String {
+    let units = bytes.chunks_exact(2).map(|pair| unit([pair[0], pair[1]]));
+    char::decode_utf16(units)
+        .map(|c| c.unwrap_or(char::REPLACEMENT_CHARACTER))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const LATIN1: &[u8] = include_bytes!("../../tests/fixtures/encoding/latin1.txt");
+    const UTF16LE_BOM: &[u8] = include_bytes!("../../tests/fixtures/encoding/utf16le_bom.txt");
+    const UTF16LE: &[u8] = include_bytes!("../../tests/fixtures/encoding/utf16le.txt");
+
+    #[test]
+    fn latin1_fixture_keeps_accented_letters() {
+        assert!(!looks_binary(LATIN1));
+        assert_eq!(
+            decode_with_encoding(LATIN1),
+            (Decoded::Text("// Café résumé: naïve façade\r\nfn main() {}\r\n".to_string()), SourceEncoding::Latin1)
+        );
+    }
+
+    #[test]
+    fn utf16le_fixture_with_bom() {
+        assert!(!looks_binary(UTF16LE_BOM));
+        assert_eq!(
+            decode_with_encoding(UTF16LE_BOM),
+            (Decoded::Text("// 中文 注释\r\nfn main() {}\r\n".to_string()), SourceEncoding::Utf16Le)
+        );
+    }
+
+    #[test]
+    fn utf16le_fixture_without_bom() {
+        // 每隔一个字节为 0，但不能因为含 NUL 被当成二进制
+        assert!(UTF16LE.contains(&0));
+        assert!(!looks_binary(UTF16LE));
+        assert_eq!(
+            decode_with_encoding(UTF16LE),
+            (Decoded::Text("fn main() {}\r\n".to_string()), SourceEncoding::Utf16LeNoBom)
+        );
+    }
+
+    #[test]
+    fn utf8_with_a_stray_byte_is_replaced_not_latin1() {
+        let (decoded, encoding) = decode_with_encoding(b"// \xE4\xB8\xAD: caf\xE9 in an otherwise UTF-8 file\n");
+        assert_eq!(decoded, Decoded::Text("// 中: caf\u{FFFD} in an otherwise UTF-8 file\n".to_string()));
+        assert_eq!(encoding, SourceEncoding::Utf8Lossy);
+    }
+
+    #[test]
+    fn nul_bytes_are_binary() {
+        assert!(matches!(decode(b"\x7fELF\x02\x01\x00\x00"), Decoded::Binary { len: 8, .. }));
+    }
+}
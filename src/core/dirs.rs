@@ -0,0 +1,20 @@
+// src/core/dirs.rs
+
+use std::path::PathBuf;
+
+/// 用户配置目录: `$XDG_CONFIG_HOME/rustprompt` 或 `~/.config/rustprompt`
+pub fn config_dir() -> Option<PathBuf> {
+    xdg_dir("XDG_CONFIG_HOME", &[".config"])
+}
+
+/// 用户数据目录 (历史、使用统计等): `$XDG_DATA_HOME/rustprompt` 或 `~/.local/share/rustprompt`
+pub fn data_dir() -> Option<PathBuf> {
+    xdg_dir("XDG_DATA_HOME", &[".local", "share"])
+}
+
+fn xdg_dir(var: &str, fallback: &[&str]) -> Option<PathBuf> {
+    let base = std::env::var_os(var).map(PathBuf::from).or_else(|| {
+        std::env::var_os("HOME").map(|home| fallback.iter().fold(PathBuf::from(home), |p, c| p.join(c)))
+    })?;
+    Some(base.join("rustprompt"))
+}
@@ -0,0 +1,22 @@
+// src/core/file_stamp.rs
+
+use std::path::Path;
+use std::time::SystemTime;
+
+/// 廉价的文件指纹: 修改时间 + 大小，只需一次 stat 即可判断文件是否可能被修改过
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct FileStamp {
+    pub modified: Option<SystemTime>,
+    pub len: u64,
+}
+
+impl FileStamp {
+    /// 读取文件当前的指纹，文件不存在或无法访问时返回 None
+    pub fn of(path: &Path) -> Option<Self> {
+        let meta = std::fs::metadata(path).ok()?;
+        Some(Self {
+            modified: meta.modified().ok(),
+            len: meta.len(),
+        })
+    }
+}
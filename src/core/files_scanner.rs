@@ -1,48 +1,209 @@
-use std::path::{Path, PathBuf};
+use std::collections::HashSet;
+use std::path::{Component, Path, PathBuf};
 use anyhow::anyhow;
+use globset::{GlobBuilder, GlobMatcher};
+use tokio_util::sync::CancellationToken;
 
 use crate::error::AppError;
-use super::ignore_rules::IgnoreConfig;
+use super::{decode, ignore_rules::IgnoreConfig, paths, progress::{Progress, ProgressEvent, SCAN_STEP}};
+
+/// scan_dir_with_progress 的结果
+#[derive(Debug, Clone, Default)]
+pub struct ScanResult {
+    pub files: Vec<PathBuf>,
+    /// 按 IgnoreConfig::ignore_binary 跳过的二进制文件数
+    pub skipped_binary: usize,
+}
 
 /// 扫描给定路径，返回所有文件（不含文件夹），并应用忽略规则
 /// 例如：隐藏文件、.gitignore、node_modules 等。
 ///
 /// 如果 path 是单一文件，则检查是否忽略；
 /// 如果 path 是文件夹，则递归扫描并排除忽略项。
+///
+/// 如果 path 含有 glob 通配符 (`*`、`?`、`[`、`{`)，则相对当前目录展开，
+/// 例如 `src/**/*.rs`、`*.toml`；没有匹配时返回空列表而不是报错。
+///
+/// 返回的路径经过规整并解析符号链接 (见 `paths::file_key`)，`/add .` 与 `/add src` 得到的同一个文件路径相同，
+/// 指向同一文件的符号链接只保留一项。
+///
+/// ignore_binary 开启时跳过二进制文件 (只读取每个文件的开头判断)。
+///
+/// 路径不存在时返回 `AppError::PathNotFound`:
+///
+/// ```
+/// use std::path::Path;
+/// use rustprompt::core::{files_scanner::scan_dir, ignore_rules::IgnoreConfig};
+/// use rustprompt::error::AppError;
+///
+/// # tokio::runtime::Runtime::new().unwrap().block_on(async {
+/// let err = scan_dir(Path::new("no/such/dir"), &IgnoreConfig::default()).await.unwrap_err();
+/// assert!(matches!(err, AppError::PathNotFound(ref p) if p == Path::new("no/such/dir")));
+/// # });
+/// ```
 pub async fn scan_dir(path: &Path, ignore_config: &IgnoreConfig) -> Result<Vec<PathBuf>, AppError> {
+    Ok(scan_dir_with_progress(path, ignore_config, &Progress::none(), &CancellationToken::new()).await?.files)
+}
+
+/// 同 scan_dir，遍历目录时每找到 SCAN_STEP 个文件发送一次 `ProgressEvent::Scanned`，结束时再发送一次总数；
+/// 每个目录项都检查 cancel，被取消时停止遍历并返回 `AppError::Cancelled`；
+/// 结果中带有跳过的二进制文件数，供 /add 报告
+pub async fn scan_dir_with_progress(
+    path: &Path,
+    ignore_config: &IgnoreConfig,
+    progress: &Progress,
+    cancel: &CancellationToken,
+) -> Result<ScanResult, AppError> {
     let path = path.to_owned();
     let config = ignore_config.clone();
+    let progress = progress.clone();
+    let cancel = cancel.clone();
 
-    let result = tokio::task::spawn_blocking(move || {
-        if !path.exists() {
-            return Err(AppError::General(anyhow!("路径不存在: {:?}", path)));
+    let (result, skipped_binary) = tokio::task::spawn_blocking(move || {
+        let files = scan_blocking(&path, &config, &progress, &cancel)?;
+        if !config.ignore_binary {
+            return Ok((files, 0));
         }
-
-        if path.is_file() {
-            if config.should_ignore_path(&path) {
-                Ok(vec![])
-            } else {
-                Ok(vec![path])
+        // 只读取每个文件的开头判断是否为二进制
+        let total = files.len();
+        let mut text = Vec::with_capacity(total);
+        for file in files {
+            if cancel.is_cancelled() {
+                return Err(AppError::Cancelled);
             }
-        } else {
-            // 如果是文件夹
-            let walker = config.build_walker(&path).build();
-            let mut files = Vec::new();
-            for entry in walker {
-                let entry = entry.map_err(|e|
-                    AppError::General(anyhow!("walk entry error: {:?}", e))
-                )?;
-                if let Some(ft) = entry.file_type() {
-                    if ft.is_file() {
-                        files.push(entry.path().to_path_buf());
-                    }
-                }
+            if !decode::sniff_binary(&file) {
+                text.push(file);
             }
-            Ok(files)
         }
+        let skipped = total - text.len();
+        Ok((text, skipped))
     }).await.map_err(|e| {
         AppError::General(anyhow!("扫描任务失败: {:?}", e))
     })??;
 
-    Ok(result)
-} 
\ No newline at end of file
+    // 规整为已选文件使用的 key: `./src/a.rs`、`src/../src/a.rs`、cwd 下的绝对路径以及指向它的符号链接都变成 `src/a.rs`
+    let cwd = std::env::current_dir().unwrap_or_else(|_| PathBuf::from("."));
+    let mut seen = HashSet::new();
+    let files = result
+        .into_iter()
+        .map(|p| paths::file_key(&p, &cwd))
+        .filter(|key| seen.insert(key.clone()))
+        .collect();
+    Ok(ScanResult { files, skipped_binary })
+}
+
+/// 在 spawn_blocking 中执行的遍历 (glob、单个文件或目录)
+fn scan_blocking(
+    path: &Path,
+    config: &IgnoreConfig,
+    progress: &Progress,
+    cancel: &CancellationToken,
+) -> Result<Vec<PathBuf>, AppError> {
+    if is_glob(&path.to_string_lossy()) {
+        return scan_glob(path, config, progress, cancel);
+    }
+    if !path.exists() {
+        return Err(AppError::PathNotFound(path.to_path_buf()));
+    }
+
+    if path.is_file() {
+        if config.should_ignore_path(path) {
+            Ok(vec![])
+        } else {
+            Ok(vec![path.to_path_buf()])
+        }
+    } else {
+        // 如果是文件夹
+        let walker = config.build_walker(path).build();
+        let mut files = Vec::new();
+        for entry in walker {
+            if cancel.is_cancelled() {
+                return Err(AppError::Cancelled);
+            }
+            let entry = entry.map_err(|e|
+                AppError::General(anyhow!("walk entry error: {:?}", e))
+            )?;
+            if let Some(ft) = entry.file_type() {
+                if ft.is_file() {
+                    files.push(entry.path().to_path_buf());
+                    if files.len() % SCAN_STEP == 0 {
+                        progress.send(ProgressEvent::Scanned(files.len()));
+                    }
+                }
+            }
+        }
+        progress.send(ProgressEvent::Scanned(files.len()));
+        Ok(files)
+    }
+}
+
+/// 参数中是否含有 glob 通配符
+pub fn is_glob(s: &str) -> bool {
+    s.contains(['*', '?', '[', '{'])
+}
+
+/// 展开 glob: 从不含通配符的最长前缀目录开始遍历 (应用忽略规则)，再逐个匹配
+///  - `*` 不跨越目录分隔符，`**` 可匹配任意层目录
+fn scan_glob(
+    pattern: &Path,
+    config: &IgnoreConfig,
+    progress: &Progress,
+    cancel: &CancellationToken,
+) -> Result<Vec<PathBuf>, AppError> {
+    let matcher = glob_matcher(pattern)?;
+
+    // 不含通配符的前缀作为遍历起点
+    let mut base = PathBuf::new();
+    for comp in pattern.components() {
+        if let Component::Normal(s) = comp {
+            if is_glob(&s.to_string_lossy()) {
+                break;
+            }
+        }
+        base.push(comp.as_os_str());
+    }
+    if base.as_os_str().is_empty() {
+        base = PathBuf::from(".");
+    }
+    if !base.is_dir() {
+        return Ok(vec![]);
+    }
+
+    let mut files = Vec::new();
+    for entry in config.build_walker(&base).build().flatten() {
+        if cancel.is_cancelled() {
+            return Err(AppError::Cancelled);
+        }
+        if !entry.file_type().is_some_and(|ft| ft.is_file()) {
+            continue;
+        }
+        if matcher.is_match(glob_candidate(entry.path())) {
+            files.push(entry.path().to_path_buf());
+            if files.len() % SCAN_STEP == 0 {
+                progress.send(ProgressEvent::Scanned(files.len()));
+            }
+        }
+    }
+    progress.send(ProgressEvent::Scanned(files.len()));
+    files.sort();
+    Ok(files)
+}
+
+/// 编译 glob 模式 (与 /add 展开时的规则相同)
+pub fn glob_matcher(pattern: &Path) -> Result<GlobMatcher, AppError> {
+    let pattern_str = glob_candidate(pattern);
+    Ok(GlobBuilder::new(&pattern_str)
+        .literal_separator(true)
+        .build()
+        .map_err(|e| AppError::General(anyhow!("无效的 glob 模式 {:?}: {}", pattern_str, e)))?
+        .compile_matcher())
+}
+
+/// 用于 glob 匹配的路径形式: 去掉开头的 `./`，统一使用 `/` 分隔
+pub fn glob_candidate(path: &Path) -> String {
+    strip_cur_dir(&path.to_string_lossy()).replace('\\', "/")
+}
+
+fn strip_cur_dir(s: &str) -> &str {
+    s.strip_prefix("./").or_else(|| s.strip_prefix(".\\")).unwrap_or(s)
+}
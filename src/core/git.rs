@@ -0,0 +1,65 @@
+// src/core/git.rs
+
+use std::path::{Path, PathBuf};
+use std::process::{Command, Stdio};
+
+/// 提示符右侧显示的仓库状态
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct GitStatus {
+    /// 当前分支名；detached HEAD 时为提交的前 7 位
+    pub branch: String,
+    /// 工作区或暂存区有未提交的修改 (不计未跟踪的文件)
+    pub dirty: bool,
+}
+
+/// 从 start 向上查找 `.git`，返回 git 目录。`.git` 是文件时 (worktree、submodule)
+/// 按其中的 `gitdir: <path>` 找到真正的目录
+pub fn find_git_dir(start: &Path) -> Option<PathBuf> {
+    for dir in start.ancestors() {
+        let dot_git = dir.join(".git");
+        if dot_git.is_dir() {
+            return Some(dot_git);
+        }
+        if dot_git.is_file() {
+            let text = std::fs::read_to_string(&dot_git).ok()?;
+            let target = text.lines().find_map(|line| line.strip_prefix("gitdir:"))?.trim();
+            return Some(dir.join(target));
+        }
+    }
+    None
+}
+
+/// 解析 `.git/HEAD` 的内容: 指向分支时返回分支名，detached HEAD 返回提交的前 7 位
+///
+/// ```
+/// use rustprompt::core::git::parse_head;
+///
+/// assert_eq!(parse_head("ref: refs/heads/feature/login\n").as_deref(), Some("feature/login"));
+/// assert_eq!(parse_head("029097b5c4e1f0a2d3b4c5d6e7f8091a2b3c4d5e\n").as_deref(), Some("029097b"));
+/// assert_eq!(parse_head(""), None);
+/// ```
+pub fn parse_head(contents: &str) -> Option<String> {
+    let head = contents.trim();
+    if let Some(reference) = head.strip_prefix("ref:") {
+        let reference = reference.trim();
+        return Some(reference.strip_prefix("refs/heads/").unwrap_or(reference).to_string());
+    }
+    if head.len() >= 7 && head.chars().all(|c| c.is_ascii_hexdigit()) {
+        return Some(head[..7].to_string());
+    }
+    None
+}
+
+/// root 所在仓库的分支和是否有未提交的修改；不在 git 仓库中时返回 None。
+/// 分支直接读取 HEAD 文件，是否有修改调用 `git status` (git 不可用时视为没有修改)
+pub fn status(root: &Path) -> Option<GitStatus> {
+    let git_dir = find_git_dir(root)?;
+    let branch = parse_head(&std::fs::read_to_string(git_dir.join("HEAD")).ok()?)?;
+    let dirty = Command::new("git")
+        .args(["status", "--porcelain=v1", "--untracked-files=no"])
+        .current_dir(root)
+        .stderr(Stdio::null())
+        .output()
+        .is_ok_and(|out| out.status.success() && !out.stdout.is_empty());
+    Some(GitStatus { branch, dirty })
+}
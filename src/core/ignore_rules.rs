@@ -1,14 +1,37 @@
 // src/core/ignore_rules.rs
 
-use std::path::Path;
-use ignore::WalkBuilder;
+use std::collections::HashMap;
+use std::fmt;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex, OnceLock};
+use ignore::{
+    gitignore::{Gitignore, GitignoreBuilder},
+    overrides::{Override, OverrideBuilder},
+    Match, WalkBuilder,
+};
+use serde::{Deserialize, Serialize};
+
+use crate::core::{file_stamp::FileStamp, paths};
 
 /// 忽略配置：管理隐藏文件/.gitignore/node_modules等
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub struct IgnoreConfig {
     pub ignore_hidden: bool,
     pub use_gitignore: bool,
     pub ignore_node_modules: bool,
+    /// 用户通过 /ignore add 添加的模式 (gitignore 语法，相对于当前目录)，优先于 .gitignore 等其它规则
+    #[serde(default)]
+    pub patterns: Vec<String>,
+    /// 扫描时跳过二进制文件 (开头含 NUL 字节等，见 decode::looks_binary)；关闭后二进制文件以一行占位说明加入
+    #[serde(default = "default_true")]
+    pub ignore_binary: bool,
+    /// /only 设置的扩展名过滤 (小写，不带 `.`): 非空时只扫描这些扩展名的文件，目录不受影响
+    #[serde(default)]
+    pub extensions: Vec<String>,
+}
+
+fn default_true() -> bool {
+    true
 }
 
 impl Default for IgnoreConfig {
@@ -17,18 +40,78 @@ impl Default for IgnoreConfig {
             ignore_hidden: true,
             use_gitignore: true,
             ignore_node_modules: true,
+            patterns: Vec::new(),
+            ignore_binary: true,
+            extensions: Vec::new(),
         }
     }
 }
 
+/// should_ignore_path 判断某个路径被忽略的原因
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum IgnoreReason {
+    Hidden,
+    Git,
+    NodeModules,
+    /// 用户通过 /ignore add 添加的模式
+    Pattern,
+    Gitignore,
+    /// 扩展名不在 /only (或 /add --ext) 的列表中
+    Extension,
+}
+
+impl fmt::Display for IgnoreReason {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Self::Hidden => "hidden files are ignored",
+            Self::Git => "inside .git",
+            Self::NodeModules => "node_modules is ignored",
+            Self::Pattern => "matches an /ignore pattern",
+            Self::Gitignore => "ignored by .gitignore",
+            Self::Extension => "extension not in the filter",
+        })
+    }
+}
+
 impl IgnoreConfig {
+    /// 关闭所有忽略规则 (`/add --force`、命令行 `--no-ignore`)，只保留二进制文件和扩展名的过滤
+    pub fn permissive(&self) -> Self {
+        Self {
+            ignore_hidden: false,
+            use_gitignore: false,
+            ignore_node_modules: false,
+            patterns: Vec::new(),
+            ignore_binary: self.ignore_binary,
+            extensions: self.extensions.clone(),
+        }
+    }
+
+    /// 文件的扩展名是否通过扩展名过滤 (没有过滤时总是通过；不区分大小写，没有扩展名的文件不通过)
+    pub fn matches_extension(&self, path: &Path) -> bool {
+        extension_allowed(&self.extensions, path)
+    }
+
     /// 根据我们的 ignore config 构建一个 WalkBuilder
     /// 
     /// `root` : 要扫描的起始目录
     pub fn build_walker(&self, root: &Path) -> WalkBuilder {
         let mut builder = WalkBuilder::new(root);
+        // standard_filters 会把 hidden、git_ignore 等全部设为 true，必须先调用，再按配置覆盖
+        builder.standard_filters(true);
 
         builder.hidden(self.ignore_hidden);
+        // filter_entry 只能设置一个: 显示隐藏文件时仍然跳过 git 的内部目录；扩展名过滤只作用于文件
+        let skip_git = !self.ignore_hidden;
+        let extensions = self.extensions.clone();
+        if skip_git || !extensions.is_empty() {
+            builder.filter_entry(move |entry| {
+                if skip_git && entry.file_name() == ".git" {
+                    return false;
+                }
+                let is_file = entry.file_type().is_some_and(|ft| ft.is_file());
+                !is_file || extension_allowed(&extensions, entry.path())
+            });
+        }
 
         if self.use_gitignore {
             builder.git_ignore(true).git_exclude(true).git_global(true);
@@ -40,28 +123,176 @@ impl IgnoreConfig {
             builder.add_ignore("**/node_modules");
             builder.add_ignore("node_modules"); // 也覆盖根目录下的
         }
-        builder.standard_filters(true);
+        match self.overrides() {
+            Ok(Some(overrides)) => {
+                builder.overrides(overrides);
+            }
+            Ok(None) => {}
+            Err(e) => log::warn!("忽略无效的 ignore 模式: {}", e),
+        }
 
         builder
     }
 
-    /// 检查单个路径是否应该被忽略 (基于配置，但不解析 .gitignore)
+    /// 检查模式是否合法 (/ignore add 时调用)
+    pub fn validate_pattern(pattern: &str) -> Result<(), String> {
+        OverrideBuilder::new(".")
+            .add(&format!("!{}", pattern))
+            .map(|_| ())
+            .map_err(|e| e.to_string())
+    }
+
+    /// 用户模式编译成的 override: 每个模式取反 (`!pattern`) 表示忽略；
+    /// 只有取反的模式时，未匹配的路径仍交给其它规则判断
+    fn overrides(&self) -> Result<Option<Override>, ignore::Error> {
+        if self.patterns.is_empty() {
+            return Ok(None);
+        }
+        let mut builder = OverrideBuilder::new(".");
+        for pattern in &self.patterns {
+            builder.add(&format!("!{}", pattern))?;
+        }
+        builder.build().map(Some)
+    }
+
+    /// 检查单个路径是否应该被忽略，规则与 build_walker 的目录扫描相同 (包括 .gitignore)
     pub fn should_ignore_path(&self, path: &Path) -> bool {
+        self.ignore_reason(path).is_some()
+    }
+
+    /// 路径被忽略的原因；不被忽略时返回 None
+    ///
+    /// ```
+    /// use rustprompt::core::ignore_rules::{IgnoreConfig, IgnoreReason};
+    ///
+    /// let repo = std::env::temp_dir().join(format!("rustprompt-gitignore-{}", std::process::id()));
+    /// std::fs::create_dir_all(repo.join(".git")).unwrap();
+    /// std::fs::write(repo.join(".gitignore"), "*.log\n!keep.log\n").unwrap();
+    ///
+    /// let config = IgnoreConfig::default();
+    /// assert_eq!(config.ignore_reason(&repo.join("debug.log")), Some(IgnoreReason::Gitignore));
+    /// assert_eq!(config.ignore_reason(&repo.join("keep.log")), None);
+    /// // /add --force 使用的规则不再忽略它
+    /// assert_eq!(config.permissive().ignore_reason(&repo.join("debug.log")), None);
+    /// std::fs::remove_dir_all(&repo).unwrap();
+    /// ```
+    pub fn ignore_reason(&self, path: &Path) -> Option<IgnoreReason> {
         if self.ignore_hidden {
             if let Some(name) = path.file_name() {
                 if name.to_string_lossy().starts_with('.') {
                     // 对于 Unix 隐藏文件
-                    return true;
+                    return Some(IgnoreReason::Hidden);
                 }
             }
             // 可选: 添加 Windows 隐藏文件检查 (需要额外 crate 或 cfg)
         }
-        if self.ignore_node_modules {
-            if path.components().any(|c| c.as_os_str() == "node_modules") {
-                return true;
+        if path.components().any(|c| c.as_os_str() == ".git") {
+            return Some(IgnoreReason::Git);
+        }
+        if self.ignore_node_modules && path.components().any(|c| c.as_os_str() == "node_modules") {
+            return Some(IgnoreReason::NodeModules);
+        }
+        // 用户模式: 路径本身或它所在的任一目录被忽略 (例如 `build` 也忽略 build/x.rs)
+        if let Ok(Some(overrides)) = self.overrides() {
+            let ignored = path
+                .ancestors()
+                .filter(|a| !a.as_os_str().is_empty())
+                .any(|a| overrides.matched(a, a != path || path.is_dir()).is_ignore());
+            if ignored {
+                return Some(IgnoreReason::Pattern);
             }
         }
-        // 注意: 此方法不处理 .gitignore。完整的忽略判断依赖于 WalkBuilder
-        false
+        if self.use_gitignore && gitignored(path) {
+            return Some(IgnoreReason::Gitignore);
+        }
+        if !path.is_dir() && !self.matches_extension(path) {
+            return Some(IgnoreReason::Extension);
+        }
+        None
+    }
+}
+
+/// 解析 /only 和 /add --ext 的扩展名列表: 逗号或空白分隔，去掉开头的 `.` 并转为小写，去重
+///
+/// ```
+/// use rustprompt::core::ignore_rules::parse_extensions;
+///
+/// assert_eq!(parse_extensions(".RS, toml,,rs md"), ["rs", "toml", "md"]);
+/// ```
+pub fn parse_extensions(list: &str) -> Vec<String> {
+    let mut extensions: Vec<String> = Vec::new();
+    for ext in list.split(|c: char| c == ',' || c.is_whitespace()) {
+        let ext = ext.trim_start_matches('.').to_lowercase();
+        if !ext.is_empty() && !extensions.contains(&ext) {
+            extensions.push(ext);
+        }
     }
-} 
\ No newline at end of file
+    extensions
+}
+
+fn extension_allowed(extensions: &[String], path: &Path) -> bool {
+    extensions.is_empty()
+        || path
+            .extension()
+            .is_some_and(|ext| extensions.iter().any(|e| ext.to_string_lossy().eq_ignore_ascii_case(e)))
+}
+
+/// 与 WalkBuilder 一样只在 git 仓库中应用 .gitignore: 从路径所在目录向上直到仓库根目录，
+/// 越深的 .gitignore 优先 (`!pattern` 可以取消上层的忽略)，最后是 .git/info/exclude 和全局 excludes
+fn gitignored(path: &Path) -> bool {
+    let cwd = std::env::current_dir().unwrap_or_else(|_| PathBuf::from("."));
+    let path = paths::normalize(&cwd.join(path));
+    let Some(repo) = path.ancestors().skip(1).find(|dir| dir.join(".git").exists()) else {
+        return false;
+    };
+    let is_dir = path.is_dir();
+    let mut matchers: Vec<Arc<Gitignore>> = path
+        .ancestors()
+        .skip(1)
+        .take_while(|dir| dir.starts_with(repo))
+        .map(|dir| cached_matcher(dir, &[dir.join(".gitignore")]))
+        .collect();
+    matchers.push(cached_matcher(repo, &[repo.join(".git/info/exclude")]));
+    for matcher in matchers {
+        match matcher.matched_path_or_any_parents(&path, is_dir) {
+            Match::Ignore(_) => return true,
+            Match::Whitelist(_) => return false,
+            Match::None => {}
+        }
+    }
+    // 全局 excludes 相对于启动时的当前目录，不在其下的路径不判断
+    let global = global_matcher();
+    path.starts_with(global.path()) && global.matched_path_or_any_parents(&path, is_dir).is_ignore()
+}
+
+/// 编译好的 .gitignore 匹配器，按目录缓存 (补全时每个候选都要判断)；文件的 mtime/大小变化时重新编译
+type MatcherCache = HashMap<PathBuf, (Vec<Option<FileStamp>>, Arc<Gitignore>)>;
+
+fn cached_matcher(dir: &Path, files: &[PathBuf]) -> Arc<Gitignore> {
+    static CACHE: OnceLock<Mutex<MatcherCache>> = OnceLock::new();
+    let stamps: Vec<Option<FileStamp>> = files.iter().map(|f| FileStamp::of(f)).collect();
+    let key = files.first().cloned().unwrap_or_else(|| dir.to_path_buf());
+    let mut cache = CACHE.get_or_init(Default::default).lock().unwrap();
+    if let Some((cached, matcher)) = cache.get(&key) {
+        if *cached == stamps {
+            return matcher.clone();
+        }
+    }
+    let mut builder = GitignoreBuilder::new(dir);
+    for (file, stamp) in files.iter().zip(&stamps) {
+        if stamp.is_some() {
+            if let Some(e) = builder.add(file) {
+                log::warn!("忽略无效的 .gitignore 规则 {:?}: {}", file, e);
+            }
+        }
+    }
+    let matcher = Arc::new(builder.build().unwrap_or_else(|_| Gitignore::empty()));
+    cache.insert(key, (stamps, matcher.clone()));
+    matcher
+}
+
+/// 全局 excludes (core.excludesFile)，启动后只读取一次
+fn global_matcher() -> &'static Gitignore {
+    static GLOBAL: OnceLock<Gitignore> = OnceLock::new();
+    GLOBAL.get_or_init(|| Gitignore::global().0)
+}
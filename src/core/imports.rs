@@ -0,0 +1,339 @@
+// src/core/imports.rs
+
+use std::collections::{HashMap, HashSet};
+use std::path::{Path, PathBuf};
+
+use super::renames::absolute_path;
+
+/// 从源码中提取出的一条 import 引用 (尚未解析到具体文件)
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ImportRef {
+    /// 相对于当前文件的路径候选 (绝对路径)，例如 `./util` -> [util.ts, util/index.ts, ...]
+    Relative(Vec<PathBuf>),
+    /// 模块路径的后缀候选，例如 `crate::core::xml` -> ["core/xml.rs", "core/xml/mod.rs", ...]
+    Suffix(Vec<String>),
+}
+
+const JS_EXTS: [&str; 6] = ["ts", "tsx", "js", "jsx", "mjs", "cjs"];
+
+/// 按扩展名用简单的逐行匹配提取 import，不做完整的语法解析。
+/// 支持 Rust (`mod`/`use crate::`/`use super::`)、JS/TS (`import`/`require`)、
+/// Python (`import`/`from ... import`) 以及 C/C++ 的 `#include "..."`。
+pub fn extract_imports(path: &Path, content: &str) -> Vec<ImportRef> {
+    let ext = path
+        .extension()
+        .map(|e| e.to_string_lossy().to_lowercase())
+        .unwrap_or_default();
+    let path = absolute_path(path);
+    let dir = path.parent().map(Path::to_path_buf).unwrap_or_default();
+
+    if ext == "rs" {
+        return rust_imports(&path, &dir, content);
+    }
+
+    let mut refs = Vec::new();
+    for line in content.lines() {
+        let line = line.trim();
+        let found = match ext.as_str() {
+            e if JS_EXTS.contains(&e) => js_import(&dir, line),
+            "py" => python_import(&dir, line),
+            "c" | "h" | "cc" | "cpp" | "cxx" | "hpp" | "hh" => c_include(&dir, line),
+            _ => None,
+        };
+        refs.extend(found);
+    }
+    refs
+}
+
+/// 把每个文件的 import 解析到已选文件上，返回 文件 -> 它依赖的已选文件。
+/// 无法解析的 import (第三方库、未选中的文件) 直接忽略；
+/// 模块路径后缀匹配到多个已选文件时 (例如两个 `util.rs`) 取路径最小的一个，结果与 HashMap 的遍历顺序无关。
+pub fn resolve_imports(
+    imports: &HashMap<PathBuf, Vec<ImportRef>>,
+    selected: &[PathBuf],
+) -> HashMap<PathBuf, Vec<PathBuf>> {
+    let by_abs: HashMap<PathBuf, &PathBuf> =
+        selected.iter().map(|p| (absolute_path(p), p)).collect();
+    let mut unix_paths: Vec<(String, &PathBuf)> = by_abs
+        .iter()
+        .map(|(abs, p)| (abs.to_string_lossy().replace('\\', "/"), *p))
+        .collect();
+    unix_paths.sort();
+
+    let mut graph = HashMap::new();
+    for path in selected {
+        let mut deps: Vec<PathBuf> = Vec::new();
+        let mut seen = HashSet::new();
+        for import in imports.get(path).into_iter().flatten() {
+            let target = match import {
+                ImportRef::Relative(candidates) => {
+                    candidates.iter().find_map(|c| by_abs.get(c).copied())
+                }
+                ImportRef::Suffix(suffixes) => suffixes.iter().find_map(|suffix| {
+                    let needle = format!("/{}", suffix);
+                    unix_paths
+                        .iter()
+                        .find(|(abs, _)| abs.ends_with(&needle))
+                        .map(|(_, p)| *p)
+                }),
+            };
+            if let Some(t) = target {
+                if t != path && seen.insert(t.clone()) {
+                    deps.push(t.clone());
+                }
+            }
+        }
+        graph.insert(path.clone(), deps);
+    }
+    graph
+}
+
+/// Rust 的 `use` 可能跨多行 (`use crate::{\n a::b,\n c,\n};`)，先按 `;` 拼出完整语句再解析
+fn rust_imports(path: &Path, dir: &Path, content: &str) -> Vec<ImportRef> {
+    let mut refs = Vec::new();
+    let mut pending: Option<String> = None;
+    for line in content.lines() {
+        let line = line.trim();
+        let line = line.strip_prefix("pub(crate) ").or_else(|| line.strip_prefix("pub ")).unwrap_or(line);
+
+        let stmt = match pending.as_mut() {
+            Some(buf) => {
+                buf.push(' ');
+                buf.push_str(line);
+                buf
+            }
+            None if line.starts_with("use ") => pending.insert(line.to_string()),
+            None => {
+                // mod foo;  (带 { } 的内联模块不对应文件)
+                if let Some(name) = line.strip_prefix("mod ").and_then(|r| r.strip_suffix(';')) {
+                    refs.push(rust_mod(path, dir, name.trim()));
+                }
+                continue;
+            }
+        };
+        if let Some(end) = stmt.find(';') {
+            let tree = stmt["use ".len()..end].to_string();
+            pending = None;
+            for use_path in expand_use_tree(&tree) {
+                refs.extend(rust_use_path(path, dir, &use_path));
+            }
+        }
+    }
+    refs
+}
+
+/// `mod foo;` -> 同级的 foo.rs / foo/mod.rs (非 mod.rs/main.rs/lib.rs 时在 <stem>/ 下)
+fn rust_mod(path: &Path, dir: &Path, name: &str) -> ImportRef {
+    let stem = path.file_stem().map(|s| s.to_string_lossy().to_string()).unwrap_or_default();
+    let base = if matches!(stem.as_str(), "mod" | "main" | "lib") {
+        dir.to_path_buf()
+    } else {
+        dir.join(stem)
+    };
+    ImportRef::Relative(vec![
+        base.join(format!("{}.rs", name)),
+        base.join(name).join("mod.rs"),
+    ])
+}
+
+/// 只解析 `crate::` 和 `super::` 开头的路径; 外部 crate 和 std 直接忽略。
+/// `super` 的文件在 mod.rs 的上一级目录，普通文件 (`src/x/b.rs`) 则就在同一目录 (`src/x/a.rs`)
+fn rust_use_path(path: &Path, dir: &Path, use_path: &str) -> Option<ImportRef> {
+    let segments: Vec<&str> = use_path
+        .split("::")
+        .map(str::trim)
+        .filter(|s| !s.is_empty() && *s != "self" && *s != "*")
+        .collect();
+
+    match segments.first() {
+        Some(&"crate") => Some(ImportRef::Suffix(module_suffixes(&segments[1..], "rs", "mod.rs"))),
+        Some(&"super") => {
+            let parent = if path.file_name().is_some_and(|n| n == "mod.rs") { dir.parent()? } else { dir };
+            let mut candidates = Vec::new();
+            for n in (1..segments.len()).rev() {
+                let rel = segments[1..=n].join("/");
+                candidates.push(parent.join(format!("{}.rs", rel)));
+                candidates.push(parent.join(&rel).join("mod.rs"));
+            }
+            // `use super::Thing`: 上级模块本身 (x/mod.rs 或 x.rs)
+            candidates.push(parent.join("mod.rs"));
+            candidates.push(parent.with_extension("rs"));
+            Some(ImportRef::Relative(candidates))
+        }
+        _ => None,
+    }
+}
+
+/// 展开 use 树: `crate::{a::{b, c as d}, e}` -> ["crate::a::b", "crate::a::c", "crate::e"]
+fn expand_use_tree(tree: &str) -> Vec<String> {
+    let tree = tree.trim();
+    let open = match tree.find('{') {
+        Some(i) => i,
+        None => {
+            let item = tree.split(" as ").next().unwrap_or("").trim();
+            return if item.is_empty() { Vec::new() } else { vec![item.to_string()] };
+        }
+    };
+    let prefix = &tree[..open];
+    let close = tree.rfind('}').filter(|c| *c > open).unwrap_or(tree.len());
+    let inner = &tree[open + 1..close];
+
+    let mut out = Vec::new();
+    let mut depth = 0;
+    let mut start = 0;
+    for (i, c) in inner.char_indices() {
+        match c {
+            '{' => depth += 1,
+            '}' => depth -= 1,
+            ',' if depth == 0 => {
+                out.extend(expand_use_tree(&inner[start..i]).into_iter().map(|s| format!("{}{}", prefix, s)));
+                start = i + 1;
+            }
+            _ => {}
+        }
+    }
+    out.extend(expand_use_tree(&inner[start..]).into_iter().map(|s| format!("{}{}", prefix, s)));
+    out
+}
+
+fn js_import(dir: &Path, line: &str) -> Option<ImportRef> {
+    let spec = if line.starts_with("import ") || line.starts_with("export ") {
+        quoted_after(line, " from ").or_else(|| quoted_after(line, "import "))
+    } else if let Some(idx) = line.find("require(") {
+        quoted_after(&line[idx..], "require(")
+    } else {
+        None
+    }?;
+    if !spec.starts_with('.') {
+        return None; // 第三方包
+    }
+
+    let base = dir.join(spec);
+    let mut candidates = vec![absolute_path(&base)];
+    for ext in JS_EXTS {
+        candidates.push(absolute_path(&PathBuf::from(format!("{}.{}", base.display(), ext))));
+    }
+    for ext in JS_EXTS {
+        candidates.push(absolute_path(&base.join(format!("index.{}", ext))));
+    }
+    Some(ImportRef::Relative(candidates))
+}
+
+fn python_import(dir: &Path, line: &str) -> Option<ImportRef> {
+    let module = if let Some(rest) = line.strip_prefix("from ") {
+        rest.split_whitespace().next()?
+    } else if let Some(rest) = line.strip_prefix("import ") {
+        rest.split([',', ' ']).next()?
+    } else {
+        return None;
+    };
+
+    let dots = module.chars().take_while(|c| *c == '.').count();
+    let segments: Vec<&str> = module[dots..].split('.').filter(|s| !s.is_empty()).collect();
+    if dots == 0 {
+        return Some(ImportRef::Suffix(module_suffixes(&segments, "py", "__init__.py")));
+    }
+
+    // from . import x / from ..pkg import y: 从当前目录向上 (dots - 1) 级
+    let mut base = dir.to_path_buf();
+    for _ in 1..dots {
+        base = base.parent()?.to_path_buf();
+    }
+    let rel = segments.join("/");
+    Some(ImportRef::Relative(vec![
+        base.join(format!("{}.py", rel)),
+        base.join(&rel).join("__init__.py"),
+    ]))
+}
+
+fn c_include(dir: &Path, line: &str) -> Option<ImportRef> {
+    let rest = line.strip_prefix("#include")?.trim_start();
+    let header = rest.strip_prefix('"')?.split('"').next()?;
+    Some(ImportRef::Relative(vec![absolute_path(&dir.join(header))]))
+}
+
+/// `a::b::c` -> ["a/b/c.rs", "a/b/c/mod.rs", "a/b.rs", "a/b/mod.rs", "a.rs", "a/mod.rs"]
+/// (最后一段可能是类型或函数名，所以依次尝试更短的前缀)
+fn module_suffixes(segments: &[&str], ext: &str, index_file: &str) -> Vec<String> {
+    let mut out = Vec::new();
+    for n in (1..=segments.len()).rev() {
+        let rel = segments[..n].join("/");
+        out.push(format!("{}.{}", rel, ext));
+        out.push(format!("{}/{}", rel, index_file));
+    }
+    out
+}
+
+/// 取出 marker 之后第一对引号 ('...' 或 "...") 之间的内容
+fn quoted_after<'a>(line: &'a str, marker: &str) -> Option<&'a str> {
+    let rest = &line[line.find(marker)? + marker.len()..];
+    let start = rest.find(['\'', '"'])?;
+    let quote = rest[start..].chars().next()?;
+    let inner = &rest[start + 1..];
+    inner.find(quote).map(|end| &inner[..end])
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// 用 extract_imports 从假想的源码 (文件不需要存在) 建立依赖图
+    fn graph(files: &[(&str, &str)]) -> (Vec<PathBuf>, HashMap<PathBuf, Vec<PathBuf>>) {
+        let selected: Vec<PathBuf> = files.iter().map(|(p, _)| PathBuf::from(p)).collect();
+        let imports = files
+            .iter()
+            .map(|(p, content)| (PathBuf::from(p), extract_imports(Path::new(p), content)))
+            .collect();
+        let resolved = resolve_imports(&imports, &selected);
+        (selected, resolved)
+    }
+
+    fn deps<'a>(graph: &'a HashMap<PathBuf, Vec<PathBuf>>, path: &str) -> Vec<&'a str> {
+        graph[Path::new(path)].iter().map(|p| p.to_str().unwrap()).collect()
+    }
+
+    #[test]
+    fn rust_cycle_resolves_both_edges() {
+        let (_, graph) = graph(&[
+            ("/p/src/main.rs", "mod a;\nmod b;\nuse std::fmt;\n"),
+            ("/p/src/a.rs", "use crate::b::helper;\nuse crate::b::{Other, helper as h};\n"),
+            ("/p/src/b.rs", "use super::a::Thing;\nuse self::inner::*;\n"),
+            ("/p/src/a/inner.rs", "use super::Thing;\n"),
+        ]);
+        assert_eq!(deps(&graph, "/p/src/main.rs"), ["/p/src/a.rs", "/p/src/b.rs"]);
+        // 同一个文件只记一次
+        assert_eq!(deps(&graph, "/p/src/a.rs"), ["/p/src/b.rs"]);
+        assert_eq!(deps(&graph, "/p/src/b.rs"), ["/p/src/a.rs"]);
+        assert_eq!(deps(&graph, "/p/src/a/inner.rs"), ["/p/src/a.rs"]);
+    }
+
+    #[test]
+    fn ambiguous_suffix_picks_the_smallest_path() {
+        let files = [
+            ("/p/tools/src/util.rs", ""),
+            ("/p/src/util.rs", ""),
+            ("/p/src/lib.rs", "use crate::util::parse;\n"),
+            ("/p/app/main.py", "import util\nfrom .helpers import x\n"),
+            ("/p/app/util.py", ""),
+            ("/p/util.py", ""),
+            ("/p/app/helpers.py", ""),
+        ];
+        // 多次构建 (HashMap 的遍历顺序每次不同) 结果都一样
+        for _ in 0..8 {
+            let (_, graph) = graph(&files);
+            assert_eq!(deps(&graph, "/p/src/lib.rs"), ["/p/src/util.rs"]);
+            assert_eq!(deps(&graph, "/p/app/main.py"), ["/p/app/util.py", "/p/app/helpers.py"]);
+        }
+    }
+
+    #[test]
+    fn unselected_and_external_imports_are_ignored() {
+        let (_, graph) = graph(&[
+            ("/p/web/app.ts", "import React from 'react';\nimport { a } from './missing';\nconst b = require('./lib');\n"),
+            ("/p/web/lib/index.js", "export const b = 1;\n"),
+            ("/p/c/main.c", "#include <stdio.h>\n#include \"main.h\"\n"),
+        ]);
+        assert_eq!(deps(&graph, "/p/web/app.ts"), ["/p/web/lib/index.js"]);
+        assert!(deps(&graph, "/p/c/main.c").is_empty());
+    }
+}
@@ -0,0 +1,127 @@
+// src/core/indent.rs
+
+/// 缩进压缩的结果
+pub struct CompressedIndent {
+    /// 转换后的文本
+    pub content: String,
+    /// 原始缩进单位 (多少个空格算一级)，写入 document 元数据以便还原
+    pub unit: usize,
+}
+
+/// 检测文件的缩进单位: 取所有缩进行行首空格数的最大公约数。
+///
+/// 以下情况返回 None (不做转换):
+///   - 行首空白中出现 ASCII 空格以外的字符: 制表符 (例如 Makefile 或混合缩进)、NBSP 等 Unicode 空白，
+///     compress_indentation 只替换空格，否则转换后无法还原
+///   - 没有任何缩进行，或者最大公约数小于 2 (存在奇数对齐的续行)
+pub fn detect_indent_unit(content: &str) -> Option<usize> {
+    let mut unit = 0usize;
+    for line in content.lines() {
+        let rest = line.trim_start_matches(' ');
+        if rest.starts_with(char::is_whitespace) {
+            return None;
+        }
+        // 只有空白的行不参与检测，转换时也保持原样
+        if rest.is_empty() {
+            continue;
+        }
+        let n = line.len() - rest.len();
+        if n > 0 {
+            unit = gcd(unit, n);
+        }
+    }
+    if unit >= 2 { Some(unit) } else { None }
+}
+
+/// 将每行行首的空格按缩进单位替换为制表符 (每级一个 tab)。
+///
+/// 转换只涉及行首，且要求所有缩进都是单位的整数倍，因此可以用 unit 无损还原。
+/// 已知限制: 文档中的 Markdown 缩进代码块、字符串字面量内的对齐空格也会被一并转换。
+pub fn compress_indentation(content: &str) -> Option<CompressedIndent> {
+    let unit = detect_indent_unit(content)?;
+    let mut out = String::with_capacity(content.len());
+    for (i, line) in content.split('\n').enumerate() {
+        if i > 0 {
+            out.push('\n');
+        }
+        let trimmed = line.trim_start_matches(' ');
+        let spaces = line.len() - trimmed.len();
+        if trimmed.trim().is_empty() {
+            out.push_str(line);
+            continue;
+        }
+        for _ in 0..spaces / unit {
+            out.push('\t');
+        }
+        out.push_str(trimmed);
+    }
+    Some(CompressedIndent { content: out, unit })
+}
+
+fn gcd(a: usize, b: usize) -> usize {
+    if b == 0 { a } else { gcd(b, a % b) }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// 按 unit 把行首的制表符还原成空格 (compress_indentation 的逆操作)
+    fn expand(compressed: &CompressedIndent) -> String {
+        compressed
+            .content
+            .split('\n')
+            .map(|line| {
+                let rest = line.trim_start_matches('\t');
+                format!("{}{}", " ".repeat((line.len() - rest.len()) * compressed.unit), rest)
+            })
+            .collect::<Vec<_>>()
+            .join("\n")
+    }
+
+    const YAML: &str = "services:\n  web:\n    image: nginx\n    ports:\n      - \"80:80\"\n\n  db:\n    image: postgres\n";
+    const JSON: &str = "{\n    \"name\": \"rustprompt\",\n    \"deps\": {\n        \"serde\": \"1\"\n    },\n    \n    \"list\": [1, 2]\n}\n";
+    const PYTHON: &str = "class A:\n    def f(self):\n        if self.x:\n            return 1\n        return 2\n";
+
+    #[test]
+    fn detects_unit_of_fixtures() {
+        assert_eq!(detect_indent_unit(YAML), Some(2));
+        assert_eq!(detect_indent_unit(JSON), Some(4));
+        assert_eq!(detect_indent_unit(PYTHON), Some(4));
+    }
+
+    #[test]
+    fn fixtures_round_trip() {
+        for fixture in [YAML, JSON, PYTHON] {
+            let compressed = compress_indentation(fixture).unwrap();
+            assert!(compressed.content.len() < fixture.len());
+            assert_eq!(expand(&compressed), fixture);
+        }
+    }
+
+    #[test]
+    fn compresses_one_tab_per_level() {
+        let compressed = compress_indentation(PYTHON).unwrap();
+        assert_eq!(compressed.content, "class A:\n\tdef f(self):\n\t\tif self.x:\n\t\t\treturn 1\n\t\treturn 2\n");
+    }
+
+    #[test]
+    fn whitespace_only_lines_are_kept() {
+        let compressed = compress_indentation(JSON).unwrap();
+        assert!(compressed.content.contains("\n    \n"));
+    }
+
+    #[test]
+    fn skips_tabs_and_unicode_spaces() {
+        assert_eq!(detect_indent_unit("a:\n\tb: 1\n"), None);
+        assert_eq!(detect_indent_unit("a:\n    b: 1\n  \u{a0}c: 2\n"), None);
+        assert_eq!(detect_indent_unit("a:\n    b: 1\n  \u{3000}c: 2\n"), None);
+        assert!(compress_indentation("x\n    y\n  \u{a0}z\n").is_none());
+    }
+
+    #[test]
+    fn skips_odd_or_missing_indentation() {
+        assert_eq!(detect_indent_unit("a\nb\n"), None);
+        assert_eq!(detect_indent_unit("a\n    b\n     c\n"), None);
+    }
+}
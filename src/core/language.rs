@@ -0,0 +1,68 @@
+// src/core/language.rs
+
+use std::path::Path;
+
+/// 根据文件扩展名 (或少数特殊文件名) 推断语言名，用于 payload 中的分组
+pub fn detect_language(path: &Path) -> String {
+    let file_name = path
+        .file_name()
+        .map(|n| n.to_string_lossy().to_string())
+        .unwrap_or_default();
+
+    match file_name.as_str() {
+        "Dockerfile" => return "dockerfile".to_string(),
+        "Makefile" | "makefile" => return "make".to_string(),
+        _ => {}
+    }
+
+    let ext = match path.extension() {
+        Some(e) => e.to_string_lossy().to_lowercase(),
+        None => return "text".to_string(),
+    };
+
+    let lang = match ext.as_str() {
+        "rs" => "rust",
+        "toml" => "toml",
+        "ts" | "tsx" | "mts" | "cts" => "typescript",
+        "js" | "jsx" | "mjs" | "cjs" => "javascript",
+        "py" | "pyi" => "python",
+        "md" | "markdown" => "markdown",
+        "json" => "json",
+        "yaml" | "yml" => "yaml",
+        "html" | "htm" => "html",
+        "css" | "scss" | "sass" | "less" => "css",
+        "go" => "go",
+        "java" => "java",
+        "kt" | "kts" => "kotlin",
+        "c" | "h" => "c",
+        "cc" | "cpp" | "cxx" | "hpp" | "hh" => "cpp",
+        "cs" => "csharp",
+        "rb" => "ruby",
+        "php" => "php",
+        "swift" => "swift",
+        "sh" | "bash" | "zsh" => "shell",
+        "sql" => "sql",
+        "xml" => "xml",
+        "txt" => "text",
+        other => return other.to_string(),
+    };
+    lang.to_string()
+}
+
+/// 语言名的显示形式，用于 markdown 等格式的分组标题 (例如 `rust` -> `Rust`，`typescript` -> `TypeScript`)
+pub fn language_title(language: &str) -> String {
+    match language {
+        "typescript" => "TypeScript".to_string(),
+        "javascript" => "JavaScript".to_string(),
+        "cpp" => "C++".to_string(),
+        "csharp" => "C#".to_string(),
+        "toml" | "json" | "yaml" | "html" | "css" | "sql" | "xml" | "php" => language.to_uppercase(),
+        _ => {
+            let mut chars = language.chars();
+            match chars.next() {
+                Some(first) => first.to_uppercase().chain(chars).collect(),
+                None => String::new(),
+            }
+        }
+    }
+}
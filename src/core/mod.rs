@@ -3,4 +3,21 @@ pub mod tokenizer;
 pub mod xml;
 pub mod clipboard;
 pub mod tree_builder;
-pub mod ignore_rules; 
\ No newline at end of file
+pub mod ignore_rules;
+pub mod indent;
+pub mod language;
+pub mod renames;
+pub mod file_stamp;
+pub mod validate;
+pub mod imports;
+pub mod ordering;
+pub mod overlap;
+pub mod template;
+pub mod roots;
+pub mod decode;
+pub mod dirs;
+pub mod paths;
+pub mod progress;
+pub mod search;
+pub mod templating;
+pub mod git;
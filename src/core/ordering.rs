@@ -0,0 +1,184 @@
+// src/core/ordering.rs
+
+use std::cmp::Reverse;
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+use std::path::PathBuf;
+
+use super::file_stamp::FileStamp;
+
+/// payload 中真实文件的排列策略
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum SortStrategy {
+    /// 按路径字典序 (默认)
+    #[default]
+    Path,
+    /// token 数多的在前
+    TokensDesc,
+    /// 最近修改的在前
+    Recency,
+    /// 被依赖的文件在前 (基于 import 图的拓扑序，尽力而为)
+    Dependency,
+}
+
+impl SortStrategy {
+    pub const NAMES: &'static [&'static str] = &["path", "tokens-desc", "recency", "dependency"];
+
+    pub fn parse(name: &str) -> Option<Self> {
+        match name.trim().to_lowercase().as_str() {
+            "path" => Some(Self::Path),
+            "tokens-desc" | "tokens" => Some(Self::TokensDesc),
+            "recency" => Some(Self::Recency),
+            "dependency" | "deps" => Some(Self::Dependency),
+            _ => None,
+        }
+    }
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::Path => "path",
+            Self::TokensDesc => "tokens-desc",
+            Self::Recency => "recency",
+            Self::Dependency => "dependency",
+        }
+    }
+}
+
+/// 排序所需的每个文件的信息，由调用方从 AppState 中收集
+#[derive(Debug, Default)]
+pub struct OrderingInputs<'a> {
+    pub tokens: Option<&'a HashMap<PathBuf, usize>>,
+    pub stamps: Option<&'a HashMap<PathBuf, FileStamp>>,
+    /// 文件 -> 它依赖的 (已选) 文件
+    pub dependencies: Option<&'a HashMap<PathBuf, Vec<PathBuf>>>,
+}
+
+/// 按策略给文件排序。所有策略在相同条件下都以路径作为次序，保证结果稳定。
+pub fn order_paths(paths: &[PathBuf], strategy: SortStrategy, inputs: &OrderingInputs) -> Vec<PathBuf> {
+    let mut sorted: Vec<PathBuf> = paths.to_vec();
+    sorted.sort();
+
+    match strategy {
+        SortStrategy::Path => {}
+        SortStrategy::TokensDesc => {
+            let tokens = |p: &PathBuf| inputs.tokens.and_then(|t| t.get(p)).copied().unwrap_or(0);
+            sorted.sort_by_key(|p| Reverse(tokens(p)));
+        }
+        SortStrategy::Recency => {
+            let modified = |p: &PathBuf| inputs.stamps.and_then(|s| s.get(p)).and_then(|s| s.modified);
+            // None (无法获取 mtime) 排在最后
+            sorted.sort_by_key(|p| Reverse(modified(p)));
+        }
+        SortStrategy::Dependency => {
+            if let Some(deps) = inputs.dependencies {
+                sorted = topological_order(&sorted, deps);
+            }
+        }
+    }
+    sorted
+}
+
+/// Kahn 算法: 依赖在前。每一步从可用节点中取路径最小者，保证结果确定；
+/// 无依赖信息的文件退化为路径顺序。遇到循环依赖时，释放剩余文件中未满足依赖最少
+/// (相同时路径最小) 的一个来打破环，然后继续。
+fn topological_order(sorted: &[PathBuf], deps: &HashMap<PathBuf, Vec<PathBuf>>) -> Vec<PathBuf> {
+    let mut remaining: BTreeMap<&PathBuf, usize> = BTreeMap::new();
+    let mut dependents: HashMap<&PathBuf, Vec<&PathBuf>> = HashMap::new();
+    for p in sorted {
+        let ds: Vec<&PathBuf> = deps
+            .get(p)
+            .into_iter()
+            .flatten()
+            .filter(|d| *d != p && sorted.contains(d))
+            .collect();
+        remaining.insert(p, ds.len());
+        for d in ds {
+            dependents.entry(d).or_default().push(p);
+        }
+    }
+
+    let mut ready: BTreeSet<&PathBuf> = remaining
+        .iter()
+        .filter(|(_, n)| **n == 0)
+        .map(|(p, _)| *p)
+        .collect();
+    let mut out = Vec::with_capacity(sorted.len());
+
+    loop {
+        let p = match ready.pop_first() {
+            Some(p) => p,
+            None => match remaining.iter().min_by_key(|(_, n)| **n) {
+                Some((p, _)) => *p,
+                None => break,
+            },
+        };
+        remaining.remove(p);
+        out.push(p.clone());
+        for dependent in dependents.get(p).into_iter().flatten() {
+            if let Some(n) = remaining.get_mut(dependent) {
+                *n -= 1;
+                if *n == 0 {
+                    ready.insert(dependent);
+                }
+            }
+        }
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn paths(names: &[&str]) -> Vec<PathBuf> {
+        names.iter().map(PathBuf::from).collect()
+    }
+
+    fn graph(edges: &[(&str, &[&str])]) -> HashMap<PathBuf, Vec<PathBuf>> {
+        edges.iter().map(|(from, to)| (PathBuf::from(from), paths(to))).collect()
+    }
+
+    fn by_dependency(files: &[&str], deps: &HashMap<PathBuf, Vec<PathBuf>>) -> Vec<PathBuf> {
+        let inputs = OrderingInputs { dependencies: Some(deps), ..OrderingInputs::default() };
+        order_paths(&paths(files), SortStrategy::Dependency, &inputs)
+    }
+
+    #[test]
+    fn dependencies_come_first_and_ties_follow_path_order() {
+        // d 依赖 b 和 c，b、c 都依赖 a；e 没有依赖。b 与 c、a 与 e 同时可用时按路径
+        let deps = graph(&[("d", &["b", "c"]), ("c", &["a"]), ("b", &["a"])]);
+        assert_eq!(by_dependency(&["e", "d", "c", "b", "a"], &deps), paths(&["a", "b", "c", "d", "e"]));
+    }
+
+    #[test]
+    fn cycle_is_broken_at_the_file_with_fewest_unmet_dependencies() {
+        // a -> b -> c -> a 构成环，d 依赖 a 和 c: 环中未满足依赖都是 1 个，取路径最小的 a
+        let deps = graph(&[("a", &["b"]), ("b", &["c"]), ("c", &["a"]), ("d", &["a", "c"])]);
+        assert_eq!(by_dependency(&["d", "c", "b", "a"], &deps), paths(&["a", "c", "b", "d"]));
+
+        // x 未满足 2 个依赖，y 只有 1 个: 先释放 y
+        let deps = graph(&[("x", &["y", "z"]), ("y", &["x"]), ("z", &["x"])]);
+        assert_eq!(by_dependency(&["x", "y", "z"], &deps), paths(&["y", "x", "z"]));
+    }
+
+    #[test]
+    fn self_and_unselected_dependencies_are_ignored() {
+        let deps = graph(&[("a", &["a", "outside"]), ("b", &["a", "a"])]);
+        assert_eq!(by_dependency(&["b", "a"], &deps), paths(&["a", "b"]));
+        // 没有依赖信息时退化为路径顺序
+        let none = OrderingInputs::default();
+        assert_eq!(order_paths(&paths(&["b", "a"]), SortStrategy::Dependency, &none), paths(&["a", "b"]));
+    }
+
+    #[test]
+    fn token_ties_keep_path_order() {
+        let tokens: HashMap<PathBuf, usize> = [("a", 5), ("b", 9), ("c", 5), ("d", 9)]
+            .into_iter()
+            .map(|(p, n)| (PathBuf::from(p), n))
+            .collect();
+        let inputs = OrderingInputs { tokens: Some(&tokens), ..OrderingInputs::default() };
+        assert_eq!(
+            order_paths(&paths(&["d", "c", "b", "a"]), SortStrategy::TokensDesc, &inputs),
+            paths(&["b", "d", "a", "c"])
+        );
+    }
+}
@@ -0,0 +1,196 @@
+// src/core/overlap.rs
+
+use std::collections::hash_map::DefaultHasher;
+use std::collections::{BTreeSet, HashMap};
+use std::hash::{Hash, Hasher};
+use std::path::PathBuf;
+
+/// 连续多少行相同才算作重复 (太短会把 `}` 之类的行误判为重复)
+const WINDOW: usize = 4;
+/// 参与分析的内容总量上限，超过时直接跳过，保证分析不会拖慢 /copy
+const SIZE_CUTOFF: usize = 8 * 1024 * 1024;
+/// 用于滚动哈希的基数
+const BASE: u64 = 1_000_003;
+
+/// 一个虚拟文档 (命令输出、diff 等) 与已选文件内容的重复情况
+#[derive(Debug, Clone)]
+pub struct Overlap {
+    pub document: PathBuf,
+    /// 重复内容的字节数
+    pub duplicate_bytes: usize,
+    /// 与之重复的已选文件
+    pub files: Vec<PathBuf>,
+}
+
+impl Overlap {
+    /// 粗略估计的 token 数 (约 4 字节 / token)，避免在这里调用分词器
+    pub fn approx_tokens(&self) -> usize {
+        self.duplicate_bytes.div_ceil(4)
+    }
+}
+
+/// 找出虚拟文档中与已选文件内容重复的部分。
+///  - 对每个文件内容中连续 WINDOW 行 (去掉首尾空白、跳过空行) 计算滚动哈希
+///  - 虚拟文档中命中的窗口所覆盖的行计为重复
+///  - 内容总量超过 SIZE_CUTOFF 时返回空结果
+pub fn find_overlaps(files: &[(&PathBuf, &str)], virtual_docs: &[(&PathBuf, &str)]) -> Vec<Overlap> {
+    let total: usize = files.iter().chain(virtual_docs).map(|(_, c)| c.len()).sum();
+    if total > SIZE_CUTOFF || virtual_docs.is_empty() {
+        return Vec::new();
+    }
+
+    // 窗口哈希 -> 首次出现在哪个文件
+    let mut index: HashMap<u64, usize> = HashMap::new();
+    for (file_idx, (_, content)) in files.iter().enumerate() {
+        let lines = significant_lines(content);
+        for (hash, _) in window_hashes(&lines) {
+            index.entry(hash).or_insert(file_idx);
+        }
+    }
+    if index.is_empty() {
+        return Vec::new();
+    }
+
+    let mut overlaps = Vec::new();
+    for (doc, content) in virtual_docs {
+        let lines = significant_lines(content);
+        let mut covered = vec![false; lines.len()];
+        let mut sources = BTreeSet::new();
+        for (hash, start) in window_hashes(&lines) {
+            if let Some(&file_idx) = index.get(&hash) {
+                sources.insert(file_idx);
+                covered[start..start + WINDOW].iter_mut().for_each(|c| *c = true);
+            }
+        }
+        let duplicate_bytes: usize = lines
+            .iter()
+            .zip(&covered)
+            .filter(|(_, c)| **c)
+            .map(|(l, _)| l.len() + 1)
+            .sum();
+        if duplicate_bytes > 0 {
+            overlaps.push(Overlap {
+                document: (*doc).clone(),
+                duplicate_bytes,
+                files: sources.into_iter().map(|i| files[i].0.clone()).collect(),
+            });
+        }
+    }
+    overlaps
+}
+
+fn significant_lines(content: &str) -> Vec<&str> {
+    content.lines().map(str::trim).filter(|l| !l.is_empty()).collect()
+}
+
+/// 对每个长度为 WINDOW 的行窗口计算多项式滚动哈希，返回 (哈希, 窗口起始行)
+fn window_hashes(lines: &[&str]) -> Vec<(u64, usize)> {
+    if lines.len() < WINDOW {
+        return Vec::new();
+    }
+    let line_hashes: Vec<u64> = lines
+        .iter()
+        .map(|l| {
+            let mut h = DefaultHasher::new();
+            l.hash(&mut h);
+            h.finish()
+        })
+        .collect();
+
+    // BASE^(WINDOW-1)，用于移出窗口最左侧的行
+    let high = (1..WINDOW).fold(1u64, |acc, _| acc.wrapping_mul(BASE));
+    let mut hash = line_hashes[..WINDOW]
+        .iter()
+        .fold(0u64, |acc, h| acc.wrapping_mul(BASE).wrapping_add(*h));
+    let mut out = Vec::with_capacity(lines.len() - WINDOW + 1);
+    out.push((hash, 0));
+    for start in 1..=lines.len() - WINDOW {
+        hash = hash
+            .wrapping_sub(line_hashes[start - 1].wrapping_mul(high))
+            .wrapping_mul(BASE)
+            .wrapping_add(line_hashes[start + WINDOW - 1]);
+        out.push((hash, start));
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// 20 行互不相同的"源文件"
+    fn source() -> String {
+        (1..=20).map(|i| format!("    let value_{} = compute({});\n", i, i)).collect()
+    }
+
+    fn run(files: &[(&str, &str)], docs: &[(&str, &str)]) -> Vec<Overlap> {
+        let files: Vec<(PathBuf, &str)> = files.iter().map(|(p, c)| (PathBuf::from(p), *c)).collect();
+        let docs: Vec<(PathBuf, &str)> = docs.iter().map(|(p, c)| (PathBuf::from(p), *c)).collect();
+        let files: Vec<(&PathBuf, &str)> = files.iter().map(|(p, c)| (p, *c)).collect();
+        let docs: Vec<(&PathBuf, &str)> = docs.iter().map(|(p, c)| (p, *c)).collect();
+        find_overlaps(&files, &docs)
+    }
+
+    #[test]
+    fn counts_copied_lines_inside_a_capture() {
+        let source = source();
+        let copied: Vec<&str> = source.lines().skip(5).take(8).collect();
+        // 前后是命令自己的输出，行首缩进不同也算重复
+        let capture = format!(
+            "running 3 tests\ntest a ... ok\n{}\ntest result: ok\n",
+            copied.iter().map(|l| l.trim()).collect::<Vec<_>>().join("\n")
+        );
+        let overlaps = run(&[("src/lib.rs", &source)], &[("cargo test", &capture)]);
+
+        assert_eq!(overlaps.len(), 1);
+        assert_eq!(overlaps[0].document, PathBuf::from("cargo test"));
+        assert_eq!(overlaps[0].files, [PathBuf::from("src/lib.rs")]);
+        let expected: usize = copied.iter().map(|l| l.trim().len() + 1).sum();
+        assert_eq!(overlaps[0].duplicate_bytes, expected);
+        assert_eq!(overlaps[0].approx_tokens(), expected.div_ceil(4));
+    }
+
+    #[test]
+    fn ignores_runs_shorter_than_the_window() {
+        let source = source();
+        let short: Vec<&str> = source.lines().take(WINDOW - 1).collect();
+        let diff = format!("@@ -1,3 +1,3 @@\n{}\n+new line\n", short.join("\n"));
+        assert!(run(&[("src/lib.rs", &source)], &[("git diff", &diff)]).is_empty());
+    }
+
+    #[test]
+    fn reports_every_file_a_document_duplicates() {
+        let a: String = (0..6).map(|i| format!("alpha line {}\n", i)).collect();
+        let b: String = (0..6).map(|i| format!("beta line {}\n", i)).collect();
+        let capture = format!("{}noise\n{}", a, b);
+        let overlaps = run(&[("a.rs", &a), ("b.rs", &b)], &[("capture", &capture)]);
+        assert_eq!(overlaps.len(), 1);
+        assert_eq!(overlaps[0].files, [PathBuf::from("a.rs"), PathBuf::from("b.rs")]);
+        assert_eq!(overlaps[0].duplicate_bytes, a.len() + b.len());
+    }
+
+    #[test]
+    fn unrelated_documents_have_no_overlap() {
+        let source = source();
+        let capture: String = (0..30).map(|i| format!("unrelated output {}\n", i)).collect();
+        assert!(run(&[("src/lib.rs", &source)], &[("capture", &capture)]).is_empty());
+        assert!(run(&[("src/lib.rs", &source)], &[]).is_empty());
+    }
+
+    #[test]
+    fn skips_content_over_the_size_cutoff() {
+        let big = "same line\n".repeat(SIZE_CUTOFF / 10 + 1);
+        assert!(run(&[("big.log", &big)], &[("capture", "same line\nsame line\nsame line\nsame line\n")]).is_empty());
+    }
+
+    #[test]
+    fn rolling_hash_matches_hashing_each_window() {
+        let source = source();
+        let lines = significant_lines(&source);
+        let rolled = window_hashes(&lines);
+        assert_eq!(rolled.len(), lines.len() - WINDOW + 1);
+        for (hash, start) in rolled {
+            assert_eq!(window_hashes(&lines[start..start + WINDOW]), [(hash, 0)]);
+        }
+    }
+}
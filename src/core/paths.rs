@@ -0,0 +1,90 @@
+// src/core/paths.rs
+
+use std::path::{Component, Path, PathBuf};
+
+/// 按字面规整路径: 去掉 `.`，`..` 与前一段抵消 (不访问文件系统，不解析符号链接)。
+/// 开头无法抵消的 `..` 保留，例如 `../lib/./a.rs` -> `../lib/a.rs`
+pub fn normalize(path: &Path) -> PathBuf {
+    let mut out: Vec<Component> = Vec::new();
+    for component in path.components() {
+        match component {
+            Component::CurDir => {}
+            Component::ParentDir => match out.last() {
+                Some(Component::Normal(_)) => {
+                    out.pop();
+                }
+                // 根目录的上一级仍是根目录
+                Some(Component::RootDir | Component::Prefix(_)) => {}
+                _ => out.push(component),
+            },
+            other => out.push(other),
+        }
+    }
+    if out.is_empty() {
+        return PathBuf::from(".");
+    }
+    out.iter().collect()
+}
+
+/// 已选文件在 AppState 中使用的 key: 规整后的路径，位于 cwd 之下的绝对路径转换为相对路径，
+/// 这样 `./src/a.rs`、`src/a.rs` 和拖入的 `/home/me/proj/src/a.rs` 是同一个文件
+pub fn selection_key(path: &Path, cwd: &Path) -> PathBuf {
+    let normalized = normalize(path);
+    if normalized.is_absolute() {
+        if let Ok(relative) = normalized.strip_prefix(cwd) {
+            if !relative.as_os_str().is_empty() {
+                return relative.to_path_buf();
+            }
+        }
+    }
+    normalized
+}
+
+/// 写入 `<source>` 以及 /context、/list 中展示的路径: 相对于项目根目录；
+/// 不在根目录之下时使用 (规整后的) 绝对路径。path 为相对路径时相对于 cwd
+pub fn display_path(path: &Path, root: &Path) -> PathBuf {
+    let cwd = std::env::current_dir().unwrap_or_else(|_| PathBuf::from("."));
+    let absolute = normalize(&cwd.join(path));
+    match absolute.strip_prefix(root) {
+        Ok(relative) if !relative.as_os_str().is_empty() => relative.to_path_buf(),
+        _ => absolute,
+    }
+}
+
+/// 用户输入的相对路径: 先相对于 cwd；cwd 下不存在、项目根目录下存在时改为根目录下的路径
+/// (补全和 /context 显示的是相对于根目录的路径，两者不同时也能直接使用)。绝对路径和都不存在时原样返回
+///
+/// ```
+/// use std::path::Path;
+/// use rustprompt::core::paths::resolve_in_root;
+///
+/// let root = std::env::temp_dir().join(format!("rustprompt-resolve-{}", std::process::id()));
+/// std::fs::create_dir_all(root.join("src")).unwrap();
+/// std::fs::write(root.join("src/a.rs"), "").unwrap();
+/// let cwd = root.join("src");
+///
+/// assert_eq!(resolve_in_root(Path::new("a.rs"), &cwd, &root), Path::new("a.rs"));
+/// assert_eq!(resolve_in_root(Path::new("src/a.rs"), &cwd, &root), root.join("src/a.rs"));
+/// assert_eq!(resolve_in_root(Path::new("src/b.rs"), &cwd, &root), Path::new("src/b.rs"));
+/// # std::fs::remove_dir_all(&root).unwrap();
+/// ```
+pub fn resolve_in_root(path: &Path, cwd: &Path, root: &Path) -> PathBuf {
+    if path.is_absolute() || cwd.join(path).exists() {
+        return path.to_path_buf();
+    }
+    let in_root = root.join(path);
+    if in_root.exists() {
+        in_root
+    } else {
+        path.to_path_buf()
+    }
+}
+
+/// 用于判断两个路径是否为同一文件的 key: 先解析符号链接 (文件不存在时退回按字面规整)，
+/// 再按 selection_key 转换，指向同一目标的符号链接因此得到相同的 key
+pub fn file_key(path: &Path, cwd: &Path) -> PathBuf {
+    match (std::fs::canonicalize(path), std::fs::canonicalize(cwd)) {
+        (Ok(target), Ok(cwd)) => selection_key(&target, &cwd),
+        _ => selection_key(path, cwd),
+    }
+}
@@ -0,0 +1,43 @@
+// src/core/progress.rs
+
+use tokio::sync::mpsc::{unbounded_channel, UnboundedReceiver, UnboundedSender};
+
+/// 扫描目录时每找到这么多个文件发送一次 Scanned 事件
+pub const SCAN_STEP: usize = 100;
+
+/// 耗时操作 (/add 大目录) 的进度事件
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ProgressEvent {
+    /// 扫描中: 当前路径下已找到的文件数
+    Scanned(usize),
+    /// 读取文件并生成 snippet: 已完成 / 总数
+    Read { done: usize, total: usize },
+    /// 文件已读完，开始生成项目树、合并并计算 token
+    Tokenizing,
+}
+
+/// 进度事件的发送端。接收端由调用方 (executor) 持有并负责展示；
+/// `Progress::none()` 不发送任何事件，供不需要进度的调用使用
+#[derive(Debug, Clone, Default)]
+pub struct Progress {
+    tx: Option<UnboundedSender<ProgressEvent>>,
+}
+
+impl Progress {
+    pub fn none() -> Self {
+        Self { tx: None }
+    }
+
+    /// 新建发送端及对应的接收端；所有发送端 (包括 clone) drop 后接收端结束
+    pub fn channel() -> (Self, UnboundedReceiver<ProgressEvent>) {
+        let (tx, rx) = unbounded_channel();
+        (Self { tx: Some(tx) }, rx)
+    }
+
+    /// 发送一个事件 (不需要 await，可在 spawn_blocking 中调用)；接收端已关闭时静默丢弃
+    pub fn send(&self, event: ProgressEvent) {
+        if let Some(tx) = &self.tx {
+            let _ = tx.send(event);
+        }
+    }
+}
@@ -0,0 +1,232 @@
+// src/core/renames.rs
+
+use std::collections::hash_map::DefaultHasher;
+use std::collections::{HashMap, HashSet};
+use std::hash::{Hash, Hasher};
+use std::path::{Component, Path, PathBuf};
+use std::process::Command;
+
+use super::ignore_rules::IgnoreConfig;
+
+/// 文件内容指纹: 长度 + 内容哈希，用于在文件被改名后重新找到它
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ContentFingerprint {
+    /// 文件的完整长度
+    pub len: u64,
+    /// 参与哈希的开头字节数；超过 max_file_bytes 只读取了开头部分时小于 len
+    pub hashed: u64,
+    pub hash: u64,
+}
+
+impl ContentFingerprint {
+    pub fn of(content: &[u8]) -> Self {
+        Self::of_prefix(content, content.len() as u64)
+    }
+
+    /// 只读取了文件开头 prefix 的指纹 (文件被截断时)，len 为文件的完整长度
+    pub fn of_prefix(prefix: &[u8], len: u64) -> Self {
+        let mut hasher = DefaultHasher::new();
+        prefix.hash(&mut hasher);
+        Self {
+            len,
+            hashed: prefix.len() as u64,
+            hash: hasher.finish(),
+        }
+    }
+}
+
+/// 把路径转为绝对路径，并去掉 `.`/`..`，以便与 git 输出的路径比较
+/// (文件可能已经不存在，所以不能依赖 canonicalize)
+pub fn absolute_path(path: &Path) -> PathBuf {
+    let joined = if path.is_absolute() {
+        path.to_path_buf()
+    } else {
+        std::env::current_dir().unwrap_or_else(|_| PathBuf::from(".")).join(path)
+    };
+    let mut out = PathBuf::new();
+    for comp in joined.components() {
+        match comp {
+            Component::CurDir => {}
+            Component::ParentDir => {
+                out.pop();
+            }
+            other => out.push(other.as_os_str()),
+        }
+    }
+    out
+}
+
+/// 从 git 的记录中找出改名，返回 旧绝对路径 -> 新绝对路径:
+///  - 已暂存的改名 (`git mv`，状态 `R`)
+///  - 未暂存的改名 (直接 `mv`): git 只看到一个删除 (`D`) 和一个未跟踪文件 (`??`)，
+///    未跟踪文件与被删除文件在 HEAD 中的内容 (blob) 完全相同时视为改名
+///
+/// 使用 `--porcelain -z`，路径原样输出 (空格、非 ASCII 字符、`\t` 都不加引号或转义)。
+/// 不在 git 仓库中或 git 不可用时返回空表。
+pub fn detect_git_renames(root: &Path) -> HashMap<PathBuf, PathBuf> {
+    let mut renames = HashMap::new();
+
+    let toplevel = match git(root, &["rev-parse", "--show-toplevel"]) {
+        Some(out) => PathBuf::from(String::from_utf8_lossy(&out).trim().to_string()),
+        None => return renames,
+    };
+    let status = ["status", "--porcelain=v1", "-z", "--untracked-files=all"];
+    let Some(output) = git(root, &status) else {
+        return renames;
+    };
+
+    let mut deleted = Vec::new();
+    let mut untracked = Vec::new();
+    // 每条记录为 "XY path\0"；改名和复制多一个字段，格式为 "XY new\0old\0"
+    let mut fields = output.split(|b| *b == 0);
+    while let Some(entry) = fields.next() {
+        if entry.len() < 4 {
+            continue;
+        }
+        let (x, y, path) = (entry[0], entry[1], path_from_bytes(&entry[3..]));
+        if matches!(x, b'R' | b'C') || y == b'R' {
+            let Some(old) = fields.next() else { break };
+            if x == b'R' || y == b'R' {
+                let old = toplevel.join(path_from_bytes(old));
+                renames.insert(absolute_path(&old), absolute_path(&toplevel.join(&path)));
+            }
+        } else if (x, y) == (b'?', b'?') {
+            untracked.push(path);
+        } else if x == b'D' || y == b'D' {
+            deleted.push(path);
+        }
+    }
+
+    for (old, new) in match_unstaged_renames(&toplevel, &deleted, &untracked) {
+        renames
+            .entry(absolute_path(&toplevel.join(old)))
+            .or_insert_with(|| absolute_path(&toplevel.join(new)));
+    }
+    renames
+}
+
+/// 在 root 中运行 git，成功时返回标准输出
+fn git(root: &Path, args: &[&str]) -> Option<Vec<u8>> {
+    git_with_paths(root, args, &[])
+}
+
+/// 同 git，参数后追加 `-- paths...`
+fn git_with_paths(root: &Path, args: &[&str], paths: &[&PathBuf]) -> Option<Vec<u8>> {
+    let mut command = Command::new("git");
+    command.args(args).current_dir(root);
+    if !paths.is_empty() {
+        command.arg("--").args(paths);
+    }
+    match command.output() {
+        Ok(out) if out.status.success() => Some(out.stdout),
+        _ => None,
+    }
+}
+
+/// git 输出的原始路径字节 (相对于仓库根目录)
+fn path_from_bytes(bytes: &[u8]) -> PathBuf {
+    #[cfg(unix)]
+    {
+        use std::os::unix::ffi::OsStrExt;
+        PathBuf::from(std::ffi::OsStr::from_bytes(bytes))
+    }
+    #[cfg(not(unix))]
+    {
+        PathBuf::from(String::from_utf8_lossy(bytes).into_owned())
+    }
+}
+
+/// 把被删除的已跟踪文件与内容相同的未跟踪文件配对 (路径都相对于仓库根目录)。
+/// 先按 HEAD 中的 blob 大小筛选，只对大小相同的未跟踪文件计算 blob id。
+/// 比较的是最后一次提交的内容，改名前还修改过 (未提交) 的文件不会被认出
+fn match_unstaged_renames(
+    toplevel: &Path,
+    deleted: &[PathBuf],
+    untracked: &[PathBuf],
+) -> Vec<(PathBuf, PathBuf)> {
+    if deleted.is_empty() || untracked.is_empty() {
+        return Vec::new();
+    }
+    // 从仓库根目录运行，路径与 status 的输出一致
+    let deleted: Vec<&PathBuf> = deleted.iter().collect();
+    let Some(listing) = git_with_paths(toplevel, &["ls-tree", "-r", "-l", "-z", "HEAD"], &deleted) else {
+        return Vec::new();
+    };
+    // 每条记录为 "mode type blob size\tpath\0"
+    let mut blobs: Vec<(u64, String, PathBuf)> = Vec::new();
+    for entry in listing.split(|b| *b == 0) {
+        let Some(tab) = entry.iter().position(|b| *b == b'\t') else { continue };
+        let meta = String::from_utf8_lossy(&entry[..tab]);
+        let fields: Vec<&str> = meta.split_whitespace().collect();
+        if let [_, "blob", blob, size] = fields[..] {
+            if let Ok(size) = size.parse() {
+                blobs.push((size, blob.to_string(), path_from_bytes(&entry[tab + 1..])));
+            }
+        }
+    }
+
+    let candidates: Vec<&PathBuf> = untracked
+        .iter()
+        .filter(|p| {
+            let len = std::fs::metadata(toplevel.join(p)).map(|m| m.len());
+            len.is_ok_and(|len| blobs.iter().any(|(size, _, _)| *size == len))
+        })
+        .collect();
+    if candidates.is_empty() {
+        return Vec::new();
+    }
+    let Some(hashed) = git_with_paths(toplevel, &["hash-object"], &candidates) else {
+        return Vec::new();
+    };
+    let ids: Vec<String> = String::from_utf8_lossy(&hashed).lines().map(str::to_string).collect();
+
+    let mut pairs = Vec::new();
+    let mut taken = HashSet::new();
+    for (_, blob, old) in &blobs {
+        let found = candidates.iter().zip(&ids).find(|(new, id)| *id == blob && !taken.contains(**new));
+        if let Some((new, _)) = found {
+            taken.insert((*new).clone());
+            pairs.push((old.clone(), (*new).clone()));
+        }
+    }
+    pairs
+}
+
+/// 在 root 下查找内容指纹完全相同、且未被选中的文件 (应用忽略规则)
+pub fn find_by_fingerprint(
+    root: &Path,
+    fingerprint: ContentFingerprint,
+    ignore_config: &IgnoreConfig,
+    exclude: &HashSet<PathBuf>,
+) -> Option<PathBuf> {
+    let walker = ignore_config.build_walker(root).build();
+    for entry in walker.flatten() {
+        if !entry.file_type().is_some_and(|ft| ft.is_file()) {
+            continue;
+        }
+        let path = entry.path();
+        // 先用完整长度快速过滤，避免读取大量文件
+        let same_len = entry
+            .metadata()
+            .map(|m| m.len() == fingerprint.len)
+            .unwrap_or(false);
+        if !same_len || exclude.contains(&absolute_path(path)) {
+            continue;
+        }
+        // 截断读取的文件只比较当时读到的开头部分
+        if let Ok(bytes) = read_head(path, fingerprint.hashed) {
+            if ContentFingerprint::of_prefix(&bytes, fingerprint.len) == fingerprint {
+                return Some(path.to_path_buf());
+            }
+        }
+    }
+    None
+}
+
+/// 读取文件开头最多 limit 字节
+fn read_head(path: &Path, limit: u64) -> std::io::Result<Vec<u8>> {
+    use std::io::Read;
+    let mut bytes = Vec::new();
+    std::fs::File::open(path)?.take(limit).read_to_end(&mut bytes)?;
+    Ok(bytes)
+}
@@ -0,0 +1,248 @@
+// src/core/roots.rs
+
+use std::collections::BTreeSet;
+use std::path::{Component, Path, PathBuf};
+
+/// 一次 `/add <path>` 的参数 (目录、文件或 glob) 以及它最近一次展开得到的文件
+#[derive(Debug, Clone, PartialEq)]
+pub struct AddRoot {
+    pub path: PathBuf,
+    /// 最近一次展开的全部结果 (包括之后被单独 /remove 的文件，这样重新展开时不会把它们加回来)
+    pub files: BTreeSet<PathBuf>,
+}
+
+/// /context 中一个 root 的展示信息
+#[derive(Debug, Clone, PartialEq)]
+pub struct RootSummary {
+    pub path: PathBuf,
+    /// 该 root 下目前仍被选中的文件数
+    pub selected: usize,
+    /// 其中同时被其它 root 覆盖的文件数
+    pub shared: usize,
+    /// 与之重叠的其它 root
+    pub overlaps_with: Vec<PathBuf>,
+}
+
+/// 所有 add root，按添加顺序排列
+#[derive(Debug, Clone, Default)]
+pub struct AddRoots {
+    roots: Vec<AddRoot>,
+}
+
+impl AddRoots {
+    pub fn iter(&self) -> impl Iterator<Item = &AddRoot> {
+        self.roots.iter()
+    }
+
+    pub fn clear(&mut self) {
+        self.roots.clear();
+    }
+
+    /// 记录一次 /add 的展开结果；同一个 root 再次添加时合并文件集合
+    pub fn record(&mut self, path: &Path, files: impl IntoIterator<Item = PathBuf>) {
+        match self.position(path) {
+            Some(i) => self.roots[i].files.extend(files),
+            None => self.roots.push(AddRoot {
+                path: path.to_path_buf(),
+                files: files.into_iter().collect(),
+            }),
+        }
+    }
+
+    /// 用重新展开的结果替换 root 的文件集合，返回此前从未出现过的新文件
+    pub fn reexpand(&mut self, path: &Path, files: Vec<PathBuf>) -> Vec<PathBuf> {
+        let Some(i) = self.position(path) else {
+            return Vec::new();
+        };
+        let root = &mut self.roots[i];
+        let new_files: Vec<PathBuf> = files.iter().filter(|f| !root.files.contains(*f)).cloned().collect();
+        root.files = files.into_iter().collect();
+        new_files
+    }
+
+    /// 删除一个 root，返回 (只由它覆盖、应当移除的文件, 同时被其它 root 覆盖、应当保留的文件)；
+    /// root 不存在时返回 None
+    pub fn remove(&mut self, path: &Path) -> Option<(Vec<PathBuf>, Vec<PathBuf>)> {
+        let i = self.position(path)?;
+        let removed = self.roots.remove(i);
+        Some(
+            removed
+                .files
+                .into_iter()
+                .partition(|f| !self.roots.iter().any(|r| r.files.contains(f))),
+        )
+    }
+
+    /// 丢弃已经没有任何选中文件的 root (其文件都被单独 /remove 了)
+    pub fn prune(&mut self, is_selected: impl Fn(&PathBuf) -> bool) {
+        self.roots.retain(|r| r.files.iter().any(&is_selected));
+    }
+
+    /// 已选文件改名后，把所有 root 中的旧路径替换为新路径
+    pub fn rename(&mut self, old: &Path, new: &Path) {
+        for root in &mut self.roots {
+            if root.files.remove(old) {
+                root.files.insert(new.to_path_buf());
+            }
+        }
+    }
+
+    /// 每个 root 的展示信息；`is_selected` 判断文件当前是否仍被选中
+    pub fn summaries(&self, is_selected: impl Fn(&PathBuf) -> bool) -> Vec<RootSummary> {
+        self.roots
+            .iter()
+            .enumerate()
+            .map(|(i, root)| {
+                let mine: Vec<&PathBuf> = root.files.iter().filter(|f| is_selected(f)).collect();
+                let others: Vec<&AddRoot> = self
+                    .roots
+                    .iter()
+                    .enumerate()
+                    .filter(|(j, _)| *j != i)
+                    .map(|(_, r)| r)
+                    .collect();
+                let shared = mine.iter().filter(|f| others.iter().any(|r| r.files.contains(**f))).count();
+                let overlaps_with = others
+                    .iter()
+                    .filter(|r| mine.iter().any(|f| r.files.contains(*f)))
+                    .map(|r| r.path.clone())
+                    .collect();
+                RootSummary {
+                    path: root.path.clone(),
+                    selected: mine.len(),
+                    shared,
+                    overlaps_with,
+                }
+            })
+            .collect()
+    }
+
+    /// 不属于任何 root 的已选文件数 (例如跟随改名后加入的文件)
+    pub fn uncovered<'a>(&self, selected: impl IntoIterator<Item = &'a PathBuf>) -> usize {
+        selected
+            .into_iter()
+            .filter(|p| !self.roots.iter().any(|r| r.files.contains(*p)))
+            .count()
+    }
+
+    fn position(&self, path: &Path) -> Option<usize> {
+        let key = root_key(path);
+        self.roots.iter().position(|r| root_key(&r.path) == key)
+    }
+}
+
+/// 比较 root 时忽略 `./` 前缀和末尾的分隔符: `./src/` 与 `src` 视为同一个 root
+fn root_key(path: &Path) -> PathBuf {
+    path.components().filter(|c| !matches!(c, Component::CurDir)).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn paths(names: &[&str]) -> Vec<PathBuf> {
+        names.iter().map(PathBuf::from).collect()
+    }
+
+    /// `/add src` 之后再 `/add src/command`: 内层 root 的文件全部被外层覆盖
+    fn nested() -> AddRoots {
+        let mut roots = AddRoots::default();
+        roots.record(Path::new("src"), paths(&["src/main.rs", "src/command/a.rs", "src/command/b.rs"]));
+        roots.record(Path::new("src/command"), paths(&["src/command/a.rs", "src/command/b.rs"]));
+        roots
+    }
+
+    #[test]
+    fn same_root_spelled_differently_is_merged() {
+        let mut roots = AddRoots::default();
+        roots.record(Path::new("src"), paths(&["src/a.rs"]));
+        roots.record(Path::new("./src/"), paths(&["src/b.rs"]));
+        let all: Vec<&AddRoot> = roots.iter().collect();
+        assert_eq!(all.len(), 1);
+        assert_eq!(all[0].files, paths(&["src/a.rs", "src/b.rs"]).into_iter().collect());
+    }
+
+    #[test]
+    fn removing_a_nested_root_keeps_files_the_outer_root_covers() {
+        let mut roots = nested();
+        let (removed, kept) = roots.remove(Path::new("src/command")).unwrap();
+        assert!(removed.is_empty());
+        assert_eq!(kept, paths(&["src/command/a.rs", "src/command/b.rs"]));
+        assert_eq!(roots.iter().count(), 1);
+    }
+
+    #[test]
+    fn removing_the_outer_root_removes_only_its_own_files() {
+        let mut roots = nested();
+        let (removed, kept) = roots.remove(Path::new("./src")).unwrap();
+        assert_eq!(removed, paths(&["src/main.rs"]));
+        assert_eq!(kept, paths(&["src/command/a.rs", "src/command/b.rs"]));
+        assert!(roots.remove(Path::new("src")).is_none());
+    }
+
+    #[test]
+    fn partially_overlapping_roots_share_only_common_files() {
+        let mut roots = AddRoots::default();
+        roots.record(Path::new("src/*.rs"), paths(&["src/a.rs", "src/b.rs"]));
+        roots.record(Path::new("src/a.rs"), paths(&["src/a.rs"]));
+        roots.record(Path::new("docs"), paths(&["docs/x.md"]));
+        let (removed, kept) = roots.remove(Path::new("src/*.rs")).unwrap();
+        assert_eq!(removed, paths(&["src/b.rs"]));
+        assert_eq!(kept, paths(&["src/a.rs"]));
+    }
+
+    #[test]
+    fn summaries_label_overlaps_on_both_roots() {
+        let roots = nested();
+        let summaries = roots.summaries(|_| true);
+        assert_eq!(
+            summaries,
+            vec![
+                RootSummary {
+                    path: PathBuf::from("src"),
+                    selected: 3,
+                    shared: 2,
+                    overlaps_with: paths(&["src/command"]),
+                },
+                RootSummary {
+                    path: PathBuf::from("src/command"),
+                    selected: 2,
+                    shared: 2,
+                    overlaps_with: paths(&["src"]),
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn summaries_ignore_overlap_through_removed_files() {
+        let roots = nested();
+        // 共享的文件都被单独 /remove 后不再算重叠
+        let summaries = roots.summaries(|f| f == Path::new("src/main.rs"));
+        assert_eq!(summaries[0].selected, 1);
+        assert_eq!(summaries[0].shared, 0);
+        assert!(summaries[0].overlaps_with.is_empty());
+        assert_eq!(summaries[1].selected, 0);
+        assert!(summaries[1].overlaps_with.is_empty());
+    }
+
+    #[test]
+    fn disjoint_roots_have_no_overlap() {
+        let mut roots = AddRoots::default();
+        roots.record(Path::new("src"), paths(&["src/a.rs"]));
+        roots.record(Path::new("docs"), paths(&["docs/x.md"]));
+        assert!(roots.summaries(|_| true).iter().all(|s| s.shared == 0 && s.overlaps_with.is_empty()));
+    }
+
+    #[test]
+    fn reexpand_reports_only_new_files_and_prune_drops_empty_roots() {
+        let mut roots = nested();
+        let new = roots.reexpand(Path::new("src/command"), paths(&["src/command/a.rs", "src/command/b.rs", "src/command/c.rs"]));
+        assert_eq!(new, paths(&["src/command/c.rs"]));
+        assert!(roots.reexpand(Path::new("other"), paths(&["x"])).is_empty());
+
+        roots.prune(|f| f == Path::new("src/main.rs"));
+        assert_eq!(roots.iter().map(|r| r.path.clone()).collect::<Vec<_>>(), paths(&["src"]));
+        assert_eq!(roots.uncovered(&paths(&["src/main.rs", "loose.rs"])), 1);
+    }
+}
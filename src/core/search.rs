@@ -0,0 +1,131 @@
+// src/core/search.rs
+
+use std::ops::Range;
+
+use regex::{Regex, RegexBuilder};
+
+/// /grep 的一处匹配
+#[derive(Debug, Clone, PartialEq)]
+pub struct Hit {
+    /// 行号 (从 1 开始)
+    pub line: usize,
+    /// 整行内容 (不含换行符)
+    pub text: String,
+    /// 第一处匹配在 text 中的字节范围
+    pub range: Range<usize>,
+}
+
+impl Hit {
+    /// 去掉行首缩进后拆成 (匹配前, 匹配, 匹配后)，打印时高亮中间部分；匹配落在缩进里时从行首算起
+    pub fn parts(&self) -> (&str, &str, &str) {
+        let text = self.text.trim_start();
+        let offset = self.text.len() - text.len();
+        let start = self.range.start.saturating_sub(offset);
+        let end = self.range.end.saturating_sub(offset);
+        (&text[..start], &text[start..end], &text[end..])
+    }
+}
+
+/// 第 n 处匹配 (从 1 开始) 是否在 grep_max_hits 以内；max_hits 为 0 表示不限
+pub fn within_limit(n: usize, max_hits: usize) -> bool {
+    max_hits == 0 || n <= max_hits
+}
+
+/// 编译 /grep 的模式: 默认是不区分大小写的子串，`regex` 为 true 时按正则表达式处理 (区分大小写，可用 `(?i)` 关闭)
+pub fn build_matcher(pattern: &str, regex: bool) -> Result<Regex, String> {
+    if regex {
+        Regex::new(pattern).map_err(|e| e.to_string())
+    } else {
+        RegexBuilder::new(&regex::escape(pattern))
+            .case_insensitive(true)
+            .build()
+            .map_err(|e| e.to_string())
+    }
+}
+
+/// 在文本中逐行查找，每行最多返回一处匹配 (第一处)
+pub fn search(content: &str, matcher: &Regex) -> Vec<Hit> {
+    content
+        .lines()
+        .enumerate()
+        .filter_map(|(i, line)| {
+            matcher.find(line).map(|m| Hit {
+                line: i + 1,
+                text: line.to_string(),
+                range: m.range(),
+            })
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn grep(content: &str, pattern: &str, regex: bool) -> Vec<Hit> {
+        search(content, &build_matcher(pattern, regex).unwrap())
+    }
+
+    #[test]
+    fn substring_search_ignores_case() {
+        let hits = grep("fn Main() {}\nlet x = 1;\n// MAIN entry\n", "main", false);
+        assert_eq!(hits.iter().map(|h| h.line).collect::<Vec<_>>(), vec![1, 3]);
+        assert_eq!(hits[0].range, 3..7);
+        assert_eq!(hits[1].text, "// MAIN entry");
+    }
+
+    #[test]
+    fn substring_search_treats_regex_syntax_literally() {
+        let hits = grep("a.b\naxb\n(x)\n", "a.b", false);
+        assert_eq!(hits.len(), 1);
+        assert_eq!(hits[0].line, 1);
+        assert_eq!(grep("a.b\naxb\n(x)\n", "(x", false)[0].line, 3);
+    }
+
+    #[test]
+    fn regex_search_is_case_sensitive_unless_asked() {
+        let content = "fn parse()\nFn Parse()\nlet parsed = 1;\n";
+        let lines = |hits: Vec<Hit>| hits.iter().map(|h| h.line).collect::<Vec<_>>();
+        assert_eq!(lines(grep(content, r"fn \w+\(", true)), vec![1]);
+        assert_eq!(lines(grep(content, r"(?i)fn \w+\(", true)), vec![1, 2]);
+        assert_eq!(lines(grep(content, r"^let\b", true)), vec![3]);
+    }
+
+    #[test]
+    fn invalid_regex_is_an_error() {
+        let err = build_matcher("fn (", true).unwrap_err();
+        assert!(err.contains("unclosed group"), "{}", err);
+        // 不加 --regex 时同样的字符串只是普通子串
+        assert!(build_matcher("fn (", false).is_ok());
+    }
+
+    #[test]
+    fn only_the_first_match_per_line_is_reported() {
+        let hits = grep("foo foo foo\n", "foo", false);
+        assert_eq!(hits.len(), 1);
+        assert_eq!(hits[0].range, 0..3);
+    }
+
+    #[test]
+    fn highlight_offsets_respect_multibyte_chars() {
+        let hits = grep("    let 名前 = \"Ünïcödé\";\n", "ünïcödé", false);
+        assert_eq!(hits.len(), 1);
+        assert_eq!(&hits[0].text[hits[0].range.clone()], "Ünïcödé");
+        assert_eq!(hits[0].parts(), ("let 名前 = \"", "Ünïcödé", "\";"));
+    }
+
+    #[test]
+    fn match_inside_indentation_starts_at_the_trimmed_line() {
+        let hits = grep("\t\tx\n", r"\t+x", true);
+        assert_eq!(hits[0].parts(), ("", "x", ""));
+    }
+
+    #[test]
+    fn hit_limit() {
+        assert!(within_limit(1, 2));
+        assert!(within_limit(2, 2));
+        assert!(!within_limit(3, 2));
+        // 0 表示不限
+        assert!(within_limit(10_000, 0));
+    }
+}
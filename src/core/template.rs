@@ -0,0 +1,249 @@
+// src/core/template.rs
+
+use std::fmt;
+use std::path::{Path, PathBuf};
+
+use minijinja::Environment;
+use serde::Serialize;
+
+use crate::core::dirs::config_dir;
+
+/// 随程序内置的模板 (源文件位于仓库的 templates/ 目录)
+pub const BUILTIN_TEMPLATES: &[(&str, &str)] = &[
+    ("markdown", include_str!("../../templates/markdown.j2")),
+    ("compact", include_str!("../../templates/compact.j2")),
+];
+
+/// 模板文件的扩展名，按顺序尝试
+const TEMPLATE_EXTS: [&str; 3] = ["j2", "jinja", "txt"];
+
+/// payload 的输出格式
+#[derive(Debug, Clone, Default, PartialEq)]
+pub enum OutputFormat {
+    /// 默认的 `<documents>` XML
+    #[default]
+    Xml,
+    /// 用户模板 (minijinja)，source 在选择时读入
+    Custom { name: String, source: String },
+}
+
+impl OutputFormat {
+    pub fn describe(&self) -> String {
+        match self {
+            Self::Xml => "xml".to_string(),
+            Self::Custom { name, .. } => format!("custom ({})", name),
+        }
+    }
+}
+
+/// 传给模板的单个文档
+#[derive(Debug, Clone, Serialize)]
+pub struct TemplateDocument {
+    pub id: String,
+    pub path: String,
+    pub language: String,
+    pub content: String,
+    pub tokens: usize,
+    /// `/set group_by_language on` 时所在分组的标题 (例如 `Rust`)，同组的文档相邻；未分组时为空
+    pub group: Option<String>,
+}
+
+/// 传给模板的元数据
+#[derive(Debug, Clone, Serialize)]
+pub struct TemplateMetadata {
+    pub document_count: usize,
+    pub file_tokens: usize,
+    pub format: String,
+}
+
+/// 模板可以使用的全部变量: documents / tree / prompt / metadata
+#[derive(Debug, Clone, Serialize)]
+pub struct TemplateContext {
+    pub documents: Vec<TemplateDocument>,
+    pub tree: String,
+    pub prompt: String,
+    pub metadata: TemplateMetadata,
+}
+
+/// 模板语法或渲染错误，尽量带上出错的行号
+#[derive(Debug, Clone)]
+pub struct TemplateError {
+    pub template: String,
+    pub line: Option<usize>,
+    pub message: String,
+}
+
+impl fmt::Display for TemplateError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.line {
+            Some(line) => write!(f, "template {} error at line {}: {}", self.template, line, self.message),
+            None => write!(f, "template {} error: {}", self.template, self.message),
+        }
+    }
+}
+
+impl TemplateError {
+    fn from_minijinja(template: &str, e: minijinja::Error) -> Self {
+        Self {
+            template: template.to_string(),
+            line: e.line(),
+            message: e.detail().map(str::to_string).unwrap_or_else(|| e.kind().to_string()),
+        }
+    }
+}
+
+/// 用模板渲染 payload
+pub fn render(name: &str, source: &str, ctx: &TemplateContext) -> Result<String, TemplateError> {
+    let mut env = Environment::new();
+    env.set_keep_trailing_newline(true);
+    env.add_template(name, source)
+        .map_err(|e| TemplateError::from_minijinja(name, e))?;
+    let template = env.get_template(name).map_err(|e| TemplateError::from_minijinja(name, e))?;
+    template.render(ctx).map_err(|e| TemplateError::from_minijinja(name, e))
+}
+
+/// 模板的查找目录: 项目内的 `.rustprompt/templates/`，以及用户配置目录下的 `rustprompt/templates/`
+pub fn template_dirs() -> Vec<PathBuf> {
+    let mut dirs = vec![PathBuf::from(".rustprompt").join("templates")];
+    if let Some(config) = config_dir() {
+        dirs.push(config.join("templates"));
+    }
+    dirs
+}
+
+/// 按名字查找模板，返回 (名字, 模板内容)。
+/// 依次尝试: 直接给出的文件路径 -> 模板目录中的 `<name>.j2` 等 -> 内置模板
+pub fn find_template(name: &str) -> Result<(String, String), String> {
+    let as_path = Path::new(name);
+    if as_path.is_file() {
+        return read_template(as_path).map(|s| (name.to_string(), s));
+    }
+    for dir in template_dirs() {
+        for ext in TEMPLATE_EXTS {
+            let candidate = dir.join(format!("{}.{}", name, ext));
+            if candidate.is_file() {
+                return read_template(&candidate).map(|s| (name.to_string(), s));
+            }
+        }
+    }
+    if let Some((builtin, source)) = BUILTIN_TEMPLATES.iter().find(|(n, _)| *n == name) {
+        return Ok((builtin.to_string(), source.to_string()));
+    }
+    Err(format!(
+        "Template not found: {} (available: {})",
+        name,
+        available_templates().join(", ")
+    ))
+}
+
+/// 所有可用模板的名字 (目录中的模板在前，内置模板在后，去重)
+pub fn available_templates() -> Vec<String> {
+    let mut names: Vec<String> = Vec::new();
+    for dir in template_dirs() {
+        let Ok(entries) = std::fs::read_dir(&dir) else { continue };
+        for entry in entries.flatten() {
+            let path = entry.path();
+            let is_template = path
+                .extension()
+                .is_some_and(|e| TEMPLATE_EXTS.contains(&e.to_string_lossy().as_ref()));
+            if let (true, Some(stem)) = (is_template, path.file_stem()) {
+                names.push(stem.to_string_lossy().to_string());
+            }
+        }
+    }
+    names.extend(BUILTIN_TEMPLATES.iter().map(|(n, _)| n.to_string()));
+    let mut seen = std::collections::HashSet::new();
+    names.retain(|n| seen.insert(n.clone()));
+    names
+}
+
+fn read_template(path: &Path) -> Result<String, String> {
+    std::fs::read_to_string(path).map_err(|e| format!("Failed to read template {}: {}", path.display(), e))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn builtin(name: &str) -> &'static str {
+        BUILTIN_TEMPLATES.iter().find(|(n, _)| *n == name).unwrap().1
+    }
+
+    fn doc(id: &str, path: &str, language: &str, content: &str, group: Option<&str>) -> TemplateDocument {
+        TemplateDocument {
+            id: id.to_string(),
+            path: path.to_string(),
+            language: language.to_string(),
+            content: content.to_string(),
+            tokens: content.len() / 4,
+            group: group.map(str::to_string),
+        }
+    }
+
+    /// 两个文件 + 目录树 + 提示词的固定状态
+    fn fixture(group: bool) -> TemplateContext {
+        let documents = vec![
+            doc("1", "src/main.rs", "rust", "fn main() {}", group.then_some("Rust")),
+            doc("2", "README.md", "markdown", "# Demo", group.then_some("Markdown")),
+        ];
+        TemplateContext {
+            metadata: TemplateMetadata {
+                document_count: documents.len(),
+                file_tokens: documents.iter().map(|d| d.tokens).sum(),
+                format: "custom".to_string(),
+            },
+            documents,
+            tree: "demo/\n├── README.md\n└── src/\n    └── main.rs".to_string(),
+            prompt: "Explain main".to_string(),
+        }
+    }
+
+    #[test]
+    fn markdown_renders_tree_documents_and_prompt() {
+        let out = render("markdown", builtin("markdown"), &fixture(false)).unwrap();
+        assert!(out.starts_with("# Project context (2 files)\n"), "{}", out);
+        assert!(out.contains("## Project tree\n\n```\ndemo/\n"), "{}", out);
+        assert!(out.contains("## 1 · src/main.rs\n\n```rust\nfn main() {}\n```\n"), "{}", out);
+        assert!(out.contains("## 2 · README.md\n\n```markdown\n# Demo\n```\n"), "{}", out);
+        assert!(out.trim_end().ends_with("## Instruction\n\nExplain main"), "{}", out);
+        assert!(out.find("src/main.rs").unwrap() < out.find("README.md\n\n```markdown").unwrap());
+        assert!(!out.contains("## Rust files"), "{}", out);
+    }
+
+    #[test]
+    fn markdown_adds_a_heading_per_language_group() {
+        let out = render("markdown", builtin("markdown"), &fixture(true)).unwrap();
+        assert!(out.contains("## Rust files\n"), "{}", out);
+        assert!(out.contains("## Markdown files\n"), "{}", out);
+        assert!(out.contains("### 1 · src/main.rs\n"), "{}", out);
+        assert!(out.find("## Rust files").unwrap() < out.find("### 1 ·").unwrap());
+    }
+
+    #[test]
+    fn markdown_omits_empty_tree_and_prompt() {
+        let mut ctx = fixture(false);
+        ctx.tree.clear();
+        ctx.prompt.clear();
+        let out = render("markdown", builtin("markdown"), &ctx).unwrap();
+        assert!(!out.contains("## Project tree"), "{}", out);
+        assert!(!out.contains("## Instruction"), "{}", out);
+        assert!(out.contains("```rust\nfn main() {}\n```"), "{}", out);
+    }
+
+    #[test]
+    fn compact_renders_documents_and_prompt() {
+        let out = render("compact", builtin("compact"), &fixture(false)).unwrap();
+        assert_eq!(
+            out,
+            "=== 1 src/main.rs ===\nfn main() {}\n=== 2 README.md ===\n# Demo\n\n=== instruction ===\nExplain main\n\n"
+        );
+    }
+
+    #[test]
+    fn errors_carry_the_line_number() {
+        let err = render("broken", "line one\n{{ documents | nofilter }}\n", &fixture(false)).unwrap_err();
+        assert_eq!(err.template, "broken");
+        assert_eq!(err.line, Some(2));
+        assert!(err.to_string().starts_with("template broken error at line 2:"), "{}", err);
+    }
+}
@@ -0,0 +1,112 @@
+// src/core/templating.rs
+
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// expand 的结果
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Expansion {
+    pub text: String,
+    /// 没有对应值、原样保留的占位符名 (按第一次出现的顺序，不重复)
+    pub unknown: Vec<String>,
+}
+
+/// 替换 prompt 中的 `{{name}}` 占位符 (名字两侧可以有空白)，值由 lookup 提供。
+///  - lookup 返回 None 的占位符原样保留，名字记入 `unknown`
+///  - 占位符不能嵌套: `{{a {{b}} }}` 中只有最内层的 `{{b}}` 是占位符，外层的括号按普通文本保留；
+///    替换进来的值不会再次展开
+///  - 名字只能由字母、数字、`_`、`.` 组成，其它内容 (例如 `{{ x | y }}`) 不算占位符，不产生警告
+///  - `\{\{` 输出字面的 `{{` (`\}\}` 同理)，不触发替换
+///
+/// ```
+/// use rustprompt::core::templating::expand;
+///
+/// let lookup = |name: &str| match name {
+///     "file_count" => Some("3".to_string()),
+///     "tree" => Some("src/\n  {{date}}.rs".to_string()),
+///     _ => None,
+/// };
+///
+/// // 普通替换，值中的 {{date}} 不会再展开
+/// let e = expand("{{ file_count }} files:\n{{tree}}", lookup);
+/// assert_eq!(e.text, "3 files:\nsrc/\n  {{date}}.rs");
+/// assert!(e.unknown.is_empty());
+///
+/// // 未知占位符原样保留并报告 (只报告一次)
+/// let e = expand("{{nope}} and {{nope}}", lookup);
+/// assert_eq!(e.text, "{{nope}} and {{nope}}");
+/// assert_eq!(e.unknown, ["nope"]);
+///
+/// // 嵌套时只展开最内层
+/// assert_eq!(expand("{{a {{file_count}} }}", lookup).text, "{{a 3 }}");
+///
+/// // 转义
+/// assert_eq!(expand(r"\{\{file_count\}\} = {{file_count}}", lookup).text, "{{file_count}} = 3");
+/// ```
+pub fn expand(text: &str, lookup: impl Fn(&str) -> Option<String>) -> Expansion {
+    let mut result = String::with_capacity(text.len());
+    let mut unknown: Vec<String> = Vec::new();
+    let mut rest = text;
+    while !rest.is_empty() {
+        if let Some(after) = rest.strip_prefix(r"\{\{") {
+            result.push_str("{{");
+            rest = after;
+            continue;
+        }
+        if let Some(after) = rest.strip_prefix(r"\}\}") {
+            result.push_str("}}");
+            rest = after;
+            continue;
+        }
+        if let Some(after) = rest.strip_prefix("{{") {
+            // 到下一个 `}}` 之前又出现 `{{` (或转义) 时，当前的 `{{` 不是占位符
+            if let Some(end) = after.find("}}").filter(|&end| !after[..end].contains("{{") && !after[..end].contains('\\')) {
+                let name = after[..end].trim();
+                if is_name(name) {
+                    match lookup(name) {
+                        Some(value) => result.push_str(&value),
+                        None => {
+                            result.push_str(&rest[..end + 4]);
+                            if !unknown.iter().any(|u| u == name) {
+                                unknown.push(name.to_string());
+                            }
+                        }
+                    }
+                    rest = &after[end + 2..];
+                    continue;
+                }
+            }
+            result.push('{');
+            rest = &rest[1..];
+            continue;
+        }
+        let c = rest.chars().next().unwrap_or_default();
+        result.push(c);
+        rest = &rest[c.len_utf8()..];
+    }
+    Expansion { text: result, unknown }
+}
+
+fn is_name(name: &str) -> bool {
+    !name.is_empty() && name.chars().all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '.')
+}
+
+/// 今天的日期 (UTC)，例如 `2025-03-01`，用于 `{{date}}`
+pub fn today() -> String {
+    let days = SystemTime::now().duration_since(UNIX_EPOCH).map(|d| d.as_secs() / 86_400).unwrap_or(0) as i64;
+    let (y, m, d) = civil_from_days(days);
+    format!("{:04}-{:02}-{:02}", y, m, d)
+}
+
+/// 1970-01-01 起的天数 -> (年, 月, 日)，公历 (Howard Hinnant 的 civil_from_days)
+fn civil_from_days(days: i64) -> (i64, u32, u32) {
+    let z = days + 719_468;
+    let era = z.div_euclid(146_097);
+    let doe = z.rem_euclid(146_097);
+    let yoe = (doe - doe / 1460 + doe / 36_524 - doe / 146_096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let d = (doy - (153 * mp + 2) / 5 + 1) as u32;
+    let m = if mp < 10 { mp + 3 } else { mp - 9 } as u32;
+    let y = yoe + era * 400 + i64::from(m <= 2);
+    (y, m, d)
+}
@@ -1,3 +1,8 @@
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::OnceLock;
+
+use tiktoken_rs::CoreBPE;
+
 use crate::error::AppError;
 
 // Removed unused calculate_tokens function
@@ -7,11 +12,155 @@ pub async fn calculate_tokens(paths: &[PathBuf]) -> Result<usize, AppError> {
 }
 */
 
+/// 计算 token 时使用的 BPE 编码 (/model)
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Encoding {
+    /// gpt-4、gpt-3.5-turbo (默认)
+    #[default]
+    Cl100k,
+    /// gpt-4o、o1 等较新的模型
+    O200k,
+}
+
+impl Encoding {
+    pub const ALL: [Encoding; 2] = [Encoding::Cl100k, Encoding::O200k];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Self::Cl100k => "cl100k_base",
+            Self::O200k => "o200k_base",
+        }
+    }
+
+    /// 使用该编码的代表性模型，用于 /model 的列表
+    pub fn models(&self) -> &'static str {
+        match self {
+            Self::Cl100k => "gpt-4, gpt-3.5-turbo, claude",
+            Self::O200k => "gpt-4o, o1",
+        }
+    }
+
+    /// 接受编码名或模型名，例如 `o200k_base`、`o200k`、`gpt-4o`。
+    /// Claude 的分词器没有公开，用 cl100k 近似计数
+    pub fn parse(name: &str) -> Option<Self> {
+        match name.trim().to_lowercase().as_str() {
+            "cl100k_base" | "cl100k" | "gpt-4" | "gpt-3.5-turbo" | "claude" => Some(Self::Cl100k),
+            "o200k_base" | "o200k" | "gpt-4o" | "gpt-4o-mini" | "o1" => Some(Self::O200k),
+            _ => None,
+        }
+    }
+
+    pub fn index(self) -> usize {
+        self as usize
+    }
+
+    fn load(self) -> anyhow::Result<CoreBPE> {
+        match self {
+            Self::Cl100k => tiktoken_rs::cl100k_base(),
+            Self::O200k => tiktoken_rs::o200k_base(),
+        }
+    }
+}
+
+/// 各模型的上下文窗口 (token)，/copy 超过时拒绝复制 (`--force` 仍然复制)
+const CONTEXT_WINDOWS: &[(&str, usize)] = &[
+    ("gpt-4", 8_192),
+    ("gpt-3.5-turbo", 16_385),
+    ("gpt-4o", 128_000),
+    ("gpt-4o-mini", 128_000),
+    ("o1", 200_000),
+    ("claude", 200_000),
+];
+
+/// /model 补全时列出的名字: 编码名和已知上下文窗口的模型名
+pub fn model_names() -> Vec<&'static str> {
+    Encoding::ALL.iter().map(|e| e.name()).chain(CONTEXT_WINDOWS.iter().map(|&(name, _)| name)).collect()
+}
+
+/// 模型名 (/model 接受的写法，不区分大小写) 对应的上下文窗口；编码名或未知模型返回 None
+///
+/// ```
+/// use rustprompt::core::tokenizer::context_window;
+///
+/// assert_eq!(context_window("GPT-4o"), Some(128_000));
+/// assert_eq!(context_window("claude"), Some(200_000));
+/// assert_eq!(context_window("o200k_base"), None);
+/// ```
+pub fn context_window(model: &str) -> Option<usize> {
+    let model = model.trim().to_lowercase();
+    CONTEXT_WINDOWS.iter().find(|(name, _)| *name == model).map(|&(_, window)| window)
+}
+
+/// 当前使用的编码 (Encoding::index)；分词函数没有 AppState，所以放在这里
+static ACTIVE: AtomicUsize = AtomicUsize::new(0);
+
+pub fn active_encoding() -> Encoding {
+    Encoding::ALL[ACTIVE.load(Ordering::Relaxed)]
+}
+
+/// 切换之后的所有计数都使用新编码；已有的计数需要调用方重新计算
+pub fn set_active_encoding(encoding: Encoding) {
+    ACTIVE.store(encoding.index(), Ordering::Relaxed);
+}
+
+/// BPE 词表加载很慢 (需要解析整个词表)，每种编码只加载一次，之后所有计数共用。
+/// 加载失败的结果同样缓存，避免每次调用都重试。
+static BPE: [OnceLock<Result<CoreBPE, String>>; Encoding::ALL.len()] = [const { OnceLock::new() }; Encoding::ALL.len()];
+
+/// 当前编码的共享 BPE 分词器
+pub fn tokenizer() -> Result<&'static CoreBPE, AppError> {
+    tokenizer_for(active_encoding())
+}
+
+/// 指定编码的共享 BPE 分词器。首次调用时加载词表；加载期间其它线程的调用会等待，而不是重复加载
+pub fn tokenizer_for(encoding: Encoding) -> Result<&'static CoreBPE, AppError> {
+    BPE[encoding.index()]
+        .get_or_init(|| {
+            log::info!("加载 BPE 词表: {}", encoding.name());
+            encoding.load().map_err(|e| format!("{:?}", e))
+        })
+        .as_ref()
+        .map_err(|e| AppError::Tokenizer(format!("cannot load the {} vocabulary: {}", encoding.name(), e)))
+}
+
+/// 启动时在后台预先加载当前编码的词表，让第一次 /add 不必等待
+pub fn warm_up() {
+    tokio::task::spawn_blocking(|| {
+        if let Err(e) = tokenizer() {
+            log::warn!("预加载 BPE 失败: {:?}", e);
+        }
+    });
+}
+
 // NEW: 直接对字符串计算 Token 数
 pub fn calculate_tokens_in_string(s: &str) -> Result<usize, AppError> {
-    let bpe = tiktoken_rs::get_bpe_from_model("gpt-3.5-turbo")
-        .map_err(|e| AppError::General(anyhow::anyhow!("无法加载BPE: {:?}", e)))?;
-
-    let tokens = bpe.encode_ordinary(s);
+    let tokens = tokenizer()?.encode_ordinary(s);
     Ok(tokens.len())
-} 
\ No newline at end of file
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Instant;
+
+    #[test]
+    fn later_calls_reuse_the_loaded_vocabulary() {
+        let first = tokenizer_for(Encoding::Cl100k).unwrap();
+
+        // 作为对照，单独加载一次词表需要的时间
+        let started = Instant::now();
+        let fresh = Encoding::Cl100k.load().unwrap();
+        let one_load = started.elapsed();
+
+        // 之后的调用返回同一个实例，1000 次调用加起来也比加载一次快得多
+        let started = Instant::now();
+        for _ in 0..1000 {
+            assert!(std::ptr::eq(first, tokenizer_for(Encoding::Cl100k).unwrap()));
+        }
+        let cached = started.elapsed();
+        assert!(cached * 10 < one_load, "1000 cached calls took {:?}, one load {:?}", cached, one_load);
+
+        assert_eq!(first.encode_ordinary("let x = 1;"), fresh.encode_ordinary("let x = 1;"));
+        assert_eq!(calculate_tokens_in_string("let x = 1;").unwrap(), fresh.encode_ordinary("let x = 1;").len());
+    }
+}
@@ -0,0 +1,171 @@
+// src/core/validate.rs
+
+use std::fmt;
+
+use quick_xml::events::Event;
+use quick_xml::Reader;
+
+/// 校验通过时的统计信息
+#[derive(Debug, Clone, Default)]
+pub struct ValidationReport {
+    /// `<document>` 元素个数
+    pub documents: usize,
+    /// 元素总数
+    pub elements: usize,
+    /// 最大嵌套深度
+    pub max_depth: usize,
+}
+
+/// 校验失败: 出错的字节偏移和附近的文本
+#[derive(Debug, Clone)]
+pub struct ValidationError {
+    pub offset: usize,
+    pub message: String,
+    pub context: String,
+}
+
+impl fmt::Display for ValidationError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "invalid XML at byte {}: {} (near: {})",
+            self.offset, self.message, self.context
+        )
+    }
+}
+
+/// 使用 quick-xml 的流式 Reader 校验 payload 是否是良构的 XML。
+///
+/// 不构建 DOM，只检查标签是否配对、实体是否合法，以及文本中没有 XML 1.0 不允许的内容
+/// (未转义的 `]]>`、`\t` `\n` `\r` 之外的控制字符)，适合在每次 /copy 前对几十 MB 的内容执行。
+pub fn validate_xml(xml: &str) -> Result<ValidationReport, ValidationError> {
+    let mut reader = Reader::from_str(xml);
+    reader.check_end_names(true);
+
+    let mut report = ValidationReport::default();
+    let mut open: Vec<(String, usize)> = Vec::new();
+
+    loop {
+        let pos = reader.buffer_position();
+        match reader.read_event() {
+            Ok(Event::Start(e)) => {
+                let name = String::from_utf8_lossy(e.name().as_ref()).to_string();
+                report.elements += 1;
+                if name == "document" {
+                    report.documents += 1;
+                }
+                open.push((name, pos));
+                report.max_depth = report.max_depth.max(open.len());
+            }
+            Ok(Event::End(_)) => {
+                open.pop();
+            }
+            Ok(Event::Empty(_)) => {
+                report.elements += 1;
+            }
+            Ok(Event::Text(t)) => {
+                if let Err(e) = t.unescape() {
+                    return Err(error_at(xml, pos, e.to_string()));
+                }
+                if let Some(at) = t.windows(3).position(|w| w == b"]]>") {
+                    return Err(error_at(xml, pos + at, "`]]>` must be escaped in text".to_string()));
+                }
+                check_chars(xml, pos, &t)?;
+            }
+            Ok(Event::CData(t)) => {
+                // 跳过 `<![CDATA[`
+                check_chars(xml, pos + 9, &t)?;
+            }
+            Ok(Event::Eof) => break,
+            Ok(_) => {}
+            Err(e) => return Err(error_at(xml, reader.buffer_position(), e.to_string())),
+        }
+    }
+
+    if let Some((name, pos)) = open.pop() {
+        return Err(error_at(xml, pos, format!("element <{}> is never closed", name)));
+    }
+    Ok(report)
+}
+
+/// start 处开始的文本中不能有 `\t`、`\n`、`\r` 之外的控制字符 (转义成 `&#1;` 也不行)
+fn check_chars(xml: &str, start: usize, text: &[u8]) -> Result<(), ValidationError> {
+    match text.iter().position(|b| *b < 0x20 && !matches!(b, b'\t' | b'\n' | b'\r')) {
+        Some(at) => Err(error_at(xml, start + at, format!("control character U+{:04X} is not allowed", text[at]))),
+        None => Ok(()),
+    }
+}
+
+/// 构造错误，并截取 offset 前后的一小段文本 (保证落在字符边界上)
+fn error_at(xml: &str, offset: usize, message: String) -> ValidationError {
+    const RADIUS: usize = 40;
+    let offset = offset.min(xml.len());
+    let mut start = offset.saturating_sub(RADIUS);
+    while !xml.is_char_boundary(start) {
+        start -= 1;
+    }
+    let mut end = (offset + RADIUS).min(xml.len());
+    while !xml.is_char_boundary(end) {
+        end += 1;
+    }
+    ValidationError {
+        offset,
+        message,
+        context: xml[start..end].replace('\n', "⏎"),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::core::xml::{generate_single_file_snippet, merge_all_snippets, MergeOptions};
+    use std::collections::HashMap;
+    use std::path::{Path, PathBuf};
+
+    /// 两个文件的 payload；第二个文件的内容用 corrupt 替换掉 (模拟没有经过转义的 snippet)
+    fn payload(corrupt: &str) -> String {
+        let mut docs = HashMap::new();
+        for (name, content) in [("a.rs", "fn a() -> bool { 1 < 2 && true }\n"), ("b.md", "PLACEHOLDER")] {
+            docs.insert(PathBuf::from(name), generate_single_file_snippet(Path::new(name), content, 0));
+        }
+        let xml = merge_all_snippets(&docs, &MergeOptions::default());
+        xml.replace("PLACEHOLDER", corrupt)
+    }
+
+    #[test]
+    fn generated_payload_is_valid() {
+        // 文件内容中的 `]]>` 和控制字符在生成 snippet 时已经处理
+        let mut docs = HashMap::new();
+        let content = "x ]]> y \u{1b}[0m & <tag>\n";
+        docs.insert(PathBuf::from("c.txt"), generate_single_file_snippet(Path::new("c.txt"), content, 0));
+        let report = validate_xml(&merge_all_snippets(&docs, &MergeOptions::default())).unwrap();
+        assert_eq!(report.documents, 1);
+        assert_eq!(report.max_depth, 3);
+    }
+
+    #[test]
+    fn unescaped_cdata_end_fails() {
+        let xml = payload("see ]]> here");
+        let err = validate_xml(&xml).unwrap_err();
+        assert_eq!(err.offset, xml.find("]]> here").unwrap());
+        assert!(err.message.contains("]]>"), "{}", err);
+        assert!(err.context.contains("see ]]> here"), "{}", err);
+    }
+
+    #[test]
+    fn control_character_fails() {
+        let xml = payload("red \u{1b}[31m");
+        let err = validate_xml(&xml).unwrap_err();
+        assert_eq!(err.offset, xml.find('\u{1b}').unwrap());
+        assert!(err.message.contains("U+001B"), "{}", err);
+        // 允许的空白字符
+        assert!(validate_xml(&payload("a\tb\r\nc")).is_ok());
+    }
+
+    #[test]
+    fn broken_markup_fails() {
+        assert!(validate_xml(&payload("a & b")).unwrap_err().message.contains("';'"));
+        let err = validate_xml(&payload("<b>bold")).unwrap_err();
+        assert!(err.to_string().starts_with("invalid XML at byte"), "{}", err);
+    }
+}
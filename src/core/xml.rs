@@ -1,72 +1,264 @@
 // src/core/xml.rs
 use std::path::{Path, PathBuf};
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
+use std::sync::OnceLock;
 use crate::app::state::PROJECT_TREE_VIRTUAL_PATH;
+use super::language::detect_language;
+use super::tokenizer::{active_encoding, calculate_tokens_in_string, Encoding};
+use quick_xml::escape::{escape, partial_escape, unescape};
+
+/// 控制 merge 阶段的拼装方式
+#[derive(Debug, Clone, Default)]
+pub struct MergeOptions {
+    /// 是否把同一语言的文档包进 `<group language="...">`
+    pub group_by_language: bool,
+    /// 分组的语言优先级，未列出的语言按名字排在后面
+    pub language_priority: Vec<String>,
+    /// 真实文件的排列顺序 (由 /sort 策略计算)；为空或未列出的文件按路径排序
+    pub order: Vec<PathBuf>,
+    /// 被静音的文件: 仍然保持选中，但不出现在 payload 中
+    pub excluded: HashSet<PathBuf>,
+}
 
 /// 生成单个文件的 snippet (不包含 <documents> 根标签)
 pub fn generate_single_file_snippet(
     path: &Path,
     content: &str,
     index: usize, // 临时索引，在 merge 时会被替换
+) -> String {
+    generate_single_file_snippet_with_attrs(path, content, index, &[])
+}
+
+/// 同 `generate_single_file_snippet`，但可以在 `<document>` 上附加额外的元数据属性
+/// (例如缩进压缩记录的 `indent_unit`)。属性统一放在 index 之后，保证 merge 时的 index 替换不受影响。
+///
+/// 路径和文件内容中的 `<`、`>`、`&` 会被转义，保证生成的 XML 是良构的。
+pub fn generate_single_file_snippet_with_attrs(
+    path: &Path,
+    content: &str,
+    index: usize,
+    attrs: &[(&str, String)],
 ) -> String {
     let path_str = path.to_string_lossy();
+    let extra: String = attrs
+        .iter()
+        .map(|(k, v)| format!(r#" {}="{}""#, k, escape(v.as_str())))
+        .collect();
     // 使用原始字符串简化转义
     format!(
-        r#"<document index="{idx}">
+        r#"<document index="{idx}"{extra}>
 <source>{src}</source>
 <document_content>
 {body}
 </document_content>
 </document>"#,
         idx = index,
-        src = path_str,
-        body = content,
+        extra = extra,
+        src = partial_escape(&strip_invalid_xml_chars(&path_str)),
+        body = partial_escape(&strip_invalid_xml_chars(content)),
     )
 }
 
+/// 按组排列的真实文件: (分组语言名, 组内按顺序排列的 (路径, snippet))。未开启分组时只有一个 None 组
+type Layout<'a> = Vec<(Option<String>, Vec<(&'a PathBuf, &'a String)>)>;
+
+/// 文档的短 ID，与其 index 一一对应 (例如 index=14 -> `D14`)
+pub fn document_id(index: usize) -> String {
+    format!("D{}", index)
+}
+
 /// 合并 partial_docs 里的 snippet，生成完整的 <documents>... XML。
 /// 其中:
 ///   - __PROJECT_TREE__ 对应的 snippet 被视为 index=1
-///   - 其余文档按路径排序后，从 index=2 开始
-pub fn merge_all_snippets(partial_docs: &HashMap<PathBuf, String>) -> String {
+///   - 其余文档按 options.order 排序 (默认为路径顺序) 后，从 index=2 开始
+///   - 开启分组时，文档在各自的 `<group>` 内保持路径顺序，index 仍然全局连续；项目树不参与分组
+///   - 每个 `<document>` 带有 `id="D<index>"`，选中文件与顺序不变时 ID 保持不变
+pub fn merge_all_snippets(partial_docs: &HashMap<PathBuf, String>, options: &MergeOptions) -> String {
     // 1) 找到项目树 snippet (若不存在则为空)
     let tree_key = PathBuf::from(PROJECT_TREE_VIRTUAL_PATH);
     let maybe_tree_snip = partial_docs.get(&tree_key);
 
-    // 2) 收集其它文件 snippet
-    let mut real_files: Vec<(&PathBuf, &String)> = partial_docs
-        .iter()
-        .filter(|(k, _)| **k != tree_key) // 过滤掉项目树
-        .collect();
-
-    // 以路径字符串排序，确保一致性
-    real_files.sort_by(|a, b| a.0.cmp(&b.0));
-
-    // 3) 开始拼装
+    // 2) 开始拼装
     let mut result = String::new();
     result.push_str("<documents>\n");
 
-    // 3.1) 若有项目树 snippet
+    // 2.1) 若有项目树 snippet
     if let Some(tree_snip) = maybe_tree_snip {
         // 强行把它当成 index=1
-        let updated = replace_doc_index(tree_snip, 1);
+        let updated = place_document(tree_snip, 1);
         result.push_str(&updated);
         result.push('\n'); // 每个 snippet 后加换行
     }
 
-    // 3.2) 依次给真实文件 snippet 分配 index=2,3,...
+    // 2.2) 依次给真实文件 snippet 分配 index=2,3,...
     let mut doc_index = 2;
-    for (_, snip) in real_files {
-        let updated = replace_doc_index(snip, doc_index);
-        result.push_str(&updated);
-        result.push('\n'); // 每个 snippet 后加换行
-        doc_index += 1;
+    for (language, group) in layout_documents(partial_docs, options) {
+        if let Some(language) = &language {
+            // 语言名来自扩展名，可能含有引号
+            result.push_str(&format!("<group language=\"{}\">\n", escape(language.as_str())));
+        }
+        for (_, snip) in group {
+            result.push_str(&place_document(snip, doc_index));
+            result.push('\n'); // 每个 snippet 后加换行
+            doc_index += 1;
+        }
+        if language.is_some() {
+            result.push_str("</group>\n");
+        }
     }
 
     result.push_str("</documents>");
     result
 }
 
+/// 由每个 snippet 的 token 数估算 merge_all_snippets 结果的 token 数，避免每次增删文件都对整个 XML 重新分词:
+/// 各 snippet 之和 + `<documents>` 外层标签 + 每个文档插入的 id 属性和换行 + 分组标签。
+///  - `snippet_tokens` 中没有记录的 snippet 现场分词
+///  - 分词在 snippet 的边界处可能合并方式不同，误差通常只有几个 token
+pub fn estimate_merged_tokens(
+    partial_docs: &HashMap<PathBuf, String>,
+    snippet_tokens: &HashMap<PathBuf, usize>,
+    options: &MergeOptions,
+) -> usize {
+    let markup = markup_tokens();
+    let tokens_of = |path: &PathBuf, snippet: &String| {
+        snippet_tokens
+            .get(path)
+            .copied()
+            .unwrap_or_else(|| calculate_tokens_in_string(snippet).unwrap_or(0))
+    };
+
+    let mut total = markup.documents;
+    let tree_key = PathBuf::from(PROJECT_TREE_VIRTUAL_PATH);
+    if let Some(tree) = partial_docs.get(&tree_key) {
+        total += tokens_of(&tree_key, tree) + markup.per_document;
+    }
+    for (language, group) in layout_documents(partial_docs, options) {
+        if language.is_some() {
+            total += markup.per_group;
+        }
+        total += group
+            .iter()
+            .map(|(path, snippet)| tokens_of(path, snippet) + markup.per_document)
+            .sum::<usize>();
+    }
+    total
+}
+
+/// merge 时在 snippet 之外加入的标记各自的 token 数
+struct MarkupTokens {
+    documents: usize,
+    per_document: usize,
+    per_group: usize,
+}
+
+/// 每种编码只在第一次使用时按代表性的文本分词计算
+fn markup_tokens() -> &'static MarkupTokens {
+    static MARKUP: [OnceLock<MarkupTokens>; Encoding::ALL.len()] = [const { OnceLock::new() }; Encoding::ALL.len()];
+    MARKUP[active_encoding().index()].get_or_init(|| {
+        let count = |s: &str| calculate_tokens_in_string(s).unwrap_or(0);
+        MarkupTokens {
+            documents: count("<documents>\n</documents>"),
+            per_document: count(r#" id="D10""#) + count("\n"),
+            per_group: count("<group language=\"rust\">\n</group>\n"),
+        }
+    })
+}
+
+/// 与 merge_all_snippets 相同的编号规则得到的 (ID, 路径) 列表，按 payload 中的顺序排列
+pub fn document_ids(partial_docs: &HashMap<PathBuf, String>, options: &MergeOptions) -> Vec<(String, PathBuf)> {
+    document_groups(partial_docs, options)
+        .into_iter()
+        .map(|(id, path, _)| (id, path))
+        .collect()
+}
+
+/// 同 document_ids，并带上每个文档所在的分组 (语言名)；未开启分组时以及项目树为 None
+pub fn document_groups(
+    partial_docs: &HashMap<PathBuf, String>,
+    options: &MergeOptions,
+) -> Vec<(String, PathBuf, Option<String>)> {
+    let tree_key = PathBuf::from(PROJECT_TREE_VIRTUAL_PATH);
+    let mut ids = Vec::with_capacity(partial_docs.len());
+    if partial_docs.contains_key(&tree_key) {
+        ids.push((document_id(1), tree_key, None));
+    }
+    let real_files = layout_documents(partial_docs, options)
+        .into_iter()
+        .flat_map(|(language, group)| group.into_iter().map(move |(path, _)| (path, language.clone())));
+    for (index, (path, language)) in (2..).zip(real_files) {
+        ids.push((document_id(index), path.clone(), language));
+    }
+    ids
+}
+
+/// 排列真实文件 (不含项目树): 先按路径排序，指定了顺序时按其中的位置排列 (稳定排序)，最后按需分组
+fn layout_documents<'a>(partial_docs: &'a HashMap<PathBuf, String>, options: &MergeOptions) -> Layout<'a> {
+    let tree_key = PathBuf::from(PROJECT_TREE_VIRTUAL_PATH);
+    let mut real_files: Vec<(&PathBuf, &String)> = partial_docs
+        .iter()
+        .filter(|(k, _)| **k != tree_key) // 过滤掉项目树
+        .filter(|(k, _)| !options.excluded.contains(*k))
+        .collect();
+
+    real_files.sort_by(|a, b| a.0.cmp(b.0));
+    if !options.order.is_empty() {
+        let position: HashMap<&PathBuf, usize> =
+            options.order.iter().enumerate().map(|(i, p)| (p, i)).collect();
+        real_files.sort_by_key(|(p, _)| position.get(p).copied().unwrap_or(usize::MAX));
+    }
+
+    if options.group_by_language {
+        group_by_language(real_files, &options.language_priority)
+            .into_iter()
+            .map(|(language, group)| (Some(language), group))
+            .collect()
+    } else {
+        vec![(None, real_files)]
+    }
+}
+
+/// 按语言把 (已排好序的) 文档分组: 组内保持原顺序 (稳定)，
+/// 组之间先按 priority 中的顺序，其余语言按名字排序
+fn group_by_language<'a>(
+    docs: Vec<(&'a PathBuf, &'a String)>,
+    priority: &[String],
+) -> Vec<(String, Vec<(&'a PathBuf, &'a String)>)> {
+    let mut groups: Vec<(String, Vec<(&PathBuf, &String)>)> = Vec::new();
+    for doc in docs {
+        let lang = detect_language(doc.0);
+        match groups.iter_mut().find(|(l, _)| *l == lang) {
+            Some((_, g)) => g.push(doc),
+            None => groups.push((lang, vec![doc])),
+        }
+    }
+
+    let rank = |lang: &str| priority.iter().position(|p| p.eq_ignore_ascii_case(lang)).unwrap_or(usize::MAX);
+    groups.sort_by(|a, b| rank(&a.0).cmp(&rank(&b.0)).then_with(|| a.0.cmp(&b.0)));
+    groups
+}
+
+/// 从 snippet 中取回 (反转义后的) 文件内容，供自定义模板使用
+pub fn snippet_content(snippet: &str) -> String {
+    const OPEN: &str = "<document_content>\n";
+    const CLOSE: &str = "\n</document_content>";
+    let body = match (snippet.find(OPEN), snippet.rfind(CLOSE)) {
+        (Some(start), Some(end)) if start + OPEN.len() <= end => &snippet[start + OPEN.len()..end],
+        _ => return String::new(),
+    };
+    unescape(body).map(|s| s.into_owned()).unwrap_or_else(|_| body.to_string())
+}
+
+/// 设置文档最终的 index，并在其后插入对应的 `id` 属性
+pub fn place_document(snippet: &str, index: usize) -> String {
+    let mut result = replace_doc_index(snippet, index);
+    let marker = format!(r#"index="{}""#, index);
+    if let Some(pos) = result.find(&marker) {
+        result.insert_str(pos + marker.len(), &format!(r#" id="{}""#, document_id(index)));
+    }
+    result
+}
+
 /// 将 snippet 里的 index="X" 替换为 index="new_index"
 /// 使用字符串查找和替换，避免引入 XML 解析库的复杂性
 fn replace_doc_index(original: &str, new_index: usize) -> String {
@@ -86,38 +278,74 @@ fn replace_doc_index(original: &str, new_index: usize) -> String {
     result
 }
 
-// Removed unused generate_xml function
-/*
-pub async fn generate_xml(paths: &[PathBuf]) -> Result<String, AppError> {
-    let mut partial_docs = HashMap::new();
-    let ignore_config = IgnoreConfig::default();
-    
-    // 1. 生成项目树 snippet
-    let current_dir = std::env::current_dir()
-        .map_err(|e| AppError::General(anyhow!("无法获取当前目录: {:?}", e)))?;
-    let tree_txt = generate_project_tree_string(&current_dir, &ignore_config)?;
-    let tree_snippet = generate_single_file_snippet(
-        Path::new(PROJECT_TREE_VIRTUAL_PATH),
-        &tree_txt,
-        0, // 临时index，merge时会变成1
-    );
-    partial_docs.insert(PathBuf::from(PROJECT_TREE_VIRTUAL_PATH), tree_snippet);
-
-    // 2. 生成真实文件 snippets
-    for path in paths {
-        match fs::read_to_string(path).await {
-            Ok(content) => {
-                let snippet = generate_single_file_snippet(path, &content, 0);
-                partial_docs.insert(path.clone(), snippet);
-            }
-            Err(err) => {
-                warn!("读取 {:?} 失败: {:?}", path, err);
+/// 生成 `<instruction>` 片段，prompt 文本同样需要转义
+pub fn instruction_snippet(prompt_text: &str) -> String {
+    format!(
+        "\n<instruction>\n{}\n</instruction>",
+        partial_escape(&strip_invalid_xml_chars(prompt_text))
+    )
+}
+
+/// XML 1.0 不允许出现除 `\t`、`\n`、`\r` 之外的控制字符 (即使转义也不行)，
+/// 二进制片段或终端粘贴的转义序列会让整个 payload 失效，这里替换为 U+FFFD
+fn strip_invalid_xml_chars(text: &str) -> std::borrow::Cow<'_, str> {
+    let invalid = |c: char| (c as u32) < 0x20 && !matches!(c, '\t' | '\n' | '\r');
+    if text.chars().any(invalid) {
+        text.chars()
+            .map(|c| if invalid(c) { '\u{FFFD}' } else { c })
+            .collect::<String>()
+            .into()
+    } else {
+        text.into()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use quick_xml::{events::Event, Reader};
+
+    /// 用 XML 解析器读回 snippet 的 (source, document_content)；内容去掉模板加上的首尾换行
+    fn parse_snippet(snippet: &str) -> (String, String) {
+        let mut reader = Reader::from_str(snippet);
+        let (mut source, mut content) = (String::new(), String::new());
+        let mut current = Vec::new();
+        loop {
+            match reader.read_event().unwrap() {
+                Event::Start(e) => current = e.name().as_ref().to_vec(),
+                Event::Text(t) if current == b"source" => source.push_str(&t.unescape().unwrap()),
+                Event::Text(t) if current == b"document_content" => content.push_str(&t.unescape().unwrap()),
+                Event::End(_) => current.clear(),
+                Event::Eof => break,
+                _ => {}
             }
         }
+        let content = content.strip_prefix('\n').and_then(|c| c.strip_suffix('\n')).unwrap().to_string();
+        (source, content)
     }
 
-    // 3. 合并所有 snippets
-    let merged = merge_all_snippets(&partial_docs);
-    Ok(merged)
+    #[test]
+    fn path_and_content_round_trip_through_a_parser() {
+        let path = Path::new("src/a&b <draft>.rs");
+        let content = "fn f(v: Vec<Foo>) -> bool {\r\n\tv.is_empty() && !v[0].ok\r\n}\n\
+                       // ]]> <![CDATA[ \"q\" 'a' &amp; &#x41;\n\
+                       let s = \"中文 é 😀 \u{7f}\u{85}\u{a0}\u{3000}\";\n";
+        let snippet = generate_single_file_snippet_with_attrs(path, content, 3, &[("indent_unit", "\t\"'<>&".to_string())]);
+
+        let (source, parsed) = parse_snippet(&snippet);
+        assert_eq!(source.as_bytes(), path.to_str().unwrap().as_bytes());
+        assert_eq!(parsed.as_bytes(), content.as_bytes());
+        assert_eq!(snippet_content(&snippet), content);
+    }
+
+    #[test]
+    fn disallowed_control_chars_become_replacement_chars() {
+        // XML 1.0 不允许这些字符，即使写成字符引用也不行
+        let content = "a\u{0}b\u{1b}[31mc\u{8}d\u{1f}\te\r\n";
+        let snippet = generate_single_file_snippet(Path::new("log\u{1b}.txt"), content, 0);
+
+        let (source, parsed) = parse_snippet(&snippet);
+        assert_eq!(source, "log\u{FFFD}.txt");
+        assert_eq!(parsed, "a\u{FFFD}b\u{FFFD}[31mc\u{FFFD}d\u{FFFD}\te\r\n");
+    }
 }
-*/ 
\ No newline at end of file
@@ -1,3 +1,5 @@
+use std::path::PathBuf;
+
 use thiserror::Error;
 
 #[derive(Error, Debug)]
@@ -6,6 +8,51 @@ pub enum AppError {
     // #[error("Unknown command: {0}")]
     // UnknownCommand(String),
 
+    /// 要扫描或读取的路径不存在
+    #[error("No such file or directory: {}", .0.display())]
+    PathNotFound(PathBuf),
+
+    /// 读写某个文件或目录失败
+    #[error("{}: {source}", path.display())]
+    Io {
+        path: PathBuf,
+        #[source]
+        source: std::io::Error,
+    },
+
+    /// 系统剪贴板不可用或写入失败
+    #[error("Clipboard unavailable: {0}")]
+    Clipboard(String),
+
+    /// 无法加载分词器 (BPE 词表)
+    #[error("Tokenizer error: {0}")]
+    Tokenizer(String),
+
+    /// payload 不是良构的 XML，或生成 XML 失败
+    #[error("XML error: {0}")]
+    Xml(String),
+
+    /// 其它错误
     #[error("General error: {0}")]
     General(#[from] anyhow::Error),
-} 
\ No newline at end of file
+
+    /// 命令在执行中被 Ctrl+C 取消 (见 ReplEngine::run_command)
+    #[error("Cancelled")]
+    Cancelled,
+}
+
+impl AppError {
+    pub fn io(path: impl Into<PathBuf>, source: std::io::Error) -> Self {
+        AppError::Io { path: path.into(), source }
+    }
+}
+
+/// io 错误的简短原因 (例如 `permission denied`)，不带 ` (os error 13)` 这样的后缀
+pub fn io_reason(e: &std::io::Error) -> String {
+    match e.kind() {
+        std::io::ErrorKind::NotFound => "not found".to_string(),
+        std::io::ErrorKind::PermissionDenied => "permission denied".to_string(),
+        std::io::ErrorKind::AlreadyExists => "already exists".to_string(),
+        _ => e.to_string(),
+    }
+}
@@ -0,0 +1,46 @@
+//! RustPrompt 的核心逻辑: 扫描文件、生成 snippet、拼装 payload、统计 token。
+//!
+//! 可执行文件 (`src/main.rs`) 只是在此之上的一层 REPL。`core` 和 `app`
+//! (交互确认 `app::output::confirm` 除外) 不向终端输出任何内容，结果都以返回值给出；
+//! `command::executor` 和 `repl` 是交互层，负责打印。
+//!
+//! 从一组路径构建 payload (与 `/copy` 复制的内容相同):
+//!
+//! ```
+//! use rustprompt::app::context::ContextBuilder;
+//!
+//! # tokio::runtime::Runtime::new().unwrap().block_on(async {
+//! let ctx = ContextBuilder::new();
+//! ctx.add("src/core/xml.rs").await?;
+//! ctx.add("Cargo.toml").await?;
+//! ctx.set_prompt("Explain how snippets are merged.")?;
+//!
+//! let payload = ctx.payload()?;
+//! assert!(payload.starts_with("<documents>"));
+//! assert!(payload.contains("<source>src/core/xml.rs</source>"));
+//! # Ok::<(), rustprompt::error::AppError>(())
+//! # }).unwrap();
+//! ```
+//!
+//! 统计一个目录的 token 数:
+//!
+//! ```
+//! use rustprompt::app::context::ContextBuilder;
+//!
+//! # tokio::runtime::Runtime::new().unwrap().block_on(async {
+//! let ctx = ContextBuilder::new();
+//! let added = ctx.add("src/core").await?;
+//! assert_eq!(added, ctx.files().len());
+//!
+//! let per_file: usize = ctx.file_tokens().iter().map(|(_, tokens)| tokens).sum();
+//! // 总数还包括项目树和 <documents> 标记
+//! assert!(ctx.token_count() > per_file);
+//! # Ok::<(), rustprompt::error::AppError>(())
+//! # }).unwrap();
+//! ```
+
+pub mod app;
+pub mod command;
+pub mod core;
+pub mod error;
+pub mod repl;
@@ -1,39 +1,329 @@
+use std::collections::HashSet;
+use std::io::{BufRead, Write};
+use std::path::PathBuf;
+use std::process::ExitCode;
 use std::sync::{Arc, Mutex};
 
 use anyhow::Result;
+use clap::{Parser, Subcommand};
 use tokio::runtime::Runtime;
+use tokio_util::sync::CancellationToken;
 
-use crate::{
-    app::state::AppState,
+use rustprompt::{
+    app::{
+        self,
+        output::{Output, ScriptOutput},
+        report::ContextReport,
+        state::AppState,
+    },
+    command::{
+        definition::{AddOptions, Command, CopyOptions},
+        executor, parser,
+        script::{self, ScriptLine},
+    },
+    core::{self, ignore_rules::IgnoreConfig, paths},
     repl::engine::ReplEngine,
 };
 
-mod app;
-mod command;
-mod error;
-mod repl;
-mod core;
+/// 命令行参数。没有 --add / --stdin / --prompt / --copy / --export / --stdout 和子命令时进入交互式 REPL，
+/// 否则依次执行这些命令后退出 (供脚本和 git hook 使用)
+#[derive(Parser, Debug)]
+#[command(name = "rustprompt", version, about = "Build LLM prompts from your project files")]
+struct Cli {
+    /// Answer yes to every confirmation prompt
+    #[arg(short = 'y', long, conflicts_with = "no")]
+    yes: bool,
+
+    /// Answer no to every confirmation prompt
+    #[arg(long)]
+    no: bool,
+
+    /// Disable colored output (also disabled by NO_COLOR or when stdout is not a terminal)
+    #[arg(long)]
+    no_color: bool,
+
+    /// Project root (paths are relative to it), defaults to the current directory
+    #[arg(long, value_name = "DIR")]
+    root: Option<PathBuf>,
+
+    /// Add a file, directory or glob to the context (repeatable)
+    #[arg(long = "add", value_name = "PATH")]
+    add: Vec<PathBuf>,
+
+    /// Add the newline-separated paths read from stdin (e.g. `fd -e rs | rustprompt --stdin --copy`)
+    #[arg(long)]
+    stdin: bool,
+
+    /// Do not apply ignore rules (hidden files, .gitignore, node_modules, /ignore patterns)
+    #[arg(long)]
+    no_ignore: bool,
+
+    /// Run the commands in a script file before anything else (repeatable); the REPL starts afterwards
+    /// unless other commands are given
+    #[arg(long = "source", value_name = "FILE")]
+    source: Vec<PathBuf>,
+
+    /// Run a command before the first prompt, e.g. `-c "/add src" -c "/mode prompt"` (repeatable);
+    /// runs after the config's on_start and --source
+    #[arg(short = 'c', long = "command", value_name = "CMD")]
+    commands: Vec<String>,
+
+    /// Exit with an error if a startup command (on_start, --source, -c) fails instead of starting anyway
+    #[arg(long)]
+    strict: bool,
+
+    /// Set the prompt text
+    #[arg(long, value_name = "TEXT")]
+    prompt: Option<String>,
+
+    /// Copy the assembled context to the clipboard
+    #[arg(long)]
+    copy: bool,
+
+    /// Write the assembled context to a file
+    #[arg(long, value_name = "FILE")]
+    export: Option<PathBuf>,
+
+    /// Print the assembled context to stdout instead of using the clipboard
+    #[arg(long)]
+    stdout: bool,
+
+    #[command(subcommand)]
+    command: Option<Query>,
+}
+
+/// 在其它参数执行完后查询状态的子命令
+#[derive(Subcommand, Debug)]
+enum Query {
+    /// Show the context (file count, tokens, selected files)
+    Context {
+        /// Print the state as JSON (schema documented on report::ContextReport)
+        #[arg(long)]
+        json: bool,
+
+        /// Print the JSON on a single line
+        #[arg(long, requires = "json")]
+        compact: bool,
+    },
+}
+
+impl Cli {
+    /// 是否给出了非交互模式的命令
+    fn is_one_shot(&self) -> bool {
+        !self.add.is_empty()
+            || self.stdin
+            || self.prompt.is_some()
+            || self.copy
+            || self.export.is_some()
+            || self.stdout
+            || self.command.is_some()
+    }
+
+    /// `context --json`: stdout 只留给 JSON
+    fn json_query(&self) -> Option<bool> {
+        match self.command {
+            Some(Query::Context { json: true, compact }) => Some(compact),
+            _ => None,
+        }
+    }
+
+    /// 启动命令，按执行顺序分组: 配置文件的 on_start、每个 --source、-c；每组带上报告失败时使用的来源
+    fn startup(&self, on_start: &[String]) -> Vec<(String, Vec<ScriptLine>)> {
+        let mut groups = Vec::new();
+        if !on_start.is_empty() {
+            groups.push((format!("{} on_start", app::config::CONFIG_FILE), script::numbered(on_start)));
+        }
+        if !self.source.is_empty() {
+            let lines: Vec<String> = self.source.iter().map(|p| format!("/source {}", parser::quote_arg(&p.display().to_string()))).collect();
+            groups.push(("--source".to_string(), script::numbered(&lines)));
+        }
+        if !self.commands.is_empty() {
+            groups.push(("-c".to_string(), script::numbered(&self.commands)));
+        }
+        groups
+    }
+
+    /// 把参数翻译成依次执行的命令 (--stdout 不对应命令，最后单独处理)；
+    /// stdin_paths 是 --stdin 读到并过滤后的路径
+    fn commands(&self, stdin_paths: Vec<PathBuf>) -> Vec<Command> {
+        // 每个路径单独一条 /add，路径不存在时命令失败而不只是警告
+        let mut commands: Vec<Command> = self.add.iter().map(|p| Command::Add(vec![p.clone()], AddOptions::default())).collect();
+        if !stdin_paths.is_empty() {
+            commands.push(Command::Add(stdin_paths, AddOptions::default()));
+        }
+        if let Some(prompt) = &self.prompt {
+            commands.push(Command::Mode(Some("prompt".to_string())));
+            commands.push(Command::AppendPromptText(prompt.clone()));
+        }
+        if self.copy {
+            commands.push(Command::Copy(CopyOptions::default()));
+        }
+        if let Some(path) = &self.export {
+            commands.push(Command::Export(path.clone(), false));
+        }
+        if let Some(Query::Context { json: false, .. }) = self.command {
+            commands.push(Command::ShowContext);
+        }
+        commands
+    }
+}
 
 /// 程序入口点
-fn main() -> Result<()> {
+fn main() -> Result<ExitCode> {
     // 初始化日志记录器
     env_logger::init();
     log::info!("日志系统已初始化");
 
+    let cli = Cli::parse();
+    // --yes / --no: 所有确认提示使用固定回答 (脚本中使用)
+    let answer = match (cli.yes, cli.no) {
+        (true, _) => Some(true),
+        (_, true) => Some(false),
+        _ => None,
+    };
+    app::output::set_confirm_override(answer);
+    app::style::init(cli.no_color);
+    // --root <dir>: 项目根目录 (<source> 中的路径相对于它)，默认为当前目录
+    let root = match &cli.root {
+        Some(dir) => Some(
+            std::fs::canonicalize(dir).map_err(|e| anyhow::anyhow!("Invalid --root {}: {}", dir.display(), e))?,
+        ),
+        None => None,
+    };
+
+    // --stdin: 先读完所有路径，之后的确认提示读到 EOF 时使用默认回答
+    let stdin_lines = if cli.stdin {
+        std::io::stdin().lock().lines().collect::<std::io::Result<Vec<String>>>()?
+    } else {
+        Vec::new()
+    };
+
     // 使用一个 tokio 运行时来支持后续的异步操作
     let rt = Runtime::new()?;
     rt.block_on(async {
         log::info!("进入 Tokio 运行时");
+        // 后台加载分词器词表，第一次计算 token 时不必等待
+        core::tokenizer::warm_up();
+
         // 初始化共享状态
-        let app_state = Arc::new(Mutex::new(AppState::new()));
+        let mut state = AppState::new();
+        if let Some(root) = root {
+            state.project_root = root;
+        }
+        // 非交互模式下 --stdout 和 context --json 的 stdout 只留给 payload / JSON，提示全部写到 stderr
+        let mut out = ScriptOutput { to_stderr: cli.stdout || cli.json_query().is_some(), failed: false };
+        // 项目配置文件 (.rustprompt.toml)，在项目根目录或 git 仓库根目录查找
+        for message in app::config::reload(&mut state) {
+            out.tagged(message);
+        }
+        if cli.no_ignore {
+            // 在配置文件之后覆盖: 只保留二进制文件的过滤，其余忽略规则全部关闭
+            state.ignore = state.ignore.permissive();
+        }
+        let startup = cli.startup(&state.on_start);
+        let app_state = Arc::new(Mutex::new(state));
         log::info!("共享状态已创建");
 
+        if cli.is_one_shot() {
+            return run_one_shot(&cli, startup, stdin_lines, app_state, out).await;
+        }
+
+        // 窗口大小变化时刷新缓存的终端宽度 (状态栏等按宽度排版)
+        app::output::watch_terminal_resize();
+
         // 创建并运行 REPL 引擎
         let mut engine = ReplEngine::new(app_state);
+        // 启动命令在读取第一行之前执行，第一次显示的提示符已经包含它们添加的文件
+        for (source, lines) in startup {
+            if !engine.run_startup(&source, lines, cli.strict).await {
+                return Ok(ExitCode::FAILURE);
+            }
+        }
         log::info!("REPL 引擎已创建，即将运行...");
         engine.run().await?;
         log::info!("REPL 引擎运行结束");
 
-        Ok(())
+        Ok(ExitCode::SUCCESS)
     })
-}
\ No newline at end of file
+}
+
+/// 不进入 REPL，先执行启动命令 (失败时只有 --strict 才停止)，再依次执行命令行给出的命令；
+/// 任一命令失败时停止并返回非零退出码
+async fn run_one_shot(
+    cli: &Cli,
+    startup: Vec<(String, Vec<ScriptLine>)>,
+    stdin_lines: Vec<String>,
+    state: Arc<Mutex<AppState>>,
+    mut out: ScriptOutput,
+) -> Result<ExitCode> {
+    let ignore = state.lock().unwrap().ignore.clone();
+    let stdin_paths = filter_stdin_paths(stdin_lines, &ignore, &mut out);
+    if cli.stdin && stdin_paths.is_empty() {
+        out.info("(Note) No paths to add from stdin".to_string());
+    }
+
+    let cancel = CancellationToken::new();
+    for (source, lines) in startup {
+        if let Err(e) = executor::run_script(&source, lines, !cli.strict, state.clone(), &cancel, &mut out).await {
+            out.error(format!("✘ {}", executor::error_message(&e)));
+            return Ok(ExitCode::FAILURE);
+        }
+    }
+    // 非 strict 时启动命令的失败不影响退出码
+    out.failed = false;
+    for cmd in cli.commands(stdin_paths) {
+        if let Err(e) = executor::execute(cmd, state.clone(), &cancel, &mut out).await {
+            out.error(format!("✘ {}", executor::error_message(&e)));
+        }
+        if out.failed {
+            return Ok(ExitCode::FAILURE);
+        }
+    }
+
+    if cli.stdout {
+        match executor::render_payload(state.clone(), &mut out).await {
+            Ok(payload) => {
+                let mut stdout = std::io::stdout().lock();
+                stdout.write_all(payload.as_bytes())?;
+                stdout.flush()?;
+            }
+            Err(e) => out.error(format!("✘ {}", executor::error_message(&e))),
+        }
+    }
+    if let Some(compact) = cli.json_query() {
+        println!("{}", ContextReport::from_state(&state.lock().unwrap()).to_json(compact));
+    }
+    Ok(if out.failed { ExitCode::FAILURE } else { ExitCode::SUCCESS })
+}
+
+/// --stdin 读到的路径: 去掉空行和首尾空白，按 paths::file_key 去重 (保持原有顺序)，
+/// 跳过被忽略规则排除的路径 (--no-ignore 时规则已关闭)。不存在的路径只警告，不中断
+fn filter_stdin_paths(lines: Vec<String>, ignore: &IgnoreConfig, out: &mut ScriptOutput) -> Vec<PathBuf> {
+    let cwd = std::env::current_dir().unwrap_or_else(|_| PathBuf::from("."));
+    let mut seen = HashSet::new();
+    let mut paths = Vec::new();
+    let mut ignored = 0;
+    for line in &lines {
+        let line = line.trim();
+        if line.is_empty() {
+            continue;
+        }
+        let path = paths::normalize(std::path::Path::new(line));
+        if !path.exists() {
+            out.warn(format!("(Warning) No such file or directory: {}", line));
+            continue;
+        }
+        if !seen.insert(paths::file_key(&path, &cwd)) {
+            continue;
+        }
+        if ignore.should_ignore_path(&path) {
+            ignored += 1;
+            continue;
+        }
+        paths.push(path);
+    }
+    if ignored > 0 {
+        out.info(format!("(Note) Skipped {} ignored paths from stdin (--no-ignore keeps them)", ignored));
+    }
+    paths
+}
@@ -1,121 +1,269 @@
 use reedline::{Completer, Span, Suggestion};
+use std::collections::BTreeSet;
 use std::fs;
 use std::path::{Path, PathBuf};
 use log::{debug, info}; // 导入日志宏
 use std::sync::{Arc, Mutex}; // <-- Import Mutex
+use std::time::{Duration, Instant};
 use crate::app::state::{AppState, ReplMode}; // <-- Import AppState and ReplMode
-use crate::core::ignore_rules::IgnoreConfig; // 引入 IgnoreConfig
+use crate::app::{presets, prompt_templates::PromptTemplates, settings::Settings};
+use crate::core::{files_scanner, ignore_rules::IgnoreConfig, paths, template, tokenizer};
+use crate::command::{parser, registry::{self, ArgCompletion}};
+use crate::repl::picker;
 
-/// 补全器，支持命令和路径
+/// 模糊补全使用的项目文件列表在这段时间内不重新扫描 (每次按 Tab 都会补全)
+const FILE_CACHE_TTL: Duration = Duration::from_secs(10);
+/// 模糊补全最多扫描的文件数，避免在很大的目录中按 Tab 时卡住
+const FILE_SCAN_LIMIT: usize = 20_000;
+/// 模糊补全最多返回的建议数
+const FUZZY_LIMIT: usize = 50;
+
+/// 项目根目录下的文件 (补全时显示的路径)，按根目录和忽略规则缓存
+struct FileCache {
+    at: Instant,
+    root: PathBuf,
+    ignore: IgnoreConfig,
+    files: Vec<String>,
+}
+
+/// 补全器，支持命令 (带说明)、路径和 command::registry 中登记的命令参数。
+/// 路径不区分大小写地按前缀匹配，没有匹配时在项目根目录下的所有文件中模糊匹配
+///
+/// ```
+/// use std::path::PathBuf;
+/// use std::sync::{Arc, Mutex};
+/// use reedline::Completer;
+/// use rustprompt::{app::state::AppState, repl::completion::CmdPromptCompleter};
+///
+/// let dir = std::env::temp_dir().join(format!("rustprompt-completion-{}", std::process::id()));
+/// std::fs::create_dir_all(dir.join("src/repl")).unwrap();
+/// let dir = dir.canonicalize().unwrap();
+/// std::fs::write(dir.join("src/repl/engine.rs"), "").unwrap();
+/// std::fs::write(dir.join("README.md"), "").unwrap();
+/// std::env::set_current_dir(&dir).unwrap();
+/// let mut state = AppState::new();
+/// state.project_root = dir.clone();
+/// // 已选文件可能以绝对路径存储 (例如用 --root 指定了其它目录)
+/// state.selected_paths.insert(dir.join("src/repl/engine.rs"));
+/// state.selected_paths.insert(PathBuf::from("/elsewhere/notes.md"));
+/// state.aliases.insert("/asrc".to_string(), "/add src --ext rs".to_string());
+///
+/// let mut completer = CmdPromptCompleter::new(Arc::new(Mutex::new(state)));
+/// let mut values = |line: &str| completer.complete(line, line.len()).into_iter().map(|s| s.value).collect::<Vec<_>>();
+/// assert_eq!(values("/mode m"), ["manual"]);
+/// assert_eq!(values("/a"), ["/add", "/alias", "/asrc", "/a"]);
+/// assert_eq!(values("/unalias "), ["asrc"]);
+/// assert_eq!(values("/model c"), ["cl100k_base", "claude"]);
+/// assert_eq!(values("/mode "), ["manual", "prompt"]);
+/// assert_eq!(values("/help co"), ["context", "copy", "config", "copyprompt", "copytree"]);
+/// assert_eq!(values("/help /ad"), ["/add"]);
+/// assert!(values("/mode manual ").is_empty());
+/// assert!(values("/grep fo").is_empty());
+/// assert_eq!(values("/add SR"), ["src/"]);
+/// assert_eq!(values("/add eng"), ["src/repl/engine.rs"]);
+/// assert_eq!(values("/add src/"), ["src/repl/"]);
+/// assert_eq!(values("/remove src/"), ["src/", "src/repl/", "src/repl/engine.rs"]);
+/// assert_eq!(values(&format!("/remove {}/src/r", dir.display())), ["src/repl/", "src/repl/engine.rs"]);
+/// assert_eq!(values("/remove /else"), ["/elsewhere/notes.md"]);
+/// assert_eq!(values("/pin ").len(), 2);
+/// # std::fs::remove_dir_all(&dir).unwrap();
+/// ```
 pub struct CmdPromptCompleter {
     pub app_state: Arc<Mutex<AppState>>, // <-- Add app_state field
+    file_cache: Option<FileCache>,
 }
 
 impl Completer for CmdPromptCompleter {
     fn complete(&mut self, line: &str, pos: usize) -> Vec<Suggestion> {
         info!("complete 方法被调用: line='{}', pos={}", line, pos);
 
-        // 如果行首不是 '/', 就不是命令行，不补全
-        if !line.starts_with('/') {
-            debug!("非 / 开头，不进行补全。");
-            return Vec::new();
-        }
-
         // 从光标前的文本获取 当前输入
+        //  - pos 是字节偏移，遇到多字节字符 (中文、emoji) 时可能不在字符边界上，
+        //    这里向前退到最近的边界，避免切片 panic
+        let mut pos = pos.min(line.len());
+        while !line.is_char_boundary(pos) {
+            pos -= 1;
+        }
         let current_input_before_cursor = &line[..pos];
+
+        // 如果行首不是 '/', 就不是命令行；Prompt 模式下只补全光标前的 @path
+        if !line.starts_with('/') {
+            return self.suggest_mention(current_input_before_cursor, pos);
+        }
         debug!("光标前输入: '{}'", current_input_before_cursor);
 
-        // 使用空格分割输入，但只分割一次，以分离命令和可能的参数部分
-        let parts: Vec<&str> = current_input_before_cursor.splitn(2, ' ').collect();
-        let cmd_part = parts.get(0).unwrap_or(&""); // 命令部分，例如 "/add" 或 "/remove /some/pa"
-        let arg_part = parts.get(1).unwrap_or(&""); // 参数部分，例如 "src/" 或 ""
+        // 与 parser 使用相同的规则拆分，第一个参数是命令
+        let tokens = parser::tokenize_spans(current_input_before_cursor);
+        let cmd_part = tokens.first().map(|t| t.value.as_str()).unwrap_or(""); // 例如 "/add"
+        // 当前正在输入的参数 (已去掉引号): 光标紧贴最后一个参数时是它，否则是一个新的空参数。
+        // span 从参数在原始输入中的起点 (包括左引号) 开始，替换时连引号一起替换
+        let (span_start, arg_part) = match tokens.last() {
+            Some(t) if tokens.len() > 1 && t.end == pos => (t.start, t.value.as_str()),
+            _ => (pos, ""),
+        };
 
         debug!("解析结果: cmd_part='{}', arg_part='{}'", cmd_part, arg_part);
 
-        // 判断是否需要进行路径补全
-        if (*cmd_part == "/add" || *cmd_part == "/remove") && current_input_before_cursor.contains(' ') {
-            // 包含空格，说明命令已输入完整，现在补全参数部分 (arg_part)
-            // 注意：这里的 arg_part 可能包含空格，但 suggest_paths 会处理
-            debug!("检测到路径补全场景...");
-            let span_start = cmd_part.len() + 1;
-            if *cmd_part == "/remove" {
-                // 如果是 /remove，调用基于上下文的补全
-                debug!("调用 suggest_context_paths...");
-                return self.suggest_context_paths(arg_part, span_start, pos);
-            } else {
-                // 如果是 /add，调用基于文件系统的补全
-                debug!("调用 suggest_paths (for /add)...");
-                return self.suggest_paths(arg_part, span_start, pos);
-            }
-        } else if !current_input_before_cursor.contains(' ') {
-             // 不包含空格，说明还在输入命令本身，补全命令
+        if !current_input_before_cursor.contains(' ') {
+            // 不包含空格，说明还在输入命令本身，补全命令
             debug!("检测到命令补全场景，调用 suggest_commands...");
-             return self.suggest_commands(current_input_before_cursor, pos);
-        } else {
-             // 其他情况（例如命令后有空格但不是 /add 或 /remove），暂时不补全
-             debug!("其他未处理的补全场景，返回空。");
-             return Vec::new();
+            return self.suggest_commands(current_input_before_cursor, pos);
         }
+        // 第几个参数 (1 开始): 光标紧贴最后一个参数时是它，否则是下一个
+        let index = if span_start == pos && arg_part.is_empty() { tokens.len() } else { tokens.len() - 1 };
+
+        // 命令已输入完整，按注册表中登记的来源补全当前参数 (arg_part)
+        let source = registry::find(cmd_part).map_or(ArgCompletion::None, |spec| spec.args);
+        let candidates: Vec<String> = match source {
+            ArgCompletion::SelectedPaths => {
+                // /remove、/mute、/pin 或 /unpin，调用基于上下文的补全；/remove 还补全已选文件所在的目录
+                debug!("调用 suggest_context_paths...");
+                return self.suggest_context_paths(arg_part, cmd_part == "/remove", span_start, pos);
+            }
+            ArgCompletion::Paths => {
+                // /add 或 /peek，调用基于文件系统的补全
+                debug!("调用 suggest_paths (for /add, /peek)...");
+                let mut ignore_config = self.app_state.lock().unwrap().ignore.clone(); // 与 /add 相同的忽略规则 (/ignore)
+                if cmd_part == "/add" {
+                    // 已输入的 --hidden / --no-ignore / --ext 同样作用于补全；还在输入选项时补全选项名，--ext 的值不补全
+                    let typing = usize::from(tokens.len() > 1 && span_start != pos);
+                    let previous: Vec<&str> = tokens[1..tokens.len() - typing].iter().map(|t| t.value.as_str()).collect();
+                    if previous.last() == Some(&"--ext") {
+                        return Vec::new();
+                    }
+                    let (options, _) = parser::split_add_flags(&previous);
+                    if !previous.contains(&"--") && arg_part.starts_with('-') {
+                        let flags = parser::ADD_FLAGS.iter().map(|f| f.to_string()).collect();
+                        return word_suggestions(flags, arg_part, span_start, pos);
+                    }
+                    ignore_config = options.apply(&ignore_config);
+                }
+                return self.suggest_paths(arg_part, &ignore_config, span_start, pos);
+            }
+            ArgCompletion::Subcommands => {
+                let previous = tokens.get(1).map(|t| t.value.as_str()).unwrap_or("");
+                return self.suggest_subcommand_args(cmd_part, index, previous, arg_part, span_start, pos);
+            }
+            ArgCompletion::Flags(flags) => flags.iter().map(|f| f.to_string()).collect(),
+            // 其余来源只补全第一个参数
+            _ if index != 1 => Vec::new(),
+            ArgCompletion::Words(words) => words.iter().map(|w| w.to_string()).collect(),
+            ArgCompletion::Models => tokenizer::model_names().into_iter().map(String::from).collect(),
+            ArgCompletion::Presets => presets::builtin().into_iter().map(|p| p.name).collect(),
+            ArgCompletion::Settings => Settings::KEYS.iter().map(|k| k.to_string()).collect(),
+            // /help add 和 /help /add 都可以
+            ArgCompletion::Commands => registry::COMMANDS
+                .iter()
+                .map(|spec| if arg_part.starts_with('/') { spec.name } else { &spec.name[1..] }.to_string())
+                .collect(),
+            ArgCompletion::None => {
+                debug!("{} 的参数不补全", cmd_part);
+                Vec::new()
+            }
+        };
+        word_suggestions(candidates, arg_part, span_start, pos)
     }
 }
 
 impl CmdPromptCompleter {
-    /// [MODIFIED] 补全命令名时，根据当前模式只列出允许的命令
-    fn suggest_commands(&self, input: &str, pos: usize) -> Vec<Suggestion> {
-        // [ADDED] 根据当前模式，列出有效命令
-        let mode = {
+    pub fn new(app_state: Arc<Mutex<AppState>>) -> Self {
+        Self { app_state, file_cache: None }
+    }
+
+    /// Prompt 模式下光标前的单词以 @ 开头时补全文件路径 (与 /add 相同的忽略规则)，替换时保留 @
+    fn suggest_mention(&mut self, before_cursor: &str, pos: usize) -> Vec<Suggestion> {
+        let word = before_cursor.rsplit(char::is_whitespace).next().unwrap_or("");
+        let word_start = before_cursor.len() - word.len();
+        let Some(partial) = word.strip_prefix('@') else {
+            debug!("非 / 开头且不是 @path，不进行补全。");
+            return Vec::new();
+        };
+        let ignore_config = {
             let st = self.app_state.lock().unwrap();
-            st.mode.clone()
+            if st.mode != ReplMode::Prompt {
+                return Vec::new();
+            }
+            st.ignore.clone()
         };
+        self.suggest_paths(partial, &ignore_config, word_start + 1, pos)
+    }
 
-        let commands = match mode {
-            ReplMode::Manual => vec![
-                "/add", 
-                "/remove", 
-                "/context", 
-                "/copy", 
-                "/reset",
-                "/mode", 
-                "/help", 
-                "/quit",
-            ],
-            ReplMode::Prompt => vec![
-                "/mode", 
-                "/prompt", 
-                "/context", 
-                "/copy", 
-                "/help", 
-                "/quit",
-            ],
+    /// 补全命令名: 只列出当前模式下可用的命令 (command::registry)，菜单中显示命令的说明；
+    /// 然后是用户别名，以及 (输入了 `/` 之后的字符时) 内置别名
+    fn suggest_commands(&self, input: &str, pos: usize) -> Vec<Suggestion> {
+        let (mode, aliases) = {
+            let st = self.app_state.lock().unwrap();
+            (st.mode.clone(), st.aliases.clone())
         };
-
         debug!("suggest_commands: input='{}', mode={:?}", input, mode);
 
+        let commands = registry::for_mode(&mode)
+            .filter(|spec| spec.name.starts_with(input))
+            .map(|spec| (spec.name.to_string(), spec.description.to_string()));
+        let user_aliases = aliases
+            .into_iter()
+            .filter(|(name, _)| name.starts_with(input))
+            .map(|(name, expansion)| (name, format!("Alias for {}", expansion)));
+        let builtin_aliases = registry::for_mode(&mode)
+            .filter(|_| input.len() > 1)
+            .flat_map(|spec| spec.aliases.iter().map(move |alias| (alias, spec.name)))
+            .filter(|(alias, _)| alias.starts_with(input))
+            .map(|(alias, name)| (alias.to_string(), format!("Alias for {}", name)));
+
         let suggestions: Vec<Suggestion> = commands
-            .into_iter() // Use into_iter() as we own the Vec now
-            .filter(|cmd| cmd.starts_with(input))
-            .map(|cmd| {
-                debug!("  -> 建议: {}", cmd);
-                Suggestion {
-                    value: cmd.to_string(),
-                    description: None,
-                    extra: None,
-                    style: None,
-                    // 替换从 input 的开头到 pos
-                    span: Span { start: 0, end: pos },
-                    append_whitespace: true, // 补全命令后加空格
-                }
+            .chain(user_aliases)
+            .chain(builtin_aliases)
+            .map(|(value, description)| Suggestion {
+                value,
+                description: Some(description),
+                extra: None,
+                style: None,
+                // 替换从 input 的开头到 pos
+                span: Span { start: 0, end: pos },
+                append_whitespace: true, // 补全命令后加空格
             })
             .collect();
         debug!("suggest_commands: 返回 {} 条建议", suggestions.len());
         suggestions
     }
 
-    /// 补全文件路径(只做一层)，并应用忽略规则
-    fn suggest_paths(&self, partial_path: &str, span_start: usize, pos: usize) -> Vec<Suggestion> {
-        debug!("suggest_paths: partial_path='{}', span_start={}, pos={}", partial_path, span_start, pos);
-        let ignore_config = IgnoreConfig::default(); // 获取默认忽略配置
+    /// 补全 /ignore 的子命令和 on/off、/unignore 的已有模式、/unalias 的别名、/template 的子命令和已保存的模板名，
+    /// 以及 /format 的格式和 custom 之后的模板名
+    fn suggest_subcommand_args(
+        &self,
+        cmd_part: &str,
+        index: usize,
+        subcommand: &str,
+        partial: &str,
+        span_start: usize,
+        pos: usize,
+    ) -> Vec<Suggestion> {
+        let candidates: Vec<String> = match (cmd_part, index) {
+            ("/ignore", 1) => ["list", "hidden", "gitignore", "node_modules", "binary", "add"].map(String::from).to_vec(),
+            ("/ignore", 2) if matches!(subcommand, "hidden" | "gitignore" | "node_modules" | "binary") => {
+                vec!["on".to_string(), "off".to_string()]
+            }
+            ("/unignore", 1) => self.app_state.lock().unwrap().ignore.patterns.clone(),
+            ("/unalias", 1) => self.app_state.lock().unwrap().aliases.keys().map(|name| name[1..].to_string()).collect(),
+            ("/template", 1) => ["list", "save", "use", "delete"].map(String::from).to_vec(),
+            // 已保存的模板名 (save 时用于覆盖已有模板)
+            ("/template", 2) if matches!(subcommand, "use" | "delete" | "save") => PromptTemplates::load().names(),
+            ("/template", 3) if subcommand == "use" => vec!["--append".to_string()],
+            ("/format", 1) => vec!["xml".to_string(), "custom".to_string()],
+            ("/format", 2) if subcommand == "custom" => template::available_templates(),
+            _ => Vec::new(),
+        };
+        word_suggestions(candidates, partial, span_start, pos)
+    }
 
+    /// 补全文件路径(只做一层)，并应用忽略规则。文件名不区分大小写地按前缀匹配，大小写也一致的排在前面；
+    /// 没有匹配时模糊匹配项目中的所有文件 (suggest_fuzzy_paths)
+    fn suggest_paths(&mut self, partial_path: &str, ignore_config: &IgnoreConfig, span_start: usize, pos: usize) -> Vec<Suggestion> {
+        debug!("suggest_paths: partial_path='{}', span_start={}, pos={}", partial_path, span_start, pos);
+        // glob 模式 (/add src/**/*.rs) 不做文件系统补全
+        if files_scanner::is_glob(partial_path) {
+            return Vec::new();
+        }
         // 获取当前工作目录作为默认基准
         let current_dir = std::env::current_dir().unwrap_or_else(|_| PathBuf::from("."));
 
@@ -134,98 +282,205 @@ impl CmdPromptCompleter {
                  let prefix = path_to_parse.file_name().unwrap_or_default().to_string_lossy();
                  (current_dir.join(parent), prefix.to_string())
             } else {
-                (current_dir, partial_path.to_string())
+                (current_dir.clone(), partial_path.to_string())
             }
         };
 
         debug!("  -> 解析后: base_dir='{:?}', prefix='{}'", base_dir, prefix);
 
         let read_dir_result = fs::read_dir(&base_dir);
-        let mut suggestions = Vec::new();
+        let prefix_lower = prefix.to_lowercase();
+        // (大小写是否与输入一致, 文件名, 建议)
+        let mut matches: Vec<(bool, String, Suggestion)> = Vec::new();
 
         if let Ok(entries) = read_dir_result {
-            for entry_result in entries {
-                if let Ok(entry) = entry_result {
-                    let entry_path = entry.path();
-                    // 应用忽略规则
-                    if ignore_config.should_ignore_path(&entry_path) {
-                        continue;
-                    }
+            for entry in entries.flatten() {
+                let entry_path = entry.path();
+                // 应用忽略规则
+                if ignore_config.should_ignore_path(&entry_path) {
+                    continue;
+                }
 
-                    if let Ok(file_type) = entry.file_type() {
-                        let file_name = entry.file_name().to_string_lossy().to_string();
+                if let Ok(file_type) = entry.file_type() {
+                    let file_name = entry.file_name().to_string_lossy().to_string();
+                    
+                    // 如果 prefix 为空，或者文件名以 prefix 开头 (不区分大小写)
+                    if prefix.is_empty() || file_name.to_lowercase().starts_with(&prefix_lower) {
+                        let exact_case = file_name.starts_with(&prefix);
+                        let mut display_name = file_name.clone();
+                        // 如果是目录，在末尾加上分隔符
+                        if file_type.is_dir() {
+                            display_name.push(std::path::MAIN_SEPARATOR);
+                        }
                         
-                        // 如果 prefix 为空，或者文件名以 prefix 开头
-                        if prefix.is_empty() || file_name.starts_with(&prefix) {
-                            let mut display_name = file_name;
-                            // 如果是目录，在末尾加上分隔符
-                            if file_type.is_dir() {
-                                display_name.push(std::path::MAIN_SEPARATOR);
-                            }
-                            
-                            // 构造替换后的完整参数值 (包含用户输入的目录部分)
-                            let value_to_insert = {
-                                let path_prefix_typed_by_user = if let Some(idx) = partial_path.rfind(std::path::MAIN_SEPARATOR) {
-                                    &partial_path[..=idx]
-                                } else {
-                                    ""
-                                };
-                                format!("{}{}", path_prefix_typed_by_user, display_name)
+                        // 构造替换后的完整参数值 (包含用户输入的目录部分)
+                        let value_to_insert = {
+                            let path_prefix_typed_by_user = if let Some(idx) = partial_path.rfind(std::path::MAIN_SEPARATOR) {
+                                &partial_path[..=idx]
+                            } else {
+                                ""
                             };
-                            
-                            debug!("    -> 匹配到: {}, 插入值: {}", display_name, value_to_insert);
-
-                            suggestions.push(Suggestion {
-                                value: value_to_insert, // 使用构造好的完整相对路径
-                                description: None,
-                                extra: None,
-                                style: None,
-                                // 替换从参数部分的开始到当前光标
-                                span: Span { start: span_start, end: pos }, 
-                                append_whitespace: !file_type.is_dir(), // 文件后加空格，目录后不加
-                            });
-                        }
+                            format!("{}{}", path_prefix_typed_by_user, display_name)
+                        };
+                        
+                        debug!("    -> 匹配到: {}, 插入值: {}", display_name, value_to_insert);
+
+                        matches.push((exact_case, file_name, Suggestion {
+                            // 使用构造好的完整相对路径；含空格时加引号，保证补全后的命令仍能正确解析
+                            value: parser::quote_arg(&value_to_insert),
+                            description: None,
+                            extra: None,
+                            style: None,
+                            // 替换从参数部分的开始到当前光标
+                            span: Span { start: span_start, end: pos }, 
+                            append_whitespace: !file_type.is_dir(), // 文件后加空格，目录后不加
+                        }));
                     }
                 }
             }
         }
+        matches.sort_by(|a, b| b.0.cmp(&a.0).then_with(|| a.1.cmp(&b.1)));
+        let suggestions: Vec<Suggestion> = matches.into_iter().map(|(_, _, s)| s).collect();
+
+        // 绝对路径和以分隔符结尾的目录只列出这一层
+        if suggestions.is_empty()
+            && !prefix.is_empty()
+            && !Path::new(partial_path).is_absolute()
+            && !partial_path.ends_with(std::path::MAIN_SEPARATOR)
+            && self.app_state.lock().unwrap().settings.fuzzy_completion
+        {
+            return self.suggest_fuzzy_paths(partial_path, ignore_config, &current_dir, span_start, pos);
+        }
         debug!("suggest_paths: 返回 {} 条建议", suggestions.len());
         suggestions
     }
 
-    /// 根据当前选中的路径 (AppState.selected_paths) 进行补全
-    fn suggest_context_paths(&self, partial_path: &str, span_start: usize, pos: usize) -> Vec<Suggestion> {
+    /// 在项目根目录下的所有文件中模糊匹配 partial_path (picker::fuzzy_score)，得分高的在前，最多 FUZZY_LIMIT 条
+    fn suggest_fuzzy_paths(
+        &mut self,
+        partial_path: &str,
+        ignore_config: &IgnoreConfig,
+        current_dir: &Path,
+        span_start: usize,
+        pos: usize,
+    ) -> Vec<Suggestion> {
+        let root = self.app_state.lock().unwrap().project_root.clone();
+        let query = partial_path.to_lowercase();
+        let files = self.project_files(&root, ignore_config, current_dir);
+        let mut scored: Vec<(i64, &String)> = files
+            .iter()
+            .filter_map(|f| picker::fuzzy_score(&query, f).map(|score| (score, f)))
+            .collect();
+        scored.sort_by(|a, b| b.0.cmp(&a.0).then_with(|| a.1.cmp(b.1)));
+        debug!("suggest_fuzzy_paths: '{}' 匹配 {} 个文件", partial_path, scored.len());
+        scored
+            .into_iter()
+            .take(FUZZY_LIMIT)
+            .map(|(_, file)| Suggestion {
+                value: parser::quote_arg(file),
+                description: None,
+                extra: None,
+                style: None,
+                span: Span { start: span_start, end: pos },
+                append_whitespace: true,
+            })
+            .collect()
+    }
+
+    /// 项目根目录下符合忽略规则的文件，路径相对于当前目录 (不在当前目录下时为绝对路径)，与手动输入给 /add 的一致。
+    /// 根目录和忽略规则不变时 FILE_CACHE_TTL 内使用缓存
+    fn project_files(&mut self, root: &Path, ignore_config: &IgnoreConfig, current_dir: &Path) -> &[String] {
+        let fresh = matches!(&self.file_cache, Some(cache)
+            if cache.at.elapsed() < FILE_CACHE_TTL && cache.root == root && cache.ignore == *ignore_config);
+        if !fresh {
+            let files = ignore_config
+                .build_walker(root)
+                .build()
+                .flatten()
+                .filter(|entry| entry.file_type().is_some_and(|ft| ft.is_file()))
+                .take(FILE_SCAN_LIMIT)
+                .map(|entry| {
+                    let path = entry.path();
+                    path.strip_prefix(current_dir).unwrap_or(path).to_string_lossy().to_string()
+                })
+                .collect();
+            self.file_cache = Some(FileCache { at: Instant::now(), root: root.to_path_buf(), ignore: ignore_config.clone(), files });
+        }
+        self.file_cache.as_ref().map_or(&[], |cache| &cache.files)
+    }
+
+    /// 根据当前选中的路径 (AppState.selected_paths) 进行补全。输入可以是相对于项目根目录的路径
+    /// (与 /context 中显示的一致) 或绝对路径，补全的值是相对于根目录的路径 (不在根目录下时为绝对路径)。
+    /// with_dirs 时还补全根目录下已选文件所在的各级目录 (以分隔符结尾)，/remove 一次移除目录下的所有文件
+    fn suggest_context_paths(&self, partial_path: &str, with_dirs: bool, span_start: usize, pos: usize) -> Vec<Suggestion> {
         debug!("suggest_context_paths: partial_path='{}', span_start={}, pos={}", partial_path, span_start, pos);
         
-        let selected_paths = {
+        let (selected_paths, root) = {
             let state = self.app_state.lock().unwrap();
             // 克隆 HashSet 以快速释放锁
-            state.selected_paths.clone() 
+            (state.selected_paths.clone(), state.project_root.clone())
         };
         
         debug!("  -> 当前选中路径数量: {}", selected_paths.len());
 
-        let mut suggestions = Vec::new();
-        
+        let cwd = std::env::current_dir().unwrap_or_else(|_| PathBuf::from("."));
+        let mut dirs: BTreeSet<String> = BTreeSet::new();
+        let mut matched: Vec<String> = Vec::new();
         for path in selected_paths {
-            // 将 PathBuf 转换为字符串以进行比较
-            let path_str = path.to_string_lossy();
-
-            // 检查路径字符串是否以用户输入的 partial_path 开头
-            if path_str.starts_with(partial_path) {
-                 debug!("    -> 匹配到: {}", path_str);
-                 suggestions.push(Suggestion {
-                    value: path_str.to_string(), // 补全的值是完整的已选路径
-                    description: None,
-                    extra: None,
-                    style: None,
-                    // 替换从参数部分的开始到当前光标
-                    span: Span { start: span_start, end: pos }, 
-                    append_whitespace: false, // remove 通常不需要加空格
-                });
+            let display = paths::display_path(&path, &root);
+            let relative = display.to_string_lossy().to_string();
+            let absolute = paths::normalize(&cwd.join(&path));
+            if with_dirs && display.is_relative() {
+                for dir in display.ancestors().skip(1).filter(|d| !d.as_os_str().is_empty()) {
+                    let dir = format!("{}{}", dir.display(), std::path::MAIN_SEPARATOR);
+                    let absolute_dir = format!("{}{}", root.join(&dir).display(), std::path::MAIN_SEPARATOR);
+                    if dir.starts_with(partial_path) || absolute_dir.starts_with(partial_path) {
+                        dirs.insert(dir);
+                    }
+                }
+            }
+
+            // 相对于根目录的路径、绝对路径或存储的路径以用户输入的 partial_path 开头
+            if relative.starts_with(partial_path)
+                || absolute.to_string_lossy().starts_with(partial_path)
+                || path.to_string_lossy().starts_with(partial_path)
+            {
+                debug!("    -> 匹配到: {}", relative);
+                matched.push(relative);
             }
         }
+        matched.extend(dirs);
+        matched.sort();
+
+        let suggestions: Vec<Suggestion> = matched
+            .iter()
+            .map(|path_str| Suggestion {
+                value: parser::quote_arg(path_str), // 补全的值是完整的已选路径
+                description: None,
+                extra: None,
+                style: None,
+                // 替换从参数部分的开始到当前光标
+                span: Span { start: span_start, end: pos },
+                append_whitespace: false, // remove 通常不需要加空格
+            })
+            .collect();
         debug!("suggest_context_paths: 返回 {} 条建议", suggestions.len());
         suggestions
     }
-} 
\ No newline at end of file
+}
+
+/// 以 partial 开头的候选词，替换 span_start..pos
+fn word_suggestions(candidates: Vec<String>, partial: &str, span_start: usize, pos: usize) -> Vec<Suggestion> {
+    candidates
+        .into_iter()
+        .filter(|c| c.starts_with(partial))
+        .map(|value| Suggestion {
+            value,
+            description: None,
+            extra: None,
+            style: None,
+            span: Span { start: span_start, end: pos },
+            append_whitespace: true,
+        })
+        .collect()
+}
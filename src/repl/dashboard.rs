@@ -0,0 +1,343 @@
+// src/repl/dashboard.rs
+
+use std::io::{self, Stdout};
+use std::path::PathBuf;
+use std::sync::{Arc, Mutex};
+
+use ratatui::{
+    backend::CrosstermBackend,
+    crossterm::{
+        event::{self, Event, KeyCode, KeyEventKind},
+        execute,
+        terminal::{disable_raw_mode, enable_raw_mode, EnterAlternateScreen, LeaveAlternateScreen},
+    },
+    layout::{Constraint, Direction, Layout},
+    style::{Color, Modifier, Style},
+    text::{Line, Span},
+    widgets::{Block, Borders, Gauge, List, ListItem, ListState, Paragraph, Wrap},
+    Frame, Terminal,
+};
+use tokio_util::sync::CancellationToken;
+
+use crate::{
+    app::{
+        output::{format_token_count, refresh_terminal_width, CapturedOutput, Level},
+        state::AppState,
+    },
+    command::{definition::Command, executor},
+    error::AppError,
+};
+
+/// 文件列表中的一行
+#[derive(Debug, Clone, PartialEq)]
+pub struct FileRow {
+    pub path: PathBuf,
+    pub tokens: usize,
+    pub muted: bool,
+}
+
+/// 从 AppState 提取出的、与终端无关的视图数据
+#[derive(Debug, Clone, Default)]
+pub struct DashboardView {
+    pub files: Vec<FileRow>,
+    pub total_tokens: usize,
+    pub budget: usize,
+    pub prompt: String,
+    pub activity: Vec<String>,
+    pub mode: String,
+}
+
+impl DashboardView {
+    pub fn from_state(st: &AppState) -> Self {
+        let mut files: Vec<FileRow> = st
+            .selected_paths
+            .iter()
+            .map(|p| FileRow {
+                path: p.clone(),
+                tokens: st.doc_tokens.get(p).copied().unwrap_or(0),
+                muted: st.muted.contains(p),
+            })
+            .collect();
+        files.sort_by(|a, b| a.path.cmp(&b.path));
+
+        Self {
+            files,
+            total_tokens: st.token_count,
+            budget: st.settings.token_budget,
+            prompt: st.prompt_text.clone(),
+            activity: st.activity.iter().rev().cloned().collect(),
+            mode: format!("{:?}", st.mode),
+        }
+    }
+
+    /// 预算使用比例 (0.0 ~ 1.0，超出预算时截断为 1.0)；未设预算时为 None
+    pub fn budget_ratio(&self) -> Option<f64> {
+        if self.budget == 0 {
+            None
+        } else {
+            Some((self.total_tokens as f64 / self.budget as f64).min(1.0))
+        }
+    }
+
+    /// 文件行的 token 条: 以最大的文件为满格
+    pub fn token_bar(&self, tokens: usize, width: usize) -> String {
+        let max = self.files.iter().map(|f| f.tokens).max().unwrap_or(0);
+        let filled = if max == 0 { 0 } else { (tokens * width).div_ceil(max) };
+        format!("{:<width$}", "█".repeat(filled.min(width)), width = width)
+    }
+}
+
+/// 需要交给 executor 执行的操作；执行后重新绘制
+enum Action {
+    Quit,
+    Remove(PathBuf),
+    ToggleMute(PathBuf),
+}
+
+/// 离开仪表盘时 (包括出错返回) 恢复终端，之后 reedline 会在下一次 read_line 时重新接管
+struct TerminalGuard;
+
+impl Drop for TerminalGuard {
+    fn drop(&mut self) {
+        let _ = disable_raw_mode();
+        let _ = execute!(io::stdout(), LeaveAlternateScreen);
+    }
+}
+
+/// 进入全屏仪表盘，直到用户按 q / Esc 返回 REPL
+///  - 删除、静音等操作通过 executor 执行，与输入命令的行为完全一致
+pub async fn run(state: Arc<Mutex<AppState>>) -> Result<(), AppError> {
+    enable_raw_mode().map_err(io_error)?;
+    let _guard = TerminalGuard;
+    execute!(io::stdout(), EnterAlternateScreen).map_err(io_error)?;
+    let mut terminal = Terminal::new(CrosstermBackend::new(io::stdout())).map_err(io_error)?;
+
+    let mut list_state = ListState::default();
+    list_state.select(Some(0));
+
+    loop {
+        let action = event_loop(&mut terminal, &state, &mut list_state).map_err(io_error)?;
+        let cmd = match action {
+            Action::Quit => break,
+            Action::Remove(path) => Command::Remove(vec![path]),
+            Action::ToggleMute(path) => Command::Mute(path),
+        };
+        // 命令的文字输出不打印到全屏界面，结果体现在文件列表和活动日志中；警告也记入活动日志
+        let mut out = CapturedOutput::default();
+        let result = Box::pin(executor::execute(cmd, state.clone(), &CancellationToken::new(), &mut out)).await;
+        let mut st = state.lock().unwrap();
+        for warning in out.at(Level::Warn) {
+            st.log_activity(warning.to_string());
+        }
+        if let Err(e) = result {
+            st.log_activity(format!("✘ {}", executor::error_message(&e)));
+        }
+        drop(st);
+        terminal.clear().map_err(io_error)?;
+    }
+    Ok(())
+}
+
+fn event_loop(
+    terminal: &mut Terminal<CrosstermBackend<Stdout>>,
+    state: &Arc<Mutex<AppState>>,
+    list_state: &mut ListState,
+) -> io::Result<Action> {
+    loop {
+        let view = DashboardView::from_state(&state.lock().unwrap());
+        if view.files.is_empty() {
+            list_state.select(None);
+        } else {
+            let i = list_state.selected().unwrap_or(0).min(view.files.len() - 1);
+            list_state.select(Some(i));
+        }
+        terminal.draw(|f| draw(f, &view, list_state))?;
+
+        let key = match event::read()? {
+            Event::Key(key) if key.kind == KeyEventKind::Press => key,
+            // 窗口大小变化: 下一轮 draw() 会按新尺寸重新布局
+            Event::Resize(..) => {
+                refresh_terminal_width();
+                continue;
+            }
+            _ => continue,
+        };
+        let current = list_state.selected().and_then(|i| view.files.get(i));
+        match key.code {
+            KeyCode::Char('q') | KeyCode::Esc => return Ok(Action::Quit),
+            KeyCode::Down | KeyCode::Char('j') => list_state.select_next(),
+            KeyCode::Up | KeyCode::Char('k') => list_state.select_previous(),
+            KeyCode::Char('m') | KeyCode::Char(' ') => {
+                if let Some(row) = current {
+                    return Ok(Action::ToggleMute(row.path.clone()));
+                }
+            }
+            KeyCode::Char('d') | KeyCode::Delete => {
+                if let Some(row) = current {
+                    return Ok(Action::Remove(row.path.clone()));
+                }
+            }
+            _ => {}
+        }
+    }
+}
+
+fn draw(f: &mut Frame, view: &DashboardView, list_state: &mut ListState) {
+    let outer = Layout::default()
+        .direction(Direction::Vertical)
+        .constraints([Constraint::Min(5), Constraint::Length(3), Constraint::Length(1)])
+        .split(f.area());
+    let columns = Layout::default()
+        .direction(Direction::Horizontal)
+        .constraints([Constraint::Percentage(60), Constraint::Percentage(40)])
+        .split(outer[0]);
+    let right = Layout::default()
+        .direction(Direction::Vertical)
+        .constraints([Constraint::Percentage(40), Constraint::Percentage(60)])
+        .split(columns[1]);
+
+    // 文件列表 + token 条
+    let bar_width = 12;
+    let items: Vec<ListItem> = view
+        .files
+        .iter()
+        .map(|row| {
+            let style = if row.muted {
+                Style::default().fg(Color::DarkGray)
+            } else {
+                Style::default()
+            };
+            let mut path = row.path.display().to_string();
+            if row.muted {
+                path.push_str(" (muted)");
+            }
+            ListItem::new(Line::from(vec![
+                Span::styled(view.token_bar(row.tokens, bar_width), Style::default().fg(Color::Cyan)),
+                Span::raw(format!(" {:>7} ", format_token_count(row.tokens))),
+                Span::styled(path, style),
+            ]))
+        })
+        .collect();
+    let files = List::new(items)
+        .block(Block::default().borders(Borders::ALL).title(format!(" Files ({}) ", view.files.len())))
+        .highlight_style(Style::default().add_modifier(Modifier::REVERSED));
+    f.render_stateful_widget(files, columns[0], list_state);
+
+    let prompt = if view.prompt.is_empty() { "(empty)".to_string() } else { view.prompt.clone() };
+    let prompt = Paragraph::new(prompt)
+        .wrap(Wrap { trim: false })
+        .block(Block::default().borders(Borders::ALL).title(format!(" Prompt ({} mode) ", view.mode)));
+    f.render_widget(prompt, right[0]);
+
+    let activity: Vec<ListItem> = view.activity.iter().map(|a| ListItem::new(a.as_str())).collect();
+    let activity = List::new(activity).block(Block::default().borders(Borders::ALL).title(" Recent activity "));
+    f.render_widget(activity, right[1]);
+
+    let budget_block = Block::default().borders(Borders::ALL).title(" Budget ");
+    match view.budget_ratio() {
+        Some(ratio) => {
+            let color = if view.total_tokens > view.budget { Color::Red } else { Color::Green };
+            let gauge = Gauge::default()
+                .block(budget_block)
+                .gauge_style(Style::default().fg(color))
+                .ratio(ratio)
+                .label(format!(
+                    "{} / {} tokens",
+                    format_token_count(view.total_tokens),
+                    format_token_count(view.budget)
+                ));
+            f.render_widget(gauge, outer[1]);
+        }
+        None => {
+            let text = format!("{} tokens (no budget, see /budget)", format_token_count(view.total_tokens));
+            f.render_widget(Paragraph::new(text).block(budget_block), outer[1]);
+        }
+    }
+
+    let help = Paragraph::new("↑/↓ select · m/space mute · d remove · q/esc back to REPL")
+        .style(Style::default().fg(Color::DarkGray));
+    f.render_widget(help, outer[2]);
+}
+
+fn io_error(e: io::Error) -> AppError {
+    AppError::General(anyhow::anyhow!("dashboard terminal error: {}", e))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ratatui::backend::TestBackend;
+
+    /// 两个已选文件 (一个静音)、一条活动记录、预算 1000
+    fn sample_state() -> AppState {
+        let mut st = AppState::new();
+        for (path, tokens) in [("src/b.rs", 300), ("src/a.rs", 100)] {
+            st.selected_paths.insert(PathBuf::from(path));
+            st.doc_tokens.insert(PathBuf::from(path), tokens);
+        }
+        st.muted.insert(PathBuf::from("src/b.rs"));
+        st.token_count = 450;
+        st.settings.token_budget = 1000;
+        st.prompt_text = "Explain the parser".to_string();
+        st.log_activity("/add src".to_string());
+        st
+    }
+
+    #[test]
+    fn view_lists_files_sorted_with_tokens_and_mute_flags() {
+        let view = DashboardView::from_state(&sample_state());
+        assert_eq!(
+            view.files,
+            [
+                FileRow { path: PathBuf::from("src/a.rs"), tokens: 100, muted: false },
+                FileRow { path: PathBuf::from("src/b.rs"), tokens: 300, muted: true },
+            ]
+        );
+        assert_eq!(view.total_tokens, 450);
+        assert_eq!(view.prompt, "Explain the parser");
+        assert_eq!(view.activity.len(), 1);
+        assert!(view.activity[0].contains("/add src"));
+    }
+
+    #[test]
+    fn budget_ratio_is_capped_and_absent_without_budget() {
+        let mut view = DashboardView::from_state(&sample_state());
+        assert_eq!(view.budget_ratio(), Some(0.45));
+        view.total_tokens = 5000;
+        assert_eq!(view.budget_ratio(), Some(1.0));
+        view.budget = 0;
+        assert_eq!(view.budget_ratio(), None);
+    }
+
+    #[test]
+    fn token_bars_scale_to_the_largest_file() {
+        let view = DashboardView::from_state(&sample_state());
+        assert_eq!(view.token_bar(300, 6), "██████");
+        assert_eq!(view.token_bar(100, 6), "██    ");
+        assert_eq!(view.token_bar(0, 6), "      ");
+        assert_eq!(DashboardView::default().token_bar(10, 4), "    ");
+    }
+
+    #[test]
+    fn draws_without_a_real_terminal() {
+        let view = DashboardView::from_state(&sample_state());
+        let mut terminal = Terminal::new(TestBackend::new(100, 20)).unwrap();
+        let mut list_state = ListState::default();
+        list_state.select(Some(0));
+        terminal.draw(|f| draw(f, &view, &mut list_state)).unwrap();
+
+        let screen: String = terminal.backend().buffer().content().iter().map(|cell| cell.symbol()).collect();
+        for expected in [" Files (2) ", "src/a.rs", "src/b.rs (muted)", "Explain the parser", "450 / 1.0k tokens"] {
+            assert!(screen.contains(expected), "missing {:?}", expected);
+        }
+    }
+
+    #[test]
+    fn draws_an_empty_context() {
+        let view = DashboardView::from_state(&AppState::new());
+        let mut terminal = Terminal::new(TestBackend::new(60, 12)).unwrap();
+        terminal.draw(|f| draw(f, &view, &mut ListState::default())).unwrap();
+        let screen: String = terminal.backend().buffer().content().iter().map(|cell| cell.symbol()).collect();
+        assert!(screen.contains(" Files (0) ") && screen.contains("(empty)"));
+    }
+}
@@ -1,21 +1,35 @@
+use std::io::IsTerminal;
 use std::sync::{Arc, Mutex};
 
 use reedline::{
-    ColumnarMenu, Emacs, KeyCode, KeyModifiers, Reedline, ReedlineEvent, ReedlineMenu, Signal, 
-    default_emacs_keybindings, // 用于获取默认绑定
+    ColumnarMenu, Emacs, Vi, Reedline, ReedlineMenu, Signal, 
+    default_emacs_keybindings, default_vi_insert_keybindings, default_vi_normal_keybindings, // 用于获取默认绑定
     MenuBuilder, // <--- 导入 MenuBuilder trait
     Validator, ValidationResult, // <--- 导入 Validator
     EditCommand,
+    ExternalPrinter,
+    History,
 };
 use anyhow::Result;
+use tokio_util::sync::CancellationToken;
 use log::debug; // <-- 导入 debug 宏
 
 use crate::{
-    app::state::{AppState, ReplMode, ReplEditorMode}, // <-- 导入 ReplEditorMode
-    command::{parser, executor, definition::Command},
+    app::{
+        output::{confirm_with, failure_footer, ConfirmInput, Level, Output, TerminalOutput},
+        keys::{self, KeyBinding, KeyEditor},
+        settings::EditMode,
+        snippet_manager::SnippetManager,
+        watch,
+        state::{AppState, ReplMode, ReplEditorMode}, // <-- 导入 ReplEditorMode
+    },
+    command::{parser, executor::{self, Effect}, definition::{AddOptions, Command}, script::ScriptLine},
     repl::{
-        prompt::CmdPrompt,
+        prompt::{CmdPrompt, QuestionPrompt},
         completion::CmdPromptCompleter,
+        highlighter::CmdHighlighter,
+        hinter::CmdHinter,
+        history::ProjectHistory,
     },
     error::AppError,
 };
@@ -27,12 +41,22 @@ use crate::{
 //     MultiLine,
 // }
 
-/// 自定义Validator：最后一行若是 :submit => 视为完成
+/// 多行编辑时，单独一行的 `:submit` 表示结束编辑 (Ctrl+S / Ctrl+Enter 也可以直接提交)
+const SUBMIT_SENTINEL: &str = ":submit";
+
+/// 命令被 Ctrl+C 取消后的提示
+const CANCELLED_NOTE: &str = "(Note) Cancelled. Token counts may be out of date until the next change or /copy.";
+
+/// 进入多行编辑时显示的提示
+const MULTILINE_HINT: &str = "Press Ctrl+S (or Ctrl+Enter) to submit, or type :submit on its own line; Ctrl+C cancels.";
+
+/// 自定义Validator：回车时最后一行若是 :submit => 视为完成，否则换行。
+/// Ctrl+S / Ctrl+Enter 绑定的是 ReedlineEvent::Submit，不经过 validator
 pub struct SubmitValidator;
 
 impl Validator for SubmitValidator {
     fn validate(&self, content: &str) -> ValidationResult {
-        if content.lines().last().map_or(false, |l| l.trim() == ":submit") {
+        if content.lines().last().is_some_and(|l| l.trim() == SUBMIT_SENTINEL) {
             ValidationResult::Complete
         } else {
             ValidationResult::Incomplete
@@ -40,144 +64,290 @@ impl Validator for SubmitValidator {
     }
 }
 
+/// 多行编辑提交的内容: 去掉结尾的 `:submit` 行 (如果有)，其余原样保留
+fn strip_submit_sentinel(buffer: &str) -> String {
+    let mut lines: Vec<&str> = buffer.lines().collect();
+    if lines.last().is_some_and(|l| l.trim() == SUBMIT_SENTINEL) {
+        lines.pop();
+    }
+    lines.join("\n")
+}
+
+/// 按设置的键位 (/set edit_mode) 创建编辑模式，再加上内置和配置文件中的绑定 (keys::apply)。
+/// vi 的 normal 和 insert 模式使用同样的绑定
+fn build_edit_mode(mode: EditMode, editor: KeyEditor, custom: &[KeyBinding]) -> Box<dyn reedline::EditMode> {
+    match mode {
+        EditMode::Emacs => {
+            let mut kb = default_emacs_keybindings();
+            keys::apply(&mut kb, custom, editor);
+            Box::new(Emacs::new(kb))
+        }
+        EditMode::Vi => {
+            let mut insert = default_vi_insert_keybindings();
+            keys::apply(&mut insert, custom, editor);
+            let mut normal = default_vi_normal_keybindings();
+            keys::apply(&mut normal, custom, editor);
+            Box::new(Vi::new(insert, normal))
+        }
+    }
+}
+
+/// 按 Ctrl+C 后等待命令自行停止的时间，超时则直接丢弃
+const CANCEL_GRACE: std::time::Duration = std::time::Duration::from_secs(1);
+
+/// 单行编辑器: 输入命令，带补全菜单和提示 (hinter)
+fn single_line_editor(
+    app_state: &Arc<Mutex<AppState>>,
+    history: &ProjectHistory,
+    external_printer: &ExternalPrinter<String>,
+) -> Reedline {
+    // 1. 创建 Completer, 传入 app_state
+    let completer = Box::new(CmdPromptCompleter::new(app_state.clone()));
+
+    // 2. 创建菜单 (用于显示补全)，并命名
+    let completion_menu = Box::new(ColumnarMenu::default().with_name("completion_menu"));
+
+    // 3. 按设置创建 Emacs 或 Vi 编辑模式 (Tab 触发菜单，配置文件可以修改绑定)
+    let edit_mode = {
+        let st = app_state.lock().unwrap();
+        build_edit_mode(st.settings.edit_mode, KeyEditor::Single, &st.keybindings)
+    };
+
+    // 4. 创建 Reedline 实例，并配置所有组件
+    Reedline::create()
+        .with_external_printer(external_printer.clone())
+        .with_history(Box::new(history.clone()))
+        .with_hinter(Box::new(CmdHinter::new(app_state.clone()))) // 历史和 /remove 路径的行内提示
+        .with_highlighter(Box::new(CmdHighlighter::new(app_state.clone()))) // 只在单行编辑器中高亮命令和路径
+        .with_completer(completer) // Use the new completer instance
+        .with_menu(ReedlineMenu::EngineCompleter(completion_menu)) // 注册菜单
+        .with_edit_mode(edit_mode) // 注册编辑模式 (包含自定义的 Tab 绑定)
+        // 没有 validator: 回车即提交
+}
+
+/// 多行编辑器: 编辑 prompt (/prompt)，回车换行，`:submit` 行或 Ctrl+S 提交；没有行内提示和高亮
+fn multiline_editor(
+    app_state: &Arc<Mutex<AppState>>,
+    history: &ProjectHistory,
+    external_printer: &ExternalPrinter<String>,
+) -> Reedline {
+    // 准备 keybinding (默认禁用 Tab，Ctrl+S / Ctrl+Enter 提交)
+    let edit_mode = {
+        let st = app_state.lock().unwrap();
+        build_edit_mode(st.settings.edit_mode, KeyEditor::Multiline, &st.keybindings)
+    };
+    Reedline::create()
+        .with_external_printer(external_printer.clone())
+        .with_history(Box::new(history.clone()))
+        .with_edit_mode(edit_mode)
+        .with_validator(Box::new(SubmitValidator))
+        .with_completer(Box::new(CmdPromptCompleter::new(app_state.clone())))
+}
+
+/// REPL 执行命令时的输出: 与 TerminalOutput 相同，确认提示的回答通过单行编辑器读取
+struct ReplOutput<'a> {
+    editor: &'a mut Reedline,
+    history: &'a ProjectHistory,
+}
+
+impl Output for ReplOutput<'_> {
+    fn emit(&mut self, level: Level, text: String) {
+        TerminalOutput.emit(level, text);
+    }
+
+    fn is_interactive(&self) -> bool {
+        TerminalOutput.is_interactive()
+    }
+
+    fn confirm(&mut self, question: &str, default: bool) -> bool {
+        let mut input = EditorInput { editor: self.editor, history: self.history };
+        confirm_with(&mut input, question, default, &mut |note| TerminalOutput.info(note))
+    }
+}
+
+/// 用编辑器读取确认提示的回答 (可以编辑、Ctrl+C 取消)；读取期间暂停记录历史，回答不会出现在上下键和 Ctrl+R 中
+struct EditorInput<'a> {
+    editor: &'a mut Reedline,
+    history: &'a ProjectHistory,
+}
+
+impl ConfirmInput for EditorInput<'_> {
+    fn is_terminal(&self) -> bool {
+        std::io::stdin().is_terminal()
+    }
+
+    fn read_answer(&mut self, prompt: &str) -> Option<String> {
+        self.history.set_recording(false);
+        let sig = self.editor.read_line(&QuestionPrompt(prompt.to_string()));
+        self.history.set_recording(true);
+        match sig {
+            Ok(Signal::Success(answer)) => Some(answer),
+            Ok(_) => None,
+            Err(e) => {
+                debug!("Failed to read a confirmation answer: {:?}", e);
+                None
+            }
+        }
+    }
+}
+
 pub struct ReplEngine {
-    /// reedline 编辑器实例
+    /// 单行编辑器 (输入命令)
     editor: Reedline,
+    /// 多行编辑器 (/prompt)。两个编辑器启动时各创建一次，切换模式时不重建，共享同一份历史
+    multiline_editor: Reedline,
     /// 全局共享状态
     app_state: Arc<Mutex<AppState>>,
     /// 动态提示符
     prompt: CmdPrompt,
     /// 是否正在运行，用于控制循环退出
     running: bool,
+    /// 后台任务 (如定时刷新) 在不打乱输入行的前提下输出信息
+    external_printer: ExternalPrinter<String>,
+    /// 跨会话保存的命令历史；单行和多行编辑器共享同一份
+    history: ProjectHistory,
     // [MODIFIED] 使用 state.rs 中的 editor_mode
     // editor_mode: ReplEditorMode, // <- 移到 AppState
 }
 
 impl ReplEngine {
     pub fn new(app_state: Arc<Mutex<AppState>>) -> Self {
-        // 1. 创建 Completer, 传入 app_state
-        let completer = Box::new(CmdPromptCompleter {
-             app_state: app_state.clone(), // <-- Pass AppState here
-        });
-
-        // 2. 创建菜单 (用于显示补全)，并命名
-        let completion_menu = Box::new(ColumnarMenu::default().with_name("completion_menu"));
-
-        // 3. 配置键位绑定 (让 Tab 触发菜单)
-        let mut keybindings = default_emacs_keybindings();
-        keybindings.add_binding(
-            KeyModifiers::NONE, // 无需修饰键 (如 Shift, Ctrl)
-            KeyCode::Tab,       // Tab 键
-            ReedlineEvent::UntilFound(vec![ // 尝试一系列事件直到成功
-                ReedlineEvent::Menu("completion_menu".to_string()), // 保持菜单名称引用，内部会处理
-                ReedlineEvent::MenuNext, // 如果菜单已打开，则选择下一项
-            ]),
-        );
-
-        // 4. 创建 Emacs 编辑模式，并传入修改后的键位绑定
-        let edit_mode = Box::new(Emacs::new(keybindings));
-
-        // 5. 创建 Reedline 实例，并配置所有组件 (初始为单行模式)
-        let editor = Reedline::create()
-            .with_completer(completer) // Use the new completer instance
-            .with_menu(ReedlineMenu::EngineCompleter(completion_menu)) // 注册菜单
-            .with_edit_mode(edit_mode); // 注册编辑模式 (包含自定义的 Tab 绑定)
-            // .with_validator(Box::new(DefaultValidator::new())) // 默认不需要显式设置 Validator
+        let external_printer = ExternalPrinter::default();
+        let history = ProjectHistory::load(app_state.clone());
+        let editor = single_line_editor(&app_state, &history, &external_printer);
+        let multiline_editor = multiline_editor(&app_state, &history, &external_printer);
 
         // 创建 Prompt 对象
-        let prompt = CmdPrompt {
-            app_state: app_state.clone(),
-        };
+        let prompt = CmdPrompt::new(app_state.clone());
 
         Self {
             editor,
+            multiline_editor,
             app_state,
             prompt,
             running: true,
+            external_printer,
+            history,
             // editor_mode: ReplEditorMode::SingleLine, // <- 状态移至 AppState
         }
     }
 
-    /// 进入多行编辑模式
+    /// 进入多行编辑模式: 切换到多行编辑器，并预填充已有的 prompt
     pub fn enter_multiline_mode(&mut self) -> Result<(), AppError> {
-        // 更新 AppState.editor_mode
-        {
+        // 更新 AppState.editor_mode，读取已有 prompt_text
+        let existing_prompt = {
             let mut st = self.app_state.lock().unwrap();
             st.editor_mode = ReplEditorMode::MultiLine;
-        }
-
-        // 准备 keybinding
-        let mut kb = default_emacs_keybindings();
-        // 禁用Tab
-        kb.add_binding(
-            KeyModifiers::NONE,
-            KeyCode::Tab,
-            ReedlineEvent::None,
-        );
-        let edit_mode = Box::new(Emacs::new(kb));
-
-        // 重新创建 editor
-        self.editor = Reedline::create()
-            .with_edit_mode(edit_mode)
-            .with_validator(Box::new(SubmitValidator))
-            .with_completer(Box::new(CmdPromptCompleter {
-                app_state: self.app_state.clone(),
-            }));
-
-        // 读取已有 prompt_text
-        let existing_prompt = {
-            let st = self.app_state.lock().unwrap();
             st.prompt_text.clone()
         };
+
+        // 多行编辑器一直保留，取消编辑时缓冲区里可能留着上次的内容
+        self.multiline_editor.run_edit_commands(&[EditCommand::Clear]);
         if !existing_prompt.is_empty() {
             // 预填充编辑器缓冲区
-            self.editor.run_edit_commands(&[EditCommand::InsertString(existing_prompt)]);
+            self.multiline_editor.run_edit_commands(&[EditCommand::InsertString(existing_prompt)]);
         }
+        TerminalOutput.info(format!("(Note) Entering multiline edit mode. {}", MULTILINE_HINT));
 
         Ok(())
     }
 
-    /// [NEW] 退出多行模式 (恢复单行配置)
-    fn exit_multiline_mode(&mut self) {
-         debug!("Exiting multiline mode...");
-         { // 更新 AppState 中的模式
+    /// 提交多行编辑: 缓冲区内容 (去掉 `:submit` 行) 替换原来的 prompt 并回到单行编辑器，返回新的 prompt
+    fn submit_multiline(&mut self, buffer: &str) -> String {
+        let final_text = strip_submit_sentinel(buffer);
+        {
             let mut st = self.app_state.lock().unwrap();
-            st.editor_mode = ReplEditorMode::SingleLine;
-         }
-
-        let mut kb = default_emacs_keybindings();
-        kb.add_binding(
-            KeyModifiers::NONE,
-            KeyCode::Tab,
-            ReedlineEvent::UntilFound(vec![
-                ReedlineEvent::Menu("completion_menu".to_string()),
-                ReedlineEvent::MenuNext,
-            ]),
-        );
-        let edit_mode = Box::new(Emacs::new(kb)); // 默认单行
-
-        // 恢复单行的 Completer 和 Menu
-        let completer = Box::new(CmdPromptCompleter {
-            app_state: self.app_state.clone(),
-        });
-        let completion_menu = Box::new(ColumnarMenu::default().with_name("completion_menu"));
-
-        // 重新配置 editor, 移除 validator (或使用默认), 恢复 completer/menu
-        self.editor = Reedline::create()
-            .with_edit_mode(edit_mode)
-            .with_completer(completer)
-            .with_menu(ReedlineMenu::EngineCompleter(completion_menu))
-            // .with_validator(Box::new(DefaultValidator::new())) // 不需要显式移除或设置默认 Validator
-            // 没有 .with_multiline(), 依赖 validator
-            ;
+            st.prompt_text = final_text.clone();
+            st.recalc_prompt_tokens();
+            TerminalOutput.info(format!("(Note) Prompt updated:\n{}", st.prompt_text));
+        }
+        self.exit_multiline_mode();
+        final_text
+    }
+
+    /// 退出多行模式，之后的输入回到单行编辑器
+    pub fn exit_multiline_mode(&mut self) {
+        debug!("Exiting multiline mode...");
+        self.app_state.lock().unwrap().editor_mode = ReplEditorMode::SingleLine;
+    }
+
+    /// 当前模式 (单行或多行) 的编辑器使用的历史；两个编辑器共享同一份，切换模式不会丢失
+    ///
+    /// ```
+    /// use std::sync::{Arc, Mutex};
+    /// use reedline::HistoryItem;
+    /// use rustprompt::{app::state::AppState, repl::engine::ReplEngine};
+    ///
+    /// let data = std::env::temp_dir().join(format!("rustprompt-doc-history-{}", std::process::id()));
+    /// std::env::set_var("XDG_DATA_HOME", &data);
+    /// let mut engine = ReplEngine::new(Arc::new(Mutex::new(AppState::new())));
+    /// let before = engine.history().count_all()?;
+    /// engine.history_mut().save(HistoryItem::from_command_line("/add src"))?;
+    /// for _ in 0..3 {
+    ///     engine.enter_multiline_mode()?;
+    ///     assert_eq!(engine.history().count_all()?, before + 1);
+    ///     engine.exit_multiline_mode();
+    /// }
+    /// assert_eq!(engine.history().count_all()?, before + 1);
+    /// std::fs::remove_dir_all(&data).ok();
+    /// # Ok::<(), Box<dyn std::error::Error>>(())
+    /// ```
+    pub fn history(&self) -> &dyn History {
+        self.active_editor().history()
+    }
+
+    pub fn history_mut(&mut self) -> &mut dyn History {
+        match self.editor_mode() {
+            ReplEditorMode::MultiLine => self.multiline_editor.history_mut(),
+            ReplEditorMode::SingleLine => self.editor.history_mut(),
+        }
+    }
+
+    fn editor_mode(&self) -> ReplEditorMode {
+        self.app_state.lock().unwrap().editor_mode
+    }
+
+    fn active_editor(&self) -> &Reedline {
+        match self.editor_mode() {
+            ReplEditorMode::MultiLine => &self.multiline_editor,
+            ReplEditorMode::SingleLine => &self.editor,
+        }
+    }
+
+    /// 按当前设置重新创建两个编辑器 (只在修改 edit_mode 或键位绑定后)；历史是共享的，不受影响
+    fn rebuild_editors(&mut self) {
+        self.editor = single_line_editor(&self.app_state, &self.history, &self.external_printer);
+        self.multiline_editor = multiline_editor(&self.app_state, &self.history, &self.external_printer);
     }
 
+    /// 进入主循环之前执行启动命令 (on_start、--source、-c)，与依次输入这些命令相同，提示符第一次显示时
+    /// 已经反映执行结果。strict 时在第一条失败的命令处停止并返回 false (调用方不再启动 REPL)，
+    /// 否则报告失败后继续执行
+    pub async fn run_startup(&mut self, source: &str, lines: Vec<ScriptLine>, strict: bool) -> bool {
+        let cancel = CancellationToken::new();
+        let result = executor::run_script(source, lines, !strict, self.app_state.clone(), &cancel, &mut TerminalOutput).await;
+        let effect = match result {
+            Ok(effect) => effect,
+            Err(e) => {
+                TerminalOutput.error(format!("✘ {}", executor::error_message(&e)));
+                return false;
+            }
+        };
+        if let Err(e) = self.apply_effect(effect) {
+            TerminalOutput.error(format!("✘ {}", executor::error_message(&e)));
+        }
+        true
+    }
 
     /// 运行主循环
     pub async fn run(&mut self) -> Result<()> {
         while self.running {
-            // 读取用户输入，传入 Prompt
-            let sig = self.editor.read_line(&self.prompt);
+            // 读取用户输入，传入 Prompt (按当前模式使用单行或多行编辑器)
+            let editor = match self.editor_mode() {
+                ReplEditorMode::MultiLine => &mut self.multiline_editor,
+                ReplEditorMode::SingleLine => &mut self.editor,
+            };
+            let sig = editor.read_line(&self.prompt);
 
             match sig {
                 Ok(Signal::Success(buffer)) => {
@@ -188,21 +358,11 @@ impl ReplEngine {
                     };
 
                     if is_multiline {
-                        // 处理多行编辑
-                        let mut lines: Vec<&str> = buffer.lines().collect();
-                        if let Some(last) = lines.last() {
-                            if last.trim() == ":submit" {
-                                lines.pop();
-                                let final_text = lines.join("\n");
-                                {
-                                    let mut st = self.app_state.lock().unwrap();
-                                    st.prompt_text = final_text;
-                                    println!("(提示) 多行编辑提交完毕，新的 prompt:\n{}", st.prompt_text);
-                                }
-                                self.exit_multiline_mode();
-                                continue;
-                            }
-                        }
+                        // 处理多行编辑: 回车 (以 :submit 结尾) 或 Ctrl+S 提交
+                        let final_text = self.submit_multiline(&buffer);
+                        // 与单行输入一样，提到的 @path 自动加入上下文
+                        self.run_command(Command::AddMentions(final_text)).await;
+                        continue;
                     }
 
                     // --- 处理单行模式下的输入 ---
@@ -214,6 +374,24 @@ impl ReplEngine {
                         continue;
                     }
 
+                    // !! 和 !<n>: 换成之前执行过的命令，先打印出来
+                    let expanded = parser::expand_history(&buffer, &self.app_state.lock().unwrap().command_log);
+                    let buffer = match expanded {
+                        None => buffer,
+                        Some(Ok(line)) => {
+                            TerminalOutput.info(format!("(Note) Rerunning: {}", line));
+                            line
+                        }
+                        Some(Err(e)) => {
+                            TerminalOutput.warn(format!("(Warning) {}", e));
+                            continue;
+                        }
+                    };
+                    // 记录命令 (未展开别名的原文)；prompt 文本和其它非命令输入不记录
+                    if buffer.starts_with('/') {
+                        self.app_state.lock().unwrap().command_log.push(buffer.trim().to_string());
+                    }
+
                     // 如果当前模式是 Prompt 并且没有以'/'开头，就当做 AppendPromptText
                     let mut is_prompt_input = false;
                     let current_repl_mode = { // 获取当前的 REPL 模式 (Manual/Prompt)
@@ -228,37 +406,49 @@ impl ReplEngine {
 
                     if is_prompt_input {
                         let cmd = Command::AppendPromptText(buffer);
-                        if let Err(e) = executor::execute(cmd, self.app_state.clone(), self).await {
-                            eprintln!("执行命令时出错: {}", e);
-                        }
+                        self.run_command(cmd).await;
                         continue; // 跳过常规 parse()
                     }
 
+                    // 用户别名在解析和模式检查之前展开
+                    let buffer = if buffer.starts_with('/') {
+                        let expanded = parser::expand_aliases(&buffer, &self.app_state.lock().unwrap().aliases);
+                        match expanded {
+                            Ok(line) => line,
+                            Err(e) => {
+                                TerminalOutput.error(e);
+                                continue;
+                            }
+                        }
+                    } else {
+                        buffer
+                    };
+
+                    // Manual 模式下粘贴/拖拽进来的裸路径: 询问是否直接添加
+                    // (绝对路径同样以 '/' 开头，所以只要不是已知命令就检查一下)
+                    let is_known_command = buffer.starts_with('/')
+                        && !matches!(parser::parse(&buffer), Ok(Command::Unknown(_)));
+                    if current_repl_mode == ReplMode::Manual && !is_known_command {
+                        if let Some(path) = parser::parse_pasted_path(&buffer) {
+                            debug!("Detected pasted path: {:?}", path);
+                            let mut out = ReplOutput { editor: &mut self.editor, history: &self.history };
+                            if out.confirm("Detected a path, add to context?", true) {
+                                let cmd = Command::Add(vec![path], AddOptions::default());
+                                self.run_command(cmd).await;
+                            }
+                            continue;
+                        }
+                    }
+
                     // 否则，正常解析命令
                     match parser::parse(&buffer) {
                         Ok(cmd) => {
-                             debug!("Parsed command: {:?}", cmd);
-                             
-                             // --- 特殊处理 /prompt 命令以进入多行模式 ---
-                             if matches!(&cmd, Command::Prompt) && current_repl_mode == ReplMode::Prompt {
-                                 debug!("Detected /prompt command in Prompt mode. Entering multiline edit.");
-                                 // 不通过 executor 执行，直接在这里切换模式
-                                 self.enter_multiline_mode()?;
-                                 continue; // 进入下一轮循环，等待多行输入
-                             }
-
-                            // --- 对于其他命令，正常执行 ---
-                            if let Err(e) = executor::execute(cmd.clone(), self.app_state.clone(), self).await {
-                                eprintln!("执行命令时出错: {}", e);
-                            }
-                            // 特殊处理 Quit 命令以停止循环
-                            if matches!(cmd, Command::Quit) {
-                                debug!("Quit command received. Stopping REPL.");
-                                self.running = false;
-                            }
+                            debug!("Parsed command: {:?}", cmd);
+                            // /prompt、/quit 等需要 REPL 配合的命令由 executor 返回 Effect，在 run_command 中处理
+                            self.run_command(cmd).await;
                         }
                         Err(e) => {
-                            eprintln!("命令解析错误: {}", e);
+                            TerminalOutput.error(format!("Invalid command: {}", executor::error_message(&e)));
                         }
                     }
                 }
@@ -270,28 +460,204 @@ impl ReplEngine {
                     };
                     if editor_mode == ReplEditorMode::MultiLine {
                          // 在多行模式下按 Ctrl+C/D，应该取消编辑并返回单行模式
-                         println!("(Note) Multiline editing cancelled.");
+                         TerminalOutput.info("(Note) Multiline editing cancelled.".to_string());
                          self.exit_multiline_mode();
                          // 不退出程序，继续循环
                     } else {
-                        // 在单行模式下按 Ctrl+C/D，退出程序
-                        println!("Bye!");
-                        self.running = false;
+                        // 在单行模式下按 Ctrl+C/D: 与 /quit 相同 (未保存的 prompt 会先确认)
+                        self.run_command(Command::Quit(false)).await;
+                        // 输入不是终端时无法回答确认，继续读取只会再次得到 EOF
+                        if self.running && !std::io::stdin().is_terminal() {
+                            self.running = false;
+                        }
                     }
                 }
                 Err(e) => {
-                    eprintln!("读取输入时出错: {:?}", e);
+                    let e = AppError::General(e.into());
+                    TerminalOutput.error(format!("Failed to read input: {}", executor::error_message(&e)));
                     self.running = false;
                 }
             }
         }
 
+        let mut st = self.app_state.lock().unwrap();
+        let usage_on = st.settings.usage_stats;
+        st.usage.finish(usage_on);
         Ok(())
     }
 
+    /// 执行一条命令 (包括 prompt 文本和粘贴的路径): 所有命令都经过这里，使用统计也在这里记录
+    async fn run_command(&mut self, cmd: Command) {
+        let usage_on = self.app_state.lock().unwrap().settings.usage_stats;
+        let produces_payload = match &cmd {
+            Command::Copy(..) => true,
+            Command::Export(path, _) => !path.as_os_str().is_empty(),
+            _ => false,
+        };
+        if usage_on {
+            self.app_state.lock().unwrap().usage.record_command(executor::command_name(&cmd));
+        }
+
+        // 执行期间按 Ctrl+C: 先触发取消令牌，让检查它的命令 (例如 /add) 自行停止并保持上下文不变
+        // (文件已经加入、正在计算 token 时取消，则保留上一次的 payload 和 token 数)；
+        // CANCEL_GRACE 内没有结束的命令直接丢弃执行中的 future 回到提示符，已写入的状态保留，
+        // 下一次修改或 /copy 时重新计算
+        let cancel = CancellationToken::new();
+        let result = {
+            let mut out = ReplOutput { editor: &mut self.editor, history: &self.history };
+            let exec = executor::execute(cmd, self.app_state.clone(), &cancel, &mut out);
+            tokio::pin!(exec);
+            tokio::select! {
+                result = &mut exec => result,
+                _ = tokio::signal::ctrl_c() => {
+                    cancel.cancel();
+                    match tokio::time::timeout(CANCEL_GRACE, &mut exec).await {
+                        Ok(result) => result,
+                        Err(_) => {
+                            TerminalOutput.info(CANCELLED_NOTE.to_string());
+                            return;
+                        }
+                    }
+                }
+            }
+        };
+        match result {
+            Ok(effect) => {
+                if usage_on && produces_payload {
+                    let mut st = self.app_state.lock().unwrap();
+                    let tokens = st.token_count + st.prompt_tokens;
+                    st.usage.record_payload(tokens);
+                }
+                if let Err(e) = self.apply_effect(effect) {
+                    self.report_failure(&e);
+                }
+            }
+            Err(AppError::Cancelled) => TerminalOutput.info(CANCELLED_NOTE.to_string()),
+            Err(e) => self.report_failure(&e),
+        }
+        if usage_on {
+            self.app_state.lock().unwrap().usage.flush_if_due();
+        }
+    }
+
+    /// 处理 executor 返回的、需要 REPL 自己完成的动作
+    fn apply_effect(&mut self, effect: Effect) -> Result<(), AppError> {
+        match effect {
+            Effect::None => {}
+            Effect::EnterMultiline => self.enter_multiline_mode()?,
+            Effect::Quit => {
+                debug!("Quit command received. Stopping REPL.");
+                self.running = false;
+            }
+            Effect::StartAutoRefresh(secs) => {
+                let printer = self.external_printer.clone();
+                let task = SnippetManager::spawn_auto_refresh(self.app_state.clone(), secs, move |msg| {
+                    let _ = printer.print(msg);
+                });
+                self.app_state.lock().unwrap().auto_refresh_task = Some(task);
+            }
+            Effect::RebuildEditor => self.rebuild_editors(),
+            Effect::StartWatch => {
+                let printer = self.external_printer.clone();
+                let handle = watch::spawn(self.app_state.clone(), move |msg| {
+                    let _ = printer.print(msg);
+                })?;
+                self.app_state.lock().unwrap().watch = Some(handle);
+            }
+        }
+        Ok(())
+    }
+
+    /// 命令执行失败: 状态栏开启时打印红色 ✘ 摘要，否则保留原来的错误输出
+    fn report_failure(&self, e: &AppError) {
+        let footer_enabled = self.app_state.lock().unwrap().settings.status_footer;
+        if footer_enabled {
+            eprintln!("{}", failure_footer(&executor::error_message(e)));
+        } else {
+            TerminalOutput.error(format!("Command failed: {}", executor::error_message(e)));
+        }
+    }
+
     /// 提供给外部的方式，让其他逻辑可触发退出
     #[allow(dead_code)]
     pub fn stop(&mut self) {
         self.running = false;
     }
-} 
\ No newline at end of file
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// 历史文件写到临时目录，不碰用户的数据目录
+    fn engine_with_prompt(prompt: &str) -> ReplEngine {
+        std::env::set_var("XDG_DATA_HOME", std::env::temp_dir().join(format!("rustprompt-engine-tests-{}", std::process::id())));
+        let mut st = AppState::new();
+        st.prompt_text = prompt.to_string();
+        ReplEngine::new(Arc::new(Mutex::new(st)))
+    }
+
+    fn prompt_text(engine: &ReplEngine) -> String {
+        engine.app_state.lock().unwrap().prompt_text.clone()
+    }
+
+    #[test]
+    fn multiline_editor_opens_with_the_prompt_and_cursor_at_the_end() {
+        let mut engine = engine_with_prompt("Explain this code.\nKeep it short.");
+        engine.enter_multiline_mode().unwrap();
+        assert_eq!(engine.multiline_editor.current_buffer_contents(), "Explain this code.\nKeep it short.");
+        assert_eq!(engine.multiline_editor.current_insertion_point(), "Explain this code.\nKeep it short.".len());
+    }
+
+    #[test]
+    fn submit_replaces_the_prompt() {
+        let mut engine = engine_with_prompt("Explain this code.");
+        engine.enter_multiline_mode().unwrap();
+        engine.multiline_editor.run_edit_commands(&[EditCommand::InsertString("\nMention tests.\n:submit".to_string())]);
+        let buffer = engine.multiline_editor.current_buffer_contents().to_string();
+        assert_eq!(engine.submit_multiline(&buffer), "Explain this code.\nMention tests.");
+        assert_eq!(prompt_text(&engine), "Explain this code.\nMention tests.");
+        assert_eq!(engine.editor_mode(), ReplEditorMode::SingleLine);
+    }
+
+    #[test]
+    fn cancelled_edit_keeps_the_prompt_and_is_not_prefilled_again() {
+        let mut engine = engine_with_prompt("Explain this code.");
+        engine.enter_multiline_mode().unwrap();
+        engine.multiline_editor.run_edit_commands(&[EditCommand::InsertString(" Half-typed".to_string())]);
+        // Ctrl+C 只退出多行模式
+        engine.exit_multiline_mode();
+        assert_eq!(prompt_text(&engine), "Explain this code.");
+
+        engine.enter_multiline_mode().unwrap();
+        assert_eq!(engine.multiline_editor.current_buffer_contents(), "Explain this code.");
+    }
+
+    #[test]
+    fn empty_prompt_opens_an_empty_editor() {
+        let mut engine = engine_with_prompt("");
+        engine.enter_multiline_mode().unwrap();
+        assert_eq!(engine.multiline_editor.current_buffer_contents(), "");
+    }
+
+    #[test]
+    fn usage_is_recorded_only_when_enabled() {
+        let mut engine = engine_with_prompt("");
+        let dir = tempfile::tempdir().unwrap();
+        let runtime = tokio::runtime::Runtime::new().unwrap();
+        let usage = |engine: &ReplEngine| engine.app_state.lock().unwrap().usage.report();
+
+        runtime.block_on(engine.run_command(Command::Add(vec!["Cargo.toml".into()], Default::default())));
+        runtime.block_on(engine.run_command(Command::ShowContext));
+        runtime.block_on(engine.run_command(Command::Export(dir.path().join("off.xml"), false)));
+        assert!(usage(&engine).is_empty(), "{:?}", usage(&engine));
+
+        engine.app_state.lock().unwrap().settings.usage_stats = true;
+        runtime.block_on(engine.run_command(Command::ShowContext));
+        runtime.block_on(engine.run_command(Command::Export(dir.path().join("on.xml"), false)));
+        let stats = usage(&engine);
+        assert_eq!(stats.commands.get("/context"), Some(&1));
+        assert_eq!(stats.commands.get("/export"), Some(&1));
+        assert_eq!(stats.payload_count(), 1);
+    }
+}
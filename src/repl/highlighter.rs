@@ -0,0 +1,160 @@
+// src/repl/highlighter.rs
+
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use nu_ansi_term::{Color, Style};
+use reedline::{Highlighter, StyledText};
+
+use crate::app::{state::AppState, style};
+use crate::command::{parser, registry};
+
+/// 路径是否存在的结果在这段时间内不重新检查 (每次按键都会重新高亮整行)
+const STAT_TTL: Duration = Duration::from_secs(2);
+/// 缓存的路径数超过这个值时清空，避免长时间运行后无限增长
+const STAT_CACHE_LIMIT: usize = 256;
+
+/// 单行编辑器的语法高亮: 已知命令 (包括别名) 绿色，未知命令红色；
+/// /add 的路径参数存在时加下划线，不存在时红色。关闭颜色时 (NO_COLOR、--no-color、/set color off) 不加样式
+pub struct CmdHighlighter {
+    app_state: Arc<Mutex<AppState>>,
+    /// 路径 -> (检查时间, 是否存在)
+    stat_cache: Mutex<HashMap<PathBuf, (Instant, bool)>>,
+}
+
+/// 一段输入的样式
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Segment {
+    Plain,
+    Command,
+    UnknownCommand,
+    Path,
+    MissingPath,
+}
+
+impl Segment {
+    fn style(self) -> Style {
+        match self {
+            Segment::Plain => Style::new(),
+            Segment::Command => Style::new().fg(Color::Green),
+            Segment::UnknownCommand => Style::new().fg(Color::Red),
+            Segment::Path => Style::new().underline(),
+            Segment::MissingPath => Style::new().fg(Color::Red),
+        }
+    }
+}
+
+impl CmdHighlighter {
+    pub fn new(app_state: Arc<Mutex<AppState>>) -> Self {
+        Self { app_state, stat_cache: Mutex::new(HashMap::new()) }
+    }
+
+    fn path_exists(&self, path: PathBuf) -> bool {
+        let mut cache = self.stat_cache.lock().unwrap();
+        if let Some((at, exists)) = cache.get(&path) {
+            if at.elapsed() < STAT_TTL {
+                return *exists;
+            }
+        }
+        if cache.len() >= STAT_CACHE_LIMIT {
+            cache.clear();
+        }
+        let exists = path.exists();
+        cache.insert(path, (Instant::now(), exists));
+        exists
+    }
+}
+
+/// 把一行输入分成带样式的片段 (字节范围)，拆分方式与 parser::tokenize 相同。
+/// 不以 `/` 开头的行 (prompt 文本) 不加样式；is_alias 判断用户定义的别名，exists 判断 /add 的路径
+///
+/// ```
+/// use rustprompt::repl::highlighter::{segments, Segment};
+///
+/// let line = "/add src \"my file.rs\" --ext rs";
+/// let parts = segments(line, |_| false, |p| p == "src");
+/// let styled: Vec<(&str, Segment)> = parts.iter().map(|(r, s)| (&line[r.clone()], *s)).collect();
+/// assert_eq!(styled, [
+///     ("/add", Segment::Command),
+///     (" ", Segment::Plain),
+///     ("src", Segment::Path),
+///     (" ", Segment::Plain),
+///     ("\"my file.rs\"", Segment::MissingPath),
+///     (" --ext rs", Segment::Plain),
+/// ]);
+/// assert_eq!(segments("/cpy", |_| false, |_| true)[0].1, Segment::UnknownCommand);
+/// assert_eq!(segments("/asrc x", |a| a == "/asrc", |_| true)[0].1, Segment::Command);
+/// assert_eq!(segments("some prompt text", |_| false, |_| true), [(0..16, Segment::Plain)]);
+/// ```
+pub fn segments(
+    line: &str,
+    is_alias: impl Fn(&str) -> bool,
+    mut exists: impl FnMut(&str) -> bool,
+) -> Vec<(std::ops::Range<usize>, Segment)> {
+    let mut styled: Vec<(std::ops::Range<usize>, Segment)> = Vec::new();
+    if line.starts_with('/') {
+        let tokens = parser::tokenize_spans(line);
+        let mut tokens = tokens.iter();
+        if let Some(cmd) = tokens.next() {
+            let known = registry::find(&cmd.value).is_some() || is_alias(&cmd.value);
+            styled.push((cmd.start..cmd.end, if known { Segment::Command } else { Segment::UnknownCommand }));
+            if registry::canonical(&cmd.value) == "/add" {
+                // 与 parser::split_add_flags 相同: --ext 的值不是路径，`--` 之后全部是路径
+                let mut only_paths = false;
+                while let Some(arg) = tokens.next() {
+                    let value = arg.value.as_str();
+                    if !only_paths && value == "--ext" {
+                        tokens.next();
+                        continue;
+                    }
+                    if !only_paths && value == "--" {
+                        only_paths = true;
+                        continue;
+                    }
+                    let is_flag = !only_paths && value.starts_with('-');
+                    // glob 在执行时才展开，不检查
+                    let is_glob = value.contains(['*', '?', '[']);
+                    if is_flag || is_glob {
+                        continue;
+                    }
+                    let segment = if exists(value) { Segment::Path } else { Segment::MissingPath };
+                    styled.push((arg.start..arg.end, segment));
+                }
+            }
+        }
+    }
+
+    // 补上没有样式的部分 (空白、其它参数)，片段连起来就是整行
+    let mut parts = Vec::new();
+    let mut pos = 0;
+    for (range, segment) in styled {
+        if range.start > pos {
+            parts.push((pos..range.start, Segment::Plain));
+        }
+        pos = range.end;
+        parts.push((range, segment));
+    }
+    if pos < line.len() {
+        parts.push((pos..line.len(), Segment::Plain));
+    }
+    parts
+}
+
+impl Highlighter for CmdHighlighter {
+    fn highlight(&self, line: &str, _cursor: usize) -> StyledText {
+        let mut text = StyledText::new();
+        if !style::enabled() || !line.starts_with('/') {
+            text.push((Style::new(), line.to_string()));
+            return text;
+        }
+        let aliases = self.app_state.lock().unwrap().aliases.clone();
+        let cwd = std::env::current_dir().unwrap_or_else(|_| PathBuf::from("."));
+        let parts = segments(line, |name| aliases.contains_key(name), |value| self.path_exists(cwd.join(Path::new(value))));
+        for (range, segment) in parts {
+            text.push((segment.style(), line[range].to_string()));
+        }
+        text
+    }
+}
@@ -0,0 +1,103 @@
+// src/repl/hinter.rs
+
+use std::sync::{Arc, Mutex};
+
+use reedline::{Hinter, History, SearchQuery};
+
+use crate::app::{
+    state::AppState,
+    style::{self, Style},
+};
+use crate::command::registry;
+use crate::core::paths;
+
+/// 单行编辑器的行内提示 (灰色显示，按 Right / End 接受):
+///  - `/remove ` 之后提示最近一次 /add 且仍在上下文中的路径
+///  - 其它输入提示以它开头的最近一条历史
+///
+/// `/set hints off` (或配置文件的 `hints = false`) 关闭。关闭颜色时也不显示，否则提示看起来和输入的文字一样。
+/// 多行编辑器不使用 hinter
+pub struct CmdHinter {
+    app_state: Arc<Mutex<AppState>>,
+    /// 当前提示 (不带样式)，接受时插入
+    current_hint: String,
+}
+
+impl CmdHinter {
+    pub fn new(app_state: Arc<Mutex<AppState>>) -> Self {
+        Self { app_state, current_hint: String::new() }
+    }
+
+    /// 最近一次 /add 的参数中仍有文件在上下文里的那个，按 /context 的方式显示 (相对于项目根目录)
+    fn recent_root(&self) -> Option<String> {
+        let st = self.app_state.lock().unwrap();
+        st.add_roots
+            .iter()
+            .filter(|root| root.files.iter().any(|f| st.selected_paths.contains(f)))
+            .last()
+            .map(|root| paths::display_path(&root.path, &st.project_root).display().to_string())
+    }
+}
+
+/// `/remove` (或 `/rm`) 正在输入第一个参数时，recent 中还没输入的部分
+///
+/// ```
+/// use rustprompt::repl::hinter::remove_hint;
+///
+/// assert_eq!(remove_hint("/remove ", "src/app").as_deref(), Some("src/app"));
+/// assert_eq!(remove_hint("/rm sr", "src/app").as_deref(), Some("c/app"));
+/// assert_eq!(remove_hint("/remove docs", "src/app"), None);
+/// assert_eq!(remove_hint("/remove src/app", "src/app"), None);
+/// assert_eq!(remove_hint("/remove a.rs ", "src/app"), None);
+/// assert_eq!(remove_hint("/remove", "src/app"), None);
+/// ```
+pub fn remove_hint(line: &str, recent: &str) -> Option<String> {
+    let (cmd, arg) = line.split_once(' ')?;
+    if registry::canonical(cmd) != "/remove" || arg.contains(char::is_whitespace) {
+        return None;
+    }
+    recent
+        .strip_prefix(arg)
+        .filter(|rest| !rest.is_empty())
+        .map(str::to_string)
+}
+
+impl Hinter for CmdHinter {
+    fn handle(&mut self, line: &str, pos: usize, history: &dyn History, use_ansi_coloring: bool, _cwd: &str) -> String {
+        let enabled = self.app_state.lock().unwrap().settings.hints && style::enabled();
+        // 只在光标位于行尾时提示
+        self.current_hint = if !enabled || line.is_empty() || pos < line.len() {
+            String::new()
+        } else {
+            // 只有输入 /remove 时才查找最近添加的路径
+            let removing = line.split_once(' ').is_some_and(|(cmd, _)| registry::canonical(cmd) == "/remove");
+            removing
+                .then(|| self.recent_root())
+                .flatten()
+                .and_then(|recent| remove_hint(line, &recent))
+                .or_else(|| {
+                    let query = SearchQuery::last_with_prefix(line.to_string(), history.session());
+                    let found = history.search(query).ok()?;
+                    found.first().and_then(|entry| entry.command_line.get(line.len()..)).map(str::to_string)
+                })
+                .unwrap_or_default()
+        };
+        if use_ansi_coloring && !self.current_hint.is_empty() {
+            style::paint(Style::Dim, &self.current_hint)
+        } else {
+            self.current_hint.clone()
+        }
+    }
+
+    fn complete_hint(&self) -> String {
+        self.current_hint.clone()
+    }
+
+    /// 按词接受 (Ctrl+Right): 到下一个空白或路径分隔符为止
+    fn next_hint_token(&self) -> String {
+        let hint = self.current_hint.as_str();
+        let start = hint.len() - hint.trim_start().len();
+        let end = hint[start..].find([' ', '/']).map_or(hint.len(), |i| start + i + 1);
+        hint[..end].to_string()
+    }
+}
@@ -0,0 +1,454 @@
+// src/repl/history.rs
+
+use std::fs::{self, OpenOptions};
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+
+use reedline::{
+    CommandLineSearch, History, HistoryItem, HistoryItemId, HistorySessionId, ReedlineError,
+    ReedlineErrorVariants, SearchDirection, SearchQuery,
+};
+
+use crate::{
+    app::{settings::HistoryScope, state::AppState},
+    command::parser,
+    core::{dirs, files_scanner},
+};
+
+/// 带项目标记的历史文件的首行；没有这一行的文件是旧的纯文本格式 (每行一条命令)
+const HEADER: &str = "#rustprompt-history v2";
+/// 与 reedline 的 FileBackedHistory 相同的换行转义，旧文件可以直接读入
+const NEWLINE_ESCAPE: &str = "<\\n>";
+
+/// 一条历史记录及其记录时所在的项目
+#[derive(Debug, Clone)]
+struct HistoryEntry {
+    /// 项目根目录 (绝对路径)；从旧格式迁移来的条目为空字符串
+    project: String,
+    command: String,
+    /// 来自其它项目 (或未知项目)，且引用了当前目录下不存在的路径
+    foreign_paths: bool,
+}
+
+/// 按项目标记的持久化历史，供 reedline 的上下键、提示 (hinter) 和 Ctrl+R 搜索使用。
+///
+/// 对外的条目顺序 (即 HistoryItemId) 是一个视图: 当前项目的条目排在最后 (最先被找到)，
+/// 引用了不存在路径的外部条目按 `/set history_scope` 降低优先级或隐藏。
+/// 单行和多行两个编辑器 (以及修改键位后重建的编辑器) 各持有一个 clone，内部用 Arc 共享同一份历史。
+/// 文件超过 `/set history_capacity` 时在下次启动时截断。
+#[derive(Clone)]
+pub struct ProjectHistory {
+    inner: Arc<Mutex<Inner>>,
+    app_state: Arc<Mutex<AppState>>,
+}
+
+struct Inner {
+    entries: Vec<HistoryEntry>,
+    file: Option<PathBuf>,
+    project: String,
+    /// 为 false 时 save 不记录 (确认提示的回答经过同一个编辑器读取，不应进入历史)
+    recording: bool,
+}
+
+impl ProjectHistory {
+    /// 从用户数据目录下的历史文件加载；旧的纯文本格式会被自动改写为带项目标记的格式 (内容不丢失)
+    pub fn load(app_state: Arc<Mutex<AppState>>) -> Self {
+        let project = project_root().to_string_lossy().to_string();
+        let capacity = app_state.lock().unwrap().settings.history_capacity;
+        let file = history_file();
+        let mut entries = Vec::new();
+        if let Some(path) = &file {
+            match read_history_file(path, &project) {
+                Ok((loaded, migrated)) => {
+                    entries = loaded;
+                    if migrated || entries.len() > capacity {
+                        entries.drain(..entries.len().saturating_sub(capacity));
+                        if let Err(e) = write_history_file(path, &entries) {
+                            log::warn!("改写历史文件失败: {:?}", e);
+                        }
+                    }
+                }
+                Err(e) if e.kind() == std::io::ErrorKind::NotFound => {}
+                Err(e) => log::warn!("读取历史文件失败: {:?}", e),
+            }
+        }
+        Self::with_entries(app_state, entries, file, project)
+    }
+
+    fn with_entries(
+        app_state: Arc<Mutex<AppState>>,
+        entries: Vec<HistoryEntry>,
+        file: Option<PathBuf>,
+        project: String,
+    ) -> Self {
+        Self {
+            inner: Arc::new(Mutex::new(Inner { entries, file, project, recording: true })),
+            app_state,
+        }
+    }
+
+    /// 暂停或恢复记录新条目；所有 clone 共享这个开关
+    pub fn set_recording(&self, on: bool) {
+        self.inner.lock().unwrap().recording = on;
+    }
+
+    fn scope(&self) -> HistoryScope {
+        self.app_state.lock().unwrap().settings.history_scope
+    }
+
+    fn capacity(&self) -> usize {
+        self.app_state.lock().unwrap().settings.history_capacity
+    }
+
+    /// 对外的条目顺序: 下标即 HistoryItemId，值为 entries 中的位置
+    fn view(inner: &Inner, scope: HistoryScope) -> Vec<usize> {
+        let all = 0..inner.entries.len();
+        match scope {
+            HistoryScope::All => all.collect(),
+            HistoryScope::Project => all.filter(|i| !inner.entries[*i].foreign_paths).collect(),
+            HistoryScope::Prefer => {
+                // 被降级的条目放在最前面，向后 (向上键) 搜索时最后才遇到
+                let (demoted, preferred): (Vec<usize>, Vec<usize>) =
+                    all.partition(|i| inner.entries[*i].foreign_paths);
+                demoted.into_iter().chain(preferred).collect()
+            }
+        }
+    }
+
+    fn item(id: usize, entry: &HistoryEntry) -> HistoryItem {
+        let mut item = HistoryItem::from_command_line(entry.command.clone());
+        item.id = Some(HistoryItemId::new(id as i64));
+        if !entry.project.is_empty() {
+            item.cwd = Some(entry.project.clone());
+        }
+        item
+    }
+}
+
+impl History for ProjectHistory {
+    fn save(&mut self, h: HistoryItem) -> reedline::Result<HistoryItem> {
+        let scope = self.scope();
+        let capacity = self.capacity();
+        let mut inner = self.inner.lock().unwrap();
+        let command = h.command_line;
+        // 空行 (包括只有空白的行) 和暂停记录期间的输入不记录
+        if !inner.recording || command.trim().is_empty() || inner.entries.last().is_some_and(|e| e.command == command) {
+            return Ok(HistoryItem::from_command_line(command));
+        }
+
+        let entry = HistoryEntry {
+            project: inner.project.clone(),
+            command,
+            foreign_paths: false,
+        };
+        if let Some(path) = &inner.file {
+            if let Err(e) = append_history_line(path, &entry) {
+                log::warn!("写入历史文件失败: {:?}", e);
+            }
+        }
+        inner.entries.push(entry);
+        if inner.entries.len() > capacity {
+            let excess = inner.entries.len() - capacity;
+            inner.entries.drain(..excess);
+        }
+
+        // 新条目属于当前项目，总是在视图的最后
+        let id = Self::view(&inner, scope).len() - 1;
+        let entry = inner.entries.last().unwrap();
+        Ok(Self::item(id, entry))
+    }
+
+    fn load(&self, id: HistoryItemId) -> reedline::Result<HistoryItem> {
+        let scope = self.scope();
+        let inner = self.inner.lock().unwrap();
+        let view = Self::view(&inner, scope);
+        view.get(id.0 as usize)
+            .map(|i| Self::item(id.0 as usize, &inner.entries[*i]))
+            .ok_or(ReedlineError(ReedlineErrorVariants::OtherHistoryError("Item does not exist")))
+    }
+
+    fn count(&self, query: SearchQuery) -> reedline::Result<i64> {
+        Ok(self.search(query)?.len() as i64)
+    }
+
+    /// 与 FileBackedHistory 相同的语义 (start_id / end_id 为开区间)，另外支持按项目 (cwd) 过滤。
+    /// reedline 没有公开 not_command_line，上下键可能连续遇到内容相同 (但不相邻) 的条目
+    fn search(&self, query: SearchQuery) -> reedline::Result<Vec<HistoryItem>> {
+        if query.start_time.is_some() || query.end_time.is_some() {
+            return Err(unsupported("filtering by time"));
+        }
+        if query.filter.hostname.is_some() || query.filter.exit_successful.is_some() {
+            return Err(unsupported("filtering by extra info"));
+        }
+
+        let scope = self.scope();
+        let inner = self.inner.lock().unwrap();
+        let view = Self::view(&inner, scope);
+
+        let (min_id, max_id) = {
+            let start = query.start_id.map(|e| e.0);
+            let end = query.end_id.map(|e| e.0);
+            match query.direction {
+                SearchDirection::Backward => (end, start),
+                SearchDirection::Forward => (start, end),
+            }
+        };
+        let min_id = min_id.map(|e| e + 1).unwrap_or(0).max(0) as usize;
+        let max_id = max_id.map(|e| e - 1).unwrap_or(view.len() as i64 - 1);
+        if max_id < 0 || min_id >= view.len() {
+            return Ok(Vec::new());
+        }
+        let max_id = (max_id as usize).min(view.len() - 1);
+        let limit = query.limit.map(|l| l.max(0) as usize).unwrap_or(usize::MAX);
+
+        let filter = &query.filter;
+        let matches = |entry: &HistoryEntry| {
+            let command_ok = match &filter.command_line {
+                Some(CommandLineSearch::Prefix(p)) => entry.command.starts_with(p),
+                Some(CommandLineSearch::Substring(p)) => entry.command.contains(p),
+                Some(CommandLineSearch::Exact(p)) => entry.command == *p,
+                None => true,
+            };
+            command_ok
+                && filter.cwd_exact.as_ref().is_none_or(|c| *c == entry.project)
+                && filter.cwd_prefix.as_ref().is_none_or(|c| entry.project.starts_with(c.as_str()))
+        };
+
+        let ids = min_id..=max_id;
+        let found = |id: usize| {
+            let entry = &inner.entries[view[id]];
+            matches(entry).then(|| Self::item(id, entry))
+        };
+        Ok(match query.direction {
+            SearchDirection::Backward => ids.rev().filter_map(found).take(limit).collect(),
+            SearchDirection::Forward => ids.filter_map(found).take(limit).collect(),
+        })
+    }
+
+    fn update(&mut self, _id: HistoryItemId, _updater: &dyn Fn(HistoryItem) -> HistoryItem) -> reedline::Result<()> {
+        Err(unsupported("updating entries"))
+    }
+
+    fn clear(&mut self) -> reedline::Result<()> {
+        let mut inner = self.inner.lock().unwrap();
+        inner.entries.clear();
+        if let Some(path) = &inner.file {
+            write_history_file(path, &[]).map_err(|e| ReedlineError(ReedlineErrorVariants::IOError(e)))?;
+        }
+        Ok(())
+    }
+
+    fn delete(&mut self, _h: HistoryItemId) -> reedline::Result<()> {
+        Err(unsupported("removing entries"))
+    }
+
+    /// save 时已经写入磁盘
+    fn sync(&mut self) -> std::io::Result<()> {
+        Ok(())
+    }
+
+    fn session(&self) -> Option<HistorySessionId> {
+        None
+    }
+}
+
+fn unsupported(feature: &'static str) -> ReedlineError {
+    ReedlineError(ReedlineErrorVariants::HistoryFeatureUnsupported {
+        history: "ProjectHistory",
+        feature,
+    })
+}
+
+/// 历史文件: 用户数据目录下的 `history`
+fn history_file() -> Option<PathBuf> {
+    dirs::data_dir().map(|dir| dir.join("history"))
+}
+
+/// 当前项目的根目录: 向上找到的第一个含 `.git` 的目录，找不到时为当前目录
+fn project_root() -> PathBuf {
+    let cwd = std::env::current_dir().unwrap_or_else(|_| PathBuf::from("."));
+    cwd.ancestors()
+        .find(|dir| dir.join(".git").exists())
+        .map(Path::to_path_buf)
+        .unwrap_or(cwd)
+}
+
+/// 读取历史文件，返回 (条目, 是否为需要迁移的旧格式)
+fn read_history_file(path: &Path, project: &str) -> std::io::Result<(Vec<HistoryEntry>, bool)> {
+    let text = fs::read_to_string(path)?;
+    let mut lines = text.lines().peekable();
+    let legacy = lines.peek().is_some_and(|first| *first != HEADER);
+    if !legacy {
+        lines.next();
+    }
+
+    let entries = lines
+        .filter(|l| !l.is_empty())
+        .map(|line| {
+            let (entry_project, command) = match line.split_once('\t') {
+                Some((p, c)) if !legacy => (p.to_string(), c),
+                _ => (String::new(), line),
+            };
+            let command = command.replace(NEWLINE_ESCAPE, "\n");
+            let foreign_paths = entry_project != project && references_missing_path(&command);
+            HistoryEntry { project: entry_project, command, foreign_paths }
+        })
+        .collect();
+    Ok((entries, legacy))
+}
+
+fn write_history_file(path: &Path, entries: &[HistoryEntry]) -> std::io::Result<()> {
+    if let Some(dir) = path.parent() {
+        fs::create_dir_all(dir)?;
+    }
+    let mut text = format!("{}\n", HEADER);
+    for entry in entries {
+        text.push_str(&encode_line(entry));
+    }
+    fs::write(path, text)
+}
+
+fn append_history_line(path: &Path, entry: &HistoryEntry) -> std::io::Result<()> {
+    if !path.exists() {
+        return write_history_file(path, std::slice::from_ref(entry));
+    }
+    let mut file = OpenOptions::new().append(true).open(path)?;
+    file.write_all(encode_line(entry).as_bytes())
+}
+
+fn encode_line(entry: &HistoryEntry) -> String {
+    format!("{}\t{}\n", entry.project, entry.command.replace('\n', NEWLINE_ESCAPE))
+}
+
+/// `/add`、`/remove`、`/mute` 的参数中是否有当前目录下不存在的路径 (glob 无法判断，视为存在)
+fn references_missing_path(command: &str) -> bool {
+    let tokens = parser::tokenize(command);
+    let Some((cmd, args)) = tokens.split_first() else {
+        return false;
+    };
+    matches!(cmd.as_str(), "/add" | "/remove" | "/mute")
+        && args.iter().any(|a| !files_scanner::is_glob(a) && !Path::new(a).exists())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// 当前项目 /a 与另一个项目 /b 混在一起的历史文件；here 是确实存在的文件
+    fn multi_project(dir: &Path, here: &Path) -> PathBuf {
+        let here = here.display();
+        let text = format!(
+            "{HEADER}\n\
+             /b\t/add /missing/b.rs\n\
+             /a\t/add {here}\n\
+             /b\t/add {here}\n\
+             /b\t/add src/**/*.rs\n\
+             /b\t/model gpt-4o\n\
+             /a\t/add /missing/a.rs\n\
+             /b\t/remove /missing/c.rs\n"
+        );
+        let path = dir.join("history");
+        fs::write(&path, text).unwrap();
+        path
+    }
+
+    fn history(path: &Path, scope: HistoryScope) -> ProjectHistory {
+        let (entries, migrated) = read_history_file(path, "/a").unwrap();
+        assert!(!migrated);
+        let mut st = AppState::new();
+        st.settings.history_scope = scope;
+        ProjectHistory::with_entries(Arc::new(Mutex::new(st)), entries, Some(path.to_path_buf()), "/a".to_string())
+    }
+
+    /// 向上键依次遇到的命令
+    fn upwards(history: &ProjectHistory) -> Vec<String> {
+        let items = history.search(SearchQuery::everything(SearchDirection::Backward, None)).unwrap();
+        items.into_iter().map(|item| item.command_line).collect()
+    }
+
+    /// 与 hinter 相同的查询: 以 prefix 开头的最近一条
+    fn hint(history: &ProjectHistory, prefix: &str) -> Option<String> {
+        let items = history.search(SearchQuery::last_with_prefix(prefix.to_string(), None)).unwrap();
+        items.into_iter().next().map(|item| item.command_line)
+    }
+
+    #[test]
+    fn only_foreign_entries_with_missing_paths_are_flagged() {
+        let dir = tempfile::tempdir().unwrap();
+        let here = dir.path().join("here.rs");
+        fs::write(&here, "").unwrap();
+        let (entries, _) = read_history_file(&multi_project(dir.path(), &here), "/a").unwrap();
+        let flags: Vec<bool> = entries.iter().map(|e| e.foreign_paths).collect();
+        // 当前项目的条目、路径存在的、glob 和不带路径的命令都不降级
+        assert_eq!(flags, [true, false, false, false, false, false, true]);
+        assert_eq!(entries[1].project, "/a");
+        assert_eq!(entries[2].project, "/b");
+    }
+
+    #[test]
+    fn prefer_scope_moves_foreign_paths_behind_everything_else() {
+        let dir = tempfile::tempdir().unwrap();
+        let here = dir.path().join("here.rs");
+        fs::write(&here, "").unwrap();
+        let path = multi_project(dir.path(), &here);
+        let here = here.display();
+
+        let prefer = history(&path, HistoryScope::Prefer);
+        assert_eq!(
+            upwards(&prefer),
+            [
+                "/add /missing/a.rs".to_string(),
+                "/model gpt-4o".to_string(),
+                "/add src/**/*.rs".to_string(),
+                format!("/add {}", here),
+                format!("/add {}", here),
+                "/remove /missing/c.rs".to_string(),
+                "/add /missing/b.rs".to_string(),
+            ]
+        );
+        assert_eq!(hint(&prefer, "/").as_deref(), Some("/add /missing/a.rs"));
+        assert_eq!(hint(&prefer, "/re").as_deref(), Some("/remove /missing/c.rs"));
+
+        let all = history(&path, HistoryScope::All);
+        assert_eq!(hint(&all, "/").as_deref(), Some("/remove /missing/c.rs"));
+        assert_eq!(upwards(&all).len(), 7);
+    }
+
+    #[test]
+    fn project_scope_hides_foreign_paths() {
+        let dir = tempfile::tempdir().unwrap();
+        let here = dir.path().join("here.rs");
+        fs::write(&here, "").unwrap();
+        let project = history(&multi_project(dir.path(), &here), HistoryScope::Project);
+        let commands = upwards(&project);
+        assert_eq!(commands.len(), 5, "{:?}", commands);
+        assert!(!commands.iter().any(|c| c.contains("/missing/b.rs") || c.contains("/missing/c.rs")));
+        assert_eq!(hint(&project, "/re"), None);
+
+        // Ctrl+R 按项目过滤时只剩 /a 的条目
+        let query = SearchQuery::last_with_prefix_and_cwd("/add".to_string(), "/a".to_string(), None);
+        let query = SearchQuery { limit: None, ..query };
+        let items = project.search(query).unwrap();
+        assert_eq!(items.len(), 2);
+        assert!(items.iter().all(|item| item.cwd.as_deref() == Some("/a")));
+    }
+
+    #[test]
+    fn saved_entries_are_tagged_and_legacy_files_migrate_without_loss() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("history");
+        fs::write(&path, "/add /missing/old.rs\n/copy<\\n>twice\n").unwrap();
+        let (entries, migrated) = read_history_file(&path, "/a").unwrap();
+        assert!(migrated);
+        write_history_file(&path, &entries).unwrap();
+
+        let mut history = history(&path, HistoryScope::Prefer);
+        history.save(HistoryItem::from_command_line("/context")).unwrap();
+        let (entries, _) = read_history_file(&path, "/a").unwrap();
+        let saved: Vec<(&str, &str, bool)> =
+            entries.iter().map(|e| (e.project.as_str(), e.command.as_str(), e.foreign_paths)).collect();
+        // 旧条目没有项目标记，引用了不存在路径时按外部条目处理
+        assert_eq!(saved, [("", "/add /missing/old.rs", true), ("", "/copy\ntwice", false), ("/a", "/context", false)]);
+    }
+}
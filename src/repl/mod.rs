@@ -1,3 +1,8 @@
 pub mod engine;
 pub mod prompt;
-pub mod completion; 
\ No newline at end of file
+pub mod completion;
+pub mod dashboard;
+pub mod history;
+pub mod highlighter;
+pub mod hinter;
+pub mod picker;
@@ -0,0 +1,236 @@
+// src/repl/picker.rs
+
+use std::collections::HashSet;
+use std::io::{self, Stdout};
+use std::path::PathBuf;
+
+use ratatui::{
+    backend::CrosstermBackend,
+    crossterm::{
+        event::{self, Event, KeyCode, KeyEventKind, KeyModifiers},
+        execute,
+        terminal::{disable_raw_mode, enable_raw_mode, EnterAlternateScreen, LeaveAlternateScreen},
+    },
+    layout::{Constraint, Direction, Layout},
+    style::{Color, Modifier, Style},
+    text::{Line, Span},
+    widgets::{Block, Borders, List, ListItem, ListState, Paragraph},
+    Frame, Terminal,
+};
+
+use crate::{app::output::refresh_terminal_width, error::AppError};
+
+/// 候选文件
+#[derive(Debug, Clone, PartialEq)]
+pub struct Candidate {
+    /// 交给 /add 的路径
+    pub path: PathBuf,
+    /// 列表中显示的路径 (相对于项目根目录)
+    pub label: String,
+    /// 已经在上下文中
+    pub selected: bool,
+}
+
+/// 与终端无关的选择器状态: 输入的过滤文本、过滤结果和已勾选的候选
+#[derive(Debug, Default)]
+pub struct PickerState {
+    candidates: Vec<Candidate>,
+    query: String,
+    /// 匹配 query 的候选下标，按匹配得分排序
+    filtered: Vec<usize>,
+    marked: HashSet<usize>,
+}
+
+impl PickerState {
+    pub fn new(candidates: Vec<Candidate>) -> Self {
+        let mut picker = Self { candidates, ..Self::default() };
+        picker.refilter();
+        picker
+    }
+
+    pub fn push_char(&mut self, c: char) {
+        self.query.push(c);
+        self.refilter();
+    }
+
+    pub fn pop_char(&mut self) {
+        self.query.pop();
+        self.refilter();
+    }
+
+    /// 勾选 / 取消勾选过滤结果中的第 row 项
+    pub fn toggle(&mut self, row: usize) {
+        if let Some(&i) = self.filtered.get(row) {
+            if !self.marked.remove(&i) {
+                self.marked.insert(i);
+            }
+        }
+    }
+
+    /// 确认选择: 有勾选时返回全部勾选项 (按列表顺序)，否则返回当前行
+    pub fn chosen(&self, row: Option<usize>) -> Vec<PathBuf> {
+        if self.marked.is_empty() {
+            return row
+                .and_then(|r| self.filtered.get(r))
+                .map(|&i| vec![self.candidates[i].path.clone()])
+                .unwrap_or_default();
+        }
+        let mut marked: Vec<usize> = self.marked.iter().copied().collect();
+        marked.sort_unstable();
+        marked.into_iter().map(|i| self.candidates[i].path.clone()).collect()
+    }
+
+    fn refilter(&mut self) {
+        let query = self.query.to_lowercase();
+        if query.is_empty() {
+            self.filtered = (0..self.candidates.len()).collect();
+            return;
+        }
+        let mut scored: Vec<(i64, usize)> = self
+            .candidates
+            .iter()
+            .enumerate()
+            .filter_map(|(i, c)| fuzzy_score(&query, &c.label).map(|score| (score, i)))
+            .collect();
+        scored.sort_by(|a, b| b.0.cmp(&a.0).then(a.1.cmp(&b.1)));
+        self.filtered = scored.into_iter().map(|(_, i)| i).collect();
+    }
+}
+
+/// 模糊匹配: query (已小写) 的字符按顺序出现在 text 中即匹配。
+/// 连续匹配、在路径分隔符或 `_`、`-`、`.` 之后的匹配得分更高，文件名部分的匹配再加分；不匹配返回 None
+pub fn fuzzy_score(query: &str, text: &str) -> Option<i64> {
+    let file_start = text.rfind('/').map(|i| i + 1).unwrap_or(0);
+    let mut score = 0;
+    let mut prev: Option<usize> = None;
+    let mut chars = text.char_indices();
+    for q in query.chars() {
+        let (pos, _) = chars.find(|(_, c)| c.to_lowercase().eq(std::iter::once(q)))?;
+        score += 1;
+        if pos > 0 && prev.is_some_and(|p| text[p..pos].chars().count() == 1) {
+            score += 5;
+        }
+        if pos == 0 || matches!(text.as_bytes()[pos - 1], b'/' | b'_' | b'-' | b'.') {
+            score += 3;
+        }
+        if pos >= file_start {
+            score += 2;
+        }
+        prev = Some(pos);
+    }
+    // 同等得分时更短的路径更靠前
+    Some(score * 1000 - text.len() as i64)
+}
+
+/// 离开选择器时 (包括出错返回) 恢复终端
+struct TerminalGuard;
+
+impl Drop for TerminalGuard {
+    fn drop(&mut self) {
+        let _ = disable_raw_mode();
+        let _ = execute!(io::stdout(), LeaveAlternateScreen);
+    }
+}
+
+/// 打开全屏选择器: 输入过滤，空格 / Tab 勾选，Enter 确认，Esc 取消 (返回 None)
+pub fn run(candidates: Vec<Candidate>) -> Result<Option<Vec<PathBuf>>, AppError> {
+    enable_raw_mode().map_err(io_error)?;
+    let _guard = TerminalGuard;
+    execute!(io::stdout(), EnterAlternateScreen).map_err(io_error)?;
+    let mut terminal = Terminal::new(CrosstermBackend::new(io::stdout())).map_err(io_error)?;
+    event_loop(&mut terminal, PickerState::new(candidates)).map_err(io_error)
+}
+
+fn event_loop(
+    terminal: &mut Terminal<CrosstermBackend<Stdout>>,
+    mut picker: PickerState,
+) -> io::Result<Option<Vec<PathBuf>>> {
+    let mut list_state = ListState::default();
+    loop {
+        if picker.filtered.is_empty() {
+            list_state.select(None);
+        } else {
+            let i = list_state.selected().unwrap_or(0).min(picker.filtered.len() - 1);
+            list_state.select(Some(i));
+        }
+        terminal.draw(|f| draw(f, &picker, &mut list_state))?;
+
+        let key = match event::read()? {
+            Event::Key(key) if key.kind == KeyEventKind::Press => key,
+            Event::Resize(..) => {
+                refresh_terminal_width();
+                continue;
+            }
+            _ => continue,
+        };
+        match key.code {
+            KeyCode::Esc => return Ok(None),
+            KeyCode::Char('c') if key.modifiers.contains(KeyModifiers::CONTROL) => return Ok(None),
+            KeyCode::Enter => return Ok(Some(picker.chosen(list_state.selected()))),
+            KeyCode::Down => list_state.select_next(),
+            KeyCode::Up => list_state.select_previous(),
+            KeyCode::Char(' ') | KeyCode::Tab => {
+                if let Some(row) = list_state.selected() {
+                    picker.toggle(row);
+                    list_state.select_next();
+                }
+            }
+            KeyCode::Backspace => {
+                picker.pop_char();
+                list_state.select(Some(0));
+            }
+            KeyCode::Char(c) => {
+                picker.push_char(c);
+                list_state.select(Some(0));
+            }
+            _ => {}
+        }
+    }
+}
+
+fn draw(f: &mut Frame, picker: &PickerState, list_state: &mut ListState) {
+    let outer = Layout::default()
+        .direction(Direction::Vertical)
+        .constraints([Constraint::Length(3), Constraint::Min(3), Constraint::Length(1)])
+        .split(f.area());
+
+    let query = Paragraph::new(format!("> {}", picker.query))
+        .block(Block::default().borders(Borders::ALL).title(" Filter "));
+    f.render_widget(query, outer[0]);
+
+    // 只构建可见范围附近的行，项目很大时也不会每次重绘都处理全部候选
+    let height = outer[1].height as usize;
+    let limit = list_state.selected().unwrap_or(0) + height;
+    let items: Vec<ListItem> = picker
+        .filtered
+        .iter()
+        .take(limit)
+        .map(|&i| {
+            let candidate = &picker.candidates[i];
+            let mark = if picker.marked.contains(&i) { "[x] " } else { "[ ] " };
+            let mut spans = vec![Span::styled(mark, Style::default().fg(Color::Cyan)), Span::raw(candidate.label.clone())];
+            if candidate.selected {
+                spans.push(Span::styled(" (in context)", Style::default().fg(Color::DarkGray)));
+            }
+            ListItem::new(Line::from(spans))
+        })
+        .collect();
+    let title = format!(
+        " Files ({}/{}, {} marked) ",
+        picker.filtered.len(),
+        picker.candidates.len(),
+        picker.marked.len()
+    );
+    let list = List::new(items)
+        .block(Block::default().borders(Borders::ALL).title(title))
+        .highlight_style(Style::default().add_modifier(Modifier::REVERSED));
+    f.render_stateful_widget(list, outer[1], list_state);
+
+    let help = Paragraph::new("type to filter · ↑/↓ move · space/tab mark · enter add · esc cancel")
+        .style(Style::default().fg(Color::DarkGray));
+    f.render_widget(help, outer[2]);
+}
+
+fn io_error(e: io::Error) -> AppError {
+    AppError::General(anyhow::anyhow!("file picker terminal error: {}", e))
+}
@@ -1,41 +1,171 @@
 use std::borrow::Cow;
+use std::path::PathBuf;
 use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
 
-use reedline::{Prompt, PromptEditMode, PromptHistorySearch};
-use crate::app::state::{AppState, ReplMode};
+use reedline::{Color, Prompt, PromptEditMode, PromptHistorySearch, PromptViMode};
+use crate::app::{
+    output::{format_budget, format_token_count, BUDGET_WARN_PERCENT},
+    state::{AppState, ReplEditorMode, ReplMode},
+    style::{self, paint, Style},
+};
+use crate::core::git::{self, GitStatus};
+
+/// 多行编辑时显示在右侧的提示 (完整说明见进入多行编辑时打印的 (Note))
+const MULTILINE_RIGHT_HINT: &str = "Ctrl+S submit · Ctrl+C cancel";
+
+/// 提示符每次按键都会重绘，git 状态最多每隔这么久重新读取一次
+const GIT_STATUS_TTL: Duration = Duration::from_secs(2);
 
 pub struct CmdPrompt {
     pub app_state: Arc<Mutex<AppState>>,
+    /// 上次读取 git 状态的时间、项目根目录和结果
+    git_cache: Mutex<Option<(Instant, PathBuf, Option<GitStatus>)>>,
+}
+
+impl CmdPrompt {
+    pub fn new(app_state: Arc<Mutex<AppState>>) -> Self {
+        Self { app_state, git_cache: Mutex::new(None) }
+    }
+
+    /// 项目根目录所在仓库的分支，例如 `main*` (`*` 表示有未提交的修改)；不在 git 仓库中时为空
+    fn git_segment(&self, root: PathBuf) -> String {
+        let mut cache = self.git_cache.lock().unwrap();
+        let fresh = matches!(&*cache, Some((at, cached_root, _)) if at.elapsed() < GIT_STATUS_TTL && *cached_root == root);
+        if !fresh {
+            let status = git::status(&root);
+            *cache = Some((Instant::now(), root, status));
+        }
+        match cache.as_ref().and_then(|(_, _, status)| status.as_ref()) {
+            Some(status) => {
+                let dirty = if status.dirty { paint(Style::Yellow, "*") } else { String::new() };
+                format!("{}{}", paint(Style::Cyan, &status.branch), dirty)
+            }
+            None => String::new(),
+        }
+    }
+}
+
+/// 提示符左侧，例如 `[3] files | [1.2k/128k] tokens [manual] `
+///
+/// ```
+/// use rustprompt::app::state::{AppState, ReplEditorMode, ReplMode};
+/// use rustprompt::repl::prompt::render_left;
+///
+/// let mut st = AppState::new();
+/// st.settings.token_budget = 0;
+/// assert_eq!(render_left(&st), "[0] files | [0] tokens [manual] ");
+/// st.mode = ReplMode::Prompt;
+/// st.file_count = 3;
+/// st.token_count = 1234;
+/// assert_eq!(render_left(&st), "[3] files | [1.2k] tokens [prompt] ");
+/// st.settings.token_budget = 128_000;
+/// st.editor_mode = ReplEditorMode::MultiLine;
+/// assert_eq!(render_left(&st), "[3] files | [1.2k/128k] tokens [editing prompt] ");
+/// ```
+pub fn render_left(state: &AppState) -> String {
+    let raw_token_count = state.token_count;
+
+    // 转换 token_count 到格式化字符串 (1.2k)；/set token_breakdown on 时显示为 文件+prompt (1.2k+412)；
+    // 设置了预算时显示为 42.1k/128k
+    let mut token_str = format_token_count(raw_token_count);
+    if state.settings.token_breakdown {
+        token_str = format!("{}+{}", token_str, format_token_count(state.prompt_tokens));
+    }
+    let budget = state.settings.token_budget;
+    if budget > 0 {
+        token_str = format!("{}/{}", token_str, format_budget(budget));
+    }
+    // 启用颜色时: 文件数青色，token 数加粗，接近预算变黄，超出预算变红
+    let token_style = if budget > 0 && raw_token_count > budget {
+        Style::Red
+    } else if budget > 0 && raw_token_count * 100 > budget * BUDGET_WARN_PERCENT {
+        Style::Yellow
+    } else {
+        Style::Bold
+    };
+
+    format!(
+        "[{}] files | [{}] tokens {} ",
+        paint(Style::Cyan, &state.file_count.to_string()),
+        paint(token_style, &token_str),
+        mode_segment(state)
+    )
+}
+
+/// 当前模式: manual 变暗；prompt 模式下输入的是 prompt 文本而不是命令，用黄色提醒
+fn mode_segment(state: &AppState) -> String {
+    let (label, style) = match (state.editor_mode, &state.mode) {
+        (ReplEditorMode::MultiLine, _) => ("[editing prompt]", Style::Highlight),
+        (_, ReplMode::Prompt) => ("[prompt]", Style::Yellow),
+        (_, ReplMode::Manual) => ("[manual]", Style::Dim),
+    };
+    paint(style, label)
 }
 
 impl Prompt for CmdPrompt {
     fn render_prompt_left(&self) -> Cow<'_, str> {
-        let state = self.app_state.lock().unwrap();
-        let file_count = state.file_count;
-        let raw_token_count = state.token_count;
-        let current_mode = &state.mode;
-
-        // 转换 token_count 到格式化字符串
-        let token_str = if raw_token_count < 1000 {
-            raw_token_count.to_string()
+        Cow::Owned(render_left(&self.app_state.lock().unwrap()))
+    }
+
+    /// 颜色由 render_prompt_left 中的各部分自己决定，其余部分用终端默认颜色
+    fn get_prompt_color(&self) -> Color {
+        Color::Reset
+    }
+
+    fn get_indicator_color(&self) -> Color {
+        if style::enabled() {
+            Color::Cyan
         } else {
-            let val = raw_token_count as f64 / 1000.0;
-            format!("{:.1}k", val) // 1.2k
-        };
+            Color::Reset
+        }
+    }
+
+    fn get_prompt_right_color(&self) -> Color {
+        Color::Reset
+    }
 
-        // 根据 ReplMode 确定模式字符串
-        let mode_str = match current_mode {
-            ReplMode::Manual => "manual",
-            ReplMode::Prompt => "prompt",
+    /// 多行编辑时是提交/取消的提示，否则是 git 分支
+    fn render_prompt_right(&self) -> Cow<'_, str> {
+        let (editor_mode, root) = {
+            let st = self.app_state.lock().unwrap();
+            (st.editor_mode, st.project_root.clone())
         };
+        if editor_mode == ReplEditorMode::MultiLine {
+            Cow::Owned(paint(Style::Dim, MULTILINE_RIGHT_HINT))
+        } else {
+            Cow::Owned(self.git_segment(root))
+        }
+    }
 
-        // 使用 format! 创建 String，并包含模式，然后转换为 Cow
-        Cow::Owned(format!(
-            "[{}] files | [{}] tokens]({}) > ",
-            file_count,
-            token_str, // 使用格式化后的字符串
-            mode_str // 添加模式字符串
-        ))
+    /// vi 键位时显示当前是 normal 还是 insert 模式
+    fn render_prompt_indicator(&self, prompt_mode: PromptEditMode) -> Cow<'_, str> {
+        match prompt_mode {
+            PromptEditMode::Vi(PromptViMode::Normal) => Cow::Borrowed("[N]> "),
+            PromptEditMode::Vi(PromptViMode::Insert) => Cow::Borrowed("[I]> "),
+            _ => Cow::Borrowed("> "), // 稍微改变一下指示符
+        }
+    }
+
+    /// 多行编辑的后续行
+    fn render_prompt_multiline_indicator(&self) -> Cow<'_, str> {
+        Cow::Borrowed("… ")
+    }
+
+    fn render_prompt_history_search_indicator(
+        &self,
+        _history_search: PromptHistorySearch,
+    ) -> Cow<'_, str> {
+        Cow::Borrowed(" history search>> ")
+    }
+}
+
+/// 确认提示 (例如 `Quit anyway? [y/N] `) 读取回答时使用的提示符: 只显示问题本身
+pub struct QuestionPrompt(pub String);
+
+impl Prompt for QuestionPrompt {
+    fn render_prompt_left(&self) -> Cow<'_, str> {
+        Cow::Borrowed(&self.0)
     }
 
     fn render_prompt_right(&self) -> Cow<'_, str> {
@@ -43,17 +173,17 @@ impl Prompt for CmdPrompt {
     }
 
     fn render_prompt_indicator(&self, _prompt_mode: PromptEditMode) -> Cow<'_, str> {
-        Cow::Borrowed("> ") // 稍微改变一下指示符
+        Cow::Borrowed("")
     }
 
     fn render_prompt_multiline_indicator(&self) -> Cow<'_, str> {
-        Cow::Borrowed(". ")
+        Cow::Borrowed("")
     }
 
     fn render_prompt_history_search_indicator(
         &self,
         _history_search: PromptHistorySearch,
     ) -> Cow<'_, str> {
-        Cow::Borrowed(" history search>> ")
+        Cow::Borrowed("")
     }
-} 
\ No newline at end of file
+}
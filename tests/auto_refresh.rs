@@ -0,0 +1,61 @@
+// tests/auto_refresh.rs
+//
+// auto_refresh=before_copy: /copy 之前重新读取在磁盘上修改过的文件，不需要先 /refresh
+
+mod common;
+
+use std::fs;
+use std::path::Path;
+use std::time::{Duration, SystemTime};
+
+use common::{add, run, state_in};
+use rustprompt::command::definition::{Command, CopyOptions};
+
+/// 写入同样长度的新内容，并把 mtime 往后调，只能靠 mtime 发现变化
+fn edit_in_place(path: &Path, content: &str) {
+    assert_eq!(fs::metadata(path).unwrap().len(), content.len() as u64);
+    fs::write(path, content).unwrap();
+    let later = SystemTime::now() + Duration::from_secs(10);
+    fs::File::options().write(true).open(path).unwrap().set_modified(later).unwrap();
+}
+
+/// 复制到临时文件 (不碰系统剪贴板)；复制的内容记录在 last_copied
+fn copy() -> Command {
+    Command::Copy(CopyOptions { backend: Some("file".to_string()), ..CopyOptions::default() })
+}
+
+#[tokio::test]
+async fn copy_rereads_files_edited_since_they_were_added() {
+    let dir = tempfile::tempdir().unwrap();
+    let file = dir.path().join("a.rs");
+    let other = dir.path().join("b.rs");
+    fs::write(&file, "fn old() {}\n").unwrap();
+    fs::write(&other, "fn b() {}\n").unwrap();
+    let state = state_in(dir.path());
+    run(add(dir.path()), &state).await;
+
+    edit_in_place(&file, "fn new() {}\n");
+    run(copy(), &state).await;
+
+    let st = state.lock().unwrap();
+    let copied = st.last_copied.as_deref().unwrap();
+    assert!(copied.contains("fn new() {}"), "{}", copied);
+    assert!(!copied.contains("fn old() {}"));
+    assert!(copied.contains("fn b() {}"));
+}
+
+#[tokio::test]
+async fn copy_keeps_the_old_snippet_when_auto_refresh_is_off() {
+    let dir = tempfile::tempdir().unwrap();
+    let file = dir.path().join("a.rs");
+    fs::write(&file, "fn old() {}\n").unwrap();
+    let state = state_in(dir.path());
+    run(add(&file), &state).await;
+    run(Command::Set(Some("auto_refresh".to_string()), Some("off".to_string())), &state).await;
+
+    edit_in_place(&file, "fn new() {}\n");
+    run(copy(), &state).await;
+
+    let copied = state.lock().unwrap().last_copied.clone().unwrap();
+    assert!(copied.contains("fn old() {}"), "{}", copied);
+}
@@ -0,0 +1,91 @@
+// tests/binary_transition.rs
+//
+// 已选的文本文件在 /add 和 /refresh 之间被改写成二进制内容
+
+mod common;
+
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+
+use common::{add, run, state_in};
+use rustprompt::{
+    app::{
+        output::{CapturedOutput, Level},
+        settings::BinaryTransition,
+        state::AppState,
+    },
+    command::definition::Command,
+    core::xml::snippet_content,
+};
+
+const TEXT: &str = "build step output\nline two\n";
+
+/// 添加 out.txt，然后用二进制内容覆盖它并 /refresh
+async fn rewrite_as_binary(root: &Path, transition: BinaryTransition) -> (Arc<Mutex<AppState>>, PathBuf, CapturedOutput) {
+    let file = root.join("out.txt");
+    fs::write(&file, TEXT).unwrap();
+    let state = state_in(root);
+    state.lock().unwrap().settings.binary_transition = transition;
+    run(add(&file), &state).await;
+    assert_eq!(snippet_content(&state.lock().unwrap().partial_docs[&file]), TEXT);
+
+    let mut binary = vec![0x7f, b'E', b'L', b'F', 2, 1, 1, 0];
+    binary.extend((0..=255u8).cycle().take(4096));
+    fs::write(&file, binary).unwrap();
+    let out = run(Command::Refresh, &state).await;
+    (state, file, out)
+}
+
+fn warnings(out: &CapturedOutput) -> String {
+    out.at(Level::Warn).join("\n")
+}
+
+#[tokio::test]
+async fn placeholder_replaces_the_content() {
+    let dir = tempfile::tempdir().unwrap();
+    let (state, file, out) = rewrite_as_binary(dir.path(), BinaryTransition::Placeholder).await;
+    let st = state.lock().unwrap();
+    let content = snippet_content(&st.partial_docs[&file]);
+    assert!(content.contains("was text when it was added but now looks binary, 4104 bytes"), "{}", content);
+    assert!(!content.contains('\u{FFFD}'));
+    assert_eq!(st.binary_files.get(&file), Some(&BinaryTransition::Placeholder));
+    assert!(warnings(&out).contains("out.txt now looks binary; its content is replaced by a placeholder"), "{}", warnings(&out));
+}
+
+#[tokio::test]
+async fn keep_last_retains_the_text_version() {
+    let dir = tempfile::tempdir().unwrap();
+    let (state, file, out) = rewrite_as_binary(dir.path(), BinaryTransition::KeepLast).await;
+    {
+        let st = state.lock().unwrap();
+        assert_eq!(snippet_content(&st.partial_docs[&file]), TEXT);
+        assert_eq!(st.binary_files.get(&file), Some(&BinaryTransition::KeepLast));
+    }
+    assert!(warnings(&out).contains("keeping its last text version"), "{}", warnings(&out));
+
+    let context = run(Command::ShowContext, &state).await.text();
+    assert!(context.contains("(now binary; last text version kept)"), "{}", context);
+}
+
+#[tokio::test]
+async fn drop_removes_the_file() {
+    let dir = tempfile::tempdir().unwrap();
+    let (state, file, out) = rewrite_as_binary(dir.path(), BinaryTransition::Drop).await;
+    let st = state.lock().unwrap();
+    assert!(!st.selected_paths.contains(&file));
+    assert!(!st.partial_docs.contains_key(&file));
+    assert!(warnings(&out).contains("removed it from the context"), "{}", warnings(&out));
+}
+
+#[tokio::test]
+async fn text_again_clears_the_flag() {
+    let dir = tempfile::tempdir().unwrap();
+    let (state, file, _) = rewrite_as_binary(dir.path(), BinaryTransition::Placeholder).await;
+    fs::write(&file, "fixed\n").unwrap();
+    let out = run(Command::Refresh, &state).await;
+    let st = state.lock().unwrap();
+    assert_eq!(snippet_content(&st.partial_docs[&file]), "fixed\n");
+    assert!(!st.binary_files.contains_key(&file));
+    assert!(out.at(Level::Warn).is_empty());
+}
@@ -0,0 +1,76 @@
+// tests/cache_merged.rs
+//
+// /set cache_merged off 只影响内存占用: add → 修改文件 → /refresh 的每一步，导出的 payload 与开启缓存时逐字节相同
+
+mod common;
+
+use std::fs;
+use std::path::Path;
+use std::sync::{Arc, Mutex};
+
+use common::{add, run, state_in};
+use rustprompt::{app::state::{AppState, ReplMode}, command::definition::Command};
+
+fn set_mode(mode: ReplMode, states: [&Arc<Mutex<AppState>>; 2]) {
+    for state in states {
+        state.lock().unwrap().mode = mode.clone();
+    }
+}
+
+fn state(root: &Path, cache_merged: bool) -> Arc<Mutex<AppState>> {
+    let state = state_in(root);
+    state.lock().unwrap().settings.cache_merged = cache_merged;
+    state
+}
+
+/// 对两个状态执行同一条命令，然后比较 /export 写出的 payload
+async fn step(cmd: Command, cached: &Arc<Mutex<AppState>>, uncached: &Arc<Mutex<AppState>>, out: &Path) -> String {
+    run(cmd.clone(), cached).await;
+    run(cmd, uncached).await;
+    let (a, b) = (out.join("cached.xml"), out.join("uncached.xml"));
+    run(Command::Export(a.clone(), true), cached).await;
+    run(Command::Export(b.clone(), true), uncached).await;
+    let (a, b) = (fs::read(a).unwrap(), fs::read(b).unwrap());
+    assert_eq!(String::from_utf8_lossy(&a), String::from_utf8_lossy(&b));
+    assert_eq!(cached.lock().unwrap().token_count, uncached.lock().unwrap().token_count);
+    String::from_utf8(a).unwrap()
+}
+
+#[tokio::test]
+async fn payload_is_identical_with_and_without_the_cache() {
+    let dir = tempfile::tempdir().unwrap();
+    let exports = tempfile::tempdir().unwrap();
+    let file = dir.path().join("a.rs");
+    fs::write(&file, "fn a() {}\n").unwrap();
+    let cached = state(dir.path(), true);
+    let uncached = state(dir.path(), false);
+
+    let payload = step(add(dir.path()), &cached, &uncached, exports.path()).await;
+    assert!(payload.contains("fn a() {}"), "{}", payload);
+    assert!(!cached.lock().unwrap().cached_xml.is_empty());
+    assert!(uncached.lock().unwrap().cached_xml.is_empty());
+
+    // 普通文本行只在 Prompt 模式下追加到 prompt
+    set_mode(ReplMode::Prompt, [&cached, &uncached]);
+    let prompt = Command::AppendPromptText("Explain a & b".to_string());
+    let payload = step(prompt, &cached, &uncached, exports.path()).await;
+    set_mode(ReplMode::Manual, [&cached, &uncached]);
+    assert!(payload.contains("Explain a &amp; b"), "{}", payload);
+
+    // 大小也变化，mtime 精度较粗的文件系统上同样会被发现
+    fs::write(&file, "fn a() -> u32 { 42 }\n").unwrap();
+    let payload = step(Command::Refresh, &cached, &uncached, exports.path()).await;
+    assert!(payload.contains("u32 { 42 }"), "{}", payload);
+    assert!(!payload.contains("fn a() {}"));
+
+    fs::write(dir.path().join("b.rs"), "fn b() {}\n").unwrap();
+    let payload = step(add(dir.path().join("b.rs")), &cached, &uncached, exports.path()).await;
+    assert!(payload.contains("fn b() {}"));
+
+    // 中途关闭缓存也得到同样的内容
+    let off = Command::Set(Some("cache_merged".to_string()), Some("off".to_string()));
+    let before = payload;
+    let payload = step(off, &cached, &uncached, exports.path()).await;
+    assert_eq!(payload, before);
+    assert!(cached.lock().unwrap().cached_xml.is_empty());
+}
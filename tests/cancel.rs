@@ -0,0 +1,66 @@
+// tests/cancel.rs
+//
+// 取消正在进行的 token 计算 (REPL 中为 Ctrl+C): rebuild 与切换编码后的重新计数被取消时保留上一次的结果
+
+mod common;
+
+use std::fs;
+
+use common::{add, run, state_in};
+use rustprompt::{app::snippet_manager::SnippetManager, error::AppError};
+use tokio_util::sync::CancellationToken;
+
+fn cancelled() -> CancellationToken {
+    let cancel = CancellationToken::new();
+    cancel.cancel();
+    cancel
+}
+
+#[tokio::test]
+async fn cancelled_rebuild_keeps_the_previous_payload() {
+    let dir = tempfile::tempdir().unwrap();
+    let first = dir.path().join("first.rs");
+    let second = dir.path().join("second.rs");
+    fs::write(&first, "fn first() {}\n").unwrap();
+    fs::write(&second, "fn second() {}\n".repeat(30)).unwrap();
+    let state = state_in(dir.path());
+    run(add(&first), &state).await;
+    let (xml, tokens) = {
+        let st = state.lock().unwrap();
+        (st.cached_xml.clone(), st.token_count)
+    };
+    assert!(xml.contains("first.rs"));
+
+    // 新文件已经读入，但还没有 rebuild
+    SnippetManager::add_files_snippet(state.clone(), vec![second.clone()]).await.unwrap();
+    state.lock().unwrap().selected_paths.insert(second);
+    let err = SnippetManager::rebuild_and_recalc_with_cancel(state.clone(), &cancelled()).await.unwrap_err();
+    assert!(matches!(err, AppError::Cancelled), "{:?}", err);
+    {
+        let st = state.lock().unwrap();
+        assert_eq!(st.cached_xml, xml);
+        assert_eq!(st.token_count, tokens);
+    }
+
+    SnippetManager::rebuild_and_recalc(state.clone()).await.unwrap();
+    let st = state.lock().unwrap();
+    assert!(st.cached_xml.contains("second.rs"));
+    assert!(st.token_count > tokens);
+}
+
+#[tokio::test]
+async fn cancelled_recount_keeps_the_token_counts() {
+    let dir = tempfile::tempdir().unwrap();
+    let file = dir.path().join("a.rs");
+    fs::write(&file, "fn a() {}\n").unwrap();
+    let state = state_in(dir.path());
+    run(add(&file), &state).await;
+    state.lock().unwrap().doc_tokens.insert(file.clone(), 12345);
+
+    let err = SnippetManager::recount_tokens(state.clone(), &cancelled()).await.unwrap_err();
+    assert!(matches!(err, AppError::Cancelled), "{:?}", err);
+    assert_eq!(state.lock().unwrap().doc_tokens[&file], 12345);
+
+    SnippetManager::recount_tokens(state.clone(), &CancellationToken::new()).await.unwrap();
+    assert_ne!(state.lock().unwrap().doc_tokens[&file], 12345);
+}
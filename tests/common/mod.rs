@@ -0,0 +1,42 @@
+// tests/common/mod.rs
+//
+// 集成测试共用的辅助函数: 每个测试文件用 `mod common;` 引入，各自只用到其中一部分
+
+#![allow(dead_code)]
+
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+
+use rustprompt::{
+    app::{output::CapturedOutput, state::AppState},
+    command::{
+        definition::{AddOptions, Command},
+        executor,
+    },
+    error::AppError,
+};
+use tokio_util::sync::CancellationToken;
+
+/// 执行一条命令并返回它的输出；命令失败时 panic
+pub async fn run(cmd: Command, state: &Arc<Mutex<AppState>>) -> CapturedOutput {
+    try_run(cmd, state).await.unwrap()
+}
+
+/// 同 run，但把命令的错误返回给调用方
+pub async fn try_run(cmd: Command, state: &Arc<Mutex<AppState>>) -> Result<CapturedOutput, AppError> {
+    let mut out = CapturedOutput::default();
+    executor::execute(cmd, state.clone(), &CancellationToken::new(), &mut out).await?;
+    Ok(out)
+}
+
+/// 以 root 为项目根目录的新状态，项目树只包含 root 下的文件
+pub fn state_in(root: &Path) -> Arc<Mutex<AppState>> {
+    let mut st = AppState::new();
+    st.project_root = root.to_path_buf();
+    Arc::new(Mutex::new(st))
+}
+
+/// 使用默认选项的 `/add <path>`
+pub fn add(path: impl Into<PathBuf>) -> Command {
+    Command::Add(vec![path.into()], AddOptions::default())
+}
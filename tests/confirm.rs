@@ -0,0 +1,79 @@
+// tests/confirm.rs
+//
+// 确认提示不会等待看不见的输入: stdin 不是终端时使用默认值，--yes / --no 固定回答，采用的回答都作为提示输出
+
+mod common;
+
+use std::fs;
+use std::sync::{Mutex, MutexGuard};
+
+use common::{add, run, state_in};
+use rustprompt::{
+    app::output::{set_confirm_override, CapturedOutput, Level, Output},
+    command::definition::Command,
+};
+
+/// --yes / --no 是全局设置，同一进程中的测试依次执行
+static OVERRIDE: Mutex<()> = Mutex::new(());
+
+fn with_override(answer: Option<bool>) -> MutexGuard<'static, ()> {
+    let guard = OVERRIDE.lock().unwrap_or_else(|e| e.into_inner());
+    set_confirm_override(answer);
+    guard
+}
+
+const QUESTION: &str = "This clears 1 files and the prompt (0 tokens) from the context. Continue?";
+
+#[test]
+fn captured_output_answers_with_the_default_like_a_pipe() {
+    let _guard = with_override(None);
+    let mut out = CapturedOutput::default();
+    assert!(out.confirm("Follow?", true));
+    assert!(!out.confirm("Quit anyway?", false));
+    assert_eq!(
+        out.at(Level::Info),
+        ["(Note) Follow? -> yes (stdin is not a terminal)", "(Note) Quit anyway? -> no (stdin is not a terminal)"]
+    );
+}
+
+#[test]
+fn overrides_win_over_the_default() {
+    let _guard = with_override(Some(true));
+    let mut out = CapturedOutput::default();
+    assert!(out.confirm("Quit anyway?", false));
+    set_confirm_override(Some(false));
+    assert!(!out.confirm("Follow?", true));
+    set_confirm_override(None);
+    assert_eq!(out.at(Level::Info), ["(Note) Quit anyway? -> yes (--yes)", "(Note) Follow? -> no (--no)"]);
+}
+
+#[test]
+fn reset_is_cancelled_without_a_terminal_and_follows_the_overrides() {
+    let _guard = with_override(None);
+    // 同步测试中持有锁，异步部分在自己的运行时中执行
+    tokio::runtime::Runtime::new().unwrap().block_on(async {
+        let dir = tempfile::tempdir().unwrap();
+        let file = dir.path().join("a.txt");
+        fs::write(&file, "hello\n").unwrap();
+        let state = state_in(dir.path());
+        run(add(&file), &state).await;
+
+        // 默认回答 no: 不提问，直接取消
+        let out = run(Command::Reset(false, false), &state).await;
+        assert!(out.text().contains(&format!("(Note) {} -> no (stdin is not a terminal)", QUESTION)), "{}", out.text());
+        assert!(out.text().contains("Reset cancelled"), "{}", out.text());
+        assert_eq!(state.lock().unwrap().selected_paths.len(), 1);
+
+        set_confirm_override(Some(false));
+        let out = run(Command::Reset(false, false), &state).await;
+        assert!(out.text().contains(&format!("(Note) {} -> no (--no)", QUESTION)), "{}", out.text());
+        assert_eq!(state.lock().unwrap().selected_paths.len(), 1);
+
+        set_confirm_override(Some(true));
+        let out = run(Command::Reset(false, false), &state).await;
+        set_confirm_override(None);
+        assert!(out.text().contains(&format!("(Note) {} -> yes (--yes)", QUESTION)), "{}", out.text());
+        assert!(!out.text().contains("Reset cancelled"), "{}", out.text());
+        assert!(state.lock().unwrap().selected_paths.is_empty());
+    });
+}
@@ -0,0 +1,91 @@
+// tests/doc_ids.rs
+//
+// 文档 ID (D1、D2...) 与 payload 中的 id 属性一致: 增删文件之后重新编号，会话恢复后映射回同样的文件
+
+mod common;
+
+use std::fs;
+use std::path::PathBuf;
+use std::sync::{Arc, Mutex};
+
+use common::{add, run, state_in};
+use rustprompt::{
+    app::{snippet_manager::SnippetManager, state::AppState},
+    command::definition::Command,
+};
+
+fn ids(state: &Arc<Mutex<AppState>>) -> Vec<(String, PathBuf)> {
+    state.lock().unwrap().doc_ids.clone()
+}
+
+fn names(ids: &[(String, PathBuf)]) -> Vec<(&str, String)> {
+    ids.iter().map(|(id, p)| (id.as_str(), p.display().to_string())).collect()
+}
+
+/// payload 中 id 属性之后的 `<source>`
+fn source_in_payload(state: &Arc<Mutex<AppState>>, id: &str) -> String {
+    let payload = SnippetManager::assemble_payload(&state.lock().unwrap()).unwrap();
+    let at = payload.find(&format!(r#" id="{}">"#, id)).unwrap();
+    let rest = &payload[at..];
+    let start = rest.find("<source>").unwrap() + "<source>".len();
+    rest[start..start + rest[start..].find("</source>").unwrap()].to_string()
+}
+
+async fn which(state: &Arc<Mutex<AppState>>, id: &str) -> String {
+    run(Command::Which(id.to_string()), state).await.text()
+}
+
+#[test]
+fn doc_ids_accept_any_spelling() {
+    let mut st = AppState::new();
+    st.doc_ids = vec![("D1".to_string(), PathBuf::from("__PROJECT_TREE__")), ("D2".to_string(), PathBuf::from("a.rs"))];
+    for raw in ["D2", "d2", "2", " D2 "] {
+        assert_eq!(st.resolve_doc_id(raw), Some((2, &PathBuf::from("a.rs"))), "{:?}", raw);
+    }
+    for raw in ["D3", "D", "x2", "-2", ""] {
+        assert_eq!(st.resolve_doc_id(raw), None, "{:?}", raw);
+    }
+}
+
+/// 会话文件写在当前目录的 .rustprompt/ 下: 整个流程在临时目录中运行 (只有这一个测试改变当前目录)
+#[tokio::test]
+async fn ids_follow_the_payload_and_survive_a_session_restore() {
+    let dir = tempfile::tempdir().unwrap();
+    let previous = std::env::current_dir().unwrap();
+    std::env::set_current_dir(dir.path()).unwrap();
+    for name in ["a.rs", "b.rs", "c.rs", "d.rs"] {
+        fs::write(name, format!("// {}\n", name)).unwrap();
+    }
+    let state = state_in(dir.path());
+    for name in ["c.rs", "a.rs", "b.rs"] {
+        run(add(name), &state).await;
+    }
+    let before = ids(&state);
+    assert_eq!(names(&before), [("D1", "__PROJECT_TREE__".into()), ("D2", "a.rs".into()), ("D3", "b.rs".into()), ("D4", "c.rs".into())]);
+
+    // 排在后面的新文件不改变已有的 ID
+    run(add("d.rs"), &state).await;
+    assert_eq!(ids(&state)[..4], before[..]);
+    assert_eq!(which(&state, "5").await.trim(), "5 -> d.rs");
+
+    // 移除之后重新编号，并与 payload 一致
+    run(Command::Remove(vec!["b.rs".into()]), &state).await;
+    let after_remove = ids(&state);
+    assert_eq!(names(&after_remove[1..]), [("D2", "a.rs".into()), ("D3", "c.rs".into()), ("D4", "d.rs".into())]);
+    for (id, path) in &after_remove[1..] {
+        assert_eq!(source_in_payload(&state, id), path.display().to_string());
+    }
+    assert_eq!(which(&state, "D3").await.trim(), "D3 -> c.rs");
+    assert!(which(&state, "D5").await.contains("IDs run D1..D4"));
+
+    // 保存 → 清空 → 恢复: 同样的 ID 映射回同样的文件
+    run(Command::Save(Some("ids".to_string())), &state).await;
+    run(Command::Reset(false, true), &state).await;
+    assert!(ids(&state).is_empty());
+    run(Command::Load(Some("ids".to_string())), &state).await;
+    assert_eq!(ids(&state), after_remove);
+    assert_eq!(which(&state, "D3").await.trim(), "D3 -> c.rs");
+    assert_eq!(source_in_payload(&state, "D4"), "d.rs");
+
+    std::env::set_current_dir(previous).unwrap();
+}
@@ -0,0 +1 @@
+export const answer = (): number => 42;
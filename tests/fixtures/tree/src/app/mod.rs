@@ -0,0 +1 @@
+// src/app/mod.rs
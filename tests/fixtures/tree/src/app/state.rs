@@ -0,0 +1 @@
+// src/app/state.rs
@@ -0,0 +1 @@
+// src/core/mod.rs
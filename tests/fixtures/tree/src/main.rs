@@ -0,0 +1 @@
+// src/main.rs
@@ -0,0 +1 @@
+// tests/cli.rs
@@ -0,0 +1,49 @@
+// tests/grouping.rs
+//
+// group_by_language 的快照测试: rust/ts/md 混合的选择在 XML 和 markdown 格式下分别按语言分组，
+// 组内保持路径顺序，index 全局连续，项目树和 instruction 不在任何组内
+
+mod common;
+
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+
+use common::{add, run, state_in};
+use rustprompt::{
+    app::{snippet_manager::SnippetManager, state::AppState},
+    command::definition::Command,
+};
+
+fn fixture() -> PathBuf {
+    Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/fixtures/grouping")
+}
+
+/// 选中整个 fixture，开启分组 (rust 优先，其余按名字)，并设置一条 prompt
+async fn grouped_state() -> Arc<Mutex<AppState>> {
+    let state = state_in(&fixture());
+    let set = |key: &str, value: &str| Command::Set(Some(key.to_string()), Some(value.to_string()));
+    run(set("group_by_language", "on"), &state).await;
+    run(set("language_priority", "rust"), &state).await;
+    run(add(fixture()), &state).await;
+    state.lock().unwrap().prompt_text = "Explain how answer() is used.".to_string();
+    state
+}
+
+fn payload(state: &Arc<Mutex<AppState>>) -> String {
+    SnippetManager::assemble_payload(&state.lock().unwrap()).unwrap()
+}
+
+#[tokio::test]
+async fn xml_groups_by_language() {
+    let state = grouped_state().await;
+    let xml = payload(&state);
+    assert_eq!(xml, include_str!("fixtures/grouping.xml.golden").trim_end(), "\n{}", xml);
+}
+
+#[tokio::test]
+async fn markdown_has_a_heading_per_language() {
+    let state = grouped_state().await;
+    run(Command::Format(Some("custom".to_string()), Some("markdown".to_string())), &state).await;
+    let markdown = payload(&state);
+    assert_eq!(markdown.trim_end(), include_str!("fixtures/grouping.md.golden").trim_end(), "\n{}", markdown);
+}
@@ -0,0 +1,85 @@
+// tests/library.rs
+//
+// 不经过 REPL，直接通过库的 API 构建上下文
+
+use std::fs;
+use std::path::Path;
+
+use rustprompt::{
+    app::context::ContextBuilder,
+    core::{tokenizer::calculate_tokens_in_string, validate::validate_xml, xml::snippet_content},
+    error::AppError,
+};
+
+/// 临时 git 项目: 两个源文件、一个文档、一个被 .gitignore 忽略的构建产物
+fn fixture() -> tempfile::TempDir {
+    let dir = tempfile::tempdir().unwrap();
+    let root = dir.path();
+    fs::create_dir_all(root.join(".git")).unwrap();
+    fs::create_dir_all(root.join("src")).unwrap();
+    fs::create_dir_all(root.join("target")).unwrap();
+    fs::write(root.join("src/main.rs"), "fn main() {\n    println!(\"hello\");\n}\n").unwrap();
+    fs::write(root.join("src/lib.rs"), "pub fn add(a: i32, b: i32) -> i32 {\n    a + b\n}\n").unwrap();
+    fs::write(root.join("README.md"), "# Fixture\n\nUses `Vec<T>` & friends.\n").unwrap();
+    fs::write(root.join("target/out.txt"), "build output\n").unwrap();
+    fs::write(root.join(".gitignore"), "target/\n").unwrap();
+    dir
+}
+
+fn builder(root: &Path) -> ContextBuilder {
+    let ctx = ContextBuilder::new();
+    ctx.state().lock().unwrap().project_root = root.to_path_buf();
+    ctx
+}
+
+#[tokio::test]
+async fn payload_from_paths() {
+    let dir = fixture();
+    let ctx = builder(dir.path());
+    assert_eq!(ctx.add(dir.path().join("src/lib.rs")).await.unwrap(), 1);
+    assert_eq!(ctx.add(dir.path().join("README.md")).await.unwrap(), 1);
+    // 重复添加不算新文件
+    assert_eq!(ctx.add(dir.path().join("README.md")).await.unwrap(), 0);
+    ctx.set_prompt("Explain add().").unwrap();
+
+    let payload = ctx.payload().unwrap();
+    let report = validate_xml(&payload).unwrap();
+    // 两个文件加项目树
+    assert_eq!(report.documents, 3);
+    assert!(payload.contains("Uses `Vec&lt;T&gt;` &amp; friends."), "{}", payload);
+    assert!(payload.contains("a + b"));
+    assert!(payload.trim_end().ends_with("</documents>"));
+    assert!(payload.contains("Explain add()."));
+}
+
+#[tokio::test]
+async fn token_counts_for_a_directory() {
+    let dir = fixture();
+    let ctx = builder(dir.path());
+    // 隐藏文件和 target/ 被忽略
+    assert_eq!(ctx.add(dir.path()).await.unwrap(), 3);
+
+    let files = ctx.files();
+    let names: Vec<_> = files.iter().map(|p| p.strip_prefix(dir.path()).unwrap().to_path_buf()).collect();
+    assert_eq!(names, ["README.md", "src/lib.rs", "src/main.rs"].map(std::path::PathBuf::from));
+
+    let file_tokens = ctx.file_tokens();
+    let st = ctx.state();
+    let st = st.lock().unwrap();
+    for (path, tokens) in &file_tokens {
+        let snippet = &st.partial_docs[path];
+        assert_eq!(*tokens, calculate_tokens_in_string(snippet).unwrap(), "{}", path.display());
+        assert_eq!(snippet_content(snippet), fs::read_to_string(path).unwrap());
+    }
+    let per_file: usize = file_tokens.iter().map(|(_, tokens)| tokens).sum();
+    assert!(st.token_count > per_file);
+}
+
+#[tokio::test]
+async fn missing_path_is_an_error() {
+    let dir = fixture();
+    let ctx = builder(dir.path());
+    let err = ctx.add(dir.path().join("no/such/file.rs")).await.unwrap_err();
+    assert!(matches!(err, AppError::PathNotFound(_)), "{:?}", err);
+    assert!(ctx.files().is_empty());
+}
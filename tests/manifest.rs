@@ -0,0 +1,156 @@
+// tests/manifest.rs
+//
+// /preview 和 /context --json 的清单不拼装 payload，这里和真正拼装出的 payload 逐项对比
+
+use std::fs;
+
+use quick_xml::{events::Event, Reader};
+use rustprompt::{
+    app::{
+        context::ContextBuilder,
+        output::CapturedOutput,
+        report::ContextReport,
+        snippet_manager::{InstructionPlacement, SnippetManager},
+    },
+    command::{definition::Command, executor},
+};
+use tokio_util::sync::CancellationToken;
+
+/// payload 中的一个文档: (id, source, 内容)
+type Document = (String, String, String);
+
+/// 按出现顺序取出 payload 中的文档，以及 instruction 之前的最后一个文档的 id
+fn parse_payload(payload: &str) -> (Vec<Document>, Option<Option<String>>) {
+    let mut reader = Reader::from_str(payload);
+    let mut documents: Vec<Document> = Vec::new();
+    let mut instruction = None;
+    let mut current = None;
+    loop {
+        match reader.read_event().unwrap() {
+            Event::Start(e) => match e.name().as_ref() {
+                b"document" => {
+                    let id = e.try_get_attribute("id").unwrap().unwrap().unescape_value().unwrap().into_owned();
+                    documents.push((id, String::new(), String::new()));
+                }
+                b"instruction" => instruction = Some(documents.last().map(|d| d.0.clone())),
+                name => current = Some(name.to_vec()),
+            },
+            Event::Text(t) => {
+                let text = t.unescape().unwrap();
+                let doc = documents.last_mut();
+                match (current.as_deref(), doc) {
+                    (Some(b"source"), Some(doc)) => doc.1.push_str(&text),
+                    (Some(b"document_content"), Some(doc)) => doc.2.push_str(&text),
+                    _ => {}
+                }
+            }
+            Event::End(_) => current = None,
+            Event::Eof => break,
+            _ => {}
+        }
+    }
+    (documents, instruction)
+}
+
+/// 三个文件，其中 big.log 超过 max_file_bytes 被截断
+async fn context() -> (tempfile::TempDir, ContextBuilder) {
+    let dir = tempfile::tempdir().unwrap();
+    let root = dir.path();
+    fs::create_dir_all(root.join("src")).unwrap();
+    fs::write(root.join("src/main.rs"), "fn main() {}\n").unwrap();
+    fs::write(root.join("notes.md"), "# Notes\n\n`a < b`\n").unwrap();
+    fs::write(root.join("big.log"), "log line\n".repeat(40)).unwrap();
+
+    let ctx = ContextBuilder::new();
+    {
+        let st = ctx.state();
+        let mut st = st.lock().unwrap();
+        st.project_root = root.to_path_buf();
+        st.settings.max_file_bytes = 100;
+    }
+    assert_eq!(ctx.add(root).await.unwrap(), 3);
+    ctx.set_prompt("Summarize the log.").unwrap();
+    (dir, ctx)
+}
+
+#[tokio::test]
+async fn manifest_matches_assembled_payload() {
+    let (_dir, ctx) = context().await;
+    let payload = ctx.payload().unwrap();
+    let (documents, instruction) = parse_payload(&payload);
+
+    let st = ctx.state();
+    let st = st.lock().unwrap();
+    let manifest = SnippetManager::manifest(&st);
+
+    let ids: Vec<&str> = manifest.documents.iter().map(|d| d.id.as_str()).collect();
+    assert_eq!(ids, documents.iter().map(|d| d.0.as_str()).collect::<Vec<_>>());
+    assert_eq!(ids, ["D1", "D2", "D3", "D4"]);
+    for (doc, (_, source, content)) in manifest.documents.iter().zip(&documents).skip(1) {
+        assert_eq!(st.display_path(&doc.source).to_string_lossy(), source.as_str());
+        assert_eq!(doc.truncated.is_some(), content.contains("[truncated, original"), "{}", source);
+        assert_eq!(doc.tokens, st.doc_tokens[&doc.source]);
+    }
+    let truncated: Vec<&str> = documents.iter().filter(|d| d.2.contains("[truncated")).map(|d| d.1.as_str()).collect();
+    assert_eq!(truncated, ["big.log"]);
+    assert_eq!(manifest.documents[1].truncated, Some(360));
+
+    assert_eq!(manifest.instruction, InstructionPlacement::AfterDocument(instruction.unwrap()));
+    assert_eq!(manifest.total_tokens, st.token_count + st.prompt_tokens);
+    assert!(manifest.approx_bytes <= payload.len());
+    assert!(manifest.muted.is_empty());
+}
+
+#[tokio::test]
+async fn muted_and_grouped_documents() {
+    let (dir, ctx) = context().await;
+    let mut out = CapturedOutput::default();
+    ctx.state().lock().unwrap().settings.group_by_language = true;
+    let mute = Command::Mute(dir.path().join("notes.md"));
+    executor::execute(mute, ctx.state(), &CancellationToken::new(), &mut out).await.unwrap();
+
+    let payload = ctx.payload().unwrap();
+    let (documents, _) = parse_payload(&payload);
+    let st = ctx.state();
+    let st = st.lock().unwrap();
+    let manifest = SnippetManager::manifest(&st);
+
+    assert!(manifest.grouped_by_language);
+    let listed: Vec<(String, String)> = manifest
+        .documents
+        .iter()
+        .skip(1)
+        .map(|d| (d.id.clone(), st.display_path(&d.source).to_string_lossy().into_owned()))
+        .collect();
+    let parsed: Vec<(String, String)> = documents.iter().skip(1).map(|d| (d.0.clone(), d.1.clone())).collect();
+    assert_eq!(listed, parsed);
+    assert_eq!(manifest.muted.len(), 1);
+    assert_eq!(manifest.muted[0].source, dir.path().join("notes.md"));
+    assert!(!payload.contains("notes.md</source>"));
+}
+
+#[tokio::test]
+async fn json_and_preview_flag_truncated_files() {
+    let (_dir, ctx) = context().await;
+    let payload = ctx.payload().unwrap();
+    let (documents, _) = parse_payload(&payload);
+
+    let report = ContextReport::from_state(&ctx.state().lock().unwrap());
+    let sources: Vec<&str> = report.manifest.documents.iter().map(|d| d.source.as_str()).collect();
+    assert_eq!(sources[0], "(project tree)");
+    assert_eq!(sources[1..], documents[1..].iter().map(|d| d.1.as_str()).collect::<Vec<_>>()[..]);
+    let truncated: Vec<&str> = report.manifest.documents.iter().filter(|d| d.truncated).map(|d| d.source.as_str()).collect();
+    assert_eq!(truncated, ["big.log"]);
+    assert!(report.files.iter().any(|f| f.path == "big.log" && f.truncated));
+    assert_eq!(report.manifest.instruction, "after_document");
+    assert_eq!(report.manifest.instruction_after.as_deref(), Some("D4"));
+
+    let json = report.to_json(true);
+    assert!(json.contains(r#"{"id":"D2","source":"big.log","tokens":"#), "{}", json);
+
+    let mut out = CapturedOutput::default();
+    executor::execute(Command::Preview, ctx.state(), &CancellationToken::new(), &mut out).await.unwrap();
+    let text = out.text();
+    assert!(text.contains("big.log  (truncated, original 360 B)"), "{}", text);
+    assert!(text.contains("Instruction: after D4, before </documents>"), "{}", text);
+}
@@ -0,0 +1,44 @@
+// tests/mute.rs
+//
+// /mute 的参数与 /pin 一样按已选文件的 key 规整: 同一文件的不同写法切换的是同一条记录
+
+mod common;
+
+use std::fs;
+use std::path::Path;
+
+use common::{add, run, state_in};
+use rustprompt::command::definition::Command;
+
+#[tokio::test]
+async fn mute_accepts_other_spellings_of_a_selected_file() {
+    let dir = tempfile::tempdir().unwrap();
+    fs::create_dir(dir.path().join("sub")).unwrap();
+    let file = dir.path().join("a.rs");
+    fs::write(&file, "fn a() {}\n").unwrap();
+    let state = state_in(dir.path());
+    run(add(&file), &state).await;
+
+    // 相对于项目根目录、带 `..` 的写法
+    let out = run(Command::Mute(Path::new("sub/../a.rs").to_path_buf()), &state).await;
+    assert!(out.text().contains("Muted"), "{}", out.text());
+    assert_eq!(state.lock().unwrap().muted.iter().collect::<Vec<_>>(), vec![&file]);
+
+    // 另一种写法切换回来，而不是再记一条
+    let out = run(Command::Mute(dir.path().join("./sub/../a.rs")), &state).await;
+    assert!(out.text().contains("Unmuted"), "{}", out.text());
+    assert!(state.lock().unwrap().muted.is_empty());
+}
+
+#[tokio::test]
+async fn mute_rejects_files_outside_the_context() {
+    let dir = tempfile::tempdir().unwrap();
+    fs::write(dir.path().join("a.rs"), "fn a() {}\n").unwrap();
+    fs::write(dir.path().join("b.rs"), "fn b() {}\n").unwrap();
+    let state = state_in(dir.path());
+    run(add(dir.path().join("a.rs")), &state).await;
+
+    let out = run(Command::Mute(Path::new("b.rs").to_path_buf()), &state).await;
+    assert!(out.text().contains("is not in the context"), "{}", out.text());
+    assert!(state.lock().unwrap().muted.is_empty());
+}
@@ -0,0 +1,99 @@
+// tests/oversize.rs
+//
+// max_file_bytes 的边界: 正好等于上限的文件完整加入，多一个字节就按 oversize 跳过或截断
+
+mod common;
+
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+
+use common::{add, run};
+use rustprompt::{
+    app::{output::Level, settings::Oversize, state::AppState},
+    command::definition::Command,
+    core::xml::snippet_content,
+};
+
+const LIMIT: usize = 64;
+
+fn limited_state(root: &Path, oversize: Oversize) -> Arc<Mutex<AppState>> {
+    let state = common::state_in(root);
+    {
+        let mut st = state.lock().unwrap();
+        st.settings.max_file_bytes = LIMIT;
+        st.settings.oversize = oversize;
+    }
+    state
+}
+
+/// at_limit.txt 正好 LIMIT 字节，over_limit.txt 多一个字节
+fn fixture() -> (tempfile::TempDir, PathBuf, PathBuf) {
+    let dir = tempfile::tempdir().unwrap();
+    let at = dir.path().join("at_limit.txt");
+    let over = dir.path().join("over_limit.txt");
+    fs::write(&at, "a".repeat(LIMIT)).unwrap();
+    fs::write(&over, "b".repeat(LIMIT + 1)).unwrap();
+    (dir, at, over)
+}
+
+#[tokio::test]
+async fn skip_keeps_files_at_the_limit() {
+    let (dir, at, over) = fixture();
+    let state = limited_state(dir.path(), Oversize::Skip);
+    let out = run(add(dir.path()), &state).await;
+
+    let st = state.lock().unwrap();
+    assert!(st.selected_paths.contains(&at));
+    assert!(!st.selected_paths.contains(&over));
+    assert_eq!(snippet_content(&st.partial_docs[&at]), "a".repeat(LIMIT));
+    assert!(st.truncated.is_empty());
+
+    let warnings = out.at(Level::Warn).join("\n");
+    assert!(warnings.contains("Skipped 1 files larger than 64 B"), "{}", warnings);
+    assert!(out.text().contains("over_limit.txt (65 B)"), "{}", out.text());
+}
+
+#[tokio::test]
+async fn truncate_cuts_one_byte_over_the_limit() {
+    let (dir, at, over) = fixture();
+    let state = limited_state(dir.path(), Oversize::Truncate);
+    run(add(dir.path()), &state).await;
+
+    {
+        let st = state.lock().unwrap();
+        assert_eq!(snippet_content(&st.partial_docs[&at]), "a".repeat(LIMIT));
+        assert_eq!(snippet_content(&st.partial_docs[&over]), format!("{}\n[truncated, original 65 B]", "b".repeat(LIMIT)));
+        assert_eq!(st.truncated.keys().collect::<Vec<_>>(), [&over]);
+    }
+    let context = run(Command::ShowContext, &state).await.text();
+    assert!(context.contains("over_limit.txt\" (truncated; original 65 B)"), "{}", context);
+    assert!(!context.contains("at_limit.txt\" (truncated"), "{}", context);
+}
+
+#[tokio::test]
+async fn selected_file_growing_past_the_limit_is_truncated_on_refresh() {
+    let (dir, at, _) = fixture();
+    let state = limited_state(dir.path(), Oversize::Skip);
+    run(add(&at), &state).await;
+    assert!(state.lock().unwrap().truncated.is_empty());
+
+    fs::write(&at, "a".repeat(LIMIT + 1)).unwrap();
+    run(Command::Refresh, &state).await;
+    let st = state.lock().unwrap();
+    assert!(st.selected_paths.contains(&at));
+    assert_eq!(st.truncated.get(&at), Some(&(LIMIT as u64 + 1)));
+}
+
+#[tokio::test]
+async fn truncation_inside_a_multibyte_char() {
+    let dir = tempfile::tempdir().unwrap();
+    let file = dir.path().join("wide.txt");
+    // 62 个 ASCII 字节之后是 3 字节的 "中"，上限落在它的中间；不完整的字符被去掉，其余仍按 UTF-8 解码
+    fs::write(&file, format!("é{}中", "x".repeat(LIMIT - 3))).unwrap();
+    let state = limited_state(dir.path(), Oversize::Truncate);
+    run(add(&file), &state).await;
+
+    let content = snippet_content(&state.lock().unwrap().partial_docs[&file]);
+    assert_eq!(content, format!("é{}\n[truncated, original 66 B]", "x".repeat(LIMIT - 3)));
+}
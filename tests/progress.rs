@@ -0,0 +1,86 @@
+// tests/progress.rs
+//
+// 扫描和读取通过 Progress 通道发送的事件
+
+use std::fs;
+use std::sync::{Arc, Mutex};
+
+use rustprompt::{
+    app::{snippet_manager::SnippetManager, state::AppState},
+    core::{
+        files_scanner::scan_dir_with_progress,
+        ignore_rules::IgnoreConfig,
+        progress::{Progress, ProgressEvent, SCAN_STEP},
+    },
+    error::AppError,
+};
+use tokio::sync::mpsc::UnboundedReceiver;
+use tokio_util::sync::CancellationToken;
+
+const FILES: usize = 2 * SCAN_STEP + 50;
+
+fn fixture() -> tempfile::TempDir {
+    let dir = tempfile::tempdir().unwrap();
+    for i in 0..FILES {
+        let sub = dir.path().join(format!("pkg{}", i % 7));
+        fs::create_dir_all(&sub).unwrap();
+        fs::write(sub.join(format!("f{}.txt", i)), format!("file {}\n", i)).unwrap();
+    }
+    dir
+}
+
+/// 所有发送端 drop 之后收集通道中的全部事件
+fn drain(mut events: UnboundedReceiver<ProgressEvent>) -> Vec<ProgressEvent> {
+    let mut all = Vec::new();
+    while let Ok(event) = events.try_recv() {
+        all.push(event);
+    }
+    all
+}
+
+#[tokio::test]
+async fn scanning_reports_every_step_and_the_total() {
+    let dir = fixture();
+    let (progress, events) = Progress::channel();
+    let result = scan_dir_with_progress(dir.path(), &IgnoreConfig::default(), &progress, &CancellationToken::new())
+        .await
+        .unwrap();
+    drop(progress);
+
+    assert_eq!(result.files.len(), FILES);
+    let expected = [ProgressEvent::Scanned(SCAN_STEP), ProgressEvent::Scanned(2 * SCAN_STEP), ProgressEvent::Scanned(FILES)];
+    assert_eq!(drain(events), expected);
+}
+
+#[tokio::test]
+async fn reading_reports_each_file() {
+    let dir = fixture();
+    let files = scan_dir_with_progress(dir.path(), &IgnoreConfig::default(), &Progress::none(), &CancellationToken::new())
+        .await
+        .unwrap()
+        .files;
+    let state = Arc::new(Mutex::new(AppState::new()));
+    let (progress, events) = Progress::channel();
+    SnippetManager::add_files_snippet_with_progress(state.clone(), files, &progress, &CancellationToken::new())
+        .await
+        .unwrap();
+    drop(progress);
+
+    let events = drain(events);
+    let expected: Vec<ProgressEvent> = (1..=FILES).map(|done| ProgressEvent::Read { done, total: FILES }).collect();
+    assert_eq!(events, expected);
+    assert_eq!(state.lock().unwrap().partial_docs.len(), FILES);
+}
+
+#[tokio::test]
+async fn cancelled_scan_sends_no_total() {
+    let dir = fixture();
+    let cancel = CancellationToken::new();
+    cancel.cancel();
+    let (progress, events) = Progress::channel();
+    let err = scan_dir_with_progress(dir.path(), &IgnoreConfig::default(), &progress, &cancel).await.unwrap_err();
+    drop(progress);
+
+    assert!(matches!(err, AppError::Cancelled), "{:?}", err);
+    assert!(drain(events).is_empty());
+}
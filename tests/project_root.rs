@@ -0,0 +1,63 @@
+// tests/project_root.rs
+//
+// project_root 与当前目录不同时 (--root)，项目树、改名检测和 /pin 都以 project_root 为准
+
+mod common;
+
+use std::fs;
+use std::path::Path;
+
+use common::{add, run, state_in};
+use rustprompt::{
+    app::{settings::FollowRenames, state::PROJECT_TREE_VIRTUAL_PATH},
+    command::definition::Command,
+    core::xml::snippet_content,
+};
+
+#[tokio::test]
+async fn tree_lists_the_project_root() {
+    let dir = tempfile::tempdir().unwrap();
+    fs::create_dir_all(dir.path().join("docs")).unwrap();
+    fs::write(dir.path().join("docs/guide.md"), "# Guide\n").unwrap();
+    let state = state_in(dir.path());
+    run(add(dir.path().join("docs/guide.md")), &state).await;
+
+    let st = state.lock().unwrap();
+    let tree = snippet_content(&st.partial_docs[Path::new(PROJECT_TREE_VIRTUAL_PATH)]);
+    assert!(tree.contains("guide.md"), "{}", tree);
+    // 当前目录 (本仓库) 的文件不出现在树中
+    assert!(!tree.contains("Cargo.toml"), "{}", tree);
+}
+
+#[tokio::test]
+async fn refresh_follows_renames_under_the_project_root() {
+    let dir = tempfile::tempdir().unwrap();
+    let old = dir.path().join("draft.md");
+    fs::write(&old, "unique content for the rename test\n").unwrap();
+    let state = state_in(dir.path());
+    state.lock().unwrap().settings.follow_renames = FollowRenames::Auto;
+    run(add(&old), &state).await;
+
+    let new = dir.path().join("final.md");
+    fs::rename(&old, &new).unwrap();
+    run(Command::Refresh, &state).await;
+
+    let st = state.lock().unwrap();
+    let selected: Vec<_> = st.selected_paths.iter().cloned().collect();
+    assert_eq!(selected, [fs::canonicalize(&new).unwrap()]);
+}
+
+#[tokio::test]
+async fn pin_resolves_paths_relative_to_the_project_root() {
+    let dir = tempfile::tempdir().unwrap();
+    fs::write(dir.path().join("pinned-fixture.md"), "keep me\n").unwrap();
+    let state = state_in(dir.path());
+    run(add(dir.path().join("pinned-fixture.md")), &state).await;
+
+    let out = run(Command::Pin("pinned-fixture.md".into()), &state).await;
+    assert!(out.text().contains("Pinned pinned-fixture.md"), "{}", out.text());
+    assert_eq!(state.lock().unwrap().pinned.len(), 1);
+
+    run(Command::Unpin("pinned-fixture.md".into()), &state).await;
+    assert!(state.lock().unwrap().pinned.is_empty());
+}
@@ -0,0 +1,169 @@
+// tests/properties.rs
+//
+// 命令解析、补全和 payload 拼装的属性测试: 任意输入 (多字节字符、控制字符、非 UTF-8 字节) 都不能 panic，
+// 合法的命令经过 quote_arg 格式化后再解析得到相同的结果，生成的 XML 总是良构的。
+// 用例数有上限，随 `cargo test` 一起运行；更长时间的随机测试见 fuzz/
+
+use std::collections::HashMap;
+use std::path::PathBuf;
+use std::sync::{Arc, Mutex};
+
+use proptest::collection::vec;
+use proptest::prelude::*;
+use reedline::Completer;
+use rustprompt::{
+    app::state::{AppState, PROJECT_TREE_VIRTUAL_PATH},
+    command::{
+        definition::{AddOptions, Command},
+        parser, registry,
+    },
+    core::{
+        validate::validate_xml,
+        xml::{generate_single_file_snippet_with_attrs, instruction_snippet, merge_all_snippets, snippet_content, MergeOptions},
+    },
+    repl::completion::CmdPromptCompleter,
+};
+
+/// 偏向引号、转义、XML 特殊字符、控制字符和多字节字符的任意文本
+fn text() -> impl Strategy<Value = String> {
+    let special = prop::sample::select(vec![
+        '<', '>', '&', '"', '\'', '\\', ']', ';', '!', '*', '[', '\0', '\u{1b}', '\u{7f}', '\r', '\n', '\t', ' ', '\u{a0}',
+        '\u{3000}', '中', 'é', '😀', '/', '@', '-', '=',
+    ]);
+    vec(prop_oneof![3 => special, 1 => any::<char>()], 0..40).prop_map(|chars| chars.into_iter().collect())
+}
+
+/// 任意字节组成的路径: Unix 上文件名可以不是 UTF-8
+fn path_from_bytes(bytes: &[u8]) -> PathBuf {
+    #[cfg(unix)]
+    {
+        use std::os::unix::ffi::OsStrExt;
+        PathBuf::from(std::ffi::OsStr::from_bytes(bytes))
+    }
+    #[cfg(not(unix))]
+    {
+        PathBuf::from(String::from_utf8_lossy(bytes).into_owned())
+    }
+}
+
+/// XML 1.0 不允许的控制字符在 snippet 中被替换为 U+FFFD
+fn xml_safe(text: &str) -> String {
+    text.chars()
+        .map(|c| if (c as u32) < 0x20 && !matches!(c, '\t' | '\n' | '\r') { '\u{FFFD}' } else { c })
+        .collect()
+}
+
+/// 命令名 (包括内置别名) 加任意参数
+fn command_line() -> impl Strategy<Value = String> {
+    let names: Vec<&'static str> = registry::COMMANDS
+        .iter()
+        .flat_map(|spec| std::iter::once(spec.name).chain(spec.aliases.iter().copied()))
+        .collect();
+    (prop::sample::select(names), vec(text(), 0..4)).prop_map(|(name, args)| format!("{} {}", name, args.join(" ")))
+}
+
+/// 不会被当作 /add 选项的路径参数
+fn path_arg() -> impl Strategy<Value = String> {
+    text().prop_filter("paths are non-empty and not flags", |p| !p.is_empty() && !p.starts_with('-'))
+}
+
+proptest! {
+    #![proptest_config(ProptestConfig { cases: 256, failure_persistence: None, ..ProptestConfig::default() })]
+
+    #[test]
+    fn parse_never_panics(line in text(), bytes in vec(any::<u8>(), 0..48)) {
+        let _ = parser::parse(&line);
+        let _ = parser::parse(&format!("/{}", line));
+        let _ = parser::parse(&format!("/add {}", String::from_utf8_lossy(&bytes)));
+    }
+
+    #[test]
+    fn command_lines_never_panic(line in command_line()) {
+        let _ = parser::parse(&line);
+        let _ = parser::tokenize_spans(&line);
+    }
+
+    #[test]
+    fn token_spans_lie_on_char_boundaries(line in text()) {
+        for token in parser::tokenize_spans(&line) {
+            prop_assert!(token.start < token.end && token.end <= line.len());
+            prop_assert!(line.is_char_boundary(token.start) && line.is_char_boundary(token.end));
+        }
+    }
+
+    #[test]
+    fn quoted_args_round_trip(args in vec(text(), 1..5)) {
+        let line = args.iter().map(|a| parser::quote_arg(a)).collect::<Vec<_>>().join(" ");
+        prop_assert_eq!(parser::tokenize(&line), args);
+    }
+
+    #[test]
+    fn add_and_remove_round_trip(paths in vec(path_arg(), 1..4)) {
+        let formatted = paths.iter().map(|p| parser::quote_arg(p)).collect::<Vec<_>>().join(" ");
+        let expected: Vec<PathBuf> = paths.iter().map(PathBuf::from).collect();
+        match parser::parse(&format!("/add {}", formatted)) {
+            Ok(Command::Add(parsed, options)) => {
+                prop_assert_eq!(&parsed, &expected);
+                prop_assert_eq!(options, AddOptions::default());
+            }
+            other => prop_assert!(false, "{:?}", other),
+        }
+        match parser::parse(&format!("/remove {}", formatted)) {
+            Ok(Command::Remove(parsed)) => prop_assert_eq!(parsed, expected),
+            other => prop_assert!(false, "{:?}", other),
+        }
+    }
+
+    #[test]
+    fn snippet_round_trips_content(path in vec(any::<u8>(), 1..24), content in text(), attr in text()) {
+        let path = path_from_bytes(&path);
+        let snippet = generate_single_file_snippet_with_attrs(&path, &content, 0, &[("indent_unit", attr)]);
+        prop_assert_eq!(snippet_content(&snippet), xml_safe(&content));
+    }
+
+    #[test]
+    fn merged_payload_is_well_formed(
+        files in vec((vec(any::<u8>(), 1..16), text()), 0..6),
+        tree in text(),
+        prompt in text(),
+        group_by_language in any::<bool>(),
+    ) {
+        let mut docs = HashMap::new();
+        let tree_path = PathBuf::from(PROJECT_TREE_VIRTUAL_PATH);
+        docs.insert(tree_path.clone(), generate_single_file_snippet_with_attrs(&tree_path, &tree, 0, &[]));
+        for (path, content) in &files {
+            let path = path_from_bytes(path);
+            let snippet = generate_single_file_snippet_with_attrs(&path, content, 0, &[]);
+            docs.insert(path, snippet);
+        }
+        let options = MergeOptions { group_by_language, ..Default::default() };
+        let mut payload = merge_all_snippets(&docs, &options);
+        payload.insert_str(payload.len() - "</documents>".len(), &instruction_snippet(&prompt));
+
+        let report = validate_xml(&payload);
+        prop_assert!(report.is_ok(), "{:?}\n{}", report, payload);
+        prop_assert_eq!(report.unwrap().documents, docs.len());
+    }
+}
+
+proptest! {
+    // 补全会读取文件系统，用例少一些
+    #![proptest_config(ProptestConfig { cases: 64, failure_persistence: None, ..ProptestConfig::default() })]
+
+    #[test]
+    fn completion_never_panics(line in text(), prefix in prop::sample::select(vec!["", "/", "/add ", "/remove ", "/help ", "/set ", "/format custom ", "/a"]), cut in any::<prop::sample::Index>()) {
+        let mut state = AppState::new();
+        state.project_root = std::env::temp_dir();
+        state.selected_paths.insert(PathBuf::from("src/中文 目录/main.rs"));
+        let mut completer = CmdPromptCompleter::new(Arc::new(Mutex::new(state)));
+
+        let line = format!("{}{}", prefix, line);
+        // pos 可以落在多字节字符中间 (补全器自己退回到字符边界)
+        let pos = cut.index(line.len() + 1);
+        for suggestion in completer.complete(&line, pos) {
+            let span = suggestion.span;
+            prop_assert!(span.start <= span.end && span.end <= line.len(), "{:?} for {:?}", span, line);
+            prop_assert!(line.is_char_boundary(span.start) && line.is_char_boundary(span.end));
+        }
+    }
+}
@@ -0,0 +1,133 @@
+// tests/renames.rs
+//
+// /refresh 跟随改名: git 的改名记录 (已暂存的 git mv、未暂存的 mv、带特殊字符的路径) 与按内容指纹查找 (含截断读取的文件)
+
+mod common;
+
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process::Command as Process;
+use std::sync::{Arc, Mutex};
+
+use common::{add, run};
+use rustprompt::{
+    app::{settings::{FollowRenames, Oversize}, state::AppState},
+    command::definition::Command,
+    core::renames::{absolute_path, detect_git_renames},
+};
+
+fn git(dir: &Path, args: &[&str]) {
+    let status = Process::new("git")
+        .args(["-c", "user.name=t", "-c", "user.email=t@t"])
+        .args(args)
+        .current_dir(dir)
+        .output()
+        .unwrap();
+    assert!(status.status.success(), "git {:?}: {}", args, String::from_utf8_lossy(&status.stderr));
+}
+
+/// 提交了 files 的 git 仓库
+fn repo(files: &[(&str, &str)]) -> tempfile::TempDir {
+    let dir = tempfile::tempdir().unwrap();
+    git(dir.path(), &["init", "-q"]);
+    for (name, content) in files {
+        fs::write(dir.path().join(name), content).unwrap();
+    }
+    git(dir.path(), &["add", "-A"]);
+    git(dir.path(), &["commit", "-q", "-m", "init"]);
+    dir
+}
+
+fn following_state(root: &Path) -> Arc<Mutex<AppState>> {
+    let state = common::state_in(root);
+    state.lock().unwrap().settings.follow_renames = FollowRenames::Auto;
+    state
+}
+
+fn abs(dir: &Path, name: &str) -> PathBuf {
+    absolute_path(&dir.join(name))
+}
+
+#[test]
+fn staged_renames_keep_spaces_tabs_and_non_ascii_paths() {
+    let dir = repo(&[("a b.txt", "spaces\n"), ("ü\tx.rs", "tab\n")]);
+    git(dir.path(), &["mv", "a b.txt", "c d.txt"]);
+    git(dir.path(), &["mv", "ü\tx.rs", "名字\ty.rs"]);
+
+    let renames = detect_git_renames(dir.path());
+    assert_eq!(renames.len(), 2, "{:?}", renames);
+    assert_eq!(renames[&abs(dir.path(), "a b.txt")], abs(dir.path(), "c d.txt"));
+    assert_eq!(renames[&abs(dir.path(), "ü\tx.rs")], abs(dir.path(), "名字\ty.rs"));
+}
+
+#[test]
+fn unstaged_moves_pair_the_deleted_file_with_identical_content() {
+    let dir = repo(&[("old.txt", "moved content\n"), ("gone.txt", "deleted\n")]);
+    fs::rename(dir.path().join("old.txt"), dir.path().join("new.txt")).unwrap();
+    fs::remove_file(dir.path().join("gone.txt")).unwrap();
+    // 大小相同但内容不同的未跟踪文件不算改名
+    fs::write(dir.path().join("other.txt"), "deleted?\n").unwrap();
+
+    let renames = detect_git_renames(dir.path());
+    assert_eq!(renames.len(), 1, "{:?}", renames);
+    assert_eq!(renames[&abs(dir.path(), "old.txt")], abs(dir.path(), "new.txt"));
+}
+
+#[tokio::test]
+async fn refresh_follows_a_git_rename_without_a_fingerprint() {
+    let dir = repo(&[("a b.txt", "same\n"), ("keep.txt", "same\n")]);
+    let old = dir.path().join("a b.txt");
+    let state = following_state(dir.path());
+    run(add(&old), &state).await;
+    git(dir.path(), &["mv", "a b.txt", "c d.txt"]);
+    // 没有指纹时只能靠 git 的记录; keep.txt 内容相同，按指纹会找错
+    state.lock().unwrap().fingerprints.clear();
+
+    run(Command::Refresh, &state).await;
+    let st = state.lock().unwrap();
+    assert!(st.selected_paths.contains(&dir.path().join("c d.txt")), "{:?}", st.selected_paths);
+    assert!(!st.selected_paths.contains(&old));
+}
+
+#[tokio::test]
+async fn refresh_follows_a_plain_rename_by_fingerprint() {
+    let dir = tempfile::tempdir().unwrap();
+    let old = dir.path().join("before.rs");
+    fs::write(&old, "fn main() {}\n").unwrap();
+    let state = following_state(dir.path());
+    run(add(&old), &state).await;
+
+    let new = dir.path().join("after.rs");
+    fs::rename(&old, &new).unwrap();
+    let out = run(Command::Refresh, &state).await;
+
+    assert!(out.text().contains("appears renamed to"), "{}", out.text());
+    let st = state.lock().unwrap();
+    assert!(st.selected_paths.contains(&new), "{:?}", st.selected_paths);
+    assert!(!st.selected_paths.contains(&old));
+}
+
+#[tokio::test]
+async fn refresh_follows_a_truncated_file_by_its_prefix() {
+    let dir = tempfile::tempdir().unwrap();
+    let old = dir.path().join("big.log");
+    fs::write(&old, "x".repeat(64) + &"y".repeat(64)).unwrap();
+    // 开头相同、完整长度不同的文件不算
+    fs::write(dir.path().join("shorter.log"), "x".repeat(64) + &"y".repeat(63)).unwrap();
+    let state = following_state(dir.path());
+    {
+        let mut st = state.lock().unwrap();
+        st.settings.max_file_bytes = 64;
+        st.settings.oversize = Oversize::Truncate;
+    }
+    run(add(&old), &state).await;
+    assert!(state.lock().unwrap().truncated.contains_key(&old));
+
+    let new = dir.path().join("renamed.log");
+    fs::rename(&old, &new).unwrap();
+    run(Command::Refresh, &state).await;
+
+    let st = state.lock().unwrap();
+    assert!(st.selected_paths.contains(&new), "{:?}", st.selected_paths);
+    assert!(st.truncated.contains_key(&new));
+}
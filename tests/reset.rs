@@ -0,0 +1,49 @@
+// tests/reset.rs
+//
+// /reset 之后 /context 的 token 分项: 没有固定文件时全部归零，有固定文件时只计算留下的文件
+
+mod common;
+
+use std::fs;
+
+use common::{add, run, state_in};
+use rustprompt::command::definition::Command;
+
+fn breakdown(text: &str) -> &str {
+    text.lines().find(|l| l.starts_with("Token breakdown:")).unwrap_or_default()
+}
+
+#[tokio::test]
+async fn reset_without_pins_zeroes_the_breakdown() {
+    let dir = tempfile::tempdir().unwrap();
+    fs::write(dir.path().join("a.rs"), "fn a() {}\n".repeat(20)).unwrap();
+    let state = state_in(dir.path());
+    run(add(dir.path()), &state).await;
+    {
+        let st = state.lock().unwrap();
+        assert!(st.file_tokens > 0 && st.tree_tokens > 0);
+    }
+
+    run(Command::Reset(false, true), &state).await;
+    let out = run(Command::ShowContext, &state).await;
+    assert_eq!(
+        breakdown(&out.text()),
+        "Token breakdown: files 0 · project tree 0 · markup 0 · prompt 0 (total 0)"
+    );
+}
+
+#[tokio::test]
+async fn reset_with_pins_counts_only_the_kept_files() {
+    let dir = tempfile::tempdir().unwrap();
+    let kept = dir.path().join("kept.rs");
+    fs::write(&kept, "fn kept() {}\n").unwrap();
+    fs::write(dir.path().join("dropped.rs"), "fn dropped() {}\n".repeat(50)).unwrap();
+    let state = state_in(dir.path());
+    run(add(dir.path()), &state).await;
+    run(Command::Pin(kept.clone()), &state).await;
+
+    run(Command::Reset(false, true), &state).await;
+    let st = state.lock().unwrap();
+    assert_eq!(st.selected_paths.len(), 1);
+    assert_eq!(st.file_tokens, st.doc_tokens[&kept]);
+}
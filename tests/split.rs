@@ -0,0 +1,90 @@
+// tests/split.rs
+//
+// /copy --split 的上限边界: 正好等于上限的部分不再拆分，多一个 token 就把最后一个文件移到下一部分；
+// 项目树 (和 instruction) 本身就达到上限时拒绝拆分
+
+mod common;
+
+use std::fs;
+use std::sync::{Arc, Mutex};
+
+use common::{add, run, state_in};
+use rustprompt::{
+    app::{snippet_manager::SnippetManager, state::AppState},
+    command::executor::error_message,
+};
+
+/// 项目中有 a.txt、b.txt 两个文件
+async fn context() -> (tempfile::TempDir, Arc<Mutex<AppState>>) {
+    let dir = tempfile::tempdir().unwrap();
+    fs::write(dir.path().join("a.txt"), "alpha ".repeat(40)).unwrap();
+    fs::write(dir.path().join("b.txt"), "beta ".repeat(60)).unwrap();
+    let state = state_in(dir.path());
+    run(add(dir.path()), &state).await;
+    (dir, state)
+}
+
+/// 不拆分时 (一个部分) 的 token 数
+fn whole(st: &AppState) -> usize {
+    let split = SnippetManager::split_payload(st, usize::MAX / 2).unwrap();
+    assert_eq!(split.parts.len(), 1);
+    split.parts[0].tokens
+}
+
+#[tokio::test]
+async fn a_part_exactly_at_the_limit_stays_whole() {
+    let (_dir, state) = context().await;
+    let st = state.lock().unwrap();
+    let limit = whole(&st);
+
+    let split = SnippetManager::split_payload(&st, limit).unwrap();
+    assert_eq!(split.parts.len(), 1);
+    assert_eq!(split.parts[0].tokens, limit);
+    assert_eq!(split.parts[0].documents, 2);
+    assert!(split.oversized.is_empty());
+}
+
+#[tokio::test]
+async fn one_token_over_moves_the_last_file_to_a_new_part() {
+    let (_dir, state) = context().await;
+    let st = state.lock().unwrap();
+    let limit = whole(&st) - 1;
+
+    let split = SnippetManager::split_payload(&st, limit).unwrap();
+    assert_eq!(split.parts.len(), 2);
+    assert!(split.parts.iter().all(|p| p.documents == 1 && p.tokens <= limit));
+    assert!(split.parts[0].text.contains("a.txt</source>"));
+    assert!(split.parts[1].text.starts_with(r#"<documents part="2" of="2">"#));
+    assert!(split.parts[1].text.contains("b.txt</source>"));
+    assert!(split.oversized.is_empty());
+}
+
+#[tokio::test]
+async fn a_single_file_one_over_is_reported_as_oversized() {
+    let dir = tempfile::tempdir().unwrap();
+    fs::write(dir.path().join("only.txt"), "gamma ".repeat(80)).unwrap();
+    let state = state_in(dir.path());
+    run(add(dir.path()), &state).await;
+    let st = state.lock().unwrap();
+    let limit = whole(&st);
+
+    assert!(SnippetManager::split_payload(&st, limit).unwrap().oversized.is_empty());
+    let split = SnippetManager::split_payload(&st, limit - 1).unwrap();
+    assert_eq!(split.parts.len(), 1);
+    assert_eq!(split.oversized.len(), 1);
+    assert!(split.oversized[0].0.ends_with("only.txt"));
+    assert_eq!(split.oversized[0].1, limit);
+}
+
+#[tokio::test]
+async fn the_tree_alone_over_the_limit_is_an_error() {
+    let (_dir, state) = context().await;
+    let st = state.lock().unwrap();
+    // 每一部分都要带上项目树和外层标签，上限等于项目树本身的 token 数时放不下任何文件
+    for limit in [st.tree_tokens, st.tree_tokens / 2] {
+        let err = SnippetManager::split_payload(&st, limit).err().unwrap();
+        let message = error_message(&err);
+        assert!(message.contains("alone are"), "{}", message);
+        assert!(message.contains(&format!("split limit of {}", limit)), "{}", message);
+    }
+}
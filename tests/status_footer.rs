@@ -0,0 +1,50 @@
+// tests/status_footer.rs
+
+use std::sync::{Arc, Mutex};
+
+use rustprompt::{
+    app::{
+        output::{failure_footer, format_token_count, CapturedOutput, Level},
+        state::AppState,
+    },
+    command::{definition::Command, executor},
+};
+use tokio_util::sync::CancellationToken;
+
+#[tokio::test]
+async fn footer_after_add() {
+    let state = Arc::new(Mutex::new(AppState::new()));
+    state.lock().unwrap().settings.token_budget = 5_000;
+    let mut out = CapturedOutput::default();
+    let add = Command::Add(vec!["Cargo.toml".into()], Default::default());
+    executor::execute(add, state.clone(), &CancellationToken::new(), &mut out).await.unwrap();
+
+    let tokens = state.lock().unwrap().token_count;
+    let expected = format!("✔ 1 files · {} tokens · prompt 0 · budget {}%", format_token_count(tokens), tokens * 100 / 5_000);
+    assert_eq!(out.at(Level::Info).last(), Some(&expected.as_str()));
+}
+
+#[tokio::test]
+async fn footer_without_budget() {
+    let state = Arc::new(Mutex::new(AppState::new()));
+    state.lock().unwrap().settings.token_budget = 0;
+    let mut out = CapturedOutput::default();
+    let add = Command::Add(vec!["Cargo.toml".into()], Default::default());
+    executor::execute(add, state.clone(), &CancellationToken::new(), &mut out).await.unwrap();
+
+    let footer = out.at(Level::Info).last().unwrap().to_string();
+    assert!(footer.starts_with("✔ 1 files · "), "{}", footer);
+    assert!(footer.ends_with(" tokens · prompt 0"), "{}", footer);
+}
+
+#[tokio::test]
+async fn failed_add_has_no_success_footer() {
+    let state = Arc::new(Mutex::new(AppState::new()));
+    let mut out = CapturedOutput::default();
+    let add = Command::Add(vec!["no/such/file.rs".into()], Default::default());
+    let err = executor::execute(add, state.clone(), &CancellationToken::new(), &mut out).await.unwrap_err();
+
+    assert!(!out.text().contains('✔'), "{}", out.text());
+    assert_eq!(failure_footer(&executor::error_message(&err)), "✘ No such file or directory: no/such/file.rs");
+    assert_eq!(state.lock().unwrap().file_count, 0);
+}
@@ -0,0 +1,110 @@
+// tests/symlinks.rs
+//
+// 同一文件的不同写法 (相对、`./`、绝对路径、符号链接) 只算一个已选文件
+
+#![cfg(unix)]
+
+mod common;
+
+use std::fs;
+use std::os::unix::fs::symlink;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+
+use common::{add, run, try_run};
+use rustprompt::{
+    app::{snippet_manager::SnippetManager, state::AppState},
+    command::definition::Command,
+    core::validate::validate_xml,
+    error::AppError,
+};
+
+/// 当前目录之下的临时项目 (相对路径要能指向它)，返回 (目录, 相对于当前目录的路径)。
+/// 目录名不以 `.` 开头，否则其中的文件按隐藏文件忽略
+fn fixture() -> (tempfile::TempDir, PathBuf) {
+    let dir = tempfile::Builder::new().prefix("symlinks-").tempdir_in(env!("CARGO_TARGET_TMPDIR")).unwrap();
+    fs::create_dir_all(dir.path().join("src")).unwrap();
+    fs::write(dir.path().join("src/main.rs"), "fn main() {}\n").unwrap();
+    let cwd = std::env::current_dir().unwrap();
+    let relative = dir.path().strip_prefix(&cwd).unwrap().to_path_buf();
+    (dir, relative)
+}
+
+/// 临时项目在 target/ 下，不应用本仓库的 .gitignore；项目树只包含临时项目
+fn new_state(root: &Path) -> Arc<Mutex<AppState>> {
+    let state = common::state_in(root);
+    state.lock().unwrap().ignore.use_gitignore = false;
+    state
+}
+
+fn documents(state: &Arc<Mutex<AppState>>) -> usize {
+    let payload = SnippetManager::assemble_payload(&state.lock().unwrap()).unwrap();
+    // 减去项目树
+    validate_xml(&payload).unwrap().documents - 1
+}
+
+#[tokio::test]
+async fn relative_dotted_and_absolute_adds_are_one_file() {
+    let (dir, relative) = fixture();
+    let state = new_state(dir.path());
+    run(add(relative.join("src/main.rs")), &state).await;
+    run(add(Path::new(".").join(&relative).join("src/../src/main.rs")), &state).await;
+    run(add(dir.path().join("src/main.rs")), &state).await;
+    run(add(dir.path().join("src")), &state).await;
+
+    let selected: Vec<PathBuf> = state.lock().unwrap().selected_paths.iter().cloned().collect();
+    assert_eq!(selected, [relative.join("src/main.rs")]);
+    assert_eq!(documents(&state), 1);
+}
+
+#[tokio::test]
+async fn symlinked_file_collapses_to_its_target() {
+    let (dir, relative) = fixture();
+    symlink(dir.path().join("src/main.rs"), dir.path().join("link.rs")).unwrap();
+    let state = new_state(dir.path());
+    run(add(dir.path().join("link.rs")), &state).await;
+    run(add(relative.join("src/main.rs")), &state).await;
+
+    let selected: Vec<PathBuf> = state.lock().unwrap().selected_paths.iter().cloned().collect();
+    assert_eq!(selected, [relative.join("src/main.rs")]);
+    assert_eq!(documents(&state), 1);
+}
+
+#[tokio::test]
+async fn symlink_loops_terminate() {
+    let (dir, relative) = fixture();
+    // 指向祖先目录的链接: 遍历不跟随目录链接，不会无限递归
+    symlink(dir.path(), dir.path().join("src/up")).unwrap();
+    // 互相指向的链接无法解析，按不存在处理
+    symlink(dir.path().join("b"), dir.path().join("a")).unwrap();
+    symlink(dir.path().join("a"), dir.path().join("b")).unwrap();
+
+    let state = new_state(dir.path());
+    run(add(dir.path()), &state).await;
+    run(add(dir.path().join("src/up")), &state).await;
+    let selected: Vec<PathBuf> = state.lock().unwrap().selected_paths.iter().cloned().collect();
+    assert_eq!(selected, [relative.join("src/main.rs")]);
+
+    let err = try_run(add(dir.path().join("a")), &state).await.unwrap_err();
+    assert!(matches!(err, AppError::PathNotFound(_)), "{:?}", err);
+}
+
+#[tokio::test]
+async fn context_merges_duplicates_from_old_sessions() {
+    let (dir, relative) = fixture();
+    symlink(dir.path().join("src/main.rs"), dir.path().join("link.rs")).unwrap();
+    let state = new_state(dir.path());
+    run(add(relative.join("src/main.rs")), &state).await;
+    {
+        // 旧版本保存的会话: 同一文件的三种写法
+        let mut st = state.lock().unwrap();
+        st.selected_paths.insert(Path::new(".").join(&relative).join("src/main.rs"));
+        st.selected_paths.insert(dir.path().join("link.rs"));
+    }
+    let out = run(Command::ShowContext, &state).await;
+    assert!(out.text().contains("(Note) Merged 2 duplicate selections:"), "{}", out.text());
+
+    let st = state.lock().unwrap();
+    assert_eq!(st.selected_paths.iter().cloned().collect::<Vec<_>>(), [relative.join("src/main.rs")]);
+    assert_eq!(st.file_count, 1);
+}
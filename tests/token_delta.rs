@@ -0,0 +1,86 @@
+// tests/token_delta.rs
+//
+// /add、/remove 和 prompt 文本之后的变化摘要
+
+mod common;
+
+use std::fs;
+use std::sync::{Arc, Mutex};
+
+use common::{add, state_in};
+use rustprompt::{
+    app::{
+        output::{delta_summary, format_delta, format_thousands, Level},
+        state::{AppState, ReplMode},
+    },
+    command::definition::Command,
+};
+
+/// 执行命令，返回 (Note) 一类的提示行
+async fn info_lines(cmd: Command, state: &Arc<Mutex<AppState>>) -> Vec<String> {
+    common::run(cmd, state).await.at(Level::Info).into_iter().map(String::from).collect()
+}
+
+fn status(state: &Arc<Mutex<AppState>>) -> (usize, usize) {
+    let st = state.lock().unwrap();
+    (st.selected_paths.len(), st.token_count)
+}
+
+#[test]
+fn delta_formatting() {
+    assert_eq!(format_thousands(0), "0");
+    assert_eq!(format_thousands(999), "999");
+    assert_eq!(format_thousands(45_210), "45,210");
+    assert_eq!(format_thousands(1_234_567), "1,234,567");
+    assert_eq!(format_delta(10, 10), "+0");
+    assert_eq!(format_delta(36_779, 45_210), "+8,431");
+    assert_eq!(format_delta(45_210, 36_779), "-8,431");
+    assert_eq!(delta_summary(3, 15, 36_779, 45_210), "(Note) +12 files, +8,431 tokens (total 45,210)");
+}
+
+#[tokio::test]
+async fn add_then_remove_round_trip() {
+    let dir = tempfile::tempdir().unwrap();
+    fs::create_dir_all(dir.path().join("lib")).unwrap();
+    fs::write(dir.path().join("main.rs"), "fn main() {}\n").unwrap();
+    for i in 0..3 {
+        fs::write(dir.path().join(format!("lib/m{}.rs", i)), format!("pub fn f{}() -> u32 {{ {} }}\n", i, i).repeat(40)).unwrap();
+    }
+    let state = state_in(dir.path());
+
+    // 第一次 /add 还会生成项目树，之后的 /add、/remove 只改变文件本身
+    info_lines(add(dir.path().join("main.rs")), &state).await;
+    let (files0, tokens0) = status(&state);
+
+    let notes = info_lines(add(dir.path().join("lib")), &state).await;
+    let (files1, tokens1) = status(&state);
+    assert_eq!(files1, files0 + 3);
+    assert!(tokens1 > tokens0);
+    assert!(notes.contains(&delta_summary(files0, files1, tokens0, tokens1)), "{:?}", notes);
+
+    let notes = info_lines(Command::Remove(vec![dir.path().join("lib")]), &state).await;
+    assert_eq!(status(&state), (files0, tokens0));
+    let expected = format!("(Note) -3 files, -{} tokens (total {})", format_thousands(tokens1 - tokens0), format_thousands(tokens0));
+    assert!(notes.contains(&expected), "{:?}", notes);
+}
+
+#[tokio::test]
+async fn repeated_add_reports_zero() {
+    let state = Arc::new(Mutex::new(AppState::new()));
+    info_lines(add("Cargo.toml"), &state).await;
+    let (files, tokens) = status(&state);
+    let notes = info_lines(add("Cargo.toml"), &state).await;
+    assert!(notes.contains(&delta_summary(files, files, tokens, tokens)), "{:?}", notes);
+    assert!(notes.iter().any(|n| n.starts_with("(Note) +0 files, +0 tokens")), "{:?}", notes);
+}
+
+#[tokio::test]
+async fn prompt_text_reports_its_tokens() {
+    let state = Arc::new(Mutex::new(AppState::new()));
+    state.lock().unwrap().mode = ReplMode::Prompt;
+    let notes = info_lines(Command::AppendPromptText("Explain the parser.".into()), &state).await;
+    let tokens = state.lock().unwrap().prompt_tokens;
+    assert!(tokens > 0);
+    let expected = format!("(Note) Added to prompt: +{} tokens (prompt total {})", tokens, tokens);
+    assert!(notes.contains(&expected), "{:?}", notes);
+}
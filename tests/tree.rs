@@ -0,0 +1,33 @@
+// tests/tree.rs
+//
+// 项目树的 golden 测试: tests/fixtures/tree 中有多个同级目录各自带有子目录
+
+use std::path::Path;
+
+use rustprompt::core::{ignore_rules::IgnoreConfig, tree_builder::generate_project_tree_string};
+
+fn fixture() -> &'static Path {
+    Path::new(concat!(env!("CARGO_MANIFEST_DIR"), "/tests/fixtures/tree"))
+}
+
+#[test]
+fn sibling_directories_with_children() {
+    let tree = generate_project_tree_string(fixture(), &IgnoreConfig::default()).unwrap();
+    assert_eq!(tree, include_str!("fixtures/tree.golden").trim_end(), "\n{}", tree);
+}
+
+#[test]
+fn subtree_of_a_nested_directory() {
+    let tree = generate_project_tree_string(&fixture().join("src"), &IgnoreConfig::default()).unwrap();
+    let expected = "src\n├── app\n│   ├── mod.rs\n│   └── state.rs\n├── core\n│   └── mod.rs\n└── main.rs";
+    assert_eq!(tree, expected, "\n{}", tree);
+}
+
+#[test]
+fn filtered_files_keep_their_directories() {
+    // 只保留 .md: 其它文件不出现，目录照常列出，连接线按剩下的条目计算
+    let config = IgnoreConfig { extensions: vec!["md".to_string()], ..IgnoreConfig::default() };
+    let tree = generate_project_tree_string(fixture(), &config).unwrap();
+    let expected = "tree\n├── README.md\n├── docs\n│   ├── guide.md\n│   └── img\n├── src\n│   ├── app\n│   └── core\n└── tests";
+    assert_eq!(tree, expected, "\n{}", tree);
+}
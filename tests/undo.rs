@@ -0,0 +1,78 @@
+// tests/undo.rs
+//
+// /undo 只记录真正改变了选择或 prompt 的命令
+
+use std::sync::{Arc, Mutex};
+
+use rustprompt::{
+    app::{
+        output::{set_confirm_override, CapturedOutput},
+        state::AppState,
+    },
+    command::{
+        definition::{AddOptions, Command},
+        executor,
+    },
+};
+use tokio_util::sync::CancellationToken;
+
+async fn run(cmd: Command, state: &Arc<Mutex<AppState>>) -> CapturedOutput {
+    let mut out = CapturedOutput::default();
+    let _ = executor::execute(cmd, state.clone(), &CancellationToken::new(), &mut out).await;
+    out
+}
+
+fn add(path: &str) -> Command {
+    Command::Add(vec![path.into()], AddOptions::default())
+}
+
+fn undo_depth(state: &Arc<Mutex<AppState>>) -> usize {
+    state.lock().unwrap().undo_stack.len()
+}
+
+#[tokio::test]
+async fn failed_and_usage_only_commands_leave_no_snapshot() {
+    let state = Arc::new(Mutex::new(AppState::new()));
+    run(add("no/such/file.rs"), &state).await;
+    assert_eq!(undo_depth(&state), 0);
+
+    // 只有选项没有路径: 打印用法
+    let hidden = AddOptions { hidden: true, ..AddOptions::default() };
+    run(Command::Add(vec![], hidden), &state).await;
+    assert_eq!(undo_depth(&state), 0);
+
+    run(Command::Remove(vec![]), &state).await;
+    run(Command::Remove(vec!["Cargo.toml".into()]), &state).await;
+    assert_eq!(undo_depth(&state), 0);
+}
+
+#[tokio::test]
+async fn repeated_add_records_one_step() {
+    let state = Arc::new(Mutex::new(AppState::new()));
+    run(add("Cargo.toml"), &state).await;
+    run(add("Cargo.toml"), &state).await;
+    assert_eq!(undo_depth(&state), 1);
+    assert_eq!(state.lock().unwrap().undo_stack[0].label, "/add Cargo.toml");
+
+    run(Command::Undo, &state).await;
+    assert!(state.lock().unwrap().selected_paths.is_empty());
+    assert_eq!(undo_depth(&state), 0);
+}
+
+#[tokio::test]
+async fn cancelled_reset_leaves_no_snapshot() {
+    let state = Arc::new(Mutex::new(AppState::new()));
+    state.lock().unwrap().settings.confirm_destructive = true;
+    run(add("Cargo.toml"), &state).await;
+
+    set_confirm_override(Some(false));
+    let out = run(Command::Reset(false, false), &state).await;
+    set_confirm_override(None);
+    assert!(out.text().contains("Reset cancelled"), "{}", out.text());
+    assert_eq!(state.lock().unwrap().selected_paths.len(), 1);
+    assert_eq!(undo_depth(&state), 1);
+
+    run(Command::Reset(false, true), &state).await;
+    assert!(state.lock().unwrap().selected_paths.is_empty());
+    assert_eq!(undo_depth(&state), 2);
+}